@@ -1,9 +1,11 @@
 import React, { useEffect } from 'react';
-import { useAppStore } from './store/appStore';
 import { Sidebar } from './components/Sidebar';
 import { MainEditor } from './components/MainEditor';
 import { AudioControls } from './components/AudioControls';
 import { format } from 'date-fns';
+import { listen } from '@tauri-apps/api/event';
+import { invoke } from '@tauri-apps/api/core';
+import { useAppStore, Navigation } from './store/appStore';
 import './App.css';
 
 function App() {
@@ -15,11 +17,74 @@ function App() {
   } = useAppStore();
 
   useEffect(() => {
-    // Load today's daily note on startup
+    // Windows opened on a page by open_page_window are routed to it with
+    // ?page=; otherwise open the gita:// link the app was launched with, or
+    // else today's daily note
     const today = format(new Date(), 'yyyy-MM-dd');
-    loadDailyNote(today);
+    const pageId = new URLSearchParams(window.location.search).get('page');
+    const launch = pageId
+      ? invoke<Navigation>('resolve_link', { url: `gita://page/${pageId}` })
+      : invoke<Navigation | null>('take_launch_navigation');
+    launch
+      .catch(() => null)
+      .then(navigation => navigation
+        ? useAppStore.getState().openNavigation(navigation)
+        : loadDailyNote(today));
   }, [loadDailyNote]);
 
+  useEffect(() => {
+    // Recordings can also be started and stopped from the global shortcut or tray
+    const started = listen<{ recording_id: string; page_id: string }>('audio://recording-started', ({ payload }) => {
+      useAppStore.setState(state => ({
+        audioState: {
+          ...state.audioState,
+          isRecording: true,
+          recordingId: payload.recording_id,
+          pageId: payload.page_id,
+          startTime: Date.now(),
+        },
+      }));
+    });
+    const stopped = listen<{ recording_id: string }>('audio://recording-stopped', ({ payload }) => {
+      useAppStore.setState(state => state.audioState.recordingId === payload.recording_id ? {
+        audioState: {
+          ...state.audioState,
+          isRecording: false,
+          recordingId: undefined,
+          pageId: undefined,
+          startTime: undefined,
+        },
+      } : {});
+    });
+    // Picked from the tray menu
+    const openDailyNote = listen<string>('app://open-daily-note', ({ payload }) => {
+      useAppStore.getState().loadDailyNote(payload);
+    });
+    // gita:// links opened while the app is running
+    const navigate = listen<Navigation>('app://navigate', ({ payload }) => {
+      useAppStore.getState().openNavigation(payload);
+    });
+    // Blocks and pages written in another window, or by an import, reload
+    // the page here if it shows them. The focused window made its own edits.
+    const refresh = ({ payload }: { payload: { ids: string[] } }) => {
+      const { currentPage, blocks } = useAppStore.getState();
+      if (!currentPage?.page_title || document.hasFocus()) return;
+      const shown = new Set([currentPage.id, ...blocks.map(block => block.id)]);
+      if (payload.ids.some(id => shown.has(id))) {
+        useAppStore.getState().loadPage(currentPage.page_title);
+      }
+    };
+    const dataChanges = ['data://block-changed', 'data://block-deleted', 'data://page-changed']
+      .map(event => listen<{ ids: string[] }>(event, refresh));
+    return () => {
+      dataChanges.forEach(changes => changes.then(unlisten => unlisten()));
+      started.then(unlisten => unlisten());
+      stopped.then(unlisten => unlisten());
+      openDailyNote.then(unlisten => unlisten());
+      navigate.then(unlisten => unlisten());
+    };
+  }, []);
+
   return (
     <div className="app">
       <div className="app-header">
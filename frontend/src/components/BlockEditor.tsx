@@ -68,7 +68,7 @@ export const BlockEditor: React.FC<BlockEditorProps> = ({ block, renderContent }
   }, [isEditing, content]);
 
   return (
-    <div className="block-editor">
+    <div className="block-editor" data-block-id={block.id}>
       <div className="block-controls">
         <div className="block-bullet">•</div>
         {block.audio_timestamp && (
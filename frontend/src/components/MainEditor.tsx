@@ -1,5 +1,5 @@
 import React, { useState, useRef, useEffect } from 'react';
-import { useAppStore } from '../store/appStore';
+import { useAppStore, CURRENT_RECORDING_POSITION } from '../store/appStore';
 import { BlockEditor } from './BlockEditor';
 import { format } from 'date-fns';
 
@@ -25,11 +25,10 @@ export const MainEditor: React.FC = () => {
 
       // Calculate audio metadata if recording
       let audioMeta;
-      if (audioState.isRecording && audioState.recordingId && audioState.startTime) {
-        const timestamp = Math.floor((Date.now() - audioState.startTime) / 1000);
+      if (audioState.isRecording && audioState.recordingId) {
         audioMeta = {
           recording_id: audioState.recordingId,
-          timestamp,
+          timestamp_ms: CURRENT_RECORDING_POSITION,
         };
       }
 
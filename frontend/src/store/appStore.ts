@@ -1,5 +1,5 @@
 import { create } from 'zustand';
-import { invoke } from '@tauri-apps/api/core';
+import { invoke, Channel } from '@tauri-apps/api/core';
 
 export interface Block {
   id: string;
@@ -11,28 +11,59 @@ export interface Block {
   created_at: string;
   updated_at: string;
   audio_timestamp?: AudioTimestamp;
+  source?: string;
 }
 
 export interface AudioTimestamp {
   id: number;
   block_id: string;
   recording_id: string;
+  timestamp_ms: number;
   timestamp_seconds: number;
   recording?: AudioRecording;
+  segment?: RecordingSegment;
 }
 
+// One file of a recording split every few minutes; offsets are relative to the whole recording
+export interface RecordingSegment {
+  index: number;
+  file_path: string;
+  start_ms: number;
+  duration_ms: number;
+}
+
+// Error returned by every command
+export interface AppError {
+  code: 'not_found' | 'validation' | 'conflict' | 'audio' | 'database' | 'io' | 'internal';
+  message: string;
+  details?: Record<string, unknown> | null;
+}
+
+export const errorMessage = (error: unknown): string =>
+  typeof error === 'object' && error !== null && 'message' in error
+    ? (error as AppError).message
+    : String(error);
+
 export interface AudioRecording {
   id: string;
   page_id: string;
   file_path: string;
   duration_seconds?: number;
   recorded_at: string;
+  system_audio?: boolean;
+}
+
+export interface DeviceConfigSummary {
+  sample_format: string;
+  sample_rate: number;
+  channels: number;
 }
 
 export interface AudioDevice {
   name: string;
   is_default: boolean;
   device_type: string;
+  default_config?: DeviceConfigSummary;
 }
 
 export interface AudioState {
@@ -53,9 +84,32 @@ export interface CreateBlockRequest {
 
 export interface AudioMeta {
   recording_id: string;
-  timestamp: number;
+  timestamp_ms: number;
 }
 
+// Sent over the channel of stream_page_blocks
+type PageBlocksEvent =
+  | { event: 'blocks'; blocks: Block[] }
+  | { event: 'finished'; total: number };
+
+// Cancel token of the page being streamed, so opening another page stops it
+let pageStreamToken: string | undefined;
+
+// Where a gita:// link points, sent as app://navigate
+export type Navigation =
+  | { kind: 'page'; page_id: string; page_title: string; block_id?: string | null }
+  | {
+      kind: 'recording';
+      page_id: string;
+      page_title: string;
+      recording: AudioRecording;
+      timestamp_ms: number;
+      segment?: RecordingSegment | null;
+    };
+
+// Asks the backend to stamp the block with the live recording position
+export const CURRENT_RECORDING_POSITION = -1;
+
 interface AppState {
   // Data state
   blocks: Block[];
@@ -69,6 +123,7 @@ interface AppState {
   // Actions
   loadDailyNote: (date: string) => Promise<void>;
   loadPage: (title: string) => Promise<void>;
+  openNavigation: (navigation: Navigation) => Promise<void>;
   createBlock: (blockData: CreateBlockRequest, audioMeta?: AudioMeta) => Promise<Block>;
   updateBlockContent: (blockId: string, content: string) => Promise<void>;
   deleteBlock: (blockId: string) => Promise<void>;
@@ -97,6 +152,8 @@ export const useAppStore = create<AppState>((set, get) => ({
     // @ts-expect-error __TAURI__ is injected by Tauri
     if (window.__TAURI__) {
       try {
+        // Save edits to the page being left before reading the next one
+        await invoke('flush_pending_writes');
         const blocks: Block[] = await invoke('get_daily_note', { date });
         const currentPage = blocks.find(block => block.is_page);
         set({
@@ -107,7 +164,7 @@ export const useAppStore = create<AppState>((set, get) => ({
       } catch (error) {
         console.error("Error invoking get_daily_note:", error);
         set({
-          error: error as string,
+          error: errorMessage(error),
           isLoading: false
         });
       }
@@ -126,15 +183,35 @@ export const useAppStore = create<AppState>((set, get) => ({
     // @ts-expect-error __TAURI__ is injected by Tauri
     if (window.__TAURI__) {
       try {
+        await invoke('flush_pending_writes');
         const page: Block | null = await invoke('get_page_by_title', { title });
         if (page) {
-          const children: Block[] = await invoke('get_block_children', { parentId: page.id });
-          const blocks = [page, ...children];
           set({
-            blocks,
+            blocks: [page],
             currentPage: page,
             isLoading: false
           });
+          // Large pages arrive in batches, shown as they come in
+          const channel = new Channel<PageBlocksEvent>();
+          channel.onmessage = message => {
+            if (message.event === 'blocks' && get().currentPage?.id === page.id) {
+              set(state => ({ blocks: [...state.blocks, ...message.blocks] }));
+            }
+          };
+          if (pageStreamToken) {
+            invoke('cancel_pending', { tokenId: pageStreamToken }).catch(() => {});
+          }
+          const cancelToken = crypto.randomUUID();
+          pageStreamToken = cancelToken;
+          try {
+            await invoke('stream_page_blocks', { pageTitle: title, channel, cancelToken });
+          } catch (error) {
+            // Cancelled because another page was opened
+            if (pageStreamToken !== cancelToken) return;
+            throw error;
+          } finally {
+            if (pageStreamToken === cancelToken) pageStreamToken = undefined;
+          }
         } else {
           // Create new page
           // Ensure createBlock itself is guarded or this will fail if __TAURI__ is not present
@@ -166,7 +243,7 @@ export const useAppStore = create<AppState>((set, get) => ({
       } catch (error) {
         console.error("Error invoking get_page_by_title or get_block_children:", error);
         set({ 
-          error: error as string,
+          error: errorMessage(error),
           isLoading: false 
         });
       }
@@ -180,6 +257,27 @@ export const useAppStore = create<AppState>((set, get) => ({
     }
   },
 
+  openNavigation: async (navigation: Navigation) => {
+    await get().loadPage(navigation.page_title);
+    if (navigation.kind === 'recording') {
+      get().playAudioFromTimestamp({
+        id: 0,
+        block_id: '',
+        recording_id: navigation.recording.id,
+        timestamp_ms: navigation.timestamp_ms,
+        timestamp_seconds: Math.floor(navigation.timestamp_ms / 1000),
+        recording: navigation.recording,
+        segment: navigation.segment ?? undefined,
+      });
+    } else if (navigation.block_id) {
+      const blockId = navigation.block_id;
+      // Wait for the page's blocks to render before scrolling to the linked one
+      setTimeout(() => {
+        document.querySelector(`[data-block-id="${blockId}"]`)?.scrollIntoView({ block: 'center' });
+      }, 0);
+    }
+  },
+
   createBlock: async (blockData: CreateBlockRequest, audioMeta?: AudioMeta) => {
     // @ts-expect-error __TAURI__ is injected by Tauri
     if (window.__TAURI__) {
@@ -193,7 +291,7 @@ export const useAppStore = create<AppState>((set, get) => ({
         return newBlock;
       } catch (error) {
         console.error("Error invoking create_block:", error);
-        set({ error: error as string });
+        set({ error: errorMessage(error) });
         throw error; // Re-throw or handle as per desired UX
       }
     } else {
@@ -211,7 +309,8 @@ export const useAppStore = create<AppState>((set, get) => ({
     // @ts-expect-error __TAURI__ is injected by Tauri
     if (window.__TAURI__) {
       try {
-        await invoke('update_block_content', { blockId, content });
+        const written = await invoke<boolean>('update_block_content', { blockId, content });
+        if (!written) return; // Content was unchanged
 
         set(state => ({
           blocks: state.blocks.map(block =>
@@ -222,7 +321,7 @@ export const useAppStore = create<AppState>((set, get) => ({
         }));
       } catch (error) {
         console.error("Error invoking update_block_content:", error);
-        set({ error: error as string });
+        set({ error: errorMessage(error) });
         throw error; // Re-throw or handle
       }
     } else {
@@ -243,7 +342,7 @@ export const useAppStore = create<AppState>((set, get) => ({
         }));
       } catch (error) {
         console.error("Error invoking delete_block:", error);
-        set({ error: error as string });
+        set({ error: errorMessage(error) });
         throw error; // Re-throw or handle
       }
     } else {
@@ -359,9 +458,16 @@ export const useAppStore = create<AppState>((set, get) => ({
   playAudioFromTimestamp: (audioTimestamp: AudioTimestamp) => {
     if (!audioTimestamp.recording) return;
 
+    // Segmented recordings play the file holding the timestamp, offset within it
+    const segment = audioTimestamp.segment;
+    const filePath = segment ? segment.file_path : audioTimestamp.recording.file_path;
+    const offsetSeconds = (segment
+      ? audioTimestamp.timestamp_ms - segment.start_ms
+      : audioTimestamp.timestamp_ms) / 1000;
+
     // Create audio element and play from timestamp
-    const audio = new Audio(`file://${audioTimestamp.recording.file_path}`);
-    audio.currentTime = audioTimestamp.timestamp_seconds;
+    const audio = new Audio(`file://${filePath}`);
+    audio.currentTime = Math.max(0, offsetSeconds);
     audio.play().catch(error => {
       console.error('Failed to play audio:', error);
     });
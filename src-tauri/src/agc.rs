@@ -0,0 +1,164 @@
+// Level the gain steers towards, in dBFS RMS
+const TARGET_DBFS: f32 = -18.0;
+
+// Limits on how far a quiet input is boosted or a loud one cut
+const MAX_GAIN_DB: f32 = 24.0;
+const MIN_GAIN_DB: f32 = -12.0;
+
+// Input quieter than this is treated as silence, so pauses don't pump the gain up
+const GATE_DBFS: f32 = -55.0;
+
+// Samples are hard-limited just below full scale
+const LIMIT: f32 = 0.98;
+
+// Time constants of the running RMS level and of the faster one used for gating
+const RMS_WINDOW_MS: f32 = 50.0;
+const GATE_WINDOW_MS: f32 = 5.0;
+
+pub const DEFAULT_ATTACK_MS: f32 = 10.0;
+pub const DEFAULT_RELEASE_MS: f32 = 500.0;
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+/// One-pole smoothing coefficient for a time constant at a sample rate
+fn coefficient(time_ms: f32, sample_rate: u32) -> f32 {
+    let samples = time_ms.max(0.1) / 1000.0 * sample_rate.max(1) as f32;
+    1.0 - (-1.0 / samples).exp()
+}
+
+/// Automatic gain control: a running RMS tracker steers the gain towards a
+/// target level and a hard limiter keeps the result from clipping
+pub struct AutoGain {
+    rms_coef: f32,
+    gate_coef: f32,
+    attack_coef: f32,  // Used while the gain is coming down
+    release_coef: f32, // Used while the gain is going up
+    mean_square: f32,
+    gate_mean_square: f32,
+    gain: f32,
+}
+
+impl AutoGain {
+    pub fn new(sample_rate: u32, attack_ms: f32, release_ms: f32) -> Self {
+        AutoGain {
+            rms_coef: coefficient(RMS_WINDOW_MS, sample_rate),
+            gate_coef: coefficient(GATE_WINDOW_MS, sample_rate),
+            attack_coef: coefficient(attack_ms, sample_rate),
+            release_coef: coefficient(release_ms, sample_rate),
+            mean_square: 0.0,
+            gate_mean_square: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    /// Scale interleaved samples in place towards the target level
+    pub fn process(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            self.mean_square += self.rms_coef * (power - self.mean_square);
+            self.gate_mean_square += self.gate_coef * (power - self.gate_mean_square);
+
+            // Hold the gain as soon as the input drops out, before the slower level catches up
+            if 10.0 * self.gate_mean_square.max(1e-12).log10() > GATE_DBFS {
+                let level_db = 10.0 * self.mean_square.max(1e-12).log10();
+                let desired = db_to_gain((TARGET_DBFS - level_db).clamp(MIN_GAIN_DB, MAX_GAIN_DB));
+                let coef = if desired < self.gain { self.attack_coef } else { self.release_coef };
+                self.gain += coef * (desired - self.gain);
+            }
+
+            for sample in frame.iter_mut() {
+                *sample = (*sample * self.gain).clamp(-LIMIT, LIMIT);
+            }
+        }
+    }
+
+    /// Gain currently applied, in dB
+    pub fn gain_db(&self) -> f32 {
+        gain_to_db(self.gain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    /// A 220Hz tone whose amplitude follows `amplitude(t)` for `seconds`
+    fn tone(seconds: f32, amplitude: impl Fn(f32) -> f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                amplitude(t) * (t * 220.0 * std::f32::consts::TAU).sin()
+            })
+            .collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        10.0 * mean_square.log10()
+    }
+
+    #[test]
+    fn test_quiet_input_is_raised_to_target() {
+        // -40 dBFS RMS, well below the target
+        let mut samples = tone(3.0, |_| db_to_gain(-37.0));
+        let mut agc = AutoGain::new(SAMPLE_RATE, DEFAULT_ATTACK_MS, DEFAULT_RELEASE_MS);
+        agc.process(&mut samples, 1);
+
+        let settled = &samples[samples.len() - SAMPLE_RATE as usize..];
+        assert!((rms_db(settled) - TARGET_DBFS).abs() < 1.5, "settled at {} dBFS", rms_db(settled));
+        assert!(agc.gain_db() > 20.0);
+    }
+
+    #[test]
+    fn test_rising_ramp_is_evened_out() {
+        // Amplitude ramps from barely audible to full scale
+        let mut samples = tone(4.0, |t| 0.01 + t * 0.2475);
+        let input_spread = rms_db(&samples[samples.len() - 1600..]) - rms_db(&samples[8000..9600]);
+
+        let mut agc = AutoGain::new(SAMPLE_RATE, DEFAULT_ATTACK_MS, DEFAULT_RELEASE_MS);
+        agc.process(&mut samples, 1);
+
+        assert!(samples.iter().all(|s| s.abs() <= LIMIT));
+        // The level swing is evened out
+        let output_spread = rms_db(&samples[samples.len() - 1600..]) - rms_db(&samples[8000..9600]);
+        assert!(output_spread.abs() < input_spread / 2.0, "{} vs {}", output_spread, input_spread);
+    }
+
+    #[test]
+    fn test_loud_onset_is_cut_quickly() {
+        let mut samples = tone(0.5, |t| if t < 0.25 { 0.05 } else { 0.9 });
+        let mut agc = AutoGain::new(SAMPLE_RATE, DEFAULT_ATTACK_MS, DEFAULT_RELEASE_MS);
+        agc.process(&mut samples, 1);
+
+        // The boosted gain meets the jump before attack pulls it down, so only the limiter stops clipping
+        assert!(samples.iter().all(|s| s.abs() <= LIMIT));
+        assert!(samples.iter().any(|s| s.abs() == LIMIT));
+
+        // Within 100ms of the jump the output is back near the target
+        let after_onset = &samples[(0.35 * SAMPLE_RATE as f32) as usize..];
+        assert!(rms_db(after_onset) < TARGET_DBFS + 3.0, "{} dBFS", rms_db(after_onset));
+    }
+
+    #[test]
+    fn test_silence_holds_gain() {
+        let mut agc = AutoGain::new(SAMPLE_RATE, DEFAULT_ATTACK_MS, DEFAULT_RELEASE_MS);
+        let mut speech = tone(2.0, |_| 0.1);
+        agc.process(&mut speech, 1);
+        let gain_db = agc.gain_db();
+
+        // A pause doesn't push the gain towards its maximum
+        let mut silence = vec![0.0; SAMPLE_RATE as usize * 2];
+        agc.process(&mut silence, 1);
+
+        assert!(agc.gain_db() - gain_db < 3.0, "{} -> {} dB", gain_db, agc.gain_db());
+        assert!(silence.iter().all(|&s| s == 0.0));
+    }
+}
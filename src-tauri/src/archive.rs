@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use tracing::{info, warn};
+use crate::config::AppConfig;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::jobs::{Progress, Untracked};
+use crate::models::{BackupReport, Block, DatabaseExport, ExportSummary, ImportReport};
+use crate::page_bundle::check_writable;
+
+/// Name of the JSON export inside a backup directory
+pub const BACKUP_EXPORT_FILE: &str = "gita.json";
+
+// Directory of a backup the recordings are copied into
+const BACKUP_RECORDINGS_DIR: &str = "recordings";
+
+/// A line of a Markdown outline and how deeply it's nested
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    pub depth: usize,
+    pub content: String,
+}
+
+/// Where backups go when no directory is given
+pub fn default_backups_dir(config: &AppConfig) -> PathBuf {
+    config.data_dir.join("backups")
+}
+
+/// Width of a line's indentation, a tab counting as four spaces
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Split a Markdown outline into blocks. Each bullet is a block nested
+/// under the closest less indented bullet above it, and indented lines that
+/// aren't bullets continue the block above. Other lines are top-level blocks.
+pub fn parse_outline(markdown: &str) -> Vec<OutlineItem> {
+    let mut items: Vec<OutlineItem> = Vec::new();
+    let mut open_indents: Vec<usize> = Vec::new(); // Indentation of the bullets each item may nest under
+
+    for line in markdown.lines() {
+        let text = line.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let indent = indent_width(line);
+        let bullet = ["- ", "* ", "+ "].iter()
+            .find_map(|marker| text.strip_prefix(marker))
+            .or_else(|| matches!(text, "-" | "*" | "+").then_some(""));
+
+        match (bullet, items.last_mut()) {
+            (Some(content), _) => {
+                while open_indents.last().is_some_and(|&open| open >= indent) {
+                    open_indents.pop();
+                }
+                items.push(OutlineItem { depth: open_indents.len(), content: content.trim().to_string() });
+                open_indents.push(indent);
+            }
+            (None, Some(last)) if open_indents.last().is_some_and(|&open| indent > open) => {
+                last.content.push('\n');
+                last.content.push_str(text);
+            }
+            (None, _) => {
+                open_indents.clear();
+                items.push(OutlineItem { depth: 0, content: text.to_string() });
+            }
+        }
+    }
+    items
+}
+
+/// A new page titled `title` followed by the blocks of the outline in
+/// `markdown`, ready to be written in one transaction
+pub fn outline_page(title: &str, markdown: &str, now: DateTime<Utc>) -> Vec<Block> {
+    let new_block = |content: Option<String>, parent_id: Option<String>, order: i32| Block {
+        id: uuid::Uuid::new_v4().to_string(),
+        content,
+        parent_id,
+        order,
+        is_page: false,
+        page_title: None,
+        created_at: now,
+        updated_at: now,
+        audio_timestamp: None,
+        source: None,
+    };
+    let page = Block { is_page: true, page_title: Some(title.to_string()), ..new_block(None, None, 0) };
+
+    // parents[depth] is the block items at that depth go under
+    let mut parents = vec![page.id.clone()];
+    let mut next_order: HashMap<String, i32> = HashMap::new();
+    let mut blocks = vec![page];
+    for item in parse_outline(markdown) {
+        parents.truncate(item.depth + 1);
+        let parent_id = parents[item.depth].clone();
+        let order = next_order.entry(parent_id.clone()).or_insert(0);
+        let block = new_block(Some(item.content), Some(parent_id), *order);
+        *order += 1;
+        parents.push(block.id.clone());
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// `(@mm:ss)` position of a block in the recording it was written during
+fn timestamp_suffix(timestamp_ms: i64) -> String {
+    let seconds = timestamp_ms.max(0) / 1000;
+    format!("(@{:02}:{:02})", seconds / 60, seconds % 60)
+}
+
+fn render_block(out: &mut String, block: &Block, children: &HashMap<String, Vec<Block>>, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let text = block.content.as_deref().or(block.page_title.as_deref()).unwrap_or("");
+    let mut lines = text.lines();
+    let _ = write!(out, "{}- {}", indent, lines.next().unwrap_or(""));
+    // Continuation lines sit under the bullet's text, as `parse_outline` expects
+    for line in lines {
+        let _ = write!(out, "\n{}  {}", indent, line);
+    }
+    if let Some(timestamp) = &block.audio_timestamp {
+        let _ = write!(out, " {}", timestamp_suffix(timestamp.timestamp_ms));
+    }
+    out.push('\n');
+    for child in children.get(&block.id).into_iter().flatten() {
+        render_block(out, child, children, depth + 1);
+    }
+}
+
+/// Render `root` and the blocks below it as a Markdown outline that
+/// `parse_outline` reads back, one bullet per block. `children` holds each
+/// block's children in order. Blocks written during a recording end with
+/// their position in it.
+pub fn render_outline(root: &Block, children: &HashMap<String, Vec<Block>>) -> String {
+    let mut out = String::new();
+    render_block(&mut out, root, children, 0);
+    out
+}
+
+/// The block `block_id` and everything below it as a Markdown outline
+pub async fn block_markdown(db: &DatomicPeerClient, block_id: &str) -> Result<String, AppError> {
+    let mut root = db.get_block(block_id).await?
+        .ok_or_else(|| AppError::not_found(format!("Block not found: {}", block_id)))?;
+    root.audio_timestamp = db.get_block_audio_timestamp(block_id).await?;
+
+    // One level at a time; a block already seen is skipped in case of a cycle
+    let mut seen = HashSet::from([root.id.clone()]);
+    let mut children: HashMap<String, Vec<Block>> = HashMap::new();
+    let mut level = vec![root.id.clone()];
+    while !level.is_empty() {
+        let mut next_level = Vec::new();
+        for (parent_id, mut blocks) in db.get_children_for_parents(&level).await? {
+            blocks.retain(|block| seen.insert(block.id.clone()));
+            for block in &mut blocks {
+                block.audio_timestamp = db.get_block_audio_timestamp(&block.id).await?;
+                next_level.push(block.id.clone());
+            }
+            children.insert(parent_id, blocks);
+        }
+        level = next_level;
+    }
+    Ok(render_outline(&root, &children))
+}
+
+/// Titles and contents of the `.md` files directly in `dir`, by file name
+fn read_markdown_files(dir: &Path) -> Result<Vec<(String, String)>, AppError> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        AppError::validation(format!("Can't read {}: {}", dir.display(), e))
+            .with_details(serde_json::json!({ "dir": dir.display().to_string() }))
+    })?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("md")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    paths.into_iter()
+        .filter_map(|path| Some((path.file_stem()?.to_string_lossy().trim().to_string(), path)))
+        .filter(|(title, _)| !title.is_empty())
+        .map(|(title, path)| Ok((title, fs::read_to_string(&path)?)))
+        .collect()
+}
+
+/// Create a page for each Markdown file in `dir`, titled after the file,
+/// with the file's outline as its blocks. Titles that already have a page
+/// are skipped so an import can be run again after adding files, or after
+/// it was cancelled. Progress is reported a file at a time.
+pub async fn import_markdown(db: &DatomicPeerClient, dir: &Path, progress: &impl Progress) -> Result<ImportReport, AppError> {
+    let files = tauri::async_runtime::spawn_blocking({
+        let dir = dir.to_path_buf();
+        move || read_markdown_files(&dir)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Import task failed: {}", e)))??;
+
+    let mut report = ImportReport::default();
+    let total = files.len();
+    for (done, (title, markdown)) in files.into_iter().enumerate() {
+        progress.check_cancelled()?;
+        if db.get_page_by_title(&title).await?.is_some() {
+            warn!("Not importing {}: the page already exists", title);
+            progress.progress(done + 1, total, &title);
+            report.pages_skipped.push(title);
+            continue;
+        }
+
+        // A page is written whole or not at all, so a failed import can be rerun
+        let mut blocks = outline_page(&title, &markdown, Utc::now());
+        for block in &mut blocks {
+            block.source = Some("import".to_string());
+        }
+        db.create_blocks(&blocks).await?;
+        report.blocks_created += blocks.len() - 1;
+        progress.progress(done + 1, total, &title);
+        report.pages_created.push(title);
+    }
+
+    info!(
+        "Imported {} pages with {} blocks from {}, skipped {}",
+        report.pages_created.len(), report.blocks_created, dir.display(), report.pages_skipped.len()
+    );
+    Ok(report)
+}
+
+/// Every block, recording and block timestamp in the database. Progress is
+/// reported a recording at a time, as each needs its timestamps read.
+pub async fn export_database(db: &DatomicPeerClient, progress: &impl Progress) -> Result<DatabaseExport, AppError> {
+    let blocks = db.get_all_blocks().await?;
+    let recordings = db.get_all_recordings().await?;
+    let mut timestamps = Vec::new();
+    for (done, recording) in recordings.iter().enumerate() {
+        progress.check_cancelled()?;
+        timestamps.extend(db.get_recording_timestamps(&recording.id).await?);
+        progress.progress(done + 1, recordings.len(), &recording.id);
+    }
+
+    Ok(DatabaseExport {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: db.schema_version().await?,
+        exported_at: Utc::now(),
+        blocks,
+        recordings,
+        timestamps,
+    })
+}
+
+/// Write `export` to `path` through a temporary file, so an interrupted
+/// export never leaves a truncated file in place of the last good one
+fn write_export(path: &Path, export: &DatabaseExport) -> Result<(), AppError> {
+    let json = serde_json::to_vec_pretty(export)
+        .map_err(|e| AppError::internal(format!("Failed to encode export: {}", e)))?;
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, json)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Export the whole database as JSON to `out`, replacing any file there.
+/// A cancelled export leaves any earlier file in place.
+pub async fn export_json(db: &DatomicPeerClient, out: &Path, progress: &impl Progress) -> Result<ExportSummary, AppError> {
+    if let Some(parent) = out.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        check_writable(parent)?;
+    }
+
+    let export = export_database(db, progress).await?;
+    progress.check_cancelled()?;
+    let summary = ExportSummary {
+        path: out.display().to_string(),
+        blocks: export.blocks.len(),
+        recordings: export.recordings.len(),
+        timestamps: export.timestamps.len(),
+    };
+    tauri::async_runtime::spawn_blocking({
+        let out = out.to_path_buf();
+        move || write_export(&out, &export)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Export task failed: {}", e)))??;
+
+    info!("Exported {} blocks and {} recordings to {}", summary.blocks, summary.recordings, out.display());
+    Ok(summary)
+}
+
+/// Copy the files below `from` into `to`, leaving out `skip` in case the
+/// backups are kept among the recordings. Returns the files and bytes copied.
+fn copy_tree(from: &Path, to: &Path, skip: &Path) -> io::Result<(usize, u64)> {
+    fs::create_dir_all(to)?;
+    let mut copied = (0, 0);
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.starts_with(skip) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            let (files, bytes) = copy_tree(&path, &to.join(entry.file_name()), skip)?;
+            copied = (copied.0 + files, copied.1 + bytes);
+        } else {
+            copied = (copied.0 + 1, copied.1 + fs::copy(&path, to.join(entry.file_name()))?);
+        }
+    }
+    Ok(copied)
+}
+
+/// Write a JSON export and a copy of the recordings into a new timestamped
+/// directory under `backups_dir`
+pub async fn backup(db: &DatomicPeerClient, recordings_dir: &Path, backups_dir: &Path) -> Result<BackupReport, AppError> {
+    check_writable(backups_dir)?;
+    let backups_dir = backups_dir.canonicalize()?;
+    let dir = backups_dir.join(format!("gita-backup-{}", Utc::now().format("%Y%m%d-%H%M%S")));
+    fs::create_dir(&dir).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => AppError::conflict(format!("Backup {} already exists", dir.display())),
+        _ => AppError::from(e),
+    })?;
+
+    let export = export_json(db, &dir.join(BACKUP_EXPORT_FILE), &Untracked).await?;
+
+    // No recordings directory yet just means nothing has been recorded
+    let (files_copied, bytes_copied) = match recordings_dir.canonicalize() {
+        Ok(recordings_dir) => tauri::async_runtime::spawn_blocking({
+            let to = dir.join(BACKUP_RECORDINGS_DIR);
+            move || copy_tree(&recordings_dir, &to, &backups_dir)
+        })
+        .await
+        .map_err(|e| AppError::internal(format!("Backup task failed: {}", e)))??,
+        Err(_) => (0, 0),
+    };
+
+    info!("Backed up {} blocks and {} recording files to {}", export.blocks, files_copied, dir.display());
+    Ok(BackupReport {
+        dir: dir.display().to_string(),
+        export,
+        files_copied,
+        bytes_copied,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AudioTimestamp;
+
+    fn item(depth: usize, content: &str) -> OutlineItem {
+        OutlineItem { depth, content: content.to_string() }
+    }
+
+    #[test]
+    fn test_parse_outline_nests_bullets() {
+        let markdown = "# Weekly sync\n\
+                        - Agenda\n\
+                        \x20 - Budget for [[Q3]]\n\
+                        \x20   - Needs sign-off\n\
+                        \x20     continued on a second line\n\
+                        \x20 * Hiring\n\
+                        \n\
+                        - Actions\n\
+                        \t- Send notes\n\
+                        Closing remarks\n\
+                        - After";
+
+        assert_eq!(parse_outline(markdown), vec![
+            item(0, "# Weekly sync"),
+            item(0, "Agenda"),
+            item(1, "Budget for [[Q3]]"),
+            item(2, "Needs sign-off\ncontinued on a second line"),
+            item(1, "Hiring"),
+            item(0, "Actions"),
+            item(1, "Send notes"),
+            item(0, "Closing remarks"),
+            item(0, "After"),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_outline_never_skips_a_level() {
+        // Deeper indentation than the bullet above nests one level at most
+        let items = parse_outline("- Top\n        - Far in\n  - Less far\n- Back");
+        assert_eq!(items.iter().map(|item| item.depth).collect::<Vec<_>>(), vec![0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_outline_page_links_blocks_to_parents_in_order() {
+        let blocks = outline_page("Weekly sync", "- Agenda\n  - Budget\n  - Hiring\n- Actions", Utc::now());
+        let (page, children) = blocks.split_first().unwrap();
+        assert!(page.is_page);
+        assert_eq!(page.page_title.as_deref(), Some("Weekly sync"));
+
+        let summary: Vec<(&str, &str, i32)> = children.iter()
+            .map(|block| (block.content.as_deref().unwrap(), block.parent_id.as_deref().unwrap(), block.order))
+            .collect();
+        assert_eq!(summary, vec![
+            ("Agenda", page.id.as_str(), 0),
+            ("Budget", children[0].id.as_str(), 0),
+            ("Hiring", children[0].id.as_str(), 1),
+            ("Actions", page.id.as_str(), 1),
+        ]);
+        assert!(children.iter().all(|block| !block.is_page && block.page_title.is_none()));
+    }
+
+    fn block(id: &str, content: &str, parent_id: Option<&str>, order: i32) -> Block {
+        Block {
+            id: id.to_string(),
+            content: Some(content.to_string()),
+            parent_id: parent_id.map(str::to_string),
+            order,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_render_outline_nests_blocks_with_timestamps() {
+        let mut budget = block("budget", "Budget for [[Q3]]\nneeds sign-off", Some("agenda"), 0);
+        budget.audio_timestamp = Some(AudioTimestamp {
+            block_id: "budget".to_string(),
+            recording_id: "rec-1".to_string(),
+            timestamp_ms: 754_900,
+            timestamp_seconds: 754,
+            recording: None,
+            segment: None,
+        });
+        let children = HashMap::from([
+            ("agenda".to_string(), vec![budget, block("hiring", "Hiring", Some("agenda"), 1)]),
+            ("hiring".to_string(), vec![block("roles", "Two roles", Some("hiring"), 0)]),
+        ]);
+
+        let markdown = render_outline(&block("agenda", "Agenda", Some("page"), 0), &children);
+
+        assert_eq!(markdown, "- Agenda\n\
+                              \x20 - Budget for [[Q3]]\n\
+                              \x20   needs sign-off (@12:34)\n\
+                              \x20 - Hiring\n\
+                              \x20   - Two roles\n");
+        // What's copied pastes back as the same outline
+        let items: Vec<(usize, String)> = parse_outline(&markdown).into_iter().map(|item| (item.depth, item.content)).collect();
+        assert_eq!(items, vec![
+            (0, "Agenda".to_string()),
+            (1, "Budget for [[Q3]]\nneeds sign-off (@12:34)".to_string()),
+            (1, "Hiring".to_string()),
+            (2, "Two roles".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_render_outline_uses_page_title() {
+        let page = Block { content: None, is_page: true, page_title: Some("Weekly sync".to_string()), ..block("page", "", None, 0) };
+        assert_eq!(render_outline(&page, &HashMap::new()), "- Weekly sync\n");
+        assert_eq!(timestamp_suffix(3_725_000), "(@62:05)");
+    }
+
+    #[test]
+    fn test_copy_tree_skips_backups_inside_recordings() {
+        let recordings = tempfile::TempDir::new().unwrap();
+        let out = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(recordings.path().join("2024").join("05")).unwrap();
+        fs::create_dir_all(recordings.path().join("backups").join("gita-backup-1")).unwrap();
+        fs::write(recordings.path().join("a.wav"), b"RIFF").unwrap();
+        fs::write(recordings.path().join("2024").join("05").join("b.wav"), b"RIFF audio").unwrap();
+        fs::write(recordings.path().join("backups").join("gita-backup-1").join(BACKUP_EXPORT_FILE), b"{}").unwrap();
+
+        let copied = copy_tree(recordings.path(), out.path(), &recordings.path().join("backups")).unwrap();
+
+        assert_eq!(copied, (2, 14));
+        assert_eq!(fs::read(out.path().join("2024").join("05").join("b.wav")).unwrap(), b"RIFF audio");
+        assert!(!out.path().join("backups").exists());
+    }
+}
@@ -1,28 +1,270 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Device, Host};
-use hound::{WavSpec, WavWriter};
-use std::sync::{Arc, Mutex};
+use hound::{WavReader, WavSpec, WavWriter};
+use rubato::{FftFixedIn, Resampler};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
-use std::time::Instant;
-use anyhow::{Result, anyhow};
-use crate::models::AudioDevice;
+use std::time::{Duration, Instant};
+use crate::agc::{self, AutoGain};
+use crate::errors::AudioEngineError;
+use crate::silence::{SilenceDetector, SilenceSplit};
+#[cfg(feature = "opus")]
+use crate::ogg_opus::{self, OggOpusWriter};
+use crate::models::{AudioDevice, DeviceCaps, InputLevel, DeviceConfigRange, DeviceConfigSummary, DevicesChanged, NormalizationReport, RecordingSegment, RecordingSummary, RecordingVerification, SegmentSplit, SilenceInterval, WavFileCheck};
+
+type Result<T> = std::result::Result<T, AudioEngineError>;
+
+// How often the writer rewrites the WAV header so a crash leaves a playable file
+const HEADER_FLUSH_INTERVAL_SECS: u32 = 2;
+
+// Samples between progress callbacks while normalizing
+const NORMALIZE_PROGRESS_INTERVAL: u64 = 1 << 16;
+
+// Input frames per resampler pass when converting to the target sample rate
+const RESAMPLE_CHUNK_FRAMES: usize = 1024;
+
+// Opus always decodes to 48kHz, so Opus recordings are encoded at that rate too
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+
+// Samples scaled at a time when writing a gain-adjusted playback copy
+const PLAYBACK_GAIN_CHUNK_SAMPLES: usize = 4096;
+
+// Furthest playback can be turned up or down
+pub const MAX_PLAYBACK_GAIN_DB: f32 = 24.0;
+
+// Upper bound on buffered monitoring audio (~100ms of 48kHz stereo) so latency can't build up
+const MONITOR_BUFFER_SAMPLES: usize = 9600;
+
+/// How a new recording is encoded
+#[derive(Debug, Clone, Copy)]
+pub enum Encoding {
+    /// WAV at this sample rate, or the device's rate if `None`, and bit
+    /// depth: 32-bit float, or 16 or 24-bit integer PCM
+    Wav(Option<u32>, u16),
+    /// Ogg/Opus at this many bits per second, always at 48kHz
+    Opus(u32),
+}
+
+impl Encoding {
+    fn sample_rate(&self) -> Option<u32> {
+        match self {
+            Encoding::Wav(rate, _) => *rate,
+            Encoding::Opus(_) => Some(OPUS_SAMPLE_RATE),
+        }
+    }
+}
+
+#[cfg(not(feature = "opus"))]
+fn opus_unavailable(bitrate: u32) -> AudioEngineError {
+    AudioEngineError::UnsupportedFormat(format!("this build can't encode {} bps Opus", bitrate))
+}
+
+/// Create a recording file, refusing to truncate one that's already there
+pub fn create_new_file(path: &str) -> Result<std::fs::File> {
+    std::fs::OpenOptions::new().write(true).create_new(true).open(path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::AlreadyExists {
+            AudioEngineError::AlreadyExists(path.to_string())
+        } else {
+            e.into()
+        }
+    })
+}
+
+/// How the writer sets up the recording file
+#[derive(Debug, Clone, Copy)]
+enum WriterMode {
+    /// New file in this encoding
+    Create(Encoding),
+    /// Continue an existing file at its own sample rate
+    Append,
+}
+
+type SplitListener = Arc<dyn Fn(SegmentSplit) + Send + Sync>;
+
+/// When the writer starts a new segment file, and who to tell
+#[derive(Clone, Default)]
+struct SplitPolicy {
+    every_minutes: Option<u32>,
+    on_silence: Option<SilenceSplit>,
+    listener: Option<SplitListener>,
+}
+
+// How a recording segment is being encoded
+enum SegmentEncoder {
+    Wav(WavWriter<std::io::BufWriter<std::fs::File>>),
+    #[cfg(feature = "opus")]
+    Opus(OggOpusWriter),
+}
+
+// The file a recording segment is being written to. Audio goes to the
+// segment's `.partial` file, which only takes the segment's own name once
+// finalized, so a file under that name is always complete.
+struct SegmentWriter {
+    encoder: SegmentEncoder,
+    path: String,
+}
+
+impl SegmentWriter {
+    fn create(path: &str, channels: u16, sample_rate: u32, encoding: Encoding) -> Result<Self> {
+        // Finalizing would otherwise replace it
+        if std::path::Path::new(path).exists() {
+            return Err(AudioEngineError::AlreadyExists(path.to_string()));
+        }
+        let partial = AudioEngine::partial_path(path);
+        let encoder = match encoding {
+            Encoding::Wav(_, bits_per_sample) => {
+                let sample_format = match bits_per_sample {
+                    32 => hound::SampleFormat::Float,
+                    16 | 24 => hound::SampleFormat::Int,
+                    bits => return Err(AudioEngineError::UnsupportedFormat(format!("{}-bit WAV", bits))),
+                };
+                let spec = WavSpec { channels, sample_rate, bits_per_sample, sample_format };
+                let file = std::io::BufWriter::new(create_new_file(&partial)?);
+                SegmentEncoder::Wav(WavWriter::new(file, spec)?)
+            }
+            #[cfg(feature = "opus")]
+            Encoding::Opus(bitrate) => SegmentEncoder::Opus(OggOpusWriter::create(&partial, channels, bitrate)?),
+            #[cfg(not(feature = "opus"))]
+            Encoding::Opus(bitrate) => return Err(opus_unavailable(bitrate)),
+        };
+        Ok(SegmentWriter { encoder, path: path.to_string() })
+    }
+
+    /// Continue the WAV recording at `path`, returning its format and the
+    /// frames already in it. A finalized file goes back to its `.partial`
+    /// name until the recording is finalized again.
+    fn append(path: &str) -> Result<(Self, WavSpec, u64)> {
+        let partial = AudioEngine::partial_path(path);
+        if !std::path::Path::new(&partial).exists() {
+            std::fs::rename(path, &partial)?;
+        }
+        let existing = WavWriter::append(&partial)?;
+        let spec = existing.spec();
+        let frames = existing.len() as u64 / spec.channels.max(1) as u64;
+        Ok((SegmentWriter { encoder: SegmentEncoder::Wav(existing), path: path.to_string() }, spec, frames))
+    }
+
+    fn channels(&self) -> u16 {
+        match &self.encoder {
+            SegmentEncoder::Wav(w) => w.spec().channels,
+            #[cfg(feature = "opus")]
+            SegmentEncoder::Opus(w) => w.channels(),
+        }
+    }
+
+    fn write_frame(&mut self, frame: &[f32]) -> Result<()> {
+        match &mut self.encoder {
+            SegmentEncoder::Wav(w) => {
+                let spec = w.spec();
+                for &sample in frame {
+                    AudioEngine::write_f32_sample(w, spec, sample)?;
+                }
+            }
+            #[cfg(feature = "opus")]
+            SegmentEncoder::Opus(w) => w.write_samples(frame)?,
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.encoder {
+            SegmentEncoder::Wav(w) => w.flush()?,
+            #[cfg(feature = "opus")]
+            SegmentEncoder::Opus(w) => w.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Finish the file and move it to the segment's own name. If finishing
+    /// fails the `.partial` file is left behind for recovery.
+    fn finalize(self) -> Result<()> {
+        match self.encoder {
+            SegmentEncoder::Wav(w) => w.finalize()?,
+            #[cfg(feature = "opus")]
+            SegmentEncoder::Opus(w) => w.finalize()?,
+        }
+        std::fs::rename(AudioEngine::partial_path(&self.path), &self.path)?;
+        Ok(())
+    }
+}
+
+// Where a WAV file's audio data starts and how long its header says the data is
+struct WavLayout {
+    data_offset: u64,
+    data_len: u64,
+    block_align: u64,
+}
 
 // Simple audio engine that doesn't store streams in shared state
 pub struct AudioEngine {
     host: Host,
     recording_state: Arc<Mutex<RecordingState>>,
+    monitor_tap: Arc<MonitorTap>,
+    input_meter: Arc<InputMeter>,
+    // Stops the device hot-plug watcher while it is running
+    device_watcher_stop: Mutex<Option<Sender<()>>>,
+    // Told whenever a recording moves on to a new segment file
+    split_listener: Mutex<Option<SplitListener>>,
+    // Opens capture streams on the recording thread; tests swap in a host without devices
+    open_capture: OpenCapture,
+    // What a panic interrupted, until a start or stop reports it
+    reset_after_panic: Mutex<Option<String>>,
 }
 
 struct RecordingState {
     is_recording: bool,
     start_time: Option<Instant>,
-    writer_thread: Option<thread::JoinHandle<()>>,
+    writer_thread: Option<thread::JoinHandle<Result<Vec<RecordingSegment>>>>,
     recording_file_path: Option<String>,
+    // Input device the recording is capturing from
+    input_device: Option<String>,
     // Store a stop signal instead of the actual streams
     stop_sender: Option<Sender<()>>,
+    // Tells the writer to discard the recording instead of finalizing it
+    discard_flag: Option<Arc<AtomicBool>>,
+    // Frames the writer has received, for precise block timestamps
+    clock: Option<Arc<RecordingClock>>,
+    // Monitoring preference, kept across recordings
+    monitoring_enabled: bool,
+    monitor_device: Option<String>,
+    // Stops the monitoring output stream while it is running
+    monitor_stop_sender: Option<Sender<()>>,
+}
+
+impl RecordingState {
+    fn idle() -> Self {
+        RecordingState {
+            is_recording: false,
+            start_time: None,
+            writer_thread: None,
+            recording_file_path: None,
+            input_device: None,
+            stop_sender: None,
+            discard_flag: None,
+            clock: None,
+            monitoring_enabled: false,
+            monitor_device: None,
+            monitor_stop_sender: None,
+        }
+    }
+}
+
+/// Streams a recording captures from, kept open on its capture thread
+struct CaptureStreams {
+    device_name: String,
+    input: cpal::Stream,
+    // System audio, if it's being captured
+    loopback: Option<cpal::Stream>,
 }
 
+/// Opens the streams for a recording from the named input device, or the
+/// default one, sending what it captures to the writer
+type OpenCapture = fn(Option<&str>, Sender<AudioSample>, Arc<MonitorTap>, Option<Arc<MonitorTap>>, Arc<InputMeter>) -> Result<CaptureStreams>;
+
 #[derive(Clone)]
 struct AudioSample {
     data: Vec<f32>,
@@ -30,39 +272,445 @@ struct AudioSample {
     channels: u16,
 }
 
+/// Position of the current recording, measured in frames delivered to the writer
+/// so stream startup latency doesn't skew block timestamps
+#[derive(Default)]
+struct RecordingClock {
+    frames: AtomicU64,
+    sample_rate: AtomicU32,
+    offset_ms: u64, // Audio already in the file when a recording is resumed
+}
+
+impl RecordingClock {
+    fn starting_at(offset_ms: u64) -> Self {
+        RecordingClock { offset_ms, ..RecordingClock::default() }
+    }
+
+    fn advance(&self, samples: usize, sample_rate: u32, channels: u16) {
+        if channels == 0 {
+            return;
+        }
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.frames.fetch_add((samples / channels as usize) as u64, Ordering::Relaxed);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.offset_ms + match self.sample_rate.load(Ordering::Relaxed) {
+            0 => 0,
+            sample_rate => self.frames.load(Ordering::Relaxed) * 1000 / sample_rate as u64,
+        }
+    }
+}
+
+/// Bounded buffer carrying captured samples from an input callback to a
+/// consumer running at its own rate: the monitoring output stream, or the
+/// writer mixing in system audio. The oldest frames are dropped on overflow.
+struct MonitorTap {
+    enabled: AtomicBool,
+    buffer: Mutex<VecDeque<f32>>,
+    sample_rate: AtomicU32,
+    channels: AtomicU16,
+}
+
+impl MonitorTap {
+    fn new() -> Self {
+        MonitorTap {
+            enabled: AtomicBool::new(false),
+            buffer: Mutex::new(VecDeque::with_capacity(MONITOR_BUFFER_SAMPLES)),
+            sample_rate: AtomicU32::new(0),
+            channels: AtomicU16::new(0),
+        }
+    }
+
+    fn push(&self, data: &[f32], sample_rate: u32, channels: u16) {
+        if !self.enabled.load(Ordering::Relaxed) || channels == 0 {
+            return;
+        }
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.channels.store(channels, Ordering::Relaxed);
+
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.extend(data.iter().copied());
+
+            // Drop whole frames so channels stay aligned
+            let channels = channels as usize;
+            let max_len = MONITOR_BUFFER_SAMPLES - MONITOR_BUFFER_SAMPLES % channels;
+            if buffer.len() > max_len {
+                let excess = (buffer.len() - max_len).div_ceil(channels) * channels;
+                let drop_len = excess.min(buffer.len());
+                buffer.drain(..drop_len);
+            }
+        }
+    }
+
+    /// A tap that starts out enabled, for a source that is always consumed
+    fn active() -> Self {
+        let tap = MonitorTap::new();
+        tap.enabled.store(true, Ordering::SeqCst);
+        tap
+    }
+
+    fn reset(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.clear();
+        }
+    }
+}
+
+/// AGC settings and the latest input levels, shared with the capture callback
+/// so AGC can be switched while recording. f32 values are stored as bits.
+struct InputMeter {
+    agc_enabled: AtomicBool,
+    agc_attack_ms: AtomicU32,
+    agc_release_ms: AtomicU32,
+    agc_gain_db: AtomicU32,
+    rms: AtomicU32,
+    peak: AtomicU32,
+}
+
+impl InputMeter {
+    fn new() -> Self {
+        InputMeter {
+            agc_enabled: AtomicBool::new(false),
+            agc_attack_ms: AtomicU32::new(agc::DEFAULT_ATTACK_MS.to_bits()),
+            agc_release_ms: AtomicU32::new(agc::DEFAULT_RELEASE_MS.to_bits()),
+            agc_gain_db: AtomicU32::new(0f32.to_bits()),
+            rms: AtomicU32::new(0f32.to_bits()),
+            peak: AtomicU32::new(0f32.to_bits()),
+        }
+    }
+
+    fn load(value: &AtomicU32) -> f32 {
+        f32::from_bits(value.load(Ordering::Relaxed))
+    }
+
+    fn store(value: &AtomicU32, level: f32) {
+        value.store(level.to_bits(), Ordering::Relaxed);
+    }
+
+    fn auto_gain(&self, sample_rate: u32) -> AutoGain {
+        AutoGain::new(sample_rate, Self::load(&self.agc_attack_ms), Self::load(&self.agc_release_ms))
+    }
+
+    /// Remember the level of the samples about to be written
+    fn measure(&self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        Self::store(&self.rms, mean_square.sqrt());
+        Self::store(&self.peak, peak);
+    }
+
+    fn level(&self) -> InputLevel {
+        InputLevel {
+            rms_db: agc::gain_to_db(Self::load(&self.rms)),
+            peak_db: agc::gain_to_db(Self::load(&self.peak)),
+            agc_gain_db: self.agc_enabled.load(Ordering::Relaxed).then(|| Self::load(&self.agc_gain_db)),
+        }
+    }
+}
+
+/// Output-side state that maps buffered input frames onto the output
+/// device's channel count and sample rate
+struct MonitorPlayback {
+    frame: Vec<f32>,
+    phase: f64,
+}
+
+impl MonitorPlayback {
+    fn new() -> Self {
+        MonitorPlayback { frame: Vec::new(), phase: 1.0 }
+    }
+
+    /// Fill `out` with interleaved frames, emitting silence when the buffer runs dry
+    fn fill(&mut self, tap: &MonitorTap, out: &mut [f32], out_channels: usize, out_rate: u32) {
+        let in_rate = tap.sample_rate.load(Ordering::Relaxed);
+        let in_channels = tap.channels.load(Ordering::Relaxed) as usize;
+        let mut buffer = match tap.buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(_) => {
+                out.fill(0.0);
+                return;
+            }
+        };
+
+        if in_rate == 0 || in_channels == 0 || out_channels == 0 {
+            out.fill(0.0);
+            return;
+        }
+        let step = in_rate as f64 / out_rate as f64;
+
+        for out_frame in out.chunks_mut(out_channels) {
+            // Advance through the input at its own rate (nearest-neighbour resampling)
+            while self.phase >= 1.0 {
+                self.phase -= 1.0;
+                self.frame.clear();
+                if buffer.len() >= in_channels {
+                    self.frame.extend(buffer.drain(..in_channels));
+                }
+            }
+            self.phase += step;
+
+            for (i, sample) in out_frame.iter_mut().enumerate() {
+                *sample = if self.frame.is_empty() { 0.0 } else { self.frame[i % self.frame.len()] };
+            }
+        }
+    }
+}
+
+/// Converts interleaved chunks of any length from the device's sample rate to
+/// the recording's, buffering input until the resampler has a full pass
+struct ChunkResampler {
+    resampler: FftFixedIn<f32>,
+    from_rate: u32,
+    to_rate: u32,
+    channels: usize,
+    pending: Vec<Vec<f32>>, // Per-channel input not yet resampled
+    delay_frames: usize,    // Leading output frames still to drop
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl ChunkResampler {
+    fn new(from_rate: u32, to_rate: u32, channels: usize) -> Result<Self> {
+        let resampler = FftFixedIn::new(from_rate as usize, to_rate as usize, RESAMPLE_CHUNK_FRAMES, 2, channels)
+            .map_err(|e| AudioEngineError::internal_error(format!("Failed to create resampler: {}", e)))?;
+        Ok(ChunkResampler {
+            delay_frames: resampler.output_delay(),
+            resampler,
+            from_rate,
+            to_rate,
+            channels,
+            pending: vec![Vec::new(); channels],
+            frames_in: 0,
+            frames_out: 0,
+        })
+    }
+
+    /// Resample `input` and append whatever output is ready to `out`
+    fn process(&mut self, input: &[f32], out: &mut Vec<f32>) -> Result<()> {
+        for frame in input.chunks_exact(self.channels) {
+            for (channel, &sample) in self.pending.iter_mut().zip(frame) {
+                channel.push(sample);
+            }
+        }
+        self.frames_in += (input.len() / self.channels) as u64;
+
+        while self.pending[0].len() >= self.resampler.input_frames_next() {
+            self.run_pass(out, u64::MAX)?;
+        }
+        Ok(())
+    }
+
+    /// Push the buffered input through, padded with silence, and append the
+    /// remaining output so the total matches the input length at the new rate
+    fn flush(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        let expected = self.frames_in * self.to_rate as u64 / self.from_rate as u64;
+        while self.frames_out < expected {
+            let needed = self.resampler.input_frames_next();
+            for channel in self.pending.iter_mut() {
+                channel.resize(channel.len().max(needed), 0.0);
+            }
+            self.run_pass(out, expected)?;
+        }
+        Ok(())
+    }
+
+    /// Resample one pass of pending input, dropping the resampler's delay and
+    /// anything past `limit` total output frames
+    fn run_pass(&mut self, out: &mut Vec<f32>, limit: u64) -> Result<()> {
+        let needed = self.resampler.input_frames_next();
+        let chunk: Vec<Vec<f32>> = self.pending.iter_mut().map(|channel| channel.drain(..needed).collect()).collect();
+        let resampled = self.resampler.process(&chunk, None)
+            .map_err(|e| AudioEngineError::internal_error(format!("Resampling failed: {}", e)))?;
+
+        let skip = self.delay_frames.min(resampled[0].len());
+        self.delay_frames -= skip;
+        let available = (resampled[0].len() - skip) as u64;
+        let take = available.min(limit.saturating_sub(self.frames_out)) as usize;
+        for i in skip..skip + take {
+            out.extend(resampled.iter().map(|channel| channel[i]));
+        }
+        self.frames_out += take as u64;
+        Ok(())
+    }
+}
+
+/// Names of the host's input and output devices and its defaults at one point in time
+#[derive(Debug, Clone, Default, PartialEq)]
+struct DeviceSnapshot {
+    inputs: BTreeSet<String>,
+    outputs: BTreeSet<String>,
+    default_input: Option<String>,
+    default_output: Option<String>,
+}
+
+impl DeviceSnapshot {
+    fn capture(host: &Host) -> Result<Self> {
+        Ok(DeviceSnapshot {
+            inputs: Self::names(host.input_devices())?,
+            outputs: Self::names(host.output_devices())?,
+            default_input: host.default_input_device().and_then(|d| d.name().ok()),
+            default_output: host.default_output_device().and_then(|d| d.name().ok()),
+        })
+    }
+
+    fn names(devices: std::result::Result<impl Iterator<Item = Device>, cpal::DevicesError>) -> Result<BTreeSet<String>> {
+        Ok(devices
+            .map_err(|e| AudioEngineError::device_error(format!("Failed to enumerate devices: {}", e)))?
+            .filter_map(|device| device.name().ok())
+            .collect())
+    }
+
+    /// Devices present in `newer` but not here and vice versa, or `None` if nothing changed
+    fn changes(&self, newer: &DeviceSnapshot) -> Option<DevicesChanged> {
+        if self == newer {
+            return None;
+        }
+
+        let device = |name: &String, device_type: &str| AudioDevice {
+            name: name.clone(),
+            is_default: false,
+            device_type: device_type.to_string(),
+            default_config: None,
+        };
+        let mut changes = DevicesChanged {
+            default_input: newer.default_input.clone(),
+            default_output: newer.default_output.clone(),
+            ..DevicesChanged::default()
+        };
+        for (old, new, device_type) in [(&self.inputs, &newer.inputs, "input"), (&self.outputs, &newer.outputs, "output")] {
+            changes.added.extend(new.difference(old).map(|name| device(name, device_type)));
+            changes.removed.extend(old.difference(new).map(|name| device(name, device_type)));
+        }
+
+        Some(changes)
+    }
+}
+
 impl AudioEngine {
     pub fn new() -> Result<Self> {
         let host = cpal::default_host();
         
-        let recording_state = Arc::new(Mutex::new(RecordingState {
-            is_recording: false,
-            start_time: None,
-            writer_thread: None,
-            recording_file_path: None,
-            stop_sender: None,
-        }));
+        let recording_state = Arc::new(Mutex::new(RecordingState::idle()));
 
         Ok(AudioEngine {
             host,
             recording_state,
+            monitor_tap: Arc::new(MonitorTap::new()),
+            input_meter: Arc::new(InputMeter::new()),
+            device_watcher_stop: Mutex::new(None),
+            split_listener: Mutex::new(None),
+            open_capture: Self::open_capture,
+            reset_after_panic: Mutex::new(None),
+        })
+    }
+
+    /// Lock the recording state. If a panic left the lock poisoned, the
+    /// recording that was running is stopped and the engine carries on idle,
+    /// so one failure doesn't break every later call.
+    fn state(&self) -> MutexGuard<'_, RecordingState> {
+        self.recording_state.lock().unwrap_or_else(|poisoned| {
+            let mut state = poisoned.into_inner();
+            eprintln!("Resetting audio engine state after a panic");
+            let interrupted = match &state.recording_file_path {
+                Some(path) if state.is_recording => format!("the recording to {} was stopped", path),
+                _ => "no recording was running".to_string(),
+            };
+            *self.reset_after_panic.lock().unwrap_or_else(PoisonError::into_inner) = Some(interrupted);
+            self.stop_monitor(&mut state);
+            // Once capture stops, the detached writer finalizes what it has
+            if let Some(stop_sender) = state.stop_sender.take() {
+                let _ = stop_sender.send(());
+            }
+            *state = RecordingState {
+                monitoring_enabled: state.monitoring_enabled,
+                monitor_device: state.monitor_device.take(),
+                ..RecordingState::idle()
+            };
+            self.recording_state.clear_poison();
+            state
         })
     }
 
+    /// Lock the recording state for starting or stopping a recording. The
+    /// first of those calls after a panic reset the engine fails with
+    /// `Poisoned`, so the interrupted recording isn't lost without a word;
+    /// the engine is idle by then and later calls go through.
+    fn checked_state(&self) -> Result<MutexGuard<'_, RecordingState>> {
+        let state = self.state();
+        match self.reset_after_panic.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            Some(interrupted) => Err(AudioEngineError::Poisoned(interrupted)),
+            None => Ok(state),
+        }
+    }
+
+    /// Poll the device list every `interval` and call `on_change` with the
+    /// devices that appeared or disappeared and the current defaults. cpal has
+    /// no portable hot-plug notifications, so this compares device names; a
+    /// poll that finds no change does nothing else. Replaces a running watcher.
+    pub fn start_device_watcher(&self, interval: Duration, on_change: impl Fn(DevicesChanged) + Send + 'static) -> Result<()> {
+        self.stop_device_watcher();
+
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let (ready_sender, ready_receiver) = mpsc::channel::<Result<()>>();
+
+        thread::spawn(move || {
+            let host = cpal::default_host();
+            let mut known = match DeviceSnapshot::capture(&host) {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_sender.send(Ok(()));
+
+            // Wake up every interval until told to stop or the engine goes away
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                match DeviceSnapshot::capture(&host) {
+                    Ok(current) => {
+                        if let Some(changes) = known.changes(&current) {
+                            on_change(changes);
+                            known = current;
+                        }
+                    }
+                    Err(e) => eprintln!("Device watcher: {}", e),
+                }
+            }
+        });
+
+        ready_receiver.recv()
+            .unwrap_or_else(|_| Err(AudioEngineError::internal_error("Device watcher thread exited unexpectedly")))?;
+
+        *self.device_watcher_stop.lock().unwrap_or_else(PoisonError::into_inner) = Some(stop_sender);
+        Ok(())
+    }
+
+    pub fn stop_device_watcher(&self) {
+        if let Some(stop_sender) = self.device_watcher_stop.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            let _ = stop_sender.send(());
+        }
+    }
+
     pub fn get_audio_devices(&self) -> Result<Vec<AudioDevice>> {
         let mut devices = Vec::new();
+        let default_input = self.default_input_device_name();
+        let default_output = self.host.default_output_device().and_then(|d| d.name().ok());
 
-        // Get input devices (microphones)
+        // Get input devices (microphones). Devices that can't report a
+        // default config are still listed, just without one.
         if let Ok(input_devices) = self.host.input_devices() {
             for device in input_devices {
                 if let Ok(name) = device.name() {
-                    let is_default = self.host.default_input_device()
-                        .map(|d| d.name().unwrap_or_default() == name)
-                        .unwrap_or(false);
-                    
                     devices.push(AudioDevice {
+                        is_default: default_input.as_deref() == Some(name.as_str()),
                         name,
-                        is_default,
                         device_type: "input".to_string(),
+                        default_config: device.default_input_config().ok().map(|config| Self::summarize_config(&config)),
                     });
                 }
             }
@@ -72,133 +720,486 @@ impl AudioEngine {
         if let Ok(output_devices) = self.host.output_devices() {
             for device in output_devices {
                 if let Ok(name) = device.name() {
-                    let is_default = self.host.default_output_device()
-                        .map(|d| d.name().unwrap_or_default() == name)
-                        .unwrap_or(false);
-                    
                     devices.push(AudioDevice {
+                        is_default: default_output.as_deref() == Some(name.as_str()),
                         name,
-                        is_default,
                         device_type: "output".to_string(),
+                        default_config: device.default_output_config().ok().map(|config| Self::summarize_config(&config)),
                     });
                 }
             }
         }
 
         Ok(devices)
-    }    pub fn start_recording(&self, file_path: &str) -> Result<()> {
-        let mut state = self.recording_state.lock().unwrap();
-        
-        if state.is_recording {
-            return Err(anyhow!("Already recording"));
+    }
+
+    fn summarize_config(config: &cpal::SupportedStreamConfig) -> DeviceConfigSummary {
+        DeviceConfigSummary {
+            sample_format: config.sample_format().to_string(),
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
         }
+    }
 
-        // Create audio channel for communication between streams and writer
-        let (audio_sender, receiver) = mpsc::channel::<AudioSample>();
-        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+    /// List every input configuration range the named device supports so the
+    /// settings UI only offers valid sample rate and channel combinations
+    pub fn supported_input_configs(&self, name: &str) -> Result<Vec<DeviceConfigRange>> {
+        let device = Self::find_input_device(&self.host, name)?
+            .ok_or_else(|| AudioEngineError::DeviceNotFound(name.to_string()))?;
 
-        // Start the audio writer thread
-        let writer_file_path = file_path.to_string();
-        let writer_thread = thread::spawn(move || {
-            Self::audio_writer_thread(receiver, &writer_file_path);
-        });
+        let configs = device.supported_input_configs()
+            .map_err(|e| AudioEngineError::device_error(format!("Failed to read configs of '{}': {}", name, e)))?;
 
-        // Create a new host for the audio thread instead of cloning
-        let audio_thread = thread::spawn(move || {
-            let host = cpal::default_host();
-            Self::audio_recording_thread(host, audio_sender, stop_receiver);
-        });
+        Ok(configs
+            .map(|range| DeviceConfigRange {
+                sample_format: range.sample_format().to_string(),
+                min_sample_rate: range.min_sample_rate().0,
+                max_sample_rate: range.max_sample_rate().0,
+                channels: range.channels(),
+                is_supported: Self::is_supported_format(range.sample_format()),
+            })
+            .collect())
+    }
 
-        state.is_recording = true;
-        state.start_time = Some(Instant::now());
-        state.writer_thread = Some(writer_thread);
-        state.recording_file_path = Some(file_path.to_string());
-        state.stop_sender = Some(stop_sender);
+    /// Report the default input config of the named device so the UI can warn
+    /// before starting a recording that would fail
+    pub fn device_capabilities(&self, name: &str) -> Result<DeviceCaps> {
+        let device = Self::find_input_device(&self.host, name)?
+            .ok_or_else(|| AudioEngineError::DeviceNotFound(name.to_string()))?;
 
-        // We need to keep the audio thread alive, but we can't store it in state
-        // For now, we'll detach it - in a production app you'd want better lifecycle management
-        std::mem::forget(audio_thread);
+        let config = device.default_input_config()
+            .map_err(|e| AudioEngineError::device_error(format!("Failed to read default config of '{}': {}", name, e)))?;
 
-        Ok(())
+        Ok(DeviceCaps {
+            device_name: device.name().map_err(|e| AudioEngineError::device_error(e.to_string()))?,
+            sample_format: config.sample_format().to_string(),
+            sample_rate: config.sample_rate().0,
+            channels: config.channels(),
+            is_supported: Self::is_supported_format(config.sample_format()),
+        })
     }
 
-    pub fn stop_recording(&self) -> Result<i32> {
-        let mut state = self.recording_state.lock().unwrap();
-        
-        if !state.is_recording {
-            return Err(anyhow!("Not currently recording"));
-        }
+    /// Whether an input device with this name is currently connected
+    pub fn has_input_device(&self, name: &str) -> Result<bool> {
+        Ok(Self::find_input_device(&self.host, name)?.is_some())
+    }
 
-        // Send stop signal to audio thread
-        if let Some(stop_sender) = state.stop_sender.take() {
-            let _ = stop_sender.send(());
-        }
+    pub fn default_input_device_name(&self) -> Option<String> {
+        self.host.default_input_device().and_then(|d| d.name().ok())
+    }
 
-        let duration = if let Some(start_time) = state.start_time {
-            start_time.elapsed().as_secs() as i32
-        } else {
-            0
-        };
+    /// How many whole seconds of `bits`-bit audio at `sample_rate` with
+    /// `channels` channels fit in `free_bytes`. Container overhead is left
+    /// out, it's a few bytes per second at most.
+    pub fn estimated_remaining_seconds(free_bytes: u64, sample_rate: u32, channels: u16, bits: u16) -> u64 {
+        let bytes_per_second = sample_rate as u64 * channels as u64 * bits as u64 / 8;
+        free_bytes.checked_div(bytes_per_second).unwrap_or(0)
+    }
 
-        // Wait for writer thread to finish
-        if let Some(writer_thread) = state.writer_thread.take() {
-            let _ = writer_thread.join();
-        }
+    fn find_input_device(host: &Host, name: &str) -> Result<Option<Device>> {
+        let mut devices = host.input_devices()
+            .map_err(|e| AudioEngineError::device_error(format!("Failed to enumerate input devices: {}", e)))?;
+        Ok(devices.find(|d| d.name().map(|n| n == name).unwrap_or(false)))
+    }
 
-        state.is_recording = false;
-        state.start_time = None;
-        state.recording_file_path = None;
+    /// Sample formats the input stream can convert to f32
+    fn is_supported_format(format: cpal::SampleFormat) -> bool {
+        matches!(format, cpal::SampleFormat::F32 | cpal::SampleFormat::I16 | cpal::SampleFormat::U16)
+    }
 
-        Ok(duration)
+    /// Whether system audio can be captured on this host. Only WASAPI exposes
+    /// output devices for loopback recording.
+    pub fn system_audio_available(&self) -> bool {
+        self.host.id().name() == "WASAPI"
     }
 
-    fn audio_recording_thread(
-        host: Host,
-        audio_sender: Sender<AudioSample>,
-        stop_receiver: Receiver<()>,
-    ) {
-        // Get default input device (microphone)
-        let input_device = match host.default_input_device() {
-            Some(device) => device,
-            None => {
-                eprintln!("No default input device available");
-                return;
-            }
+    /// Start recording from the named input device, or the system default if `None`,
+    /// splitting the audio into a new file every `segment_minutes` if set and at
+    /// every pause described by `silence_split`. With `capture_system_audio` the
+    /// default output device is captured as well and mixed into the recording.
+    /// Audio is converted to the sample rate of the `encoding` if the device
+    /// captures at a different rate.
+    pub fn start_recording(
+        &self,
+        file_path: &str,
+        device_name: Option<&str>,
+        segment_minutes: Option<u32>,
+        silence_split: Option<SilenceSplit>,
+        capture_system_audio: bool,
+        encoding: Encoding,
+    ) -> Result<()> {
+        #[cfg(not(feature = "opus"))]
+        if let Encoding::Opus(bitrate) = encoding {
+            return Err(opus_unavailable(bitrate));
+        }
+        // The writer won't replace it either, but this fails before capture starts
+        if let Some(existing) = [file_path.to_string(), Self::partial_path(file_path)]
+            .into_iter()
+            .find(|path| std::path::Path::new(path).exists())
+        {
+            return Err(AudioEngineError::AlreadyExists(existing));
+        }
+        let split = SplitPolicy {
+            every_minutes: segment_minutes,
+            on_silence: silence_split,
+            listener: self.split_listener.lock().unwrap_or_else(PoisonError::into_inner).clone(),
         };
+        self.begin_recording(file_path, device_name, split, capture_system_audio, WriterMode::Create(encoding), 0)
+    }
 
-        // Create input stream
-        let stream = match Self::create_input_stream_static(&input_device, audio_sender) {
-            Ok(stream) => stream,
-            Err(e) => {
-                eprintln!("Failed to create input stream: {}", e);
-                return;
+    /// Call `listener` from the writer thread each time a recording starts a
+    /// new segment file. Replaces any earlier listener.
+    pub fn on_segment_split(&self, listener: impl Fn(SegmentSplit) + Send + Sync + 'static) {
+        *self.split_listener.lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(listener));
+    }
+
+    /// Continue an interrupted single-file recording by appending to its file.
+    /// The time the app was closed isn't recorded: new audio follows straight
+    /// on from the old, so positions stay continuous with the file's contents.
+    pub fn resume_recording(&self, file_path: &str, device_name: Option<&str>, capture_system_audio: bool) -> Result<()> {
+        if std::path::Path::new(&Self::unfinished_path(&Self::segment_path(file_path, 1))).exists() {
+            return Err(AudioEngineError::wav_error(format!("{} was split into segments and can't be resumed", file_path)));
+        }
+        if self.state().is_recording {
+            return Err(AudioEngineError::AlreadyRecording);
+        }
+
+        // Make the header match the data so the writer appends after the last whole frame
+        let unfinished = Self::unfinished_path(file_path);
+        Self::repair_wav_file(&unfinished)?;
+        let existing_ms = Self::wav_duration_ms(&unfinished)? as u64;
+        self.begin_recording(file_path, device_name, SplitPolicy::default(), capture_system_audio, WriterMode::Append, existing_ms)
+    }
+
+    fn begin_recording(
+        &self,
+        file_path: &str,
+        device_name: Option<&str>,
+        split: SplitPolicy,
+        capture_system_audio: bool,
+        writer_mode: WriterMode,
+        offset_ms: u64,
+    ) -> Result<()> {
+        let mut state = self.checked_state()?;
+        
+        if state.is_recording {
+            return Err(AudioEngineError::AlreadyRecording);
+        }
+        if capture_system_audio && !self.system_audio_available() {
+            return Err(AudioEngineError::LoopbackUnavailable(format!(
+                "the {} audio host has no loopback devices", self.host.id().name()
+            )));
+        }
+        let loopback_tap = capture_system_audio.then(|| Arc::new(MonitorTap::active()));
+
+        // Create audio channel for communication between streams and writer
+        let (audio_sender, receiver) = mpsc::channel::<AudioSample>();
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let (ready_sender, ready_receiver) = mpsc::channel::<Result<String>>();
+
+        // Streams can't leave the thread that opens them, so it keeps them
+        // open until told to stop
+        let open_capture = self.open_capture;
+        let device_name = device_name.map(|name| name.to_string());
+        let monitor_tap = self.monitor_tap.clone();
+        let input_meter = self.input_meter.clone();
+        let capture_loopback_tap = loopback_tap.clone();
+        let audio_thread = thread::spawn(move || {
+            let CaptureStreams { device_name, input, loopback } =
+                match open_capture(device_name.as_deref(), audio_sender, monitor_tap, capture_loopback_tap, input_meter) {
+                    Ok(streams) => streams,
+                    Err(e) => {
+                        let _ = ready_sender.send(Err(e));
+                        return;
+                    }
+                };
+            let _ = ready_sender.send(Ok(device_name));
+
+            // Streams are stopped when dropped
+            let _ = stop_receiver.recv();
+            drop(loopback);
+            drop(input);
+        });
+
+        // Nothing is recording yet if capture fails, so the state stays idle
+        let input_device = ready_receiver.recv()
+            .unwrap_or_else(|_| Err(AudioEngineError::internal_error("Capture thread exited unexpectedly")))?;
+
+        // Start the audio writer thread; samples captured so far wait in the channel
+        let discard_flag = Arc::new(AtomicBool::new(false));
+        let clock = Arc::new(RecordingClock::starting_at(offset_ms));
+        let writer_file_path = file_path.to_string();
+        let writer_discard_flag = discard_flag.clone();
+        let writer_clock = clock.clone();
+        let writer_thread = thread::spawn(move || {
+            Self::audio_writer_thread(receiver, &writer_file_path, writer_discard_flag, writer_clock, split, loopback_tap, writer_mode)
+        });
+
+        state.is_recording = true;
+        // Count resumed audio towards the duration reported when the recording stops
+        state.start_time = Instant::now().checked_sub(Duration::from_millis(offset_ms)).or_else(|| Some(Instant::now()));
+        state.writer_thread = Some(writer_thread);
+        state.recording_file_path = Some(file_path.to_string());
+        state.input_device = Some(input_device);
+        state.stop_sender = Some(stop_sender);
+        state.discard_flag = Some(discard_flag);
+        state.clock = Some(clock);
+
+        // We need to keep the audio thread alive, but we can't store it in state
+        // For now, we'll detach it - in a production app you'd want better lifecycle management
+        std::mem::forget(audio_thread);
+
+        // Monitoring is a convenience, so a broken output device shouldn't stop the recording
+        if state.monitoring_enabled {
+            if let Err(e) = self.start_monitor(&mut state) {
+                eprintln!("Failed to start monitoring: {}", e);
             }
-        };
+        }
 
-        // Start the stream
-        if let Err(e) = stream.play() {
-            eprintln!("Failed to start audio stream: {}", e);
-            return;
+        Ok(())
+    }
+
+    /// Milliseconds of audio captured so far in the current recording
+    pub fn get_current_recording_time(&self) -> Result<u64> {
+        let state = self.state();
+
+        match (&state.clock, state.is_recording) {
+            (Some(clock), true) => Ok(clock.elapsed_ms()),
+            _ => Err(AudioEngineError::NotRecording),
+        }
+    }
+
+    /// Set up automatic gain control for recordings. Attack and release take
+    /// effect the next time AGC starts.
+    pub fn configure_agc(&self, enabled: bool, attack_ms: f32, release_ms: f32) {
+        InputMeter::store(&self.input_meter.agc_attack_ms, attack_ms);
+        InputMeter::store(&self.input_meter.agc_release_ms, release_ms);
+        self.set_agc(enabled);
+    }
+
+    /// Turn automatic gain control on or off, including during a recording
+    pub fn set_agc(&self, enabled: bool) {
+        self.input_meter.agc_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Level of the audio being recorded, or `None` when not recording
+    pub fn input_level(&self) -> Option<InputLevel> {
+        let state = self.state();
+        state.is_recording.then(|| self.input_meter.level())
+    }
+
+    /// Name of the input device the current recording is capturing from
+    pub fn recording_device_name(&self) -> Option<String> {
+        let state = self.state();
+        state.input_device.clone().filter(|_| state.is_recording)
+    }
+
+    /// Whether the current recording is being written to this file
+    pub fn is_recording_to(&self, file_path: &str) -> bool {
+        let state = self.state();
+        state.is_recording && state.recording_file_path.as_deref() == Some(file_path)
+    }
+
+    /// Turn live monitoring on or off, playing captured audio through the named
+    /// output device (or the default one). Takes effect immediately when recording.
+    pub fn set_monitoring(&self, enabled: bool, output_device: Option<String>) -> Result<()> {
+        let mut state = self.state();
+
+        self.stop_monitor(&mut state);
+        state.monitoring_enabled = enabled;
+        state.monitor_device = output_device;
+
+        if enabled && state.is_recording {
+            // Monitoring stays off if the output can't be opened
+            if let Err(e) = self.start_monitor(&mut state) {
+                state.monitoring_enabled = false;
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn start_monitor(&self, state: &mut RecordingState) -> Result<()> {
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+        let (ready_sender, ready_receiver) = mpsc::channel::<Result<()>>();
+        let device_name = state.monitor_device.clone();
+        let monitor_tap = self.monitor_tap.clone();
+
+        // Like the input stream, the output stream lives on its own thread until stopped
+        thread::spawn(move || {
+            let host = cpal::default_host();
+            let stream = match Self::create_monitor_stream(&host, device_name.as_deref(), monitor_tap) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_sender.send(Ok(()));
+
+            let _ = stop_receiver.recv();
+            drop(stream);
+        });
+
+        ready_receiver.recv()
+            .unwrap_or_else(|_| Err(AudioEngineError::internal_error("Monitoring thread exited unexpectedly")))?;
+
+        self.monitor_tap.enabled.store(true, Ordering::SeqCst);
+        state.monitor_stop_sender = Some(stop_sender);
+        Ok(())
+    }
+
+    fn stop_monitor(&self, state: &mut RecordingState) {
+        self.monitor_tap.reset();
+        if let Some(stop_sender) = state.monitor_stop_sender.take() {
+            let _ = stop_sender.send(());
+        }
+    }
+
+    /// Stop recording, returning its duration and the files it was written to
+    pub fn stop_recording(&self) -> Result<RecordingSummary> {
+        let (duration_seconds, _, segments) = self.end_recording(false)?;
+        Ok(RecordingSummary { duration_seconds, segments })
+    }
+
+    /// Stop the current recording and delete the partial WAV files
+    pub fn cancel_recording(&self) -> Result<()> {
+        let (_, file_path, _) = self.end_recording(true)?;
+
+        if let Some(path) = file_path {
+            // The writer only creates a file once its first samples arrive,
+            // so the first missing segment marks the end. The last one was
+            // never finalized and is still under its `.partial` name.
+            for index in 0.. {
+                let segment_path = Self::segment_path(&path, index);
+                let mut removed = false;
+                for file in [Self::partial_path(&segment_path), segment_path] {
+                    match std::fs::remove_file(&file) {
+                        Ok(()) => {
+                            println!("Discarded cancelled recording {}", file);
+                            removed = true;
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                if !removed {
+                    break;
+                }
+            }
         }
 
-        // Keep the stream alive until stop signal is received
-        let _ = stop_receiver.recv();
+        Ok(())
+    }
+
+    /// Tear down the streams and writer, returning the elapsed duration, file path and segments
+    fn end_recording(&self, discard: bool) -> Result<(i32, Option<String>, Vec<RecordingSegment>)> {
+        let mut state = self.checked_state()?;
         
-        // Stream is automatically stopped when dropped
-        drop(stream);
+        if !state.is_recording {
+            return Err(AudioEngineError::NotRecording);
+        }
+
+        if let Some(discard_flag) = state.discard_flag.take() {
+            discard_flag.store(discard, Ordering::SeqCst);
+        }
+
+        // Send stop signal to audio thread
+        if let Some(stop_sender) = state.stop_sender.take() {
+            let _ = stop_sender.send(());
+        }
+        self.stop_monitor(&mut state);
+
+        let duration = if let Some(start_time) = state.start_time {
+            start_time.elapsed().as_secs() as i32
+        } else {
+            0
+        };
+
+        // Wait for writer thread to finish and collect any write failure
+        let writer_result = match state.writer_thread.take() {
+            Some(writer_thread) => writer_thread.join()
+                .unwrap_or_else(|_| Err(AudioEngineError::internal_error("Audio writer thread panicked"))),
+            None => Ok(Vec::new()),
+        };
+
+        state.is_recording = false;
+        state.start_time = None;
+        state.clock = None;
+        state.input_device = None;
+        let file_path = state.recording_file_path.take();
+
+        // A cancelled recording is deleted anyway, so its write errors don't matter
+        let segments = match writer_result {
+            Ok(segments) => segments,
+            Err(_) if discard => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok((duration, file_path, segments))
+    }
+
+    /// Open the named input device, falling back to the default if it was
+    /// unplugged, and start capturing from it
+    fn open_capture(
+        device_name: Option<&str>,
+        audio_sender: Sender<AudioSample>,
+        monitor_tap: Arc<MonitorTap>,
+        loopback_tap: Option<Arc<MonitorTap>>,
+        input_meter: Arc<InputMeter>,
+    ) -> Result<CaptureStreams> {
+        let host = cpal::default_host();
+        let selected_device = match device_name {
+            Some(name) => match Self::find_input_device(&host, name) {
+                Ok(Some(device)) => Some(device),
+                Ok(None) => {
+                    eprintln!("Input device '{}' not found, using default input device", name);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("{}, using default input device", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let input_device = selected_device.or_else(|| host.default_input_device())
+            .ok_or(AudioEngineError::NoInputDevice)?;
+        let device_name = input_device.name().map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+
+        let input = Self::create_input_stream_static(&input_device, audio_sender, monitor_tap, input_meter)?;
+        input.play()
+            .map_err(|e| AudioEngineError::device_error(format!("Failed to start audio stream: {}", e)))?;
+
+        // System audio is mixed in by the writer; without it the microphone is still recorded
+        let loopback = loopback_tap.and_then(|tap| {
+            match Self::create_loopback_stream(&host, tap) {
+                Ok(stream) => Some(stream),
+                Err(e) => {
+                    eprintln!("Failed to capture system audio: {}", e);
+                    None
+                }
+            }
+        });
+
+        Ok(CaptureStreams { device_name, input, loopback })
     }
 
     fn create_input_stream_static(
         device: &Device,
         sender: Sender<AudioSample>,
+        monitor_tap: Arc<MonitorTap>,
+        input_meter: Arc<InputMeter>,
     ) -> Result<cpal::Stream> {
-        let config = device.default_input_config()?;
+        let config = device.default_input_config()
+            .map_err(|e| AudioEngineError::device_error(e.to_string()))?;
         
         let stream = match config.sample_format() {
-            cpal::SampleFormat::F32 => Self::create_input_stream_typed_static::<f32>(device, &config.into(), sender)?,
-            cpal::SampleFormat::I16 => Self::create_input_stream_typed_static::<i16>(device, &config.into(), sender)?,
-            cpal::SampleFormat::U16 => Self::create_input_stream_typed_static::<u16>(device, &config.into(), sender)?,
-            _ => return Err(anyhow!("Unsupported sample format")),
+            cpal::SampleFormat::F32 => Self::create_input_stream_typed_static::<f32>(device, &config.into(), sender, monitor_tap, input_meter)?,
+            cpal::SampleFormat::I16 => Self::create_input_stream_typed_static::<i16>(device, &config.into(), sender, monitor_tap, input_meter)?,
+            cpal::SampleFormat::U16 => Self::create_input_stream_typed_static::<u16>(device, &config.into(), sender, monitor_tap, input_meter)?,
+            format => return Err(AudioEngineError::UnsupportedFormat(format.to_string())),
         };
 
         Ok(stream)
@@ -208,6 +1209,8 @@ impl AudioEngine {
         device: &Device,
         config: &cpal::StreamConfig,
         sender: Sender<AudioSample>,
+        monitor_tap: Arc<MonitorTap>,
+        input_meter: Arc<InputMeter>,
     ) -> Result<cpal::Stream>
     where
         T: cpal::Sample + cpal::SizedSample + Send + 'static,
@@ -215,11 +1218,22 @@ impl AudioEngine {
     {
         let sample_rate = config.sample_rate.0;
         let channels = config.channels;
+        // Recreated whenever AGC is switched on so it starts from unity gain
+        let mut auto_gain: Option<AutoGain> = None;
 
         let stream = device.build_input_stream(
             config,
             move |data: &[T], _: &cpal::InputCallbackInfo| {
-                let samples: Vec<f32> = data.iter().map(|&sample| cpal::Sample::from_sample(sample)).collect();
+                let mut samples: Vec<f32> = data.iter().map(|&sample| cpal::Sample::from_sample(sample)).collect();
+                if input_meter.agc_enabled.load(Ordering::Relaxed) {
+                    let agc = auto_gain.get_or_insert_with(|| input_meter.auto_gain(sample_rate));
+                    agc.process(&mut samples, channels as usize);
+                    InputMeter::store(&input_meter.agc_gain_db, agc.gain_db());
+                } else {
+                    auto_gain = None;
+                }
+                input_meter.measure(&samples);
+                monitor_tap.push(&samples, sample_rate, channels);
                 
                 let audio_sample = AudioSample {
                     data: samples,
@@ -234,54 +1248,1543 @@ impl AudioEngine {
             },
             |err| eprintln!("Audio stream error: {}", err),
             None,
-        )?;
+        ).map_err(|e| AudioEngineError::device_error(e.to_string()))?;
 
         Ok(stream)
     }
 
-    fn audio_writer_thread(receiver: Receiver<AudioSample>, file_path: &str) {
-        // Initialize with default values, will be updated with first sample
-        let mut writer: Option<WavWriter<std::io::BufWriter<std::fs::File>>> = None;
-        let mut sample_count = 0u32;
-
-        while let Ok(audio_sample) = receiver.recv() {
-            // Initialize writer with first sample's parameters
-            if writer.is_none() {
-                let spec = WavSpec {
-                    channels: audio_sample.channels,
-                    sample_rate: audio_sample.sample_rate,
-                    bits_per_sample: 32, // f32 samples
-                    sample_format: hound::SampleFormat::Float,
-                };
+    /// Capture what the default output device plays. On WASAPI an input stream
+    /// opened on an output device records in loopback mode.
+    fn create_loopback_stream(host: &Host, loopback_tap: Arc<MonitorTap>) -> Result<cpal::Stream> {
+        let device = host.default_output_device()
+            .ok_or_else(|| AudioEngineError::device_error("No default output device available"))?;
+        let config = device.default_output_config()
+            .map_err(|e| AudioEngineError::device_error(e.to_string()))?;
 
-                match WavWriter::create(file_path, spec) {
-                    Ok(w) => writer = Some(w),
-                    Err(e) => {
-                        eprintln!("Failed to create WAV writer: {}", e);
-                        break;
-                    }
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => Self::create_loopback_stream_typed_static::<f32>(&device, &config.into(), loopback_tap)?,
+            cpal::SampleFormat::I16 => Self::create_loopback_stream_typed_static::<i16>(&device, &config.into(), loopback_tap)?,
+            cpal::SampleFormat::U16 => Self::create_loopback_stream_typed_static::<u16>(&device, &config.into(), loopback_tap)?,
+            format => return Err(AudioEngineError::UnsupportedFormat(format.to_string())),
+        };
+
+        stream.play().map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+        Ok(stream)
+    }
+
+    fn create_loopback_stream_typed_static<T>(
+        device: &Device,
+        config: &cpal::StreamConfig,
+        loopback_tap: Arc<MonitorTap>,
+    ) -> Result<cpal::Stream>
+    where
+        T: cpal::Sample + cpal::SizedSample + Send + 'static,
+        f32: cpal::FromSample<T>,
+    {
+        let sample_rate = config.sample_rate.0;
+        let channels = config.channels;
+
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                let samples: Vec<f32> = data.iter().map(|&sample| cpal::Sample::from_sample(sample)).collect();
+                loopback_tap.push(&samples, sample_rate, channels);
+            },
+            |err| eprintln!("System audio stream error: {}", err),
+            None,
+        ).map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+
+        Ok(stream)
+    }
+
+    /// Add system audio to microphone samples, clipping the sum to full scale
+    fn mix_samples(mic: &mut [f32], system: &[f32]) {
+        for (sample, &other) in mic.iter_mut().zip(system) {
+            *sample = (*sample + other).clamp(-1.0, 1.0);
+        }
+    }
+
+    fn create_monitor_stream(host: &Host, device_name: Option<&str>, monitor_tap: Arc<MonitorTap>) -> Result<cpal::Stream> {
+        let device = match device_name {
+            Some(name) => host.output_devices()
+                .map_err(|e| AudioEngineError::device_error(format!("Failed to enumerate output devices: {}", e)))?
+                .find(|d| d.name().map(|n| n == name).unwrap_or(false))
+                .ok_or_else(|| AudioEngineError::OutputDeviceNotFound(name.to_string()))?,
+            None => host.default_output_device()
+                .ok_or_else(|| AudioEngineError::device_error("No default output device available"))?,
+        };
+
+        let config = device.default_output_config()
+            .map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => Self::create_output_stream_typed_static::<f32>(&device, &config.into(), monitor_tap)?,
+            cpal::SampleFormat::I16 => Self::create_output_stream_typed_static::<i16>(&device, &config.into(), monitor_tap)?,
+            cpal::SampleFormat::U16 => Self::create_output_stream_typed_static::<u16>(&device, &config.into(), monitor_tap)?,
+            format => return Err(AudioEngineError::UnsupportedFormat(format.to_string())),
+        };
+
+        stream.play().map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+        Ok(stream)
+    }
+
+    fn create_output_stream_typed_static<T>(
+        device: &Device,
+        config: &cpal::StreamConfig,
+        monitor_tap: Arc<MonitorTap>,
+    ) -> Result<cpal::Stream>
+    where
+        T: cpal::Sample + cpal::SizedSample + cpal::FromSample<f32> + Send + 'static,
+    {
+        let out_rate = config.sample_rate.0;
+        let out_channels = config.channels as usize;
+        let mut playback = MonitorPlayback::new();
+        let mut scratch: Vec<f32> = Vec::new();
+
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                scratch.resize(data.len(), 0.0);
+                playback.fill(&monitor_tap, &mut scratch, out_channels, out_rate);
+                for (out, &sample) in data.iter_mut().zip(scratch.iter()) {
+                    *out = T::from_sample(sample);
+                }
+            },
+            |err| eprintln!("Monitoring stream error: {}", err),
+            None,
+        ).map_err(|e| AudioEngineError::device_error(e.to_string()))?;
+
+        Ok(stream)
+    }
+
+    /// Walk a WAV file's chunks up to the data chunk, remembering the frame size from fmt
+    fn read_wav_layout(file: &mut std::fs::File, file_path: &str) -> Result<WavLayout> {
+        let mut riff_header = [0u8; 12];
+        file.read_exact(&mut riff_header)?;
+        if &riff_header[0..4] != b"RIFF" || &riff_header[8..12] != b"WAVE" {
+            return Err(AudioEngineError::wav_error(format!("Not a WAV file: {}", file_path)));
+        }
+
+        let mut block_align = 0u64;
+        let (data_offset, data_len) = loop {
+            let mut chunk_header = [0u8; 8];
+            file.read_exact(&mut chunk_header)
+                .map_err(|_| AudioEngineError::wav_error(format!("No data chunk found in {}", file_path)))?;
+            let chunk_len = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as u64;
+            let chunk_start = file.stream_position()?;
+
+            match &chunk_header[0..4] {
+                b"data" => break (chunk_start, chunk_len),
+                b"fmt " => {
+                    let mut fmt = [0u8; 16];
+                    file.read_exact(&mut fmt)?;
+                    block_align = u16::from_le_bytes([fmt[12], fmt[13]]) as u64;
                 }
+                _ => {}
             }
 
-            // Write samples
-            if let Some(ref mut w) = writer {
-                for sample in audio_sample.data {
-                    if let Err(e) = w.write_sample(sample) {
-                        eprintln!("Failed to write audio sample: {}", e);
-                        break;
-                    }
-                    sample_count += 1;
+            // Chunks are padded to an even length
+            file.seek(SeekFrom::Start(chunk_start + chunk_len + (chunk_len & 1)))?;
+        };
+
+        if block_align == 0 {
+            return Err(AudioEngineError::wav_error(format!("Missing or invalid fmt chunk in {}", file_path)));
+        }
+        Ok(WavLayout { data_offset, data_len, block_align })
+    }
+
+    /// Fix the RIFF and data chunk lengths of a WAV file that was never finalized,
+    /// recomputing them from the file size, and return its duration in seconds.
+    pub fn repair_wav_file(file_path: &str) -> Result<i32> {
+        let mut file = std::fs::OpenOptions::new().read(true).write(true).open(file_path)?;
+        let file_len = file.metadata()?.len();
+        let WavLayout { data_offset, block_align, .. } = Self::read_wav_layout(&mut file, file_path)?;
+
+        // Drop any partially written frame at the end of the file
+        let data_len = (file_len - data_offset) / block_align * block_align;
+        let riff_len = data_offset + data_len - 8;
+        if riff_len > u32::MAX as u64 {
+            return Err(AudioEngineError::wav_error(format!("Recording too large to repair: {}", file_path)));
+        }
+        file.set_len(data_offset + data_len)?;
+
+        file.seek(SeekFrom::Start(4))?;
+        file.write_all(&(riff_len as u32).to_le_bytes())?;
+        file.seek(SeekFrom::Start(data_offset - 4))?;
+        file.write_all(&(data_len as u32).to_le_bytes())?;
+        file.sync_all()?;
+        drop(file);
+
+        let reader = WavReader::open(file_path)?;
+        let spec = reader.spec();
+        Ok((reader.duration() / spec.sample_rate) as i32)
+    }
+
+    /// Compare the data length in a WAV file's header with the audio actually on disk
+    pub fn inspect_wav_file(file_path: &str) -> Result<WavFileCheck> {
+        let mut file = std::fs::File::open(file_path)?;
+        let file_len = file.metadata()?.len();
+        let layout = Self::read_wav_layout(&mut file, file_path)?;
+        let spec = WavReader::open(file_path)?.spec();
+
+        let actual_data_len = file_len.saturating_sub(layout.data_offset);
+        let frames = actual_data_len / layout.block_align;
+        Ok(WavFileCheck {
+            file_path: file_path.to_string(),
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            duration_ms: (frames * 1000 / spec.sample_rate.max(1) as u64) as i64,
+            header_data_len: layout.data_len,
+            actual_data_len,
+        })
+    }
+
+    /// Check every file of a recording against each other and the stored duration
+    pub fn verify_wav_files(recording_id: &str, stored_duration_seconds: Option<i32>, file_paths: &[String]) -> RecordingVerification {
+        let mut files = Vec::new();
+        let mut unreadable = Vec::new();
+        for file_path in file_paths {
+            match Self::inspect_wav_file(file_path) {
+                Ok(check) => files.push(check),
+                Err(e) => unreadable.push(format!("{}: {}", file_path, e)),
+            }
+        }
+        RecordingVerification::new(recording_id, stored_duration_seconds, files, unreadable)
+    }
+
+    /// Iterate over a WAV file's samples as f32 regardless of how they were stored
+    pub fn read_f32_samples<'a>(
+        reader: &'a mut WavReader<std::io::BufReader<std::fs::File>>,
+    ) -> Box<dyn Iterator<Item = std::result::Result<f32, hound::Error>> + 'a> {
+        let spec = reader.spec();
+        match spec.sample_format {
+            hound::SampleFormat::Float => Box::new(reader.samples::<f32>()),
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                Box::new(reader.samples::<i32>().map(move |s| s.map(|s| s as f32 / scale)))
+            }
+        }
+    }
+
+    /// Approximate the integrated loudness (LUFS) and sample peak of a set of files
+    /// treated as one recording. Uses the per-channel mean square like BS.1770,
+    /// but without K-weighting or gating.
+    pub fn measure_loudness(file_paths: &[String], mut on_sample: impl FnMut()) -> Result<(f64, f32)> {
+        let mut channel_energy: Vec<f64> = Vec::new();
+        let mut frames = 0u64;
+        let mut peak = 0f32;
+
+        for path in file_paths {
+            let mut reader = WavReader::open(path)?;
+            let channels = reader.spec().channels.max(1) as usize;
+            if channel_energy.len() < channels {
+                channel_energy.resize(channels, 0.0);
+            }
+
+            for (i, sample) in Self::read_f32_samples(&mut reader).enumerate() {
+                let sample = sample?;
+                channel_energy[i % channels] += sample as f64 * sample as f64;
+                peak = peak.max(sample.abs());
+                if i % channels == 0 {
+                    frames += 1;
                 }
+                on_sample();
             }
         }
 
-        // Finalize the WAV file
-        if let Some(writer) = writer {
-            if let Err(e) = writer.finalize() {
-                eprintln!("Failed to finalize WAV file: {}", e);
-            } else {
-                println!("Audio recording saved to {} ({} samples)", file_path, sample_count);
+        let mean_square: f64 = channel_energy.iter().map(|energy| energy / frames.max(1) as f64).sum();
+        if mean_square <= 0.0 {
+            return Err(AudioEngineError::wav_error("Recording is empty or silent"));
+        }
+
+        Ok((-0.691 + 10.0 * mean_square.log10(), peak))
+    }
+
+    /// Apply one gain to every file of a recording so it reaches `target_lufs`,
+    /// limited so peaks stay below full scale. Each file is rewritten to a temp
+    /// file next to it and then renamed over the original.
+    pub fn normalize_wav_files(file_paths: &[String], target_lufs: f32, mut on_progress: impl FnMut(f32)) -> Result<NormalizationReport> {
+        let mut total_samples = 0u64;
+        for path in file_paths {
+            total_samples += WavReader::open(path)?.len() as u64;
+        }
+        // Measuring and rewriting each read every sample once
+        let total_work = (total_samples * 2).max(1);
+        let mut samples_done = 0u64;
+        let mut tick = || {
+            samples_done += 1;
+            if samples_done.is_multiple_of(NORMALIZE_PROGRESS_INTERVAL) {
+                on_progress(samples_done as f32 / total_work as f32);
+            }
+        };
+
+        let (measured_lufs, peak) = Self::measure_loudness(file_paths, &mut tick)?;
+        let max_gain_db = -20.0 * (peak as f64).log10();
+        let gain_db = (target_lufs as f64 - measured_lufs).min(max_gain_db);
+        let gain = 10f64.powf(gain_db / 20.0) as f32;
+
+        for path in file_paths {
+            let temp_path = format!("{}.normalizing", path);
+            if let Err(e) = Self::write_with_gain(path, &temp_path, gain, &mut tick) {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+            std::fs::rename(&temp_path, path)?;
+        }
+
+        Ok(NormalizationReport {
+            measured_lufs,
+            target_lufs,
+            gain_db,
+        })
+    }
+
+    fn write_with_gain(source: &str, destination: &str, gain: f32, mut on_sample: impl FnMut()) -> Result<()> {
+        let mut reader = WavReader::open(source)?;
+        let spec = reader.spec();
+        let mut writer = WavWriter::create(destination, spec)?;
+
+        for sample in Self::read_f32_samples(&mut reader) {
+            Self::write_f32_sample(&mut writer, spec, sample? * gain)?;
+            on_sample();
+        }
+
+        writer.finalize()?;
+        Ok(())
+    }
+
+    /// Scale `samples` by `gain_db`, clipping to full scale
+    pub fn apply_gain(samples: &mut [f32], gain_db: f32) {
+        let gain = agc::db_to_gain(gain_db);
+        for sample in samples {
+            *sample = (*sample * gain).clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Write a copy of `source` made `gain_db` louder or quieter for playback,
+    /// leaving the recording itself untouched
+    pub fn write_playback_gain(source: &str, destination: &str, gain_db: f32) -> Result<()> {
+        let mut reader = WavReader::open(source)?;
+        let spec = reader.spec();
+        let mut writer = WavWriter::create(destination, spec)?;
+
+        let mut chunk = Vec::with_capacity(PLAYBACK_GAIN_CHUNK_SAMPLES);
+        let mut samples = Self::read_f32_samples(&mut reader).peekable();
+        while samples.peek().is_some() {
+            chunk.clear();
+            for sample in samples.by_ref().take(PLAYBACK_GAIN_CHUNK_SAMPLES) {
+                chunk.push(sample?);
+            }
+            Self::apply_gain(&mut chunk, gain_db);
+            for &sample in &chunk {
+                Self::write_f32_sample(&mut writer, spec, sample)?;
             }
         }
+
+        writer.finalize()?;
+        Ok(())
+    }
+
+    /// Write an f32 sample in the writer's own sample format, clipping to full scale
+    fn write_f32_sample<W: Write + Seek>(writer: &mut WavWriter<W>, spec: WavSpec, sample: f32) -> Result<()> {
+        let sample = sample.clamp(-1.0, 1.0);
+        match spec.sample_format {
+            hound::SampleFormat::Float => writer.write_sample(sample)?,
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample.max(1) - 1)) as f32;
+                writer.write_sample(((sample * scale) as i32).clamp(-(scale as i32), scale as i32 - 1))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Length of a WAV file in seconds, as declared by its header
+    pub fn compute_wav_duration(file_path: &str) -> Result<f64> {
+        let reader = WavReader::open(file_path)?;
+        let sample_rate = reader.spec().sample_rate;
+        if sample_rate == 0 {
+            return Err(AudioEngineError::wav_error(format!("{} declares a sample rate of 0", file_path)));
+        }
+        Ok(reader.duration() as f64 / sample_rate as f64)
+    }
+
+    /// Length of a WAV file in milliseconds
+    pub fn wav_duration_ms(file_path: &str) -> Result<i64> {
+        let reader = WavReader::open(file_path)?;
+        Ok(reader.duration() as i64 * 1000 / reader.spec().sample_rate.max(1) as i64)
+    }
+
+    /// Find stretches quieter than `threshold_db` (dBFS) lasting at least
+    /// `min_duration_ms`. Loudness is measured over 10ms windows across all
+    /// channels. Files are treated as consecutive parts of one recording.
+    pub fn detect_silence(file_paths: &[String], threshold_db: f32, min_duration_ms: i64) -> Result<Vec<SilenceInterval>> {
+        let mut intervals = Vec::new();
+        let mut silence_start: Option<u64> = None; // Frame where the current quiet run began
+        let mut frame_offset = 0u64;
+        let mut sample_rate = 0u32;
+        let threshold = 10f64.powf(threshold_db as f64 / 20.0);
+
+        let mut close_run = |start: u64, end: u64, rate: u32| {
+            let start_ms = (start * 1000 / rate.max(1) as u64) as i64;
+            let end_ms = (end * 1000 / rate.max(1) as u64) as i64;
+            if end_ms - start_ms >= min_duration_ms {
+                intervals.push(SilenceInterval { start_ms, end_ms });
+            }
+        };
+
+        for path in file_paths {
+            let mut reader = WavReader::open(path)?;
+            let spec = reader.spec();
+            let channels = spec.channels.max(1) as usize;
+            sample_rate = spec.sample_rate;
+            let window_samples = (spec.sample_rate as usize / 100).max(1) * channels;
+
+            let mut samples = Self::read_f32_samples(&mut reader);
+            loop {
+                let window: Vec<f32> = samples.by_ref().take(window_samples).collect::<std::result::Result<_, _>>()?;
+                if window.is_empty() {
+                    break;
+                }
+                let window_frames = (window.len() / channels) as u64;
+                let mean_square = window.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / window.len() as f64;
+
+                if mean_square.sqrt() < threshold {
+                    silence_start.get_or_insert(frame_offset);
+                } else if let Some(start) = silence_start.take() {
+                    close_run(start, frame_offset, sample_rate);
+                }
+                frame_offset += window_frames;
+            }
+        }
+
+        if let Some(start) = silence_start {
+            close_run(start, frame_offset, sample_rate);
+        }
+
+        Ok(intervals)
+    }
+
+    /// Copy the part of a WAV file between two offsets into a new file with the same format
+    pub fn write_trimmed_wav(source: &str, destination: &str, start_ms: i64, end_ms: i64) -> Result<()> {
+        let mut reader = WavReader::open(source)?;
+        let spec = reader.spec();
+        let channels = spec.channels.max(1) as usize;
+        let start_sample = (start_ms.max(0) as u64 * spec.sample_rate as u64 / 1000) as usize * channels;
+        let end_sample = (end_ms.max(0) as u64 * spec.sample_rate as u64 / 1000) as usize * channels;
+
+        let mut writer = WavWriter::create(destination, spec)?;
+        for sample in Self::read_f32_samples(&mut reader)
+            .skip(start_sample)
+            .take(end_sample.saturating_sub(start_sample))
+        {
+            Self::write_f32_sample(&mut writer, spec, sample?)?;
+        }
+
+        writer.finalize()?;
+        Ok(())
+    }
+
+    /// Decode an Ogg/Opus recording file into a float WAV file
+    #[cfg(feature = "opus")]
+    pub fn decode_opus_to_wav(source: &str, destination: &str) -> Result<()> {
+        let (channels, samples) = ogg_opus::read_ogg_opus(source)?;
+        let spec = WavSpec {
+            channels,
+            sample_rate: OPUS_SAMPLE_RATE,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        let mut writer = WavWriter::create(destination, spec)?;
+        for sample in samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "opus"))]
+    pub fn decode_opus_to_wav(source: &str, _destination: &str) -> Result<()> {
+        Err(AudioEngineError::UnsupportedFormat(format!("this build can't decode Opus recordings like {}", source)))
+    }
+
+    /// Path of the `index`th file of a recording. The first segment keeps the
+    /// recording's own path so unsegmented recordings are unchanged.
+    pub fn segment_path(file_path: &str, index: usize) -> String {
+        if index == 0 {
+            return file_path.to_string();
+        }
+        let path = std::path::Path::new(file_path);
+        let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let extension = path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_else(|| "wav".to_string());
+        path.with_file_name(format!("{}-{:03}.{}", stem, index, extension))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Where a recording file is written until it's finalized
+    pub fn partial_path(file_path: &str) -> String {
+        format!("{}.partial", file_path)
+    }
+
+    /// The file holding a recording's audio: its `.partial` file if it was
+    /// never finalized, otherwise the recording's own path
+    pub fn unfinished_path(file_path: &str) -> String {
+        let partial = Self::partial_path(file_path);
+        if std::path::Path::new(&partial).exists() {
+            partial
+        } else {
+            file_path.to_string()
+        }
+    }
+
+    /// Repair a recording file that was never finalized and move it to its
+    /// own path, returning its duration in seconds
+    pub fn recover_partial_file(file_path: &str) -> Result<i32> {
+        let unfinished = Self::unfinished_path(file_path);
+        if file_path.ends_with(".ogg") {
+            return Self::recover_opus_file(&unfinished, file_path);
+        }
+        let duration = Self::repair_wav_file(&unfinished)?;
+        if unfinished != file_path {
+            std::fs::rename(&unfinished, file_path)?;
+        }
+        Ok(duration)
+    }
+
+    /// Rewrite an unfinished Ogg/Opus file with its stream ended properly.
+    /// The copy is made next to the recording and renamed over it.
+    #[cfg(feature = "opus")]
+    fn recover_opus_file(unfinished: &str, file_path: &str) -> Result<i32> {
+        let temp_path = format!("{}.repairing", file_path);
+        let frames = match ogg_opus::repair_ogg_opus(unfinished, &temp_path) {
+            Ok(frames) => frames,
+            Err(e) => {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+        std::fs::rename(&temp_path, file_path)?;
+        if unfinished != file_path {
+            std::fs::remove_file(unfinished)?;
+        }
+        Ok((frames / OPUS_SAMPLE_RATE as u64) as i32)
+    }
+
+    #[cfg(not(feature = "opus"))]
+    fn recover_opus_file(unfinished: &str, _file_path: &str) -> Result<i32> {
+        Err(AudioEngineError::UnsupportedFormat(format!("this build can't repair Opus recordings like {}", unfinished)))
+    }
+
+    fn finished_segment(file_path: &str, index: usize, start_frame: u64, end_frame: u64, sample_rate: u32) -> RecordingSegment {
+        let to_ms = |frames: u64| (frames * 1000 / sample_rate.max(1) as u64) as i64;
+        RecordingSegment {
+            index: index as i32,
+            file_path: Self::segment_path(file_path, index),
+            start_ms: to_ms(start_frame),
+            duration_ms: to_ms(end_frame - start_frame),
+        }
+    }
+
+    /// Drain audio samples into the recording file, starting new files as
+    /// `split` asks and mixing in system audio from `loopback_tap`
+    /// at the microphone's rate and channel count. Any write failure ends the
+    /// recording and is returned to `stop_recording` through the join handle.
+    fn audio_writer_thread(
+        receiver: Receiver<AudioSample>,
+        file_path: &str,
+        discard_flag: Arc<AtomicBool>,
+        clock: Arc<RecordingClock>,
+        split: SplitPolicy,
+        loopback_tap: Option<Arc<MonitorTap>>,
+        writer_mode: WriterMode,
+    ) -> Result<Vec<RecordingSegment>> {
+        // Initialize with default values, will be updated with first sample
+        let mut writer: Option<SegmentWriter> = None;
+        let mut segments = Vec::new();
+        let mut frame_count = 0u64;
+        let mut segment_start_frame = 0u64;
+        let mut sample_rate = 0u32;
+        let mut samples_since_flush = 0u32;
+        let mut loopback_playback = MonitorPlayback::new();
+        let mut loopback_samples: Vec<f32> = Vec::new();
+        // Only created when the device rate differs from the target
+        let mut resampler: Option<ChunkResampler> = None;
+        let mut resampled: Vec<f32> = Vec::new();
+        // Only created when splitting on silence
+        let mut silence_detector: Option<SilenceDetector> = None;
+        let mut split_pending = false;
+
+        let (encoding, target_sample_rate) = match writer_mode {
+            WriterMode::Create(encoding) => (encoding, encoding.sample_rate()),
+            WriterMode::Append => {
+                let (existing, spec, frames) = SegmentWriter::append(file_path)?;
+                frame_count = frames;
+                sample_rate = spec.sample_rate;
+                writer = Some(existing);
+                (Encoding::Wav(Some(spec.sample_rate), spec.bits_per_sample), Some(spec.sample_rate))
+            }
+        };
+
+        // Write interleaved frames at the file's sample rate, rotating segments
+        // as they fill up or, with `split_first`, before the first frame
+        let mut write_frames = |data: &[f32], channels: u16, rate: u32, split_first: bool| -> Result<()> {
+            if let Some(existing) = writer.as_ref().map(|w| w.channels()).filter(|&existing| existing != channels) {
+                return Err(AudioEngineError::UnsupportedFormat(format!(
+                    "{}-channel audio can't be added to a {}-channel recording", channels, existing
+                )));
+            }
+            sample_rate = rate;
+            let frames_per_segment = split.every_minutes.map(|minutes| rate as u64 * 60 * minutes as u64);
+
+            for (i, frame) in data.chunks(channels.max(1) as usize).enumerate() {
+                // Close the current file once it holds a full segment or a long pause was reached
+                let segment_full = frames_per_segment.is_some_and(|limit| frame_count - segment_start_frame >= limit);
+                if (segment_full || (split_first && i == 0)) && frame_count > segment_start_frame {
+                    if let Some(w) = writer.take() {
+                        w.finalize()?;
+                        segments.push(Self::finished_segment(file_path, segments.len(), segment_start_frame, frame_count, rate));
+                        segment_start_frame = frame_count;
+                        samples_since_flush = 0;
+                        if let Some(ref listener) = split.listener {
+                            listener(SegmentSplit {
+                                index: segments.len() as i32,
+                                file_path: Self::segment_path(file_path, segments.len()),
+                                start_ms: (frame_count * 1000 / rate.max(1) as u64) as i64,
+                            });
+                        }
+                    }
+                }
+
+                // Initialize writer with first sample's parameters
+                if writer.is_none() {
+                    writer = Some(SegmentWriter::create(&Self::segment_path(file_path, segments.len()), channels, rate, encoding)?);
+                }
+
+                if let Some(ref mut w) = writer {
+                    w.write_frame(frame)?;
+                }
+                frame_count += 1;
+                samples_since_flush += frame.len() as u32;
+            }
+
+            // Keep the header lengths current in case the app dies mid-recording
+            let flush_threshold = rate * channels as u32 * HEADER_FLUSH_INTERVAL_SECS;
+            if samples_since_flush >= flush_threshold {
+                if let Some(ref mut w) = writer {
+                    w.flush()?;
+                }
+                samples_since_flush = 0;
+            }
+            Ok(())
+        };
+
+        while let Ok(mut audio_sample) = receiver.recv() {
+            // A cancelled recording is deleted, so stop writing right away
+            if discard_flag.load(Ordering::SeqCst) {
+                break;
+            }
+
+            if let Some(ref tap) = loopback_tap {
+                loopback_samples.resize(audio_sample.data.len(), 0.0);
+                loopback_playback.fill(tap, &mut loopback_samples, audio_sample.channels.max(1) as usize, audio_sample.sample_rate);
+                Self::mix_samples(&mut audio_sample.data, &loopback_samples);
+            }
+
+            // Pauses are measured on the captured audio, before any resampling
+            if let Some(silence) = split.on_silence {
+                split_pending |= silence_detector
+                    .get_or_insert_with(|| SilenceDetector::new(silence, audio_sample.sample_rate))
+                    .feed(&audio_sample.data, audio_sample.channels);
+            }
+
+            match target_sample_rate.filter(|&rate| rate > 0 && rate != audio_sample.sample_rate) {
+                Some(target_rate) => {
+                    if resampler.is_none() {
+                        resampler = Some(ChunkResampler::new(audio_sample.sample_rate, target_rate, audio_sample.channels.max(1) as usize)?);
+                    }
+                    if let Some(ref mut r) = resampler {
+                        resampled.clear();
+                        r.process(&audio_sample.data, &mut resampled)?;
+                        write_frames(&resampled, audio_sample.channels, target_rate, split_pending)?;
+                        // The resampler may hold on to a short buffer, so split before its next output
+                        split_pending &= resampled.is_empty();
+                    }
+                }
+                None => {
+                    write_frames(&audio_sample.data, audio_sample.channels, audio_sample.sample_rate, split_pending)?;
+                    split_pending = false;
+                }
+            }
+            // The clock follows the captured audio, which the resampled file matches in length
+            clock.advance(audio_sample.data.len(), audio_sample.sample_rate, audio_sample.channels);
+        }
+
+        if let Some(mut r) = resampler.filter(|_| !discard_flag.load(Ordering::SeqCst)) {
+            resampled.clear();
+            r.flush(&mut resampled)?;
+            write_frames(&resampled, r.channels as u16, r.to_rate, false)?;
+        }
+
+        if discard_flag.load(Ordering::SeqCst) {
+            // Dropping the writer closes the file without an explicit finalize
+            println!("Audio recording to {} cancelled ({} frames discarded)", file_path, frame_count);
+            return Ok(Vec::new());
+        }
+
+        // Finalize the active segment
+        if let Some(writer) = writer {
+            writer.finalize()?;
+            segments.push(Self::finished_segment(file_path, segments.len(), segment_start_frame, frame_count, sample_rate));
+        }
+        println!("Audio recording saved to {} ({} frames in {} files)", file_path, frame_count, segments.len());
+
+        Ok(segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::AppError;
+
+    #[test]
+    fn test_cancel_when_not_recording() {
+        let engine = AudioEngine::new().unwrap();
+        let result = engine.cancel_recording();
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Not currently recording");
+    }
+
+    #[test]
+    fn test_stop_when_not_recording() {
+        let engine = AudioEngine::new().unwrap();
+        assert!(matches!(engine.stop_recording(), Err(AudioEngineError::NotRecording)));
+        assert!(matches!(AppError::from(engine.stop_recording().unwrap_err()), AppError::Conflict { .. }));
+    }
+
+    #[test]
+    fn test_start_without_input_device_stays_idle() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("nothing.wav");
+        let path_str = path.to_str().unwrap();
+
+        let mut engine = AudioEngine::new().unwrap();
+        engine.open_capture = |_, _, _, _, _| Err(AudioEngineError::NoInputDevice);
+
+        let result = engine.start_recording(path_str, Some("USB Microphone"), None, None, false, Encoding::Wav(None, 32));
+        assert!(matches!(result, Err(AudioEngineError::NoInputDevice)));
+        assert!(!engine.is_recording_to(path_str));
+        assert_eq!(engine.recording_device_name(), None);
+        assert!(matches!(engine.get_current_recording_time(), Err(AudioEngineError::NotRecording)));
+        assert!(matches!(engine.stop_recording(), Err(AudioEngineError::NotRecording)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_recording_never_overwrites_existing_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("taken.wav");
+        let path_str = path.to_str().unwrap();
+        std::fs::write(&path, b"earlier recording").unwrap();
+
+        let engine = AudioEngine::new().unwrap();
+        let result = engine.start_recording(path_str, None, None, None, false, Encoding::Wav(None, 32));
+        assert!(matches!(result, Err(AudioEngineError::AlreadyExists(_))));
+        assert!(matches!(AppError::from(result.unwrap_err()), AppError::Conflict { .. }));
+
+        // Segment files the writer opens later are guarded as well
+        assert!(matches!(
+            SegmentWriter::create(path_str, 1, 48000, Encoding::Wav(None, 16)),
+            Err(AudioEngineError::AlreadyExists(_))
+        ));
+        assert_eq!(std::fs::read(&path).unwrap(), b"earlier recording");
+    }
+
+    #[test]
+    fn test_monitor_buffer_is_bounded_and_frame_aligned() {
+        let tap = MonitorTap::new();
+        tap.enabled.store(true, Ordering::SeqCst);
+
+        // Far more than the buffer holds; the newest frames must survive
+        let samples: Vec<f32> = (0..MONITOR_BUFFER_SAMPLES * 3).map(|i| i as f32).collect();
+        tap.push(&samples, 48000, 2);
+        {
+            let buffer = tap.buffer.lock().unwrap();
+            assert!(buffer.len() <= MONITOR_BUFFER_SAMPLES);
+            assert_eq!(buffer.len() % 2, 0);
+            assert_eq!(*buffer.back().unwrap(), samples[samples.len() - 1]);
+        }
+
+        // Stereo in at 48kHz, mono out at 48kHz takes the left channel frame by frame
+        let mut playback = MonitorPlayback::new();
+        let mut out = vec![0.0f32; 4];
+        let first = *tap.buffer.lock().unwrap().front().unwrap();
+        playback.fill(&tap, &mut out, 1, 48000);
+        assert_eq!(out, vec![first, first + 2.0, first + 4.0, first + 6.0]);
+
+        tap.reset();
+        playback.fill(&tap, &mut out, 1, 48000);
+        assert!(out.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_writer_reports_io_errors() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("missing-dir").join("recording.wav");
+
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        sender.send(AudioSample { data: vec![0.0; 64], sample_rate: 8000, channels: 1 }).unwrap();
+        drop(sender);
+
+        let result = AudioEngine::audio_writer_thread(
+            receiver,
+            path.to_str().unwrap(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            SplitPolicy::default(),
+            None,
+            WriterMode::Create(Encoding::Wav(None, 32)),
+        );
+        assert!(matches!(result, Err(AudioEngineError::IoError(_))));
+    }
+
+    #[test]
+    fn test_writer_scales_integer_samples() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let record = |name: &str, bits_per_sample: u16| {
+            let path = temp_dir.path().join(name);
+            let (sender, receiver) = mpsc::channel::<AudioSample>();
+            // Half scale, full scale and past full scale, which is clipped
+            sender.send(AudioSample { data: vec![0.5, -0.5, 1.0, -1.0, 1.5], sample_rate: 8000, channels: 1 }).unwrap();
+            drop(sender);
+            AudioEngine::audio_writer_thread(
+                receiver,
+                path.to_str().unwrap(),
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(RecordingClock::default()),
+                SplitPolicy::default(),
+                None,
+                WriterMode::Create(Encoding::Wav(None, bits_per_sample)),
+            ).unwrap();
+            WavReader::open(path).unwrap()
+        };
+
+        let mut reader = record("16-bit.wav", 16);
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
+        let samples: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![16384, -16384, 32767, -32768, 32767]);
+
+        let mut reader = record("24-bit.wav", 24);
+        let samples: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![4_194_304, -4_194_304, 8_388_607, -8_388_608, 8_388_607]);
+
+        let mut reader = record("32-bit.wav", 32);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.5, -0.5, 1.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_writer_clock_counts_delivered_frames() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("clock.wav");
+        let clock = Arc::new(RecordingClock::default());
+
+        // 1.5 seconds of stereo audio at 8kHz, delivered in small buffers
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        for _ in 0..30 {
+            sender.send(AudioSample { data: vec![0.0; 800], sample_rate: 8000, channels: 2 }).unwrap();
+        }
+        drop(sender);
+
+        AudioEngine::audio_writer_thread(receiver, path.to_str().unwrap(), Arc::new(AtomicBool::new(false)), clock.clone(), SplitPolicy::default(), None, WriterMode::Create(Encoding::Wav(None, 32))).unwrap();
+        assert_eq!(clock.elapsed_ms(), 1500);
+    }
+
+    #[test]
+    fn test_writer_mixes_system_audio() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("mixed.wav");
+
+        // Mono system audio at half the microphone's rate, loud enough to clip when summed
+        let loopback_tap = Arc::new(MonitorTap::active());
+        loopback_tap.push(&[0.25, 0.75], 4000, 1);
+
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        sender.send(AudioSample { data: vec![0.5; 10], sample_rate: 8000, channels: 2 }).unwrap();
+        drop(sender);
+
+        AudioEngine::audio_writer_thread(
+            receiver,
+            path.to_str().unwrap(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            SplitPolicy::default(),
+            Some(loopback_tap),
+            WriterMode::Create(Encoding::Wav(None, 32)),
+        ).unwrap();
+
+        let mut reader = WavReader::open(&path).unwrap();
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        // Each system audio frame covers two stereo frames, then the buffer runs dry
+        assert_eq!(samples, vec![0.75, 0.75, 0.75, 0.75, 1.0, 1.0, 1.0, 1.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_writer_resamples_to_target_rate() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("resampled.wav");
+
+        // One second of a 440Hz stereo tone at 48kHz, delivered in device-sized callbacks
+        let tone: Vec<f32> = (0..48000)
+            .flat_map(|i| {
+                let sample = (i as f32 * 440.0 * std::f32::consts::TAU / 48000.0).sin() * 0.5;
+                [sample, sample]
+            })
+            .collect();
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        for chunk in tone.chunks(480 * 2) {
+            sender.send(AudioSample { data: chunk.to_vec(), sample_rate: 48000, channels: 2 }).unwrap();
+        }
+        drop(sender);
+
+        let clock = Arc::new(RecordingClock::default());
+        let segments = AudioEngine::audio_writer_thread(
+            receiver,
+            path.to_str().unwrap(),
+            Arc::new(AtomicBool::new(false)),
+            clock.clone(),
+            SplitPolicy::default(),
+            None,
+            WriterMode::Create(Encoding::Wav(Some(44100), 32)),
+        ).unwrap();
+
+        let reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 44100);
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.duration(), 44100);
+        assert_eq!(segments[0].duration_ms, 1000);
+        assert_eq!(clock.elapsed_ms(), 1000);
+
+        // The tone survives conversion at roughly its original level
+        let samples: Vec<f32> = WavReader::open(&path).unwrap().samples::<f32>().map(|s| s.unwrap()).collect();
+        let peak = samples[4410..samples.len() - 4410].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.5).abs() < 0.05, "peak {}", peak);
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_opus_recording_is_much_smaller_than_wav() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let wav_path = temp_dir.path().join("note.wav");
+        let ogg_path = temp_dir.path().join("note.ogg");
+
+        // Three seconds of a two-tone mono signal at 44.1kHz, written once per encoding
+        let tone: Vec<f32> = (0..44100 * 3)
+            .map(|i| {
+                let t = i as f32 / 44100.0;
+                0.3 * (t * 200.0 * std::f32::consts::TAU).sin() + 0.2 * (t * 600.0 * std::f32::consts::TAU).sin()
+            })
+            .collect();
+        let record = |path: &std::path::Path, encoding: Encoding| {
+            let (sender, receiver) = mpsc::channel::<AudioSample>();
+            for chunk in tone.chunks(441) {
+                sender.send(AudioSample { data: chunk.to_vec(), sample_rate: 44100, channels: 1 }).unwrap();
+            }
+            drop(sender);
+            AudioEngine::audio_writer_thread(
+                receiver,
+                path.to_str().unwrap(),
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(RecordingClock::default()),
+                SplitPolicy::default(),
+                None,
+                WriterMode::Create(encoding),
+            ).unwrap()
+        };
+        record(&wav_path, Encoding::Wav(Some(44100), 32));
+        let segments = record(&ogg_path, Encoding::Opus(24000));
+        assert_eq!(segments[0].duration_ms, 3000);
+
+        let wav_size = std::fs::metadata(&wav_path).unwrap().len();
+        let ogg_size = std::fs::metadata(&ogg_path).unwrap().len();
+        assert!(ogg_size * 10 < wav_size, "{} bytes of Opus vs {} of WAV", ogg_size, wav_size);
+
+        // The file decodes to exactly the recorded length, with the tone intact
+        let decoded_path = temp_dir.path().join("note.playback.wav");
+        AudioEngine::decode_opus_to_wav(ogg_path.to_str().unwrap(), decoded_path.to_str().unwrap()).unwrap();
+        let mut reader = WavReader::open(&decoded_path).unwrap();
+        assert_eq!(reader.spec().sample_rate, OPUS_SAMPLE_RATE);
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.duration(), OPUS_SAMPLE_RATE * 3);
+
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        let rms = |samples: &[f32]| (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        let (decoded_rms, original_rms) = (rms(&samples[4800..samples.len() - 4800]), rms(&tone));
+        assert!((decoded_rms - original_rms).abs() < 0.05, "rms {} vs {}", decoded_rms, original_rms);
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_unfinished_opus_recording_is_recovered() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("note.ogg");
+        let path_str = path.to_str().unwrap();
+        let partial = AudioEngine::partial_path(path_str);
+
+        // Twelve seconds written without finalizing, then a torn page as a crash leaves
+        let mut writer = OggOpusWriter::create(&partial, 1, 24000).unwrap();
+        writer.write_samples(&vec![0.25; OPUS_SAMPLE_RATE as usize * 12]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&partial).unwrap();
+        file.write_all(b"OggS\0\x02torn").unwrap();
+        drop(file);
+
+        let duration = AudioEngine::recover_partial_file(path_str).unwrap();
+        assert!(duration > 0 && duration <= 12, "{}s", duration);
+        assert!(!std::path::Path::new(&partial).exists());
+        assert!(!std::path::Path::new(&format!("{}.repairing", path_str)).exists());
+
+        // The repaired file ends its stream and decodes to the stored duration
+        let (channels, samples) = ogg_opus::read_ogg_opus(path_str).unwrap();
+        assert_eq!(channels, 1);
+        assert_eq!(samples.len() / OPUS_SAMPLE_RATE as usize, duration as usize);
+    }
+
+    #[test]
+    fn test_writer_skips_resampling_at_target_rate() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("native.wav");
+
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        sender.send(AudioSample { data: vec![0.25, -0.25, 0.5, -0.5], sample_rate: 44100, channels: 2 }).unwrap();
+        drop(sender);
+
+        AudioEngine::audio_writer_thread(
+            receiver,
+            path.to_str().unwrap(),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            SplitPolicy::default(),
+            None,
+            WriterMode::Create(Encoding::Wav(Some(44100), 32)),
+        ).unwrap();
+
+        // Samples pass through untouched when the rates already match
+        let samples: Vec<f32> = WavReader::open(&path).unwrap().samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.25, -0.25, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_writer_appends_to_interrupted_recording() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("interrupted.wav");
+        let path_str = path.to_str().unwrap();
+
+        // Half a second recorded before the app closed
+        let spec = WavSpec { channels: 1, sample_rate: 8000, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+        let mut writer = WavWriter::create(path_str, spec).unwrap();
+        for _ in 0..4000 {
+            writer.write_sample(0.25f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // The device now captures at a different rate, which is converted to the file's
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        sender.send(AudioSample { data: vec![0.5; 16000], sample_rate: 16000, channels: 1 }).unwrap();
+        drop(sender);
+
+        let clock = Arc::new(RecordingClock::starting_at(500));
+        let segments = AudioEngine::audio_writer_thread(
+            receiver,
+            path_str,
+            Arc::new(AtomicBool::new(false)),
+            clock.clone(),
+            SplitPolicy::default(),
+            None,
+            WriterMode::Append,
+        ).unwrap();
+
+        let reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        assert_eq!(reader.duration(), 4000 + 8000);
+        assert_eq!(segments[0].duration_ms, 1500);
+        // Positions carry on from the audio already in the file
+        assert_eq!(clock.elapsed_ms(), 1500);
+
+        // A device with a different channel count can't continue the file
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        sender.send(AudioSample { data: vec![0.5; 16], sample_rate: 8000, channels: 2 }).unwrap();
+        drop(sender);
+        let result = AudioEngine::audio_writer_thread(
+            receiver,
+            path_str,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            SplitPolicy::default(),
+            None,
+            WriterMode::Append,
+        );
+        assert!(matches!(result, Err(AudioEngineError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn test_recording_is_written_to_partial_file_until_finalized() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("meeting.wav");
+        let path_str = path.to_str().unwrap();
+        let partial = temp_dir.path().join("meeting.wav.partial");
+        let write = |samples: Vec<AudioSample>| {
+            let (sender, receiver) = mpsc::channel::<AudioSample>();
+            for sample in samples {
+                sender.send(sample).unwrap();
+            }
+            drop(sender);
+            AudioEngine::audio_writer_thread(
+                receiver,
+                path_str,
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(RecordingClock::default()),
+                SplitPolicy::default(),
+                None,
+                WriterMode::Create(Encoding::Wav(None, 16)),
+            )
+        };
+
+        // A write failure part way through leaves only the unfinished file
+        let result = write(vec![
+            AudioSample { data: vec![0.5; 8000], sample_rate: 8000, channels: 1 },
+            AudioSample { data: vec![0.5; 16], sample_rate: 8000, channels: 2 },
+        ]);
+        assert!(result.is_err());
+        assert!(partial.exists());
+        assert!(!path.exists());
+        assert_eq!(AudioEngine::unfinished_path(path_str), partial.to_str().unwrap());
+
+        // Recovery repairs it and moves it into place
+        assert_eq!(AudioEngine::recover_partial_file(path_str).unwrap(), 1);
+        assert!(!partial.exists());
+        assert_eq!(WavReader::open(&path).unwrap().duration(), 8000);
+
+        // A completed recording is renamed when finalized
+        std::fs::remove_file(&path).unwrap();
+        write(vec![AudioSample { data: vec![0.5; 4000], sample_rate: 8000, channels: 1 }]).unwrap();
+        assert!(!partial.exists());
+        assert_eq!(WavReader::open(&path).unwrap().duration(), 4000);
+        assert_eq!(AudioEngine::unfinished_path(path_str), path_str);
+    }
+
+    #[test]
+    fn test_writer_rotates_segments() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("long.wav");
+        let path_str = path.to_str().unwrap();
+
+        // 2.5 minutes of mono audio at 100Hz with one-minute segments
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        for _ in 0..30 {
+            sender.send(AudioSample { data: vec![0.0; 500], sample_rate: 100, channels: 1 }).unwrap();
+        }
+        drop(sender);
+
+        let segments = AudioEngine::audio_writer_thread(
+            receiver,
+            path_str,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            SplitPolicy { every_minutes: Some(1), ..SplitPolicy::default() },
+            None,
+            WriterMode::Create(Encoding::Wav(None, 32)),
+        ).unwrap();
+
+        let bounds: Vec<(i64, i64)> = segments.iter().map(|s| (s.start_ms, s.duration_ms)).collect();
+        assert_eq!(bounds, vec![(0, 60_000), (60_000, 60_000), (120_000, 30_000)]);
+        assert_eq!(segments[0].file_path, path_str);
+
+        for segment in &segments {
+            let reader = WavReader::open(&segment.file_path).unwrap();
+            assert_eq!(reader.duration() as i64 * 1000 / 100, segment.duration_ms);
+        }
+    }
+
+    #[test]
+    fn test_engine_recovers_from_panic() {
+        let engine = Arc::new(AudioEngine::new().unwrap());
+        let (stop_sender, stop_receiver) = mpsc::channel::<()>();
+
+        // Panic in the middle of a recording while holding the state lock
+        let panicking = engine.clone();
+        let result = thread::spawn(move || {
+            let mut state = panicking.state();
+            state.is_recording = true;
+            state.recording_file_path = Some("interrupted.wav".to_string());
+            state.stop_sender = Some(stop_sender);
+            state.monitor_device = Some("Speakers".to_string());
+            panic!("audio engine failure");
+        }).join();
+        assert!(result.is_err());
+        assert!(engine.recording_state.is_poisoned());
+
+        // Later calls see an idle engine instead of failing, and the first
+        // stop reports the recording that was cut short, once
+        assert!(!engine.is_recording_to("interrupted.wav"));
+        assert!(matches!(engine.get_current_recording_time(), Err(AudioEngineError::NotRecording)));
+        assert!(matches!(engine.stop_recording(), Err(AudioEngineError::Poisoned(ref reason)) if reason.contains("interrupted.wav")));
+        assert!(matches!(engine.stop_recording(), Err(AudioEngineError::NotRecording)));
+        assert!(!engine.recording_state.is_poisoned());
+
+        // The abandoned capture was told to stop and preferences survive
+        assert!(stop_receiver.try_recv().is_ok());
+        assert_eq!(engine.state().monitor_device.as_deref(), Some("Speakers"));
+        engine.set_monitoring(false, None).unwrap();
+    }
+
+    #[test]
+    fn test_start_recording_works_after_poison() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("after-panic.wav");
+        let path_str = path.to_str().unwrap();
+        let mut engine = AudioEngine::new().unwrap();
+        engine.open_capture = |_, _, _, _, _| Err(AudioEngineError::NoInputDevice);
+        let engine = Arc::new(engine);
+
+        let panicking = engine.clone();
+        let result = thread::spawn(move || {
+            let _state = panicking.state();
+            panic!("audio engine failure");
+        }).join();
+        assert!(result.is_err());
+
+        let start = || engine.start_recording(path_str, None, None, None, false, Encoding::Wav(None, 32));
+        let poisoned = start();
+        assert!(matches!(poisoned, Err(AudioEngineError::Poisoned(ref reason)) if reason == "no recording was running"));
+        assert!(matches!(AppError::from(poisoned.unwrap_err()), AppError::Audio { .. }));
+        // The next start gets as far as opening the input device
+        assert!(matches!(start(), Err(AudioEngineError::NoInputDevice)));
+        assert!(matches!(start(), Err(AudioEngineError::NoInputDevice)));
+    }
+
+    #[test]
+    fn test_writer_splits_on_silence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("pauses.wav");
+        let path_str = path.to_str().unwrap();
+
+        // One-second buffers at 100Hz: speech, a three-second pause, speech
+        let (sender, receiver) = mpsc::channel::<AudioSample>();
+        for level in [0.5, 0.5, 0.0, 0.0, 0.0, 0.5] {
+            sender.send(AudioSample { data: vec![level; 100], sample_rate: 100, channels: 1 }).unwrap();
+        }
+        drop(sender);
+
+        let splits = Arc::new(Mutex::new(Vec::new()));
+        let listener_splits = splits.clone();
+        let split = SplitPolicy {
+            on_silence: Some(SilenceSplit { threshold_db: -40.0, min_silence_ms: 2000 }),
+            listener: Some(Arc::new(move |split: SegmentSplit| listener_splits.lock().unwrap().push(split))),
+            ..SplitPolicy::default()
+        };
+        let segments = AudioEngine::audio_writer_thread(
+            receiver,
+            path_str,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(RecordingClock::default()),
+            split,
+            None,
+            WriterMode::Create(Encoding::Wav(None, 32)),
+        ).unwrap();
+
+        // The new file starts once the pause reaches two seconds
+        let bounds: Vec<(i64, i64)> = segments.iter().map(|s| (s.start_ms, s.duration_ms)).collect();
+        assert_eq!(bounds, vec![(0, 3000), (3000, 3000)]);
+
+        let splits = splits.lock().unwrap();
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].index, 1);
+        assert_eq!(splits[0].start_ms, 3000);
+        assert_eq!(splits[0].file_path, segments[1].file_path);
+    }
+
+    #[test]
+    fn test_default_input_device_capabilities() {
+        let engine = AudioEngine::new().unwrap();
+        let name = engine.host.default_input_device()
+            .and_then(|d| d.name().ok())
+            .unwrap_or_else(|| "missing-device".to_string());
+
+        match engine.device_capabilities(&name) {
+            Ok(caps) => {
+                assert_eq!(caps.device_name, name);
+                assert!(caps.sample_rate > 0);
+                assert!(caps.channels > 0);
+                assert!(!caps.sample_format.is_empty());
+            }
+            // Machines without a usable input device must get an error, not a panic
+            Err(e) => {
+                let message = e.to_string();
+                assert!(message.contains("device") || message.contains(&name), "Unclear error: {}", message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_input_device_config_ranges() {
+        let engine = AudioEngine::new().unwrap();
+        let Some(name) = engine.default_input_device_name() else {
+            assert!(matches!(engine.supported_input_configs("missing-device"), Err(AudioEngineError::DeviceNotFound(_))));
+            return;
+        };
+
+        // Some backends fail to enumerate configs; that must be an error, not a panic
+        if let Ok(ranges) = engine.supported_input_configs(&name) {
+            for range in ranges {
+                assert!(range.min_sample_rate <= range.max_sample_rate);
+                assert!(range.channels > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_repair_unfinalized_wav() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("crashed.wav");
+        let path_str = path.to_str().unwrap();
+
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(path_str, spec).unwrap();
+        for _ in 0..(8000 * 2 * 3) {
+            writer.write_sample(0.25f32).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // Simulate a crash: header lengths never updated and a torn final frame
+        let mut bytes = std::fs::read(&path).unwrap();
+        let data_pos = bytes.windows(4).position(|w| w == b"data").unwrap();
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        bytes[data_pos + 4..data_pos + 8].copy_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 3]);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let duration = AudioEngine::repair_wav_file(path_str).unwrap();
+        assert_eq!(duration, 3);
+
+        let reader = WavReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 8000 * 2 * 3);
+    }
+
+    #[test]
+    fn test_verify_detects_stale_header_and_duration() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("stale.wav");
+        let path_str = path.to_str().unwrap().to_string();
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path_str, spec).unwrap();
+        for _ in 0..(8000 * 4) {
+            writer.write_sample(1000i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        // A finalized file with the right duration checks out
+        let verification = AudioEngine::verify_wav_files("rec", Some(4), std::slice::from_ref(&path_str));
+        assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+        assert_eq!(verification.measured_duration_ms(), 4000);
+
+        // The header still claims the audio a crash cut off, and the database is behind
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 8000 * 2);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let verification = AudioEngine::verify_wav_files("rec", Some(4), std::slice::from_ref(&path_str));
+        assert!(verification.headers_stale());
+        assert!(verification.duration_stale());
+        assert_eq!(verification.issues.len(), 2);
+        assert_eq!(verification.files[0].duration_ms, 3000);
+
+        AudioEngine::repair_wav_file(&path_str).unwrap();
+        let verification = AudioEngine::verify_wav_files("rec", Some(3), std::slice::from_ref(&path_str));
+        assert!(verification.issues.is_empty(), "{:?}", verification.issues);
+
+        // Missing files are reported rather than failing the whole check
+        let missing = temp_dir.path().join("missing.wav").to_str().unwrap().to_string();
+        let verification = AudioEngine::verify_wav_files("rec", Some(3), &[path_str, missing]);
+        assert_eq!(verification.unreadable.len(), 1);
+        assert!(!verification.duration_stale());
+    }
+
+    #[test]
+    fn test_apply_gain_matches_decibels() {
+        let mut samples = vec![0.1f32, -0.1, 0.25, 0.0];
+        AudioEngine::apply_gain(&mut samples, 6.0);
+        let doubled = 10f32.powf(6.0 / 20.0);
+        assert!((samples[0] - 0.1 * doubled).abs() < 1e-6);
+        assert!((samples[1] + 0.1 * doubled).abs() < 1e-6);
+        assert!((samples[2] - 0.25 * doubled).abs() < 1e-6);
+        assert_eq!(samples[3], 0.0);
+
+        let mut samples = vec![0.5f32, -0.5];
+        AudioEngine::apply_gain(&mut samples, -20.0);
+        assert!((samples[0] - 0.05).abs() < 1e-6);
+        assert!((samples[1] + 0.05).abs() < 1e-6);
+
+        // Boosted peaks clip at full scale rather than wrapping
+        let mut samples = vec![0.8f32, -0.8];
+        AudioEngine::apply_gain(&mut samples, 12.0);
+        assert_eq!(samples, vec![1.0, -1.0]);
+    }
+
+    #[test]
+    fn test_normalize_reaches_target_loudness() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("quiet.wav");
+        let path_str = path.to_str().unwrap().to_string();
+
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..(8000 * 2) {
+            writer.write_sample(if i % 4 < 2 { 0.05f32 } else { -0.05f32 }).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let files = vec![path_str];
+        let report = AudioEngine::normalize_wav_files(&files, -23.0, |_| {}).unwrap();
+        assert!(report.gain_db > 0.0);
+
+        let (lufs, _) = AudioEngine::measure_loudness(&files, || {}).unwrap();
+        assert!((lufs - -23.0).abs() < 0.01, "Loudness after normalizing: {}", lufs);
+        assert!(!temp_dir.path().join("quiet.wav.normalizing").exists());
+    }
+
+    #[test]
+    fn test_detect_and_trim_silence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("meeting.wav");
+        let path_str = path.to_str().unwrap().to_string();
+
+        // 1s silence, 2s tone, 0.5s silence at 1kHz mono
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 1000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..3500 {
+            let sample = if (1000..3000).contains(&i) { if i % 2 == 0 { 8000i16 } else { -8000 } } else { 0 };
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let files = vec![path_str.clone()];
+        let silences = AudioEngine::detect_silence(&files, -40.0, 300).unwrap();
+        assert_eq!(silences, vec![
+            SilenceInterval { start_ms: 0, end_ms: 1000 },
+            SilenceInterval { start_ms: 3000, end_ms: 3500 },
+        ]);
+
+        // Quiet runs shorter than the minimum are ignored
+        assert_eq!(AudioEngine::detect_silence(&files, -40.0, 600).unwrap().len(), 1);
+
+        let trimmed = temp_dir.path().join("trimmed.wav");
+        let trimmed_str = trimmed.to_str().unwrap();
+        AudioEngine::write_trimmed_wav(&path_str, trimmed_str, 1000, 3000).unwrap();
+        assert_eq!(AudioEngine::wav_duration_ms(trimmed_str).unwrap(), 2000);
+        assert!(AudioEngine::detect_silence(&[trimmed_str.to_string()], -40.0, 300).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_device_snapshot_changes() {
+        let snapshot = |inputs: &[&str], outputs: &[&str]| DeviceSnapshot {
+            inputs: inputs.iter().map(|name| name.to_string()).collect(),
+            outputs: outputs.iter().map(|name| name.to_string()).collect(),
+            default_input: inputs.first().map(|name| name.to_string()),
+            default_output: outputs.first().map(|name| name.to_string()),
+        };
+        let before = snapshot(&["Built-in Mic"], &["Speakers"]);
+
+        assert!(before.changes(&before.clone()).is_none());
+
+        // A headset adds an input and an output while the speakers go away
+        let after = snapshot(&["Built-in Mic", "Headset Mic"], &["Headset"]);
+        let changes = before.changes(&after).expect("Device change should be detected");
+        let names = |devices: &[AudioDevice]| -> Vec<(String, String)> {
+            devices.iter().map(|d| (d.name.clone(), d.device_type.clone())).collect()
+        };
+        assert_eq!(names(&changes.added), vec![
+            ("Headset Mic".to_string(), "input".to_string()),
+            ("Headset".to_string(), "output".to_string()),
+        ]);
+        assert_eq!(names(&changes.removed), vec![("Speakers".to_string(), "output".to_string())]);
+        assert_eq!(changes.default_output.as_deref(), Some("Headset"));
+        assert!(!changes.removed_input("Built-in Mic"));
+
+        // A new default alone is reported even though no device came or went
+        let mut new_default = before.clone();
+        new_default.default_input = None;
+        let changes = before.changes(&new_default).expect("Default change should be detected");
+        assert!(changes.added.is_empty() && changes.removed.is_empty());
+
+        // The same name moving between input and output is still a change
+        let moved = snapshot(&[], &["Built-in Mic", "Speakers"]);
+        let changes = before.changes(&moved).unwrap();
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.removed.len(), 1);
+    }
+
+    #[test]
+    fn test_repair_rejects_non_wav() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("not-audio.wav");
+        std::fs::write(&path, b"definitely not a riff file").unwrap();
+
+        assert!(AudioEngine::repair_wav_file(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_compute_wav_duration() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("imported.wav");
+        let spec = WavSpec { channels: 2, sample_rate: 16000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for _ in 0..24000 * 2 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        assert_eq!(AudioEngine::compute_wav_duration(path.to_str().unwrap()).unwrap(), 1.5);
+
+        // A corrupt header is an error rather than a panic
+        let corrupt = temp_dir.path().join("corrupt.wav");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[8..12].copy_from_slice(b"AVI ");
+        std::fs::write(&corrupt, bytes).unwrap();
+        let result = AudioEngine::compute_wav_duration(corrupt.to_str().unwrap());
+        assert!(matches!(result, Err(AudioEngineError::WavError(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_remaining_seconds_for_free_space() {
+        // 16-bit mono at 48kHz is 96000 bytes a second
+        assert_eq!(AudioEngine::estimated_remaining_seconds(345_600_000, 48_000, 1, 16), 3600);
+        // 24-bit stereo at 44.1kHz is 264600 bytes a second, partial seconds don't count
+        assert_eq!(AudioEngine::estimated_remaining_seconds(264_600 * 90 + 264_599, 44_100, 2, 24), 90);
+        assert_eq!(AudioEngine::estimated_remaining_seconds(1_000_000, 48_000, 0, 16), 0);
     }
 }
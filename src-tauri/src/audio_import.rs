@@ -0,0 +1,162 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use tracing::info;
+use crate::audio_engine::AudioEngine;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::models::AudioRecording;
+
+// Bytes copied between progress reports
+const COPY_CHUNK_BYTES: usize = 1 << 20;
+
+fn copy_chunks(source: &mut File, total: u64, partial: &Path, to: &Path, on_progress: &mut impl FnMut(u64, u64)) -> io::Result<u64> {
+    let mut destination = OpenOptions::new().write(true).create_new(true).open(partial)?;
+    let mut buffer = vec![0; COPY_CHUNK_BYTES];
+    let mut copied = 0;
+    loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        destination.write_all(&buffer[..read])?;
+        copied += read as u64;
+        on_progress(copied, total);
+    }
+    destination.sync_all()?;
+    if to.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+    }
+    fs::rename(partial, to)?;
+    Ok(copied)
+}
+
+/// Copy `from` to `to` a chunk at a time, calling `on_progress` with the
+/// bytes copied so far and the total after each chunk. The copy is written
+/// beside `to` and only moved into place once complete, and `to` is never
+/// replaced. Returns the bytes copied.
+pub fn copy_with_progress(from: &Path, to: &Path, mut on_progress: impl FnMut(u64, u64)) -> io::Result<u64> {
+    let mut source = File::open(from)?;
+    let total = source.metadata()?.len();
+    let mut partial = to.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+
+    copy_chunks(&mut source, total, &partial, to, &mut on_progress).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })
+}
+
+/// Seconds of audio in the WAV file at `path` according to its header,
+/// or why it can't be attached
+pub fn check_wav(path: &Path) -> Result<i32, AppError> {
+    let details = serde_json::json!({ "path": path.display().to_string() });
+    if !path.is_file() {
+        return Err(AppError::not_found(format!("File not found: {}", path.display())).with_details(details));
+    }
+    File::open(path)?;
+    let path_str = path.to_string_lossy();
+    let seconds = AudioEngine::compute_wav_duration(&path_str).map_err(|e| {
+        AppError::validation(format!("{} isn't a WAV file: {}", path.display(), e)).with_details(details)
+    })?;
+    Ok(seconds.round() as i32)
+}
+
+/// Copy the WAV file at `source` into `recordings_dir` and add it to page
+/// `page_id` as a finished recording. `on_progress` is called as the copy
+/// goes with the bytes copied so far and the total.
+pub async fn attach_wav(
+    db: &DatomicPeerClient,
+    page_id: &str,
+    source: &Path,
+    recordings_dir: &Path,
+    on_progress: impl FnMut(u64, u64) + Send + 'static,
+) -> Result<AudioRecording, AppError> {
+    let recording_id = uuid::Uuid::new_v4().to_string();
+    let destination = recordings_dir.join(format!("{}.wav", recording_id));
+
+    let (duration_seconds, recorded_at) = tauri::async_runtime::spawn_blocking({
+        let source = source.to_path_buf();
+        let destination = destination.clone();
+        move || -> Result<(i32, DateTime<Utc>), AppError> {
+            let duration_seconds = check_wav(&source)?;
+            // The file's own date is the closest thing to when it was recorded
+            let recorded_at = fs::metadata(&source)?.modified().map(DateTime::from).unwrap_or_else(|_| Utc::now());
+            fs::create_dir_all(destination.parent().unwrap_or(Path::new(".")))?;
+            copy_with_progress(&source, &destination, on_progress)?;
+            Ok((duration_seconds, recorded_at))
+        }
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Copying {} failed: {}", source.display(), e)))??;
+
+    let recording = AudioRecording {
+        id: recording_id,
+        page_id: page_id.to_string(),
+        file_path: destination.display().to_string(),
+        duration_seconds: Some(duration_seconds),
+        recorded_at,
+        system_audio: false,
+    };
+    if let Err(e) = db.create_audio_recording(&recording).await {
+        // Without its row the copy would never be found again
+        let _ = fs::remove_file(&destination);
+        return Err(e.into());
+    }
+
+    info!("Attached {} to page {} as recording {}", source.display(), page_id, recording.id);
+    Ok(recording)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    fn write_wav(path: &Path, seconds: u32) {
+        let spec = WavSpec { channels: 1, sample_rate: 8000, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for _ in 0..8000 * seconds {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn test_check_wav_reads_duration_and_rejects_other_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let wav = dir.path().join("interview.wav");
+        let text = dir.path().join("notes.wav");
+        write_wav(&wav, 3);
+        fs::write(&text, b"not audio").unwrap();
+
+        assert_eq!(check_wav(&wav).unwrap(), 3);
+        assert!(matches!(check_wav(&text), Err(AppError::Validation { .. })));
+        assert!(matches!(check_wav(&dir.path().join("missing.wav")), Err(AppError::NotFound { .. })));
+        assert!(matches!(check_wav(dir.path()), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_copy_reports_progress_and_never_replaces() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let source = dir.path().join("long.wav");
+        let destination = dir.path().join("copy.wav");
+        let data = vec![7u8; COPY_CHUNK_BYTES * 2 + 10];
+        fs::write(&source, &data).unwrap();
+
+        let mut reports = Vec::new();
+        let copied = copy_with_progress(&source, &destination, |copied, total| reports.push((copied, total))).unwrap();
+
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(fs::read(&destination).unwrap(), data);
+        let total = data.len() as u64;
+        assert_eq!(reports, vec![(COPY_CHUNK_BYTES as u64, total), (COPY_CHUNK_BYTES as u64 * 2, total), (total, total)]);
+
+        fs::write(&source, b"newer").unwrap();
+        let error = copy_with_progress(&source, &destination, |_, _| {}).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&destination).unwrap(), data);
+        assert!(!dir.path().join("copy.wav.partial").exists());
+    }
+}
@@ -0,0 +1,82 @@
+use base64::Engine;
+use crate::errors::{DatomicError, Result};
+use crate::models::Block;
+
+/// Encode blocks with bincode, a much smaller encoding than JSON for bulk transfer
+pub fn serialize_blocks_binary(blocks: &[Block]) -> Result<Vec<u8>> {
+    bincode::serialize(blocks)
+        .map_err(|e| DatomicError::serialization_error(format!("Failed to encode blocks: {}", e)))
+}
+
+#[allow(dead_code)] // Decoding happens on the receiving side of a transfer
+pub fn deserialize_blocks_binary(bytes: &[u8]) -> Result<Vec<Block>> {
+    bincode::deserialize(bytes)
+        .map_err(|e| DatomicError::serialization_error(format!("Failed to decode blocks: {}", e)))
+}
+
+/// Encode blocks for transport as a base64 string
+pub fn export_blocks_base64(blocks: &[Block]) -> Result<String> {
+    Ok(base64::engine::general_purpose::STANDARD.encode(serialize_blocks_binary(blocks)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::models::{AudioRecording, AudioTimestamp, RecordingSegment};
+
+    #[test]
+    fn test_blocks_round_trip() {
+        let now = Utc::now();
+        let page = Block {
+            id: "page-1".to_string(),
+            content: None,
+            parent_id: None,
+            order: 0,
+            is_page: true,
+            page_title: Some("Weekly sync".to_string()),
+            created_at: now,
+            updated_at: now,
+            audio_timestamp: None,
+            source: None,
+        };
+        let note = Block {
+            id: "block-1".to_string(),
+            content: Some("Ship the beta on Friday".to_string()),
+            parent_id: Some("page-1".to_string()),
+            order: 1,
+            is_page: false,
+            page_title: None,
+            created_at: now,
+            updated_at: now,
+            audio_timestamp: Some(AudioTimestamp {
+                block_id: "block-1".to_string(),
+                recording_id: "recording-1".to_string(),
+                timestamp_ms: 95_000,
+                timestamp_seconds: 95,
+                recording: Some(AudioRecording {
+                    id: "recording-1".to_string(),
+                    page_id: "page-1".to_string(),
+                    file_path: "/tmp/sync.wav".to_string(),
+                    duration_seconds: Some(600),
+                    recorded_at: now,
+                    system_audio: true,
+                }),
+                segment: Some(RecordingSegment {
+                    index: 1,
+                    file_path: "/tmp/sync-001.wav".to_string(),
+                    start_ms: 60_000,
+                    duration_ms: 60_000,
+                }),
+            }),
+            source: None,
+        };
+        let blocks = vec![page, note];
+
+        let bytes = serialize_blocks_binary(&blocks).unwrap();
+        assert_eq!(deserialize_blocks_binary(&bytes).unwrap(), blocks);
+        assert!(bytes.len() < serde_json::to_vec(&blocks).unwrap().len());
+
+        assert!(deserialize_blocks_binary(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use crate::errors::AppError;
+
+/// Reads in progress that the frontend can cancel, keyed by the token ID it
+/// passed when starting them, e.g. when navigating away from a large page
+#[derive(Default)]
+pub struct CancelTokens {
+    tokens: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+/// A registered token, removed from `CancelTokens` when the read ends
+pub struct CancelToken<'a> {
+    tokens: &'a CancelTokens,
+    id: String,
+    pub flag: Arc<AtomicBool>,
+}
+
+impl Drop for CancelToken<'_> {
+    fn drop(&mut self) {
+        self.tokens.tokens().remove(&self.id);
+    }
+}
+
+impl CancelTokens {
+    fn tokens(&self) -> MutexGuard<'_, HashMap<String, Arc<AtomicBool>>> {
+        self.tokens.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Register `token_id` for a read that's starting. Fails if a running
+    /// read already uses it.
+    pub fn register(&self, token_id: &str) -> Result<CancelToken<'_>, AppError> {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut tokens = self.tokens();
+        if tokens.contains_key(token_id) {
+            return Err(AppError::conflict(format!("Cancel token {} is already in use", token_id)));
+        }
+        tokens.insert(token_id.to_string(), flag.clone());
+        Ok(CancelToken { tokens: self, id: token_id.to_string(), flag })
+    }
+
+    /// Ask the read registered as `token_id` to stop before its next batch
+    pub fn cancel(&self, token_id: &str) -> Result<(), AppError> {
+        let tokens = self.tokens();
+        let flag = tokens.get(token_id)
+            .ok_or_else(|| AppError::not_found(format!("No read in progress for cancel token {}", token_id)))?;
+        flag.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_cancel_only_running_reads() {
+        let tokens = CancelTokens::default();
+        let token = tokens.register("page-load").unwrap();
+        assert!(matches!(tokens.register("page-load"), Err(AppError::Conflict { .. })));
+
+        tokens.cancel("page-load").unwrap();
+        assert!(token.flag.load(Ordering::SeqCst));
+
+        // Once the read ends its token can't be cancelled, and can be reused
+        drop(token);
+        assert!(matches!(tokens.cancel("page-load"), Err(AppError::NotFound { .. })));
+        assert!(!tokens.register("page-load").unwrap().flag.load(Ordering::SeqCst));
+    }
+}
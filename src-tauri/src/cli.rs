@@ -0,0 +1,150 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use clap::{Arg, Command};
+use serde::Serialize;
+use crate::archive;
+use crate::config::AppConfig;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::integrity;
+use crate::jobs::Untracked;
+
+/// The command finished and printed its result
+pub const EXIT_OK: i32 = 0;
+/// The command failed; the error is printed to stderr
+pub const EXIT_FAILED: i32 = 1;
+/// The arguments couldn't be parsed
+pub const EXIT_USAGE: i32 = 2;
+/// `check-integrity` ran and found problems
+pub const EXIT_PROBLEMS_FOUND: i32 = 3;
+
+/// A command run without starting the app
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    ExportJson { out: PathBuf },
+    ImportMarkdown { dir: PathBuf },
+    CheckIntegrity,
+    Backup { out_dir: Option<PathBuf> },
+}
+
+fn command() -> Command {
+    Command::new("gita")
+        .about("Research & audio note-taking. Run without a command to open the app.")
+        .version(env!("CARGO_PKG_VERSION"))
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("export-json")
+                .about("Export every block, recording and timestamp as JSON")
+                .arg(Arg::new("out").long("out").value_name("FILE").required(true).help("File to write, replaced if it exists")),
+        )
+        .subcommand(
+            Command::new("import-markdown")
+                .about("Create a page from each Markdown file in a directory")
+                .arg(Arg::new("dir").value_name("DIR").required(true).help("Directory of .md files, one page each")),
+        )
+        .subcommand(
+            Command::new("check-integrity")
+                .about("Check the database for problems without changing anything"),
+        )
+        .subcommand(
+            Command::new("backup")
+                .about("Write a JSON export and a copy of the recordings to a new directory")
+                .arg(Arg::new("out-dir").long("out-dir").value_name("DIR").help("Where to create the backup [default: the backups folder in the data directory]")),
+        )
+}
+
+/// The command `args` ask for, or `None` to start the app. Anything but a
+/// known command or a help flag starts the app, since it's also launched
+/// with `gita://` links as arguments.
+pub fn parse<I, T>(args: I) -> Result<Option<CliCommand>, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let command = command();
+    let is_cli = args.get(1).and_then(|arg| arg.to_str()).is_some_and(|arg| {
+        matches!(arg, "help" | "--help" | "-h" | "--version" | "-V")
+            || command.get_subcommands().any(|subcommand| subcommand.get_name() == arg)
+    });
+    if !is_cli {
+        return Ok(None);
+    }
+
+    let matches = command.try_get_matches_from(args)?;
+    let path = |matches: &clap::ArgMatches, id: &str| matches.get_one::<String>(id).map(PathBuf::from);
+    Ok(match matches.subcommand() {
+        Some(("export-json", matches)) => path(matches, "out").map(|out| CliCommand::ExportJson { out }),
+        Some(("import-markdown", matches)) => path(matches, "dir").map(|dir| CliCommand::ImportMarkdown { dir }),
+        Some(("check-integrity", _)) => Some(CliCommand::CheckIntegrity),
+        Some(("backup", matches)) => Some(CliCommand::Backup { out_dir: path(matches, "out-dir") }),
+        _ => None,
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, AppError> {
+    serde_json::to_string_pretty(value).map_err(|e| AppError::internal(format!("Failed to encode result: {}", e)))
+}
+
+/// Run `command` against the configured database, returning its JSON
+/// output and whether it found nothing wrong
+async fn execute(command: CliCommand) -> Result<(String, bool), AppError> {
+    let config = AppConfig::load()?;
+    let db = DatomicPeerClient::new(config.clone()).await?;
+
+    match command {
+        CliCommand::ExportJson { out } => Ok((to_json(&archive::export_json(&db, &out, &Untracked).await?)?, true)),
+        CliCommand::ImportMarkdown { dir } => Ok((to_json(&archive::import_markdown(&db, &dir, &Untracked).await?)?, true)),
+        CliCommand::CheckIntegrity => {
+            let report = integrity::check_integrity(&db).await?;
+            Ok((to_json(&report)?, report.is_clean()))
+        }
+        CliCommand::Backup { out_dir } => {
+            let backups_dir = out_dir.unwrap_or_else(|| archive::default_backups_dir(&config));
+            let report = archive::backup(&db, &config.audio.recordings_dir, &backups_dir).await?;
+            Ok((to_json(&report)?, true))
+        }
+    }
+}
+
+/// Run `command`, printing its result as JSON to stdout or the error as
+/// JSON to stderr, and return the process exit code
+pub fn run(command: CliCommand) -> i32 {
+    match tauri::async_runtime::block_on(execute(command)) {
+        Ok((output, clean)) => {
+            println!("{}", output);
+            if clean { EXIT_OK } else { EXIT_PROBLEMS_FOUND }
+        }
+        Err(e) => {
+            eprintln!("{}", to_json(&e).unwrap_or_else(|_| e.to_string()));
+            EXIT_FAILED
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse(["gita", "export-json", "--out", "notes.json"]).unwrap(), Some(CliCommand::ExportJson { out: PathBuf::from("notes.json") }));
+        assert_eq!(parse(["gita", "import-markdown", "notes"]).unwrap(), Some(CliCommand::ImportMarkdown { dir: PathBuf::from("notes") }));
+        assert_eq!(parse(["gita", "check-integrity"]).unwrap(), Some(CliCommand::CheckIntegrity));
+        assert_eq!(parse(["gita", "backup"]).unwrap(), Some(CliCommand::Backup { out_dir: None }));
+        assert_eq!(parse(["gita", "backup", "--out-dir", "/mnt/backups"]).unwrap(), Some(CliCommand::Backup { out_dir: Some(PathBuf::from("/mnt/backups")) }));
+    }
+
+    #[test]
+    fn test_parse_leaves_app_launches_alone() {
+        assert_eq!(parse(["gita"]).unwrap(), None);
+        assert_eq!(parse(["gita", "gita://page/page-1"]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_rejects_incomplete_commands() {
+        assert!(parse(["gita", "export-json"]).unwrap_err().use_stderr());
+        assert!(parse(["gita", "backup", "--to", "x"]).unwrap_err().use_stderr());
+        assert!(!parse(["gita", "--help"]).unwrap_err().use_stderr());
+    }
+}
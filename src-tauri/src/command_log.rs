@@ -0,0 +1,301 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::ipc::{Invoke, InvokeBody};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Metadata, Span, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name of the span every command runs in
+const COMMAND_SPAN: &str = "command";
+/// Commands kept for the debug panel; older ones are dropped
+pub const COMMAND_LOG_CAPACITY: usize = 500;
+/// Arguments holding note text, which is logged only by length
+const REDACTED_ARGS: &[&str] = &["content", "text", "markdown", "transcript"];
+/// Longer string arguments are cut short
+const MAX_ARG_CHARS: usize = 80;
+/// Longer list arguments are logged only by length
+const MAX_ARG_ITEMS: usize = 10;
+
+/// A finished command, as shown in the debug panel
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CommandLogEntry {
+    pub command: String,
+    /// Arguments as JSON, with note text left out
+    pub args: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// False if the command logged an error while it ran
+    pub success: bool,
+    /// The first error the command logged
+    pub error: Option<String>,
+}
+
+/// The most recent commands, bounded to a fixed number of entries
+pub struct CommandLog {
+    entries: Mutex<VecDeque<CommandLogEntry>>,
+    capacity: usize,
+}
+
+impl Default for CommandLog {
+    fn default() -> Self {
+        Self::with_capacity(COMMAND_LOG_CAPACITY)
+    }
+}
+
+impl CommandLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { entries: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+    }
+
+    pub fn push(&self, entry: CommandLogEntry) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Up to `limit` entries, newest first
+    pub fn recent(&self, limit: usize) -> Vec<CommandLogEntry> {
+        self.entries.lock().unwrap().iter().rev().take(limit).cloned().collect()
+    }
+}
+
+/// `args` with note text replaced by its length and long strings and lists
+/// cut short, so the log never holds what was written
+pub fn summarize_args(args: &Value) -> Value {
+    match args {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| {
+                    let summary = match value {
+                        Value::String(text) if REDACTED_ARGS.contains(&key.as_str()) => {
+                            Value::String(format!("<{} chars>", text.chars().count()))
+                        }
+                        _ => summarize_args(value),
+                    };
+                    (key.clone(), summary)
+                })
+                .collect(),
+        ),
+        Value::Array(items) if items.len() > MAX_ARG_ITEMS => Value::String(format!("<{} items>", items.len())),
+        Value::Array(items) => Value::Array(items.iter().map(summarize_args).collect()),
+        Value::String(text) if text.chars().count() > MAX_ARG_CHARS => {
+            Value::String(format!("{}…", text.chars().take(MAX_ARG_CHARS).collect::<String>()))
+        }
+        _ => args.clone(),
+    }
+}
+
+fn command_span(command: &str, payload: &InvokeBody) -> Span {
+    let args = match payload {
+        InvokeBody::Json(args) => summarize_args(args).to_string(),
+        InvokeBody::Raw(bytes) => format!("<{} bytes>", bytes.len()),
+    };
+    tracing::debug_span!(COMMAND_SPAN, command, args = args.as_str())
+}
+
+/// Run every command `handler` takes in a span naming it and its arguments.
+/// Async commands finish inside it too, so it closes when they do.
+pub fn instrument_commands(
+    handler: impl Fn(Invoke) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        let span = command_span(invoke.message.command(), invoke.message.payload());
+        let _entered = span.enter();
+        handler(invoke)
+    }
+}
+
+/// A command span's details, kept until it closes
+struct Timing {
+    command: String,
+    args: String,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct SpanFields {
+    command: String,
+    args: String,
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "command" => self.command = value.to_string(),
+            "args" => self.args = value.to_string(),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+}
+
+#[derive(Default)]
+struct EventMessage(String);
+
+impl Visit for EventMessage {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// Adds an entry to the command log as each command span closes
+struct CommandLogLayer {
+    log: Arc<CommandLog>,
+}
+
+impl<S> Layer<S> for CommandLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != COMMAND_SPAN {
+            return;
+        }
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Timing {
+                command: fields.command,
+                args: fields.args,
+                started_at: Utc::now(),
+                started: Instant::now(),
+                error: None,
+            });
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let Some(scope) = ctx.event_scope(event) else { return };
+        for span in scope {
+            if let Some(timing) = span.extensions_mut().get_mut::<Timing>() {
+                if timing.error.is_none() {
+                    let mut message = EventMessage::default();
+                    event.record(&mut message);
+                    timing.error = Some(message.0);
+                }
+                return;
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(timing) = ctx.span(&id).and_then(|span| span.extensions_mut().remove::<Timing>()) else { return };
+        self.log.push(CommandLogEntry {
+            command: timing.command,
+            args: timing.args,
+            started_at: timing.started_at,
+            duration_ms: timing.started.elapsed().as_millis() as u64,
+            success: timing.error.is_none(),
+            error: timing.error,
+        });
+    }
+}
+
+fn is_app_target(metadata: &Metadata<'_>) -> bool {
+    let target = metadata.target();
+    target == env!("CARGO_CRATE_NAME") || target.starts_with(concat!(env!("CARGO_CRATE_NAME"), "::"))
+}
+
+/// Layer filling `log` from command spans, whatever level is being logged
+pub fn layer<S>(log: Arc<CommandLog>) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    CommandLogLayer { log }.with_filter(filter_fn(|metadata| {
+        is_app_target(metadata) && (metadata.is_span() || *metadata.level() == Level::ERROR)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tracing::error;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn entry(command: &str) -> CommandLogEntry {
+        CommandLogEntry {
+            command: command.to_string(),
+            args: "{}".to_string(),
+            started_at: Utc::now(),
+            duration_ms: 0,
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_log_keeps_newest_entries() {
+        let log = CommandLog::with_capacity(3);
+        for command in ["a", "b", "c", "d"] {
+            log.push(entry(command));
+        }
+
+        let commands: Vec<String> = log.recent(10).into_iter().map(|entry| entry.command).collect();
+        assert_eq!(commands, vec!["d", "c", "b"]);
+        assert_eq!(log.recent(1)[0].command, "d");
+    }
+
+    #[test]
+    fn test_summarize_args_leaves_out_note_text() {
+        let args = json!({
+            "blockId": "block-1",
+            "content": "Private thoughts",
+            "request": { "content": "More", "parentId": null },
+            "query": "x".repeat(100),
+            "blockIds": (0..20).collect::<Vec<_>>(),
+        });
+
+        assert_eq!(summarize_args(&args), json!({
+            "blockId": "block-1",
+            "content": "<16 chars>",
+            "request": { "content": "<4 chars>", "parentId": null },
+            "query": format!("{}…", "x".repeat(MAX_ARG_CHARS)),
+            "blockIds": "<20 items>",
+        }));
+    }
+
+    #[test]
+    fn test_layer_records_commands_and_their_errors() {
+        let log = Arc::new(CommandLog::default());
+        let subscriber = tracing_subscriber::registry().with(layer(log.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let payload = InvokeBody::Json(json!({ "content": "Secret" }));
+            command_span("update_block_content", &payload).in_scope(|| {});
+            command_span("get_block", &InvokeBody::Json(json!({}))).in_scope(|| {
+                // Nested spans, like the one an async command runs in, still count
+                tracing::debug_span!("ipc::request::run").in_scope(|| error!("Block not found"));
+            });
+        });
+
+        let entries = log.recent(10);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "get_block");
+        assert!(!entries[0].success);
+        assert_eq!(entries[0].error.as_deref(), Some("Block not found"));
+        assert_eq!(entries[1].command, "update_block_content");
+        assert_eq!(entries[1].args, r#"{"content":"<6 chars>"}"#);
+        assert!(entries[1].success);
+    }
+}
@@ -1,40 +1,199 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
+use crate::errors::RetryConfig;
+
+const CONFIG_FILE: &str = "gita-config.toml";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatomicConfig {
+    /// Explicit connection URI; when empty it is composed by `build_uri`
+    #[serde(default)]
     pub db_uri: String,
     pub transactor_host: String,
     pub transactor_port: u16,
     pub database_name: String,
     pub datomic_lib_path: Option<PathBuf>,
     pub jvm_opts: Vec<String>,
+    /// Longest a single attempt at a query or transaction may take
     pub connection_timeout_ms: u64,
+    /// Times a failing database operation is tried before giving up
     pub retry_attempts: u32,
+    /// Wait before the first retry, doubled for each retry after it
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Longest wait between retries
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_initial_delay_ms() -> u64 {
+    RetryConfig::default().initial_delay_ms
+}
+
+fn default_max_delay_ms() -> u64 {
+    RetryConfig::default().max_delay_ms
+}
+
+/// Which database blocks and recordings are stored in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// A local SQLite file; not implemented yet, so block and recording operations fail as unsupported
+    Sqlite,
+    #[default]
+    Datomic,
+}
+
+/// File format new recordings are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+    /// Ogg/Opus, far smaller for voice notes; only available with the "opus" feature
+    Opus,
+}
+
+impl AudioFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Opus => "ogg",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioConfig {
     pub recordings_dir: PathBuf,
     pub max_recording_duration_minutes: u32,
+    /// Sample rate recordings are written at; audio captured at another rate is resampled
     pub sample_rate: u32,
+    /// Bit depth of WAV recordings: 16 or 24-bit integer, or 32-bit float.
+    /// 16 bits is plenty for voice and half the size of 32.
+    #[serde(default = "default_bits_per_sample")]
+    pub bits_per_sample: u16,
     pub channels: u16,
+    /// Input device chosen by the user; `None` means the system default
+    pub input_device: Option<String>,
+    /// Output device used for live monitoring; `None` means the system default
+    pub monitor_output_device: Option<String>,
+    /// Start a new recording file every N minutes; `None` records to a single file
+    pub segment_minutes: Option<u32>,
+    /// Also start a new recording file after every long pause
+    #[serde(default)]
+    pub split_on_silence: bool,
+    /// Input quieter than this RMS level counts as a pause, in dBFS
+    #[serde(default = "default_silence_split_threshold_db")]
+    pub silence_split_threshold_db: f32,
+    /// How long a pause lasts before the recording is split
+    #[serde(default = "default_silence_split_seconds")]
+    pub silence_split_seconds: u32,
+    /// Mix system audio (loopback capture) into recordings where the platform supports it
+    #[serde(default)]
+    pub capture_system_audio: bool,
+    /// How often to check for plugged or unplugged audio devices; 0 disables the check
+    #[serde(default = "default_device_poll_interval_ms")]
+    pub device_poll_interval_ms: u64,
+    /// Even out the input level with automatic gain control while recording
+    #[serde(default)]
+    pub agc_enabled: bool,
+    /// How quickly AGC turns the gain down on louder input
+    #[serde(default = "default_agc_attack_ms")]
+    pub agc_attack_ms: f32,
+    /// How quickly AGC turns the gain back up on quieter input
+    #[serde(default = "default_agc_release_ms")]
+    pub agc_release_ms: f32,
+    #[serde(default)]
+    pub format: AudioFormat,
+    /// Bits per second of Opus recordings
+    #[serde(default = "default_opus_bitrate")]
+    pub opus_bitrate: u32,
+    /// Global shortcut that starts or stops recording into today's daily
+    /// note from any app; empty disables it
+    #[serde(default = "default_recording_hotkey")]
+    pub recording_hotkey: String,
+}
+
+// Bit depths WAV recordings can be written at
+const SUPPORTED_BITS_PER_SAMPLE: [u16; 3] = [16, 24, 32];
+
+fn default_bits_per_sample() -> u16 {
+    32
+}
+
+fn default_silence_split_threshold_db() -> f32 {
+    -45.0
+}
+
+fn default_silence_split_seconds() -> u32 {
+    5
+}
+
+fn default_device_poll_interval_ms() -> u64 {
+    2000
+}
+
+fn default_agc_attack_ms() -> f32 {
+    crate::agc::DEFAULT_ATTACK_MS
+}
+
+fn default_agc_release_ms() -> f32 {
+    crate::agc::DEFAULT_RELEASE_MS
+}
+
+fn default_opus_bitrate() -> u32 {
+    24000
+}
+
+fn default_recording_hotkey() -> String {
+    "CmdOrCtrl+Shift+R".to_string()
+}
+
+/// Audio settings changed from the UI; fields left out keep their value.
+/// Settings with their own command (input and monitor devices, system
+/// audio, AGC, the recording shortcut) aren't here because changing them
+/// has to reach the running engine too.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigPatch {
+    pub recordings_dir: Option<PathBuf>,
+    pub max_recording_duration_minutes: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub bits_per_sample: Option<u16>,
+    pub channels: Option<u16>,
+    /// 0 records to a single file
+    pub segment_minutes: Option<u32>,
+    pub split_on_silence: Option<bool>,
+    pub silence_split_threshold_db: Option<f32>,
+    pub silence_split_seconds: Option<u32>,
+    pub format: Option<AudioFormat>,
+    pub opus_bitrate: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
     pub datomic: DatomicConfig,
     pub audio: AudioConfig,
     pub log_level: String,
+    /// Also write logs to daily files in the data directory's `logs` folder
+    #[serde(default)]
+    pub log_to_file: bool,
     pub data_dir: PathBuf,
+    /// Folder picked in a dialog that imports and exports may use besides
+    /// the data directory. Not in `ConfigPatch`, so the webview can't set it.
+    #[serde(default)]
+    pub export_dir: Option<PathBuf>,
 }
 
 impl Default for DatomicConfig {
     fn default() -> Self {
         Self {
-            db_uri: "datomic:dev://localhost:8998/gita".to_string(),
+            db_uri: String::new(),
             transactor_host: "localhost".to_string(),
             transactor_port: 8998,
             database_name: "gita".to_string(),
@@ -46,6 +205,8 @@ impl Default for DatomicConfig {
             ],
             connection_timeout_ms: 30000,
             retry_attempts: 3,
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
         }
     }
 }
@@ -56,7 +217,22 @@ impl Default for AudioConfig {
             recordings_dir: PathBuf::from("recordings"),
             max_recording_duration_minutes: 120,
             sample_rate: 44100,
+            bits_per_sample: default_bits_per_sample(),
             channels: 2,
+            input_device: None,
+            monitor_output_device: None,
+            segment_minutes: None,
+            split_on_silence: false,
+            silence_split_threshold_db: default_silence_split_threshold_db(),
+            silence_split_seconds: default_silence_split_seconds(),
+            capture_system_audio: false,
+            device_poll_interval_ms: default_device_poll_interval_ms(),
+            agc_enabled: false,
+            agc_attack_ms: default_agc_attack_ms(),
+            agc_release_ms: default_agc_release_ms(),
+            format: AudioFormat::default(),
+            opus_bitrate: default_opus_bitrate(),
+            recording_hotkey: default_recording_hotkey(),
         }
     }
 }
@@ -70,13 +246,37 @@ impl Default for AppConfig {
         let recordings_dir = data_dir.join("recordings");
         
         Self {
+            backend: StorageBackend::default(),
             datomic: DatomicConfig::default(),
             audio: AudioConfig {
                 recordings_dir,
                 ..AudioConfig::default()
             },
             log_level: "info".to_string(),
+            log_to_file: false,
             data_dir,
+            export_dir: None,
+        }
+    }
+}
+
+impl DatomicConfig {
+    /// Connection URI: the explicit `db_uri` if set, otherwise composed from
+    /// the transactor host, port and database name
+    pub fn build_uri(&self) -> String {
+        if !self.db_uri.trim().is_empty() {
+            return self.db_uri.clone();
+        }
+        format!("datomic:dev://{}:{}/{}", self.transactor_host, self.transactor_port, self.database_name)
+    }
+
+    /// How database operations are retried
+    pub fn retry_config(&self) -> RetryConfig {
+        RetryConfig {
+            max_attempts: self.retry_attempts,
+            initial_delay_ms: self.initial_delay_ms,
+            max_delay_ms: self.max_delay_ms,
+            ..RetryConfig::default()
         }
     }
 }
@@ -84,13 +284,7 @@ impl Default for AppConfig {
 impl AppConfig {
     /// Load configuration from file or environment variables
     pub fn load() -> Result<Self> {
-        let mut config = Self::default();
-        
-        // Try to load from config file
-        if let Ok(config_content) = std::fs::read_to_string("gita-config.toml") {
-            config = toml::from_str(&config_content)
-                .map_err(|e| anyhow!("Failed to parse config file: {}", e))?;
-        }
+        let mut config = Self::load_file(Path::new(CONFIG_FILE))?;
         
         // Override with environment variables
         if let Ok(db_uri) = env::var("GITA_DB_URI") {
@@ -126,8 +320,112 @@ impl AppConfig {
         Ok(config)
     }
     
+    /// Read configuration from a TOML file, falling back to defaults if it doesn't exist
+    fn load_file(path: &Path) -> Result<Self> {
+        let config: Self = match std::fs::read_to_string(path) {
+            Ok(config_content) => toml::from_str(&config_content)
+                .map_err(|e| anyhow!("Failed to parse config file: {}", e))?,
+            Err(_) => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+    
+    /// Reject settings recording can't work with
+    fn validate(&self) -> Result<()> {
+        let datomic = &self.datomic;
+        if datomic.retry_attempts == 0 {
+            return Err(anyhow!("Database operations need at least one attempt"));
+        }
+        if datomic.connection_timeout_ms == 0 {
+            return Err(anyhow!("Database operations need a timeout above 0 ms"));
+        }
+        if datomic.initial_delay_ms > datomic.max_delay_ms {
+            return Err(anyhow!(
+                "Initial retry delay {} ms is longer than the maximum of {} ms",
+                datomic.initial_delay_ms, datomic.max_delay_ms
+            ));
+        }
+        let audio = &self.audio;
+        if !SUPPORTED_BITS_PER_SAMPLE.contains(&audio.bits_per_sample) {
+            return Err(anyhow!(
+                "Unsupported WAV bit depth {}, expected one of {:?}",
+                audio.bits_per_sample, SUPPORTED_BITS_PER_SAMPLE
+            ));
+        }
+        if !(8_000..=192_000).contains(&audio.sample_rate) {
+            return Err(anyhow!("Sample rate {} Hz is outside 8000-192000 Hz", audio.sample_rate));
+        }
+        if !(1..=2).contains(&audio.channels) {
+            return Err(anyhow!("Recordings can have 1 or 2 channels, not {}", audio.channels));
+        }
+        if audio.max_recording_duration_minutes == 0 {
+            return Err(anyhow!("Maximum recording duration must be at least a minute"));
+        }
+        if audio.segment_minutes == Some(0) {
+            return Err(anyhow!("Segments must be at least a minute long"));
+        }
+        let threshold_db = audio.silence_split_threshold_db;
+        if audio.silence_split_seconds == 0 || threshold_db.is_nan() || threshold_db >= 0.0 {
+            return Err(anyhow!("Silence splits need a pause of at least a second below a negative dBFS level"));
+        }
+        if !(6_000..=510_000).contains(&audio.opus_bitrate) {
+            return Err(anyhow!("Opus bitrate {} is outside 6000-510000 bits per second", audio.opus_bitrate));
+        }
+        #[cfg(not(feature = "opus"))]
+        if audio.format == AudioFormat::Opus {
+            return Err(anyhow!("Opus recordings aren't available in this build"));
+        }
+        Ok(())
+    }
+    
+    /// This configuration with `patch` applied, or an error naming the first
+    /// setting that isn't valid. Directories aren't touched here.
+    pub fn patched(&self, patch: &ConfigPatch) -> Result<Self> {
+        let mut config = self.clone();
+        let audio = &mut config.audio;
+        if let Some(recordings_dir) = &patch.recordings_dir {
+            if recordings_dir.as_os_str().is_empty() {
+                return Err(anyhow!("Recordings directory can't be empty"));
+            }
+            audio.recordings_dir = recordings_dir.clone();
+        }
+        if let Some(minutes) = patch.max_recording_duration_minutes {
+            audio.max_recording_duration_minutes = minutes;
+        }
+        if let Some(sample_rate) = patch.sample_rate {
+            audio.sample_rate = sample_rate;
+        }
+        if let Some(bits_per_sample) = patch.bits_per_sample {
+            audio.bits_per_sample = bits_per_sample;
+        }
+        if let Some(channels) = patch.channels {
+            audio.channels = channels;
+        }
+        if let Some(minutes) = patch.segment_minutes {
+            audio.segment_minutes = (minutes > 0).then_some(minutes);
+        }
+        if let Some(split_on_silence) = patch.split_on_silence {
+            audio.split_on_silence = split_on_silence;
+        }
+        if let Some(threshold_db) = patch.silence_split_threshold_db {
+            audio.silence_split_threshold_db = threshold_db;
+        }
+        if let Some(seconds) = patch.silence_split_seconds {
+            audio.silence_split_seconds = seconds;
+        }
+        if let Some(format) = patch.format {
+            audio.format = format;
+        }
+        if let Some(bitrate) = patch.opus_bitrate {
+            audio.opus_bitrate = bitrate;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+    
     /// Auto-detect Datomic installation path
-    fn detect_datomic_installation() -> Option<PathBuf> {
+    pub fn detect_datomic_installation() -> Option<PathBuf> {
         // Helper closure to check a potential root path
         let check_path = |path: PathBuf| -> Option<PathBuf> {
             if path.exists() {
@@ -181,12 +479,15 @@ impl AppConfig {
     // into database_peer_complete.rs to fix classpath resolution issues.
 
     /// Save current configuration to file
-    #[allow(dead_code)] // Acknowledging this method is currently unused
     pub fn save(&self) -> Result<()> {
+        self.save_to(Path::new(CONFIG_FILE))
+    }
+    
+    fn save_to(&self, path: &Path) -> Result<()> {
         let config_content = toml::to_string_pretty(self)
             .map_err(|e| anyhow!("Failed to serialize config: {}", e))?;
         
-        std::fs::write("gita-config.toml", config_content)
+        std::fs::write(path, config_content)
             .map_err(|e| anyhow!("Failed to write config file: {}", e))?;
         
         Ok(())
@@ -214,4 +515,169 @@ mod tests {
         assert_eq!(config.datomic.transactor_port, deserialized.datomic.transactor_port);
         assert_eq!(config.audio.sample_rate, deserialized.audio.sample_rate);
     }
+    
+    #[test]
+    fn test_build_datomic_uri() {
+        let config = DatomicConfig {
+            transactor_host: "db.internal".to_string(),
+            transactor_port: 4334,
+            database_name: "notes".to_string(),
+            ..DatomicConfig::default()
+        };
+        assert_eq!(config.build_uri(), "datomic:dev://db.internal:4334/notes");
+        
+        let explicit = DatomicConfig {
+            db_uri: "datomic:sql://gita?jdbc:postgresql://localhost/datomic".to_string(),
+            ..config
+        };
+        assert_eq!(explicit.build_uri(), "datomic:sql://gita?jdbc:postgresql://localhost/datomic");
+        
+        assert_eq!(DatomicConfig::default().build_uri(), "datomic:dev://localhost:8998/gita");
+    }
+    
+    #[test]
+    fn test_backend_defaults_to_datomic() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("gita-config.toml");
+        AppConfig::default().save_to(&path).unwrap();
+        assert_eq!(AppConfig::load_file(&path).unwrap().backend, StorageBackend::Datomic);
+
+        // Files written before the backend was configurable have no backend key
+        let saved = std::fs::read_to_string(&path).unwrap().replace("backend = \"datomic\"\n", "");
+        std::fs::write(&path, &saved).unwrap();
+        assert_eq!(AppConfig::load_file(&path).unwrap().backend, StorageBackend::Datomic);
+
+        std::fs::write(&path, format!("backend = \"sqlite\"\n{}", saved)).unwrap();
+        assert_eq!(AppConfig::load_file(&path).unwrap().backend, StorageBackend::Sqlite);
+    }
+    
+    #[test]
+    fn test_retry_config_from_datomic_config() {
+        let config = DatomicConfig {
+            retry_attempts: 5,
+            initial_delay_ms: 250,
+            max_delay_ms: 10_000,
+            ..DatomicConfig::default()
+        };
+        let retry = config.retry_config();
+        assert_eq!(retry.max_attempts, 5);
+        assert_eq!(retry.initial_delay_ms, 250);
+        assert_eq!(retry.max_delay_ms, 10_000);
+        
+        // Files written before the delays were configurable keep the old behaviour
+        let old: DatomicConfig = toml::from_str(
+            "transactor_host = \"localhost\"\ntransactor_port = 8998\ndatabase_name = \"gita\"\n\
+             jvm_opts = []\nconnection_timeout_ms = 30000\nretry_attempts = 3"
+        ).unwrap();
+        assert_eq!(old.retry_config().initial_delay_ms, RetryConfig::default().initial_delay_ms);
+        assert_eq!(old.retry_config().max_delay_ms, RetryConfig::default().max_delay_ms);
+        
+        let mut app_config = AppConfig::default();
+        app_config.datomic.retry_attempts = 0;
+        assert!(app_config.validate().is_err());
+        app_config.datomic = DatomicConfig { initial_delay_ms: 6_000, max_delay_ms: 5_000, ..DatomicConfig::default() };
+        assert!(app_config.validate().is_err());
+        app_config.datomic = DatomicConfig { connection_timeout_ms: 0, ..DatomicConfig::default() };
+        assert!(app_config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_active_input_device_persists() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("gita-config.toml");
+        
+        let config = AppConfig {
+            audio: AudioConfig {
+                input_device: Some("USB Microphone".to_string()),
+                ..AudioConfig::default()
+            },
+            ..AppConfig::default()
+        };
+        config.save_to(&path).unwrap();
+        
+        let reloaded = AppConfig::load_file(&path).unwrap();
+        assert_eq!(reloaded.audio.input_device.as_deref(), Some("USB Microphone"));
+    }
+    
+    #[test]
+    fn test_unsupported_bit_depth_is_rejected() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("gita-config.toml");
+        let save_with_bits = |bits_per_sample| {
+            let config = AppConfig {
+                audio: AudioConfig { bits_per_sample, ..AudioConfig::default() },
+                ..AppConfig::default()
+            };
+            config.save_to(&path).unwrap();
+        };
+        
+        save_with_bits(16);
+        assert_eq!(AppConfig::load_file(&path).unwrap().audio.bits_per_sample, 16);
+        
+        save_with_bits(8);
+        let error = AppConfig::load_file(&path).unwrap_err();
+        assert!(error.to_string().contains("Unsupported WAV bit depth 8"));
+    }
+    
+    #[test]
+    fn test_patch_changes_only_given_settings() {
+        let config = AppConfig::default();
+        let patch = ConfigPatch {
+            sample_rate: Some(48_000),
+            bits_per_sample: Some(16),
+            segment_minutes: Some(10),
+            ..ConfigPatch::default()
+        };
+        
+        let patched = config.patched(&patch).unwrap();
+        assert_eq!(patched.audio.sample_rate, 48_000);
+        assert_eq!(patched.audio.bits_per_sample, 16);
+        assert_eq!(patched.audio.segment_minutes, Some(10));
+        assert_eq!(patched.audio.channels, config.audio.channels);
+        assert_eq!(patched.audio.recordings_dir, config.audio.recordings_dir);
+        
+        // 0 goes back to a single file
+        let unsplit = patched.patched(&ConfigPatch { segment_minutes: Some(0), ..ConfigPatch::default() }).unwrap();
+        assert_eq!(unsplit.audio.segment_minutes, None);
+        
+        // Fields come from JSON by their names, and unknown ones are refused
+        let patch: ConfigPatch = serde_json::from_str(r#"{"channels": 1}"#).unwrap();
+        assert_eq!(patch, ConfigPatch { channels: Some(1), ..ConfigPatch::default() });
+        assert!(serde_json::from_str::<ConfigPatch>(r#"{"chanels": 1}"#).is_err());
+    }
+    
+    #[test]
+    fn test_patch_rejects_invalid_values() {
+        let config = AppConfig::default();
+        let invalid = [
+            ConfigPatch { sample_rate: Some(0), ..ConfigPatch::default() },
+            ConfigPatch { bits_per_sample: Some(12), ..ConfigPatch::default() },
+            ConfigPatch { channels: Some(6), ..ConfigPatch::default() },
+            ConfigPatch { max_recording_duration_minutes: Some(0), ..ConfigPatch::default() },
+            ConfigPatch { silence_split_threshold_db: Some(3.0), ..ConfigPatch::default() },
+            ConfigPatch { opus_bitrate: Some(100), ..ConfigPatch::default() },
+            ConfigPatch { recordings_dir: Some(PathBuf::new()), ..ConfigPatch::default() },
+        ];
+        for patch in invalid {
+            assert!(config.patched(&patch).is_err(), "{:?} was accepted", patch);
+        }
+    }
+    
+    #[test]
+    fn test_recording_hotkey_persists() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("gita-config.toml");
+        
+        // Config files written before the shortcut existed get the default
+        let mut config = AppConfig::default();
+        let mut table: toml::Table = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        table["audio"].as_table_mut().unwrap().remove("recording_hotkey");
+        std::fs::write(&path, toml::to_string(&table).unwrap()).unwrap();
+        assert_eq!(AppConfig::load_file(&path).unwrap().audio.recording_hotkey, "CmdOrCtrl+Shift+R");
+        
+        // An empty shortcut stays disabled rather than reverting to the default
+        config.audio.recording_hotkey = String::new();
+        config.save_to(&path).unwrap();
+        assert_eq!(AppConfig::load_file(&path).unwrap().audio.recording_hotkey, "");
+    }
 }
@@ -7,9 +7,7 @@ use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use crate::models::*;
 use crate::datomic_schema::gita_schema_edn;
-
-// Datomic connection URI
-const DATOMIC_URI: &str = "datomic:dev://localhost:8998/gita";
+use crate::config::DatomicConfig;
 
 /// A simplified Datomic Peer API client that uses local evaluation
 /// This implementation provides the same interface as the HTTP client
@@ -29,9 +27,9 @@ struct DatomicConnection {
 
 impl DatomicPeerClient {
     /// Create a new Datomic Peer client
-    pub async fn new() -> Result<Self> {
+    pub async fn new(config: &DatomicConfig) -> Result<Self> {
         let client = DatomicPeerClient {
-            db_uri: DATOMIC_URI.to_string(),
+            db_uri: config.build_uri(),
             connection: Arc::new(Mutex::new(None)),
         };
 
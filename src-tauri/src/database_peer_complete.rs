@@ -1,53 +1,149 @@
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use once_cell::sync::OnceCell; // Added for safer static JVM initialization
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use anyhow::anyhow; // Moved here - Required for the inlined classpath logic
-// Removed Duration, Instant from std::time
+use std::time::Duration;
 use uuid::Uuid;
-use chrono::Utc; // Removed DateTime
+use chrono::{DateTime, FixedOffset, Utc};
 use serde_json::{json, Value};
-// Removed tokio::time::timeout
+use tokio::time::timeout;
 use tracing::{info, warn, error, debug, instrument};
 
 use crate::models::*;
-use crate::datomic_schema::gita_schema_edn;
+use crate::datomic_schema::{gita_schema_edn, diff_schema, SCHEMA_VERSION};
 use crate::config::{AppConfig, DatomicConfig};
+use crate::datomic_install;
+use crate::edn;
 use crate::errors::{DatomicError, Result, RetryConfig, with_retry};
 
 use jni::{JNIEnv, JavaVM, InitArgsBuilder, JNIVersion};
+use jni::errors::Error as JniError;
 // JList, JMap confirmed unused. jlong confirmed unused.
 // JClass, JObject, JValue, JStaticMethodID are used.
-use jni::objects::{JClass, JObject, JValue, JStaticMethodID};
+use jni::objects::{GlobalRef, JClass, JObject, JValue, JStaticMethodID};
 // Removed jni::sys::{jvalue} import, as it's used via jni::sys::jvalue directly
 
 // Global JVM instance using OnceCell for thread-safe initialization
 static JVM: OnceCell<Arc<JavaVM>> = OnceCell::new();
 
+// Blocks whose links are fixed per transaction when reindexing
+const REINDEX_BATCH_SIZE: usize = 500;
+
+// A block's ID with the links `(missing, stale)` to add to and retract from it
+type LinkRepair = (String, Vec<String>, Vec<String>);
+
+type ChangeListener = Arc<dyn Fn(DataChange) + Send + Sync>;
+
+// How much longer than the transactor's own deadline a transaction thread is
+// waited for, so `Future.get` gives up first and frees the thread
+const TRANSACT_TIMEOUT_GRACE: Duration = Duration::from_secs(2);
+
+// Every block attribute, with the parent pulled as its ID
+const BLOCK_PULL_PATTERN: &str = "[:block/id :block/content :block/is_page :block/page_title {:block/parent [:block/id]} :block/order :block/created_at :block/updated_at :block/source]";
+
+// `(below ?ancestor ?e)` holds for every block `?e` under `?ancestor`, at any depth
+const DESCENDANT_RULES: &str = "[[(below ?ancestor ?e) [?e :block/parent ?ancestor]]
+                                 [(below ?ancestor ?e) [?e :block/parent ?p] (below ?ancestor ?p)]]";
+
+// Storage garbage younger than this is kept so peers reading older db values aren't affected
+const GC_STORAGE_RETENTION_DAYS: i64 = 7;
+
+/// Batches of a block's children in block order, each fetched after the
+/// `(order, id)` of the last block handed out
+pub struct ChildBatches<'a> {
+    db: &'a DatomicPeerClient,
+    parent_id: String,
+    batch_size: usize,
+    after: Option<(i32, String)>,
+    done: bool,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl ChildBatches<'_> {
+    /// Stop with a `Cancelled` error at the next batch once `cancel` is set
+    pub fn cancel_on(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// The next batch, or `None` once every child has been returned. Fails
+    /// if cancelled before every batch was read, so a cancelled read never
+    /// looks complete.
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<Block>>> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::SeqCst)) {
+            return Err(DatomicError::cancelled(format!("Reading the children of {}", self.parent_id)));
+        }
+        let batch = self.db.get_children_after(&self.parent_id, self.after.as_ref(), self.batch_size).await?;
+        self.done = batch.len() < self.batch_size;
+        match batch.last() {
+            Some(last) => {
+                self.after = Some((last.order, last.id.clone()));
+                Ok(Some(batch))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The peer's `datomic.Connection`, opened on first use and shared by every
+/// operation so they read from the same live index. It's held as a
+/// `GlobalRef`, which unlike a local `JObject` can cross threads.
+#[derive(Default)]
+struct SharedConnection {
+    conn: Mutex<Option<GlobalRef>>,
+    opened: AtomicUsize,
+    shut_down: AtomicBool,
+}
+
+impl SharedConnection {
+    /// The cached connection, connecting first if there is none. Fails once
+    /// the client was shut down rather than reconnecting to a stopped peer.
+    fn get(&self, env: &mut JNIEnv, db_uri: &str) -> Result<GlobalRef> {
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.shut_down.load(Ordering::SeqCst) {
+            return Err(DatomicError::connection_error("The Datomic client was shut down"));
+        }
+        if let Some(conn) = conn.as_ref() {
+            return Ok(conn.clone());
+        }
+        debug!("Connecting to {}", db_uri);
+        let uri = env.new_string(db_uri)?;
+        let local = env.call_static_method("datomic/Peer", "connect", "(Ljava/lang/String;)Ldatomic/Connection;", &[JValue::Object(&uri)])
+            .map_err(|e| DatomicPeerClient::java_error(env, e, DatomicError::ConnectionError))?
+            .l()?;
+        let global = env.new_global_ref(local)?;
+        self.opened.fetch_add(1, Ordering::SeqCst);
+        Ok(conn.insert(global).clone())
+    }
+
+    /// Drop the cached connection so the next operation reconnects
+    fn reset(&self) {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+}
+
+/// The database value a query runs against
+#[derive(Clone)]
+enum DbView {
+    Current,
+    AsOf(TxOrInstant),
+    History,
+}
+
 /// Production-ready Datomic Peer API client
 pub struct DatomicPeerClient {
     jvm: Arc<JavaVM>,
     config: DatomicConfig,
     retry_config: RetryConfig,
-    // connection_pool: Arc<Mutex<ConnectionPool>>, // Temporarily removed for Send/Sync diagnosis
+    change_listener: Mutex<Option<ChangeListener>>,
+    connection: Arc<SharedConnection>,
 }
 
-// #[derive(Debug)]
-// struct ConnectionPool {
-//     connections: Vec<DatomicConnection>,
-//     available: Vec<usize>,
-//     next_id: usize,
-// }
-
-// #[derive(Debug, Clone)]
-// struct DatomicConnection {
-//     id: usize,
-//     uri: String,
-//     // connection_obj: Option<jobject>, // Removed: jobject is !Send + !Sync
-//     created_at: Instant,
-//     last_used: Instant,
-//     in_use: bool,
-// }
-
 impl DatomicPeerClient {
     /// Create a new production-ready Datomic Peer client
     #[instrument(name = "datomic_peer_client_new")]
@@ -60,12 +156,9 @@ impl DatomicPeerClient {
         let client = DatomicPeerClient {
             jvm,
             config: app_config.datomic.clone(), // Corrected variable name
-            retry_config: RetryConfig::default(),
-            // connection_pool: Arc::new(Mutex::new(ConnectionPool {
-            //     connections: Vec::new(),
-            //     available: Vec::new(),
-            //     next_id: 0,
-            // })), // Temporarily removed
+            retry_config: app_config.datomic.retry_config(),
+            change_listener: Mutex::new(None),
+            connection: Arc::new(SharedConnection::default()),
         };
 
         // Initialize database and schema
@@ -75,6 +168,92 @@ impl DatomicPeerClient {
         Ok(client)
     }
 
+    /// Call `listener` after every write that changes blocks or pages,
+    /// whichever window, command or import made it. Replaces any earlier
+    /// listener.
+    pub fn on_change(&self, listener: impl Fn(DataChange) + Send + Sync + 'static) {
+        *self.change_listener.lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(listener));
+    }
+
+    fn changed(&self, change: DataChange) {
+        if change.ids.is_empty() {
+            return;
+        }
+        let listener = self.change_listener.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(listener) = listener {
+            listener(change);
+        }
+    }
+
+    /// How many times the client has connected to the database, which is
+    /// once unless the connection was lost and reopened
+    pub fn connections_opened(&self) -> usize {
+        self.connection.opened.load(Ordering::SeqCst)
+    }
+
+    /// Attach the calling thread to the JVM as a daemon, or reuse its
+    /// attachment. Operations run on the async runtime's worker and blocking
+    /// threads, which are reused, so they stay attached instead of paying
+    /// for an attach and detach every call. Daemon threads don't hold up JVM
+    /// exit and detach themselves when the thread ends.
+    fn attach(jvm: &JavaVM) -> Result<JNIEnv<'_>> {
+        match jvm.get_env() {
+            Ok(env) => Ok(env),
+            Err(JniError::JniCall(jni::errors::JniError::ThreadDetached)) => {
+                let env = jvm.attach_current_thread_as_daemon()?;
+                debug!(
+                    "Attached thread {:?} to the JVM, {} threads attached",
+                    std::thread::current().name().unwrap_or("unnamed"),
+                    jvm.threads_attached()
+                );
+                Ok(env)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Longest a single attempt at an operation may take, from `connection_timeout_ms`
+    fn operation_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.connection_timeout_ms)
+    }
+
+    /// Run the blocking JNI work `operation` with `with_retry`, each attempt
+    /// on a blocking thread and limited to the configured timeout
+    async fn retried<T: Send + 'static>(
+        &self,
+        operation_name: &str,
+        operation: impl Fn() -> Result<T> + Send + Sync + 'static,
+    ) -> Result<T> {
+        let operation = Arc::new(operation);
+        let limit = self.operation_timeout();
+        with_retry(
+            || {
+                let operation = operation.clone();
+                run_blocking(limit, move || operation())
+            },
+            &self.retry_config,
+            operation_name,
+        )
+        .await
+    }
+
+    /// Run `f` with the shared connection. A connection-level failure drops
+    /// the cached connection, so a retry or the next operation reconnects.
+    fn with_connection<T>(
+        env: &mut JNIEnv,
+        shared: &SharedConnection,
+        db_uri: &str,
+        f: impl FnOnce(&mut JNIEnv, &JObject) -> Result<T>,
+    ) -> Result<T> {
+        let conn = shared.get(env, db_uri)?;
+        let result = f(env, conn.as_obj());
+        if let Err(DatomicError::ConnectionError(message)) = &result {
+            warn!("Dropping the Datomic connection after: {}", message);
+            shared.reset();
+        }
+        result
+    }
+
     // Removed 'use anyhow::anyhow;' from here, as it's moved to the top
 
     /// Get or create the JVM instance with proper configuration
@@ -82,54 +261,10 @@ impl DatomicPeerClient {
         JVM.get_or_try_init(|| {
             info!("Initializing JVM for Datomic Peer API");
 
-            // Inline the logic from AppConfig::get_datomic_classpath
-            let classpath_result: anyhow::Result<String> = (|| {
-                // Resolve the installation root and lib directory, even if user points to 'lib'
-                let configured = datomic_config.datomic_lib_path.as_ref()
-                    .ok_or_else(|| anyhow!("Datomic lib path not configured in DatomicConfig."))?;
-                if !configured.exists() {
-                    return Err(anyhow!("Configured Datomic path does not exist: {}", configured.display()));
-                }
-                // Determine install root: parent of 'lib' if pointed at lib, otherwise the path itself
-                let install_root = if configured.file_name().and_then(|s| s.to_str()) == Some("lib") {
-                    configured.parent().unwrap_or(configured).to_path_buf()
-                } else {
-                    configured.clone()
-                };
-                if !install_root.exists() {
-                    return Err(anyhow!("Datomic install root does not exist: {}", install_root.display()));
-                }
-                let mut classpath_entries = Vec::new();
-                // Scan install root for main JARs
-                debug!("Scanning install root for JARs: {}", install_root.display());
-                for entry in std::fs::read_dir(&install_root)
-                    .map_err(|e| anyhow!("Failed to read install root directory: {}", e))? {
-                    let entry = entry.map_err(|e| anyhow!("Error reading directory entry: {}", e))?;
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("jar") {
-                        debug!("Adding main JAR: {}", path.display());
-                        classpath_entries.push(path.to_string_lossy().to_string());
-                    }
-                }
-                // Scan lib subdirectory for dependencies
-                let lib_dir = install_root.join("lib");
-                if lib_dir.exists() {
-                    debug!("Scanning dependencies in lib: {}", lib_dir.display());
-                    for entry in std::fs::read_dir(&lib_dir)
-                        .map_err(|e| anyhow!("Failed to read lib directory: {}", e))? {
-                        let entry = entry.map_err(|e| anyhow!("Error reading directory entry: {}", e))?;
-                        let path = entry.path();
-                        if path.extension().and_then(|s| s.to_str()) == Some("jar") {
-                            debug!("Adding dependency JAR: {}", path.display());
-                            classpath_entries.push(path.to_string_lossy().to_string());
-                        }
-                    }
-                }
-                if classpath_entries.is_empty() {
-                    return Err(anyhow!("No JAR files found in Datomic installation: {}", install_root.display()));
-                }
-                Ok(classpath_entries.join(if cfg!(windows) { ";" } else { ":" }))
-            })();
+            let classpath_result = datomic_config.datomic_lib_path.as_ref()
+                .ok_or_else(|| anyhow!("Datomic lib path not configured in DatomicConfig."))
+                .and_then(|configured| datomic_install::classpath_jars(configured))
+                .map(|jars| datomic_install::join_classpath(&jars));
 
             let classpath = match classpath_result {
                 Ok(cp) => cp,
@@ -190,12 +325,11 @@ impl DatomicPeerClient {
     /// Create database if it doesn't exist
     #[instrument(skip(self))]
     async fn create_database(&self) -> Result<()> {
-        let db_uri = self.config.db_uri.clone();
+        let db_uri = self.config.build_uri();
         let jvm = self.jvm.clone();
         
         let operation = move || -> Result<bool> {
-            let mut env = jvm.attach_current_thread() // Made env mutable
-                .map_err(DatomicError::from)?;
+            let mut env = Self::attach(&jvm)?;
             
             // Get Peer class
             let peer_class = env.find_class("datomic/Peer")
@@ -232,7 +366,7 @@ impl DatomicPeerClient {
             }
         };
         
-        let created = with_retry(operation, &self.retry_config, "create_database").await?;
+        let created = self.retried("create_database", operation).await?;
         
         if created {
             info!("Database created: {}", self.config.database_name);
@@ -243,25 +377,58 @@ impl DatomicPeerClient {
         Ok(())
     }
 
-    /// Ensure schema is present in the database
+    /// Ensure schema is present in the database and up to date
     #[instrument(skip(self))]
     async fn ensure_schema(&self) -> Result<()> {
         info!("Ensuring schema is present");
         
         // Check if schema exists by querying for a schema attribute
         let schema_exists = self.check_schema_exists().await?;
+        let version = if schema_exists { self.schema_version().await? } else { 0 };
         
-        if !schema_exists {
-            info!("Schema not found, transacting schema");
+        if version < SCHEMA_VERSION {
+            info!("Schema at version {}, transacting version {}", version, SCHEMA_VERSION);
+            // Re-asserting attributes that already exist is a no-op, so only new ones are added
             self.transact_schema().await?;
+            self.record_schema_version().await?;
             info!("Schema transacted successfully");
         } else {
-            info!("Schema already exists");
+            info!("Schema already exists at version {}", version);
         }
         
         Ok(())
     }
 
+    /// Latest schema version transacted into the database, or 0 if none was
+    /// recorded yet (a new database, or one created before versions were kept)
+    #[instrument(skip(self))]
+    pub async fn schema_version(&self) -> Result<i64> {
+        // Querying an attribute that isn't installed fails, so check for it first
+        let query = "[:find ?a :where [?a :db/ident :gita.schema/version]]";
+        if self.query(query, Vec::new()).await?.is_empty() {
+            return Ok(0);
+        }
+
+        let query = "[:find ?version
+                     :where [?e :db/ident :gita/schema]
+                            [?e :gita.schema/version ?version]]";
+        let results = self.query(query, Vec::new()).await?;
+        Ok(results.first()
+            .and_then(|row| row.get("version"))
+            .and_then(Value::as_i64)
+            .unwrap_or(0))
+    }
+
+    /// Store `SCHEMA_VERSION` once its attributes have been transacted
+    async fn record_schema_version(&self) -> Result<()> {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/ident".to_string(), Value::String(":gita/schema".to_string()));
+        tx_data.insert(":gita.schema/version".to_string(), Value::Number(SCHEMA_VERSION.into()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
     /// Check if schema exists
     #[instrument(skip(self))]
     async fn check_schema_exists(&self) -> Result<bool> {
@@ -273,273 +440,494 @@ impl DatomicPeerClient {
     /// Transact the schema
     #[instrument(skip(self))]
     async fn transact_schema(&self) -> Result<()> {
-        let schema_edn = gita_schema_edn();
+        let schema = gita_schema_edn();
+        let schema_edn = edn::tx_data_to_edn(schema.as_array().map(Vec::as_slice).unwrap_or_default());
         
-        let db_uri = self.config.db_uri.clone();
+        let db_uri = self.config.build_uri();
         let jvm = self.jvm.clone();
+        let connection = self.connection.clone();
+        let tx_timeout = self.operation_timeout();
         
+        // Installing the schema is idempotent, so unlike other transactions it's retried
         let operation = move || -> Result<()> {
-            let mut env = jvm.attach_current_thread() // Make env mutable
-                .map_err(DatomicError::from)?;
-            
-            // --- Inlined get_connection_jni ---
-            let peer_class_for_conn = env.find_class("datomic/Peer")?;
-            let connect_method = env.get_static_method_id(&peer_class_for_conn, "connect", "(Ljava/lang/String;)Ldatomic/Connection;")?;
-            let uri_string_for_conn = env.new_string(&db_uri)?;
-            let uri_jobject_for_conn: JObject = uri_string_for_conn.into();
-            let conn_args_raw = [jni::sys::jvalue { l: uri_jobject_for_conn.as_raw() }];
-            let conn_jvalue = unsafe {
-                env.call_static_method_unchecked::<JClass, JStaticMethodID>(peer_class_for_conn, connect_method, jni::signature::ReturnType::Object, &conn_args_raw)
-            }?;
-            let conn = conn_jvalue.l()?; // conn is JObject
-            // --- End Inlined get_connection_jni ---
-            
-            // Parse schema EDN
-            let schema_reader_class = env.find_class("java/io/StringReader") // Renamed for clarity
-                .map_err(DatomicError::from)?;
-            let schema_reader_init_mid = env.get_method_id( // Renamed for clarity
-                &schema_reader_class, // Pass JClass by reference
-                "<init>",
-                "(Ljava/lang/String;)V"
-            ).map_err(DatomicError::from)?;
-            
-            let schema_json_string = schema_edn.to_string();
-            let schema_string = env.new_string(&schema_json_string)
-                .map_err(DatomicError::from)?;
-            let schema_jobject: JObject = schema_string.into();
-            // Error E0308: Pass JObject by reference for JValue::Object
-            // This will be passed to new_object which takes &[JValue]
-            let method_args_jvalue_slice = [JValue::Object(&schema_jobject)];
-            // Convert JValue slice to raw jni::sys::jvalue slice for _unchecked call
-            let method_args_raw_for_new_object: Vec<jni::sys::jvalue> = method_args_jvalue_slice
-                .iter()
-                .map(|v| v.as_jni())
-                .collect();
-
-            // Use new_object_unchecked as we have the JMethodID.
-            // Pass the original schema_reader_class (it will be consumed here).
-            let reader_obj = unsafe {
-                env.new_object_unchecked(
-                    schema_reader_class,
-                    schema_reader_init_mid,
-                    &method_args_raw_for_new_object
-                )
-            }.map_err(DatomicError::from)?;
-            
-            // Parse using EDN reader
-            let util_class_orig = env.find_class("datomic/Util") // Renamed to avoid confusion
-                .map_err(DatomicError::from)?;
-            let read_all_method = env.get_static_method_id(
-                &util_class_orig, // Pass JClass by reference
-                "readAll",
-                "(Ljava/io/Reader;)Ljava/util/List;"
-            ).map_err(DatomicError::from)?;
-            
-            // Error E0308: Pass JObject by reference and convert to raw jvalue
-            let method_args_read_all_raw = [jni::sys::jvalue { l: reader_obj.as_raw() }];
-            let tx_data_jvalue = unsafe {
-                env.call_static_method_unchecked::<JClass, JStaticMethodID>(
-                    util_class_orig, // Pass original JClass (consumed)
-                    read_all_method,
-                    jni::signature::ReturnType::Object,
-                    &method_args_read_all_raw
-                )
-            }.map_err(DatomicError::from)?;
-            
-            // Transact
-            let connection_class = env.get_object_class(&conn) // Borrow conn
-                .map_err(DatomicError::from)?;
-            let transact_method = env.get_method_id(
-                &connection_class, // Pass JClass by reference
-                "transact",
-                "(Ljava/util/List;)Ljava/util/concurrent/Future;"
-            ).map_err(DatomicError::from)?;
-            
-            let tx_data_obj = tx_data_jvalue.l()?; // This is JObject
-            // Error E0308: Pass JObject by reference and convert to raw jvalue
-            let method_args_transact_raw = [jni::sys::jvalue { l: tx_data_obj.as_raw() }];
-            let future_jvalue = unsafe {
-                env.call_method_unchecked(
-                    &conn, // Borrow conn
-                    transact_method,
-                    jni::signature::ReturnType::Object,
-                    &method_args_transact_raw
-                )
-            }.map_err(DatomicError::from)?;
-            
-            // Wait for result
-            let future_obj = future_jvalue.l()?; // Corrected to use future_jvalue
-            let future_class = env.get_object_class(&future_obj) // Borrow future_obj
-                .map_err(DatomicError::from)?;
-            let get_method = env.get_method_id(
-                &future_class, // Pass JClass by reference
-                "get",
-                "()Ljava/lang/Object;"
-            ).map_err(DatomicError::from)?;
-            
-            let _result_jvalue = unsafe {
-                env.call_method_unchecked(
-                    &future_obj, // Borrow future_obj
-                    get_method,
-                    jni::signature::ReturnType::Object,
-                    &[]
-                )
-            }.map_err(DatomicError::from)?;
-            // _result_jvalue is not used, but the call and error handling are preserved.
-            
+            let mut env = Self::attach(&jvm)?;
+            Self::with_connection(&mut env, &connection, &db_uri, |env, conn| Self::transact_edn(env, conn, &schema_edn, tx_timeout))?;
             Ok(())
         };
         
-        with_retry(operation, &self.retry_config, "transact_schema").await?;
+        self.retried("transact_schema", operation).await?;
         Ok(())
     }
 
+    /// Parse `tx_edn` with `datomic.Util/readAll`, transact it on `conn` and
+    /// wait up to `tx_timeout` for the transactor, returning the report's
+    /// resolved tempids and transaction ID. A transaction that timed out may
+    /// still be applied later.
+    fn transact_edn(env: &mut JNIEnv, conn: &JObject, tx_edn: &str, tx_timeout: Duration) -> Result<Value> {
+        let tx_string = env.new_string(tx_edn)?;
+        let reader = env.new_object("java/io/StringReader", "(Ljava/lang/String;)V", &[JValue::Object(&tx_string)])?;
+        let tx_data = env.call_static_method("datomic/Util", "readAll", "(Ljava/io/Reader;)Ljava/util/List;", &[JValue::Object(&reader)])
+            .map_err(|e| Self::java_error(env, e, DatomicError::EdnParsingError))?
+            .l()?;
+
+        let future = env.call_method(conn, "transactAsync", "(Ljava/util/List;)Ldatomic/ListenableFuture;", &[JValue::Object(&tx_data)])
+            .map_err(|e| Self::java_error(env, e, DatomicError::TransactionError))?
+            .l()?;
+        let timeout_ms = tx_timeout.as_millis() as u64;
+        let unit = env.get_static_field("java/util/concurrent/TimeUnit", "MILLISECONDS", "Ljava/util/concurrent/TimeUnit;")?.l()?;
+        let report = env.call_method(&future, "get", "(JLjava/util/concurrent/TimeUnit;)Ljava/lang/Object;", &[JValue::Long(timeout_ms as i64), JValue::Object(&unit)]);
+        if let Err(JniError::JavaException) = report {
+            // Rethrown for `java_error` unless it's the wait running out
+            let throwable = env.exception_occurred()?;
+            env.exception_clear()?;
+            if env.is_instance_of(&throwable, "java/util/concurrent/TimeoutException")? {
+                return Err(DatomicError::timeout_error(timeout_ms));
+            }
+            env.throw(throwable)?;
+        }
+        let report = report
+            .map_err(|e| Self::java_error(env, e, DatomicError::TransactionError))?
+            .l()?;
+
+        let tempids_key = env.get_static_field("datomic/Connection", "TEMPIDS", "Ljava/lang/Object;")?.l()?;
+        let tempids = env.call_method(&report, "get", "(Ljava/lang/Object;)Ljava/lang/Object;", &[JValue::Object(&tempids_key)])?.l()?;
+        let db_after_key = env.get_static_field("datomic/Connection", "DB_AFTER", "Ljava/lang/Object;")?.l()?;
+        let db_after = env.call_method(&report, "get", "(Ljava/lang/Object;)Ljava/lang/Object;", &[JValue::Object(&db_after_key)])?.l()?;
+        let basis_t = env.call_method(&db_after, "basisT", "()J", &[])?.j()?;
+        let tx_id = env.call_static_method("datomic/Peer", "toTx", "(J)Ljava/lang/Object;", &[JValue::Long(basis_t)])?.l()?;
+
+        Ok(json!({
+            "tempids": Self::java_to_json(env, &tempids)?,
+            "tx-id": Self::java_to_json(env, &tx_id)?,
+            "basis-t": basis_t,
+        }))
+    }
+
+    /// Turn a JNI call that threw into `make` with the exception's message,
+    /// clearing the exception. The `ExecutionException` a Future throws is
+    /// unwrapped to its cause, whose message starts with Datomic's error
+    /// keyword, e.g. `:db.error/cas-failed`. An `IllegalStateException`
+    /// means the connection was released or shut down, and becomes a
+    /// `ConnectionError` whatever `make` is.
+    fn java_error(env: &mut JNIEnv, error: jni::errors::Error, make: fn(String) -> DatomicError) -> DatomicError {
+        if !matches!(error, jni::errors::Error::JavaException) {
+            return error.into();
+        }
+        let throwable: JObject = match env.exception_occurred() {
+            Ok(throwable) => throwable.into(),
+            Err(e) => return e.into(),
+        };
+        if let Err(e) = env.exception_clear() {
+            return e.into();
+        }
+        match Self::throwable_message(env, throwable) {
+            Ok((message, true)) => DatomicError::ConnectionError(message),
+            Ok((message, false)) => make(message),
+            Err(e) => e,
+        }
+    }
+
+    /// The message of `throwable`, or of its cause for an `ExecutionException`,
+    /// and whether that's an `IllegalStateException`
+    fn throwable_message<'local>(env: &mut JNIEnv<'local>, mut throwable: JObject<'local>) -> Result<(String, bool)> {
+        if env.is_instance_of(&throwable, "java/util/concurrent/ExecutionException")? {
+            let cause = env.call_method(&throwable, "getCause", "()Ljava/lang/Throwable;", &[])?.l()?;
+            if !cause.is_null() {
+                throwable = cause;
+            }
+        }
+        let illegal_state = env.is_instance_of(&throwable, "java/lang/IllegalStateException")?;
+        let mut message = env.call_method(&throwable, "getMessage", "()Ljava/lang/String;", &[])?.l()?;
+        if message.is_null() {
+            message = env.call_method(&throwable, "toString", "()Ljava/lang/String;", &[])?.l()?;
+        }
+        let message: String = env.get_string((&message).into())?.into();
+        Ok((message, illegal_state))
+    }
+
     // fn get_connection_jni ... (Removed as it's inlined)
     // fn get_database_jni ... (Removed as it's inlined)
 
-    /// Execute a query against the database
-    #[instrument(skip(self, _params))] // Changed params to _params
-    pub async fn query(&self, query: &str, _params: Vec<Value>) -> Result<Vec<HashMap<String, Value>>> { // Prefixed params
+    /// Execute a query against the database, binding `params` to the `:in`
+    /// clause after `$` in order
+    #[instrument(skip(self, params))]
+    pub async fn query(&self, query: &str, params: Vec<Value>) -> Result<Vec<HashMap<String, Value>>> {
+        self.query_view(query, None, params, DbView::Current).await
+    }
+
+    /// Execute a query using `rules`, an EDN rule set bound to `%` right
+    /// after `$`, with `params` bound to the rest of the `:in` clause
+    #[instrument(skip(self, rules, params))]
+    pub async fn query_with_rules(&self, query: &str, rules: &str, params: Vec<Value>) -> Result<Vec<HashMap<String, Value>>> {
+        self.query_view(query, Some(rules), params, DbView::Current).await
+    }
+
+    /// Execute a query against the database as it was at `t`, ignoring
+    /// everything transacted later
+    #[allow(dead_code)] // For reading past states; the app only reads history so far
+    #[instrument(skip(self, params))]
+    pub async fn query_as_of(&self, query: &str, params: Vec<Value>, t: TxOrInstant) -> Result<Vec<HashMap<String, Value>>> {
+        self.query_view(query, None, params, DbView::AsOf(t)).await
+    }
+
+    async fn query_view(&self, query: &str, rules: Option<&str>, params: Vec<Value>, view: DbView) -> Result<Vec<HashMap<String, Value>>> {
         debug!("Executing query: {}", query);
+        params.iter().try_for_each(Self::check_query_input)?;
         
         let query_str = query.to_string();
-        let db_uri = self.config.db_uri.clone();
+        let rules = rules.map(str::to_string);
+        let columns = Self::find_columns(query);
+        let db_uri = self.config.build_uri();
         let jvm = self.jvm.clone();
+        let connection = self.connection.clone();
         
         let operation = move || -> Result<Vec<HashMap<String, Value>>> {
-            let mut env = jvm.attach_current_thread().map_err(DatomicError::from)?;
-            
-            // --- Inlined get_connection_jni ---
-            let peer_class_for_conn = env.find_class("datomic/Peer")?;
-            let connect_method = env.get_static_method_id(&peer_class_for_conn, "connect", "(Ljava/lang/String;)Ldatomic/Connection;")?;
-            let uri_string_for_conn = env.new_string(&db_uri)?;
-            let uri_jobject_for_conn: JObject = uri_string_for_conn.into();
-            let conn_args_raw = [jni::sys::jvalue { l: uri_jobject_for_conn.as_raw() }];
-            let conn_jvalue = unsafe {
-                env.call_static_method_unchecked::<JClass, JStaticMethodID>(peer_class_for_conn, connect_method, jni::signature::ReturnType::Object, &conn_args_raw)
-            }?;
-            let conn_obj = conn_jvalue.l()?;
-            // --- End Inlined get_connection_jni ---
-
-            // --- Inlined get_database_jni ---
-            let connection_class_for_db = env.get_object_class(&conn_obj)?;
-            let db_method = env.get_method_id(connection_class_for_db, "db", "()Ldatomic/Database;")?;
-            let db_jvalue = unsafe {
-                env.call_method_unchecked(&conn_obj, db_method, jni::signature::ReturnType::Object, &[])
-            }?;
-            let db_obj = db_jvalue.l()?;
-            // --- End Inlined get_database_jni ---
-            
-            // Execute query (using db_obj)
-            let peer_class_for_query = env.find_class("datomic/Peer")?;
-            let query_method = env.get_static_method_id(
-                &peer_class_for_query, // Pass JClass by reference
-                "query",
-                "(Ljava/lang/String;Ldatomic/Database;)Ljava/util/Collection;"
-            )?;
-            
-            let query_jstring = env.new_string(&query_str)?;
-            let query_jobject: JObject = query_jstring.into();
-            
-            let method_args_raw = [
-                jni::sys::jvalue { l: query_jobject.as_raw() },
-                jni::sys::jvalue { l: db_obj.as_raw() }, // Use inlined db_obj
-            ];
-            let result_jvalue = unsafe {
-                env.call_static_method_unchecked::<JClass, JStaticMethodID>(
-                    peer_class_for_query, // Pass original JClass (consumed)
-                    query_method,
-                    jni::signature::ReturnType::Object,
-                    &method_args_raw
-                )
-            }?;
+            let mut env = Self::attach(&jvm)?;
             
-            Self::convert_query_result(&mut env, result_jvalue.l()?)
+            Self::with_connection(&mut env, &connection, &db_uri, |env, conn| {
+                let db_obj = env.call_method(conn, "db", "()Ldatomic/Database;", &[])
+                    .map_err(|e| Self::java_error(env, e, DatomicError::ConnectionError))?
+                    .l()?;
+                let db_obj = match &view {
+                    DbView::Current => db_obj,
+                    DbView::AsOf(t) => {
+                        let t = Self::time_point(env, t)?;
+                        env.call_method(&db_obj, "asOf", "(Ljava/lang/Object;)Ldatomic/Database;", &[JValue::Object(&t)])
+                            .map_err(|e| Self::java_error(env, e, DatomicError::QueryError))?
+                            .l()?
+                    }
+                    DbView::History => env.call_method(&db_obj, "history", "()Ldatomic/Database;", &[])?.l()?,
+                };
+
+                // Peer.q takes the database, the rules if there are any and
+                // then each :in parameter
+                let mut first_param = 1;
+                let inputs = env.new_object_array((params.len() + 1 + rules.iter().len()) as i32, "java/lang/Object", JObject::null())?;
+                env.set_object_array_element(&inputs, 0, &db_obj)?;
+                if let Some(rules) = &rules {
+                    let rules_jstring = env.new_string(rules)?;
+                    let rules_obj = env.call_static_method("datomic/Util", "read", "(Ljava/lang/String;)Ljava/lang/Object;", &[JValue::Object(&rules_jstring)])
+                        .map_err(|e| Self::java_error(env, e, DatomicError::QueryError))?
+                        .l()?;
+                    env.set_object_array_element(&inputs, 1, rules_obj)?;
+                    first_param = 2;
+                }
+                for (i, param) in params.iter().enumerate() {
+                    let input = Self::json_to_java(env, param)?;
+                    env.set_object_array_element(&inputs, first_param + i as i32, input)?;
+                }
+
+                let query_jstring = env.new_string(&query_str)?;
+                let result = env.call_static_method(
+                    "datomic/Peer",
+                    "q",
+                    "(Ljava/lang/Object;[Ljava/lang/Object;)Ljava/util/Collection;",
+                    &[JValue::Object(&query_jstring), JValue::Object(&inputs)],
+                ).map_err(|e| Self::java_error(env, e, DatomicError::QueryError))?;
+                
+                Self::convert_query_result(env, result.l()?, &columns)
+            })
         };
         
-        let results = with_retry(operation, &self.retry_config, "query").await?;
+        let results = self.retried("query", operation).await?;
         debug!("Query returned {} results", results.len());
         Ok(results)
     }
 
+    /// `t` as `Database.asOf` takes it: a `Long` for a transaction or a
+    /// `java.util.Date` for an instant. A Date only holds milliseconds, so
+    /// anything finer in the instant is dropped.
+    fn time_point<'local>(env: &mut JNIEnv<'local>, t: &TxOrInstant) -> Result<JObject<'local>> {
+        let point = match t {
+            TxOrInstant::Tx(tx) => env.call_static_method("java/lang/Long", "valueOf", "(J)Ljava/lang/Long;", &[JValue::Long(*tx)])?.l()?,
+            TxOrInstant::Instant(instant) => env.new_object("java/util/Date", "(J)V", &[JValue::Long(instant.timestamp_millis())])?,
+        };
+        Ok(point)
+    }
+
     // fn get_database_jni ... (Removed as it's inlined earlier, this is just deleting the definition)
+    /// Make sure a query parameter can be passed to `Peer.q`: a string,
+    /// number or boolean, or a vector of them for a collection binding or a
+    /// lookup ref
+    fn check_query_input(param: &Value) -> Result<()> {
+        match param {
+            Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(()),
+            Value::Array(items) => items.iter().try_for_each(Self::check_query_input),
+            Value::Null => Err(DatomicError::type_conversion_error("A query parameter can't be null")),
+            Value::Object(_) => Err(DatomicError::type_conversion_error("A query parameter can't be a map")),
+        }
+    }
 
-    /// Convert Java query result to Rust data structures
-    // Changed to take &mut JNIEnv for consistency if JNI calls are added later.
-    fn convert_query_result(_env: &mut JNIEnv, _result: JObject) -> Result<Vec<HashMap<String, Value>>> {
-        // This is a simplified conversion - in a real implementation,
-        // you'd need to handle the complex nested structure of Datomic results
-        let results = Vec::new(); // Removed mut
-        
-        // For now, return empty results
-        // TODO: Implement proper result conversion
-        debug!("Query result conversion not yet implemented");
-        
-        Ok(results)
+    /// Convert a query parameter. A vector becomes a `java.util.List`,
+    /// with a leading attribute like `[":block/id", id]` as a keyword so it
+    /// works as a lookup ref.
+    fn json_to_java<'local>(env: &mut JNIEnv<'local>, param: &Value) -> Result<JObject<'local>> {
+        let object = match param {
+            Value::String(s) => env.new_string(s)?.into(),
+            Value::Bool(b) => env.call_static_method("java/lang/Boolean", "valueOf", "(Z)Ljava/lang/Boolean;", &[JValue::Bool(*b as u8)])?.l()?,
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => env.call_static_method("java/lang/Long", "valueOf", "(J)Ljava/lang/Long;", &[JValue::Long(n)])?.l()?,
+                None => {
+                    let n = n.as_f64().unwrap_or(f64::NAN);
+                    env.call_static_method("java/lang/Double", "valueOf", "(D)Ljava/lang/Double;", &[JValue::Double(n)])?.l()?
+                }
+            },
+            Value::Array(items) => {
+                let list = env.new_object("java/util/ArrayList", "(I)V", &[JValue::Int(items.len() as i32)])?;
+                for (i, item) in items.iter().enumerate() {
+                    let element = match item.as_str().filter(|s| i == 0 && items.len() == 2 && edn::is_keyword(s)) {
+                        Some(attribute) => {
+                            let name = env.new_string(&attribute[1..])?;
+                            env.call_static_method("clojure/lang/Keyword", "intern", "(Ljava/lang/String;)Lclojure/lang/Keyword;", &[JValue::Object(&name)])?.l()?
+                        }
+                        None => Self::json_to_java(env, item)?,
+                    };
+                    env.call_method(&list, "add", "(Ljava/lang/Object;)Z", &[JValue::Object(&element)])?;
+                    env.delete_local_ref(element)?;
+                }
+                list
+            }
+            Value::Null | Value::Object(_) => {
+                return Err(DatomicError::type_conversion_error(format!("Unsupported query parameter: {}", param)));
+            }
+        };
+        Ok(object)
     }
 
-    /// Create a new block
+    /// Pull `pattern`, an EDN pull pattern like `[:block/id {:block/parent [:block/id]}]`,
+    /// for one entity given by its entity ID or a lookup ref like
+    /// `[":block/id", id]`. Returns `null` if there's no such entity.
     #[instrument(skip(self))]
-    pub async fn create_block(&self, block_data: CreateBlockRequest, audio_meta: Option<AudioMeta>) -> Result<Block> {
-        info!("Creating block with content: {:?}", block_data.content); // Use {:?} for Option
-        
-        let block_id = Uuid::new_v4().to_string();
-        let now = Utc::now();
-        
-        // Build transaction data
-        let mut tx_data = HashMap::new();
-        // tx_data.insert("db/id".to_string(), Value::String("datomic.tx".to_string())); // Removed: This entity map itself defines the new block
-        tx_data.insert(":block/id".to_string(), Value::String(block_id.clone()));
-        if let Some(content) = &block_data.content {
-            tx_data.insert(":block/content".to_string(), Value::String(content.clone()));
+    pub async fn pull(&self, entity_lookup: Value, pattern: &str) -> Result<Value> {
+        self.run_pull(vec![entity_lookup], pattern, false).await
+    }
+
+    /// Pull `pattern` for several entities in one call, in the order of
+    /// `entity_lookups`
+    #[instrument(skip(self, entity_lookups))]
+    pub async fn pull_many(&self, entity_lookups: Vec<Value>, pattern: &str) -> Result<Vec<Value>> {
+        if entity_lookups.is_empty() {
+            return Ok(Vec::new());
         }
-        tx_data.insert(":block/created_at".to_string(), Value::String(now.to_rfc3339()));
-        tx_data.insert(":block/updated_at".to_string(), Value::String(now.to_rfc3339())); // Set updated_at on creation
-        tx_data.insert(":block/is_page".to_string(), Value::Bool(block_data.is_page));
+        match self.run_pull(entity_lookups, pattern, true).await? {
+            Value::Array(pulled) => Ok(pulled),
+            other => Err(DatomicError::type_conversion_error(format!("Expected a list from pullMany, got {}", other))),
+        }
+    }
 
-        if let Some(page_title) = &block_data.page_title {
-            tx_data.insert(":block/page_title".to_string(), Value::String(page_title.clone()));
+    /// Call `Database.pull` with the first lookup, or `Database.pullMany`
+    /// with all of them, against the current database value
+    async fn run_pull(&self, entity_lookups: Vec<Value>, pattern: &str, many: bool) -> Result<Value> {
+        debug!("Pulling {} for {} entities", pattern, entity_lookups.len());
+        entity_lookups.iter().try_for_each(Self::check_query_input)?;
+
+        let pattern = pattern.to_string();
+        let db_uri = self.config.build_uri();
+        let jvm = self.jvm.clone();
+        let connection = self.connection.clone();
+
+        let operation = move || -> Result<Value> {
+            let mut env = Self::attach(&jvm)?;
+
+            Self::with_connection(&mut env, &connection, &db_uri, |env, conn| {
+                let db = env.call_method(conn, "db", "()Ldatomic/Database;", &[])
+                    .map_err(|e| Self::java_error(env, e, DatomicError::ConnectionError))?
+                    .l()?;
+                let pattern_string = env.new_string(&pattern)?;
+                let pattern = env.call_static_method("datomic/Util", "read", "(Ljava/lang/String;)Ljava/lang/Object;", &[JValue::Object(&pattern_string)])
+                    .map_err(|e| Self::java_error(env, e, DatomicError::EdnParsingError))?
+                    .l()?;
+
+                let pulled = if many {
+                    let lookups = env.new_object("java/util/ArrayList", "(I)V", &[JValue::Int(entity_lookups.len() as i32)])?;
+                    for lookup in &entity_lookups {
+                        let lookup = Self::json_to_java(env, lookup)?;
+                        env.call_method(&lookups, "add", "(Ljava/lang/Object;)Z", &[JValue::Object(&lookup)])?;
+                        env.delete_local_ref(lookup)?;
+                    }
+                    env.call_method(&db, "pullMany", "(Ljava/lang/Object;Ljava/util/List;)Ljava/util/List;", &[JValue::Object(&pattern), JValue::Object(&lookups)])
+                } else {
+                    let lookup = Self::json_to_java(env, &entity_lookups[0])?;
+                    env.call_method(&db, "pull", "(Ljava/lang/Object;Ljava/lang/Object;)Ljava/util/Map;", &[JValue::Object(&pattern), JValue::Object(&lookup)])
+                }
+                .map_err(|e| Self::java_error(env, e, DatomicError::QueryError))?
+                .l()?;
+
+                Ok(Self::pulled_keys(Self::java_to_json(env, &pulled)?))
+            })
+        };
+
+        self.retried("pull", operation).await
+    }
+
+    /// Name the keys of pulled maps after their attributes: `:block/id`
+    /// becomes `block/id` and a reverse reference like `:block/_parent`
+    /// becomes `block/_parent`, in nested maps and lists too. Keyword
+    /// values such as an enum's `:db/ident` keep their `:`.
+    fn pulled_keys(pulled: Value) -> Value {
+        match pulled {
+            Value::Object(map) => Value::Object(map.into_iter()
+                .map(|(key, value)| {
+                    let key = match key.strip_prefix(':') {
+                        Some(name) if edn::is_keyword(&key) => name.to_string(),
+                        _ => key,
+                    };
+                    (key, Self::pulled_keys(value))
+                })
+                .collect()),
+            Value::Array(items) => Value::Array(items.into_iter().map(Self::pulled_keys).collect()),
+            other => other,
         }
-        
-        if let Some(parent_id) = &block_data.parent_id {
-            tx_data.insert(":block/parent".to_string(), Value::String(parent_id.clone())); // Assuming parent_id is a string ID
+    }
+
+    /// Names for the columns of a query's rows: each :find variable without
+    /// its `?`, or the element's position for an aggregate or pull expression
+    fn find_columns(query: &str) -> Vec<String> {
+        let Some(start) = query.find(":find") else {
+            return Vec::new();
+        };
+        let mut columns = Vec::new();
+        let mut element = String::new();
+        let mut depth = 0;
+        for token in query[start + ":find".len()..]
+            .replace(['(', '['], " ( ")
+            .replace([')', ']'], " ) ")
+            .split_whitespace()
+        {
+            if depth == 0 && token.starts_with(':') {
+                break;
+            }
+            match token {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                _ => {}
+            }
+            element.push_str(token);
+            if depth == 0 {
+                let column = match element.strip_prefix('?') {
+                    Some(name) => name.to_string(),
+                    None => columns.len().to_string(),
+                };
+                columns.push(column);
+                element.clear();
+            }
         }
-        
-        tx_data.insert(":block/order".to_string(), Value::Number(block_data.order.into()));
-        
-        let mut audio_timestamp_to_return: Option<AudioTimestamp> = None;
+        columns
+    }
 
-        // Add audio metadata if present by creating a new AudioTimestamp entity
-        if let Some(audio) = &audio_meta {
-            // In a real Datomic transaction, you'd likely create a new entity for AudioTimestamp
-            // and link it. For simplicity here, we might be embedding or just using parts.
-            // The current Block model has Option<AudioTimestamp>.
-            // Let's assume we are creating an AudioTimestamp and want to link its ID or store parts.
-            // For now, let's assume the schema supports direct embedding or specific attributes for audio.
-            // Based on `Block` model, it expects `AudioTimestamp` struct.
-             audio_timestamp_to_return = Some(AudioTimestamp {
-                block_id: block_id.clone(),
-                recording_id: audio.recording_id.clone(),
-                timestamp_seconds: audio.timestamp,
-                recording: None, // Assuming we don't fetch the full recording here
-            });
-            // This part of tx_data would need to reflect how AudioTimestamp is stored/linked in Datomic.
-            // E.g., if AudioTimestamp is a separate entity:
-            // let audio_ts_id = format!("audio_ts_{}", Uuid::new_v4());
-            // tx_data.insert(":block/audio_ts_ref", Value::String(audio_ts_id)); // Example ref
-            // And another map in the transaction vector for the AudioTimestamp entity itself.
-            // For simplicity, if schema expects direct attributes on block for this:
-            tx_data.insert(":timestamp/recording_id".to_string(), Value::String(audio.recording_id.clone()));
-            tx_data.insert(":timestamp/seconds".to_string(), Value::Number(audio.timestamp.into()));
+    /// Convert the `java.util.Collection` of tuples a query returns into rows
+    /// keyed by `columns`
+    fn convert_query_result(env: &mut JNIEnv, result: JObject, columns: &[String]) -> Result<Vec<HashMap<String, Value>>> {
+        let mut rows = Vec::new();
+        if result.is_null() {
+            return Ok(rows);
+        }
 
+        let iterator = env.call_method(&result, "iterator", "()Ljava/util/Iterator;", &[])?.l()?;
+        while env.call_method(&iterator, "hasNext", "()Z", &[])?.z()? {
+            // Each tuple gets its own local frame so large results don't run out of references
+            let row = env.with_local_frame(16, |env| -> Result<HashMap<String, Value>> {
+                let tuple = env.call_method(&iterator, "next", "()Ljava/lang/Object;", &[])?.l()?;
+                let values = match Self::java_to_json(env, &tuple)? {
+                    Value::Array(values) if env.is_instance_of(&tuple, "java/util/List")? => values,
+                    // A collection find spec returns bare values
+                    value => vec![value],
+                };
+                Ok(values.into_iter()
+                    .enumerate()
+                    .map(|(i, value)| (columns.get(i).cloned().unwrap_or_else(|| i.to_string()), value))
+                    .collect())
+            })?;
+            rows.push(row);
         }
+        Ok(rows)
+    }
+
+    /// Convert a value from a query result. Keywords, UUIDs and anything else
+    /// without a JSON counterpart become their string form, e.g. `:block/id`,
+    /// and dates become RFC 3339 strings like the ones the schema stores.
+    fn java_to_json(env: &mut JNIEnv, value: &JObject) -> Result<Value> {
+        if value.is_null() {
+            return Ok(Value::Null);
+        }
+        if env.is_instance_of(value, "java/lang/String")? {
+            let string: String = env.get_string(value.into())?.into();
+            return Ok(Value::String(string));
+        }
+        if env.is_instance_of(value, "java/lang/Boolean")? {
+            return Ok(Value::Bool(env.call_method(value, "booleanValue", "()Z", &[])?.z()?));
+        }
+        for integer_class in ["java/lang/Long", "java/lang/Integer", "java/lang/Short", "java/lang/Byte"] {
+            if env.is_instance_of(value, integer_class)? {
+                return Ok(Value::Number(env.call_method(value, "longValue", "()J", &[])?.j()?.into()));
+            }
+        }
+        if env.is_instance_of(value, "java/lang/Number")? {
+            let number = env.call_method(value, "doubleValue", "()D", &[])?.d()?;
+            return Ok(serde_json::Number::from_f64(number).map(Value::Number).unwrap_or(Value::Null));
+        }
+        if env.is_instance_of(value, "java/util/Date")? {
+            let millis = env.call_method(value, "getTime", "()J", &[])?.j()?;
+            let date = DateTime::<Utc>::from_timestamp_millis(millis)
+                .ok_or_else(|| DatomicError::type_conversion_error(format!("Date out of range: {}ms", millis)))?;
+            return Ok(Value::String(date.to_rfc3339()));
+        }
+        if env.is_instance_of(value, "java/util/Map")? {
+            let entries = env.call_method(value, "entrySet", "()Ljava/util/Set;", &[])?.l()?;
+            let mut object = serde_json::Map::new();
+            Self::for_each_element(env, &entries, |env, entry| {
+                let key = env.call_method(entry, "getKey", "()Ljava/lang/Object;", &[])?.l()?;
+                let key = match Self::java_to_json(env, &key)? {
+                    Value::String(key) => key,
+                    key => key.to_string(),
+                };
+                let value = env.call_method(entry, "getValue", "()Ljava/lang/Object;", &[])?.l()?;
+                object.insert(key, Self::java_to_json(env, &value)?);
+                Ok(())
+            })?;
+            return Ok(Value::Object(object));
+        }
+        if env.is_instance_of(value, "java/util/Collection")? {
+            let mut array = Vec::new();
+            Self::for_each_element(env, value, |env, element| {
+                array.push(Self::java_to_json(env, element)?);
+                Ok(())
+            })?;
+            return Ok(Value::Array(array));
+        }
+
+        let string = env.call_method(value, "toString", "()Ljava/lang/String;", &[])?.l()?;
+        let string: String = env.get_string((&string).into())?.into();
+        Ok(Value::String(string))
+    }
+
+    /// Call `f` with each element of a `java.util.Collection`, freeing the
+    /// local references it creates as it goes
+    fn for_each_element(
+        env: &mut JNIEnv,
+        collection: &JObject,
+        mut f: impl FnMut(&mut JNIEnv, &JObject) -> Result<()>,
+    ) -> Result<()> {
+        let iterator = env.call_method(collection, "iterator", "()Ljava/util/Iterator;", &[])?.l()?;
+        while env.call_method(&iterator, "hasNext", "()Z", &[])?.z()? {
+            env.with_local_frame(8, |env| -> Result<()> {
+                let element = env.call_method(&iterator, "next", "()Ljava/lang/Object;", &[])?.l()?;
+                f(env, &element)
+            })?;
+        }
+        env.delete_local_ref(iterator)?;
+        Ok(())
+    }
+
+    /// Create a new block
+    #[instrument(skip(self))]
+    pub async fn create_block(&self, block_data: CreateBlockRequest, audio_meta: Option<AudioMeta>) -> Result<Block> {
+        info!("Creating block with content: {:?}", block_data.content); // Use {:?} for Option
+        block_data.validate()?;
         
-        // Execute transaction
-        self.transact(vec![tx_data]).await?;
-        
-        // Return created block
-        let block = Block {
-            id: block_id,
+        let now = Utc::now();
+        let mut block = Block {
+            id: Uuid::new_v4().to_string(),
             content: block_data.content,
             created_at: now,
             updated_at: now,
@@ -547,121 +935,2010 @@ impl DatomicPeerClient {
             parent_id: block_data.parent_id,
             order: block_data.order,
             is_page: block_data.is_page,
-            audio_timestamp: audio_timestamp_to_return,
+            audio_timestamp: None,
+            source: block_data.source,
         };
         
+        // Execute transaction
+        self.transact(vec![Self::block_entity(&block, &HashSet::new())]).await?;
+
+        // Link the new block to its recording position as a separate timestamp entity
+        if let Some(audio) = &audio_meta {
+            self.create_audio_timestamp(&block.id, &audio.recording_id, audio.timestamp_ms).await?;
+            // Assuming we don't fetch the full recording here
+            block.audio_timestamp = Some(AudioTimestamp::new(&block.id, &audio.recording_id, audio.timestamp_ms));
+        }
+        
+        for change in DataChange::added(std::slice::from_ref(&block)) {
+            self.changed(change);
+        }
         info!("Block created successfully: {}", block.id);
         Ok(block)
     }
 
-    /// Execute a transaction
+    /// Entity map adding `block`, with the page links in its content. The
+    /// block's ID doubles as its tempid, so a parent among `batch_ids`, the
+    /// blocks added in the same transaction, is referred to by tempid and any
+    /// other parent by the lookup ref `[:block/id parent-id]`.
+    fn block_entity(block: &Block, batch_ids: &HashSet<&str>) -> Value {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), Value::String(block.id.clone()));
+        tx_data.insert(":block/id".to_string(), Value::String(block.id.clone()));
+        if let Some(content) = &block.content {
+            tx_data.insert(":block/content".to_string(), Value::String(content.clone()));
+            let links = Block::page_links(content);
+            if !links.is_empty() {
+                tx_data.insert(":block/links".to_string(), json!(links));
+            }
+        }
+        tx_data.insert(":block/created_at".to_string(), Value::String(block.created_at.to_rfc3339()));
+        tx_data.insert(":block/updated_at".to_string(), Value::String(block.updated_at.to_rfc3339()));
+        tx_data.insert(":block/is_page".to_string(), Value::Bool(block.is_page));
+        if let Some(page_title) = &block.page_title {
+            tx_data.insert(":block/page_title".to_string(), Value::String(page_title.clone()));
+        }
+        if let Some(parent_id) = &block.parent_id {
+            let parent = match batch_ids.contains(parent_id.as_str()) {
+                true => Value::String(parent_id.clone()),
+                false => json!([":block/id", parent_id]),
+            };
+            tx_data.insert(":block/parent".to_string(), parent);
+        }
+        tx_data.insert(":block/order".to_string(), Value::Number(block.order.into()));
+        if let Some(source) = &block.source {
+            tx_data.insert(":block/source".to_string(), Value::String(source.clone()));
+        }
+        json!(tx_data)
+    }
+
+    /// Add `blocks` in a single transaction, so either all of them are
+    /// written or none are
+    #[instrument(skip(self, blocks))]
+    pub async fn create_blocks(&self, blocks: &[Block]) -> Result<()> {
+        info!("Creating {} blocks in one transaction", blocks.len());
+        let batch_ids: HashSet<&str> = blocks.iter().map(|block| block.id.as_str()).collect();
+        self.transact(blocks.iter().map(|block| Self::block_entity(block, &batch_ids)).collect()).await?;
+        for change in DataChange::added(blocks) {
+            self.changed(change);
+        }
+        Ok(())
+    }
+
+    /// Execute a transaction.
+    /// Each item is either an entity map or a list form such as `[":db/retractEntity", eid]`.
     #[instrument(skip(self, tx_data))]
-    pub async fn transact(&self, tx_data: Vec<HashMap<String, Value>>) -> Result<Value> {
+    pub async fn transact(&self, tx_data: Vec<Value>) -> Result<Value> {
         debug!("Executing transaction with {} items", tx_data.len());
         
-        // TODO: Implement proper transaction execution
-        // This is a placeholder implementation
+        let tx_edn = edn::tx_data_to_edn(&tx_data);
+        let db_uri = self.config.build_uri();
+        let jvm = self.jvm.clone();
+        let connection = self.connection.clone();
+        let tx_timeout = self.operation_timeout();
         
-        Ok(json!({
-            "db-after": {},
-            "tx-data": tx_data,
-            "tempids": {}
-        }))
+        // Waiting on the transactor's Future blocks, so it gets its own thread.
+        // A transaction isn't retried here: it may have gone through before
+        // failing, and `:db/cas` conflicts are for the caller to resolve.
+        run_blocking(tx_timeout + TRANSACT_TIMEOUT_GRACE, move || {
+            let mut env = Self::attach(&jvm)?;
+            Self::with_connection(&mut env, &connection, &db_uri, |env, conn| Self::transact_edn(env, conn, &tx_edn, tx_timeout))
+        })
+        .await
     }
 
-    /// Update a block
+    /// Update a block, returning whether anything was written. Updates that
+    /// match the stored values are skipped, so `updated-at` isn't bumped.
+    /// New content must be a string.
     #[instrument(skip(self, updates))]
-    pub async fn update_block(&self, block_id: &str, updates: HashMap<String, Value>) -> Result<()> {
+    pub async fn update_block(&self, block_id: &str, updates: HashMap<String, Value>) -> Result<bool> {
         info!("Updating block: {}", block_id);
         
+        let new_content = updates.iter()
+            .find(|(key, _)| Self::block_attribute(key) == "content")
+            .map(|(_, value)| value.as_str().ok_or_else(|| {
+                DatomicError::invalid_transaction_data(format!("Content of block {} must be a string, not {}", block_id, value))
+            }))
+            .transpose()?;
+        
+        let current = self.get_block(block_id).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Block {}", block_id)))?;
+        if current.is_unchanged_by(&updates) {
+            debug!("Block {} already up to date, skipping write", block_id);
+            return Ok(false);
+        }
+        
+        // Keep the page links in step with a content change
+        let link_tx = match new_content {
+            Some(content) => Self::link_tx(block_id, current.content.as_deref(), Some(content)),
+            None => Vec::new(),
+        };
+        
         let mut tx_data = HashMap::new();
-        tx_data.insert("block/id".to_string(), Value::String(block_id.to_string()));
-        tx_data.insert("block/updated-at".to_string(), Value::String(Utc::now().to_rfc3339()));
-        
-        // Add updates
-        for (key, value) in updates {
-            if key.starts_with("block/") {
-                tx_data.insert(key, value);
-            } else {
-                tx_data.insert(format!("block/{}", key), value);
-            }
+        tx_data.insert(":block/id".to_string(), Value::String(block_id.to_string()));
+        tx_data.insert(":block/updated_at".to_string(), Value::String(Utc::now().to_rfc3339()));
+        
+        // Add updates, as `:block/` attributes whatever prefix the key came with.
+        // A parent is given by ID and stored as a reference.
+        for (key, value) in &updates {
+            let name = Self::block_attribute(key);
+            let value = match (name.as_str(), value) {
+                ("parent", Value::String(parent_id)) => json!([":block/id", parent_id]),
+                (_, value) => value.clone(),
+            };
+            tx_data.insert(format!(":block/{}", name), value);
         }
         
-        self.transact(vec![tx_data]).await?;
+        let mut tx = vec![json!(tx_data)];
+        tx.extend(link_tx);
+        self.transact(tx).await?;
+        self.changed(DataChange::written(&current));
         info!("Block updated successfully: {}", block_id);
-        Ok(())
+        Ok(true)
     }
 
-    /// Get blocks for a page
-    #[instrument(skip(self))]
-    pub async fn get_page_blocks(&self, page_id: &str) -> Result<Vec<Block>> {
-        debug!("Getting blocks for page: {}", page_id);
-        
-        let query = "[:find ?block-id ?content ?created-at ?updated-at ?order ?parent-id ?audio-file ?audio-timestamp
-                     :in $ ?page-id
-                     :where [?e :block/page ?page-id]
-                            [?e :block/id ?block-id]
-                            [?e :block/content ?content]
-                            [?e :block/created-at ?created-at]
-                            [?e :block/updated-at ?updated-at]
-                            [(get-else $ ?e :block/order 0) ?order]
-                            [(get-else $ ?e :block/parent nil) ?parent-id]
-                            [(get-else $ ?e :block/audio-file nil) ?audio-file]
-                            [(get-else $ ?e :block/audio-timestamp nil) ?audio-timestamp]]";
+    /// The `:block/` attribute an update key names, whatever prefix it came with
+    fn block_attribute(key: &str) -> String {
+        let name = key.trim_start_matches(':');
+        name.strip_prefix("block/").unwrap_or(name).replace('-', "_")
+    }
+
+    /// Add and retract `:block/links` values for a block whose content
+    /// changes from `old_content` to `new_content`
+    fn link_tx(block_id: &str, old_content: Option<&str>, new_content: Option<&str>) -> Vec<Value> {
+        let (added, removed) = Block::link_changes(old_content, new_content);
+        let added = added.into_iter().map(|title| json!([":db/add", [":block/id", block_id], ":block/links", title]));
+        let removed = removed.into_iter().map(|title| json!([":db/retract", [":block/id", block_id], ":block/links", title]));
+        added.chain(removed).collect()
+    }
+
+    /// Convert a query row holding a block's attributes. Optional attributes
+    /// come back as empty strings when the block doesn't have them.
+    fn row_to_block(row: &HashMap<String, Value>) -> Result<Block> {
+        let timestamp = |column: &str| {
+            Self::row_string(row, column)
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok_or_else(|| DatomicError::type_conversion_error(format!("Invalid :block/{} value", column)))
+        };
+        let optional = |column: &str| Self::row_string(row, column).filter(|s| !s.is_empty());
+
+        Ok(Block {
+            id: Self::row_string(row, "block-id")
+                .ok_or_else(|| DatomicError::type_conversion_error("Missing :block/id value"))?,
+            content: optional("content"),
+            parent_id: optional("parent-id"),
+            order: row.get("order").and_then(Value::as_i64).unwrap_or(0) as i32,
+            is_page: row.get("is-page").and_then(Value::as_bool).unwrap_or(false),
+            page_title: optional("page-title"),
+            created_at: timestamp("created-at")?,
+            updated_at: timestamp("updated-at")?,
+            audio_timestamp: None,
+            source: optional("source"),
+        })
+    }
+
+    /// Convert a block pulled with `BLOCK_PULL_PATTERN`, or `None` if the
+    /// pull found no block. The parent is pulled as a map holding its `block/id`.
+    fn pull_to_block(pulled: &Value) -> Result<Option<Block>> {
+        let Some(id) = pulled.get("block/id").and_then(Value::as_str) else {
+            return Ok(None);
+        };
+        let text = |attribute: &str| pulled.get(attribute).and_then(Value::as_str).filter(|s| !s.is_empty()).map(str::to_string);
+        let timestamp = |attribute: &str| {
+            pulled.get(attribute)
+                .and_then(Value::as_str)
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok_or_else(|| DatomicError::type_conversion_error(format!("Invalid :{} value for block {}", attribute, id)))
+        };
+        let parent_id = pulled.get("block/parent")
+            .and_then(|parent| parent.get("block/id"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+
+        Ok(Some(Block {
+            id: id.to_string(),
+            content: text("block/content"),
+            parent_id: parent_id.filter(|s| !s.is_empty()),
+            order: pulled.get("block/order").and_then(Value::as_i64).unwrap_or(0) as i32,
+            is_page: pulled.get("block/is_page").and_then(Value::as_bool).unwrap_or(false),
+            page_title: text("block/page_title"),
+            created_at: timestamp("block/created_at")?,
+            updated_at: timestamp("block/updated_at")?,
+            audio_timestamp: None,
+            source: text("block/source"),
+        }))
+    }
+
+    /// Get a single block by its ID
+    #[instrument(skip(self))]
+    pub async fn get_block(&self, block_id: &str) -> Result<Option<Block>> {
+        let pulled = self.pull(json!([":block/id", block_id]), BLOCK_PULL_PATTERN).await?;
+        Self::pull_to_block(&pulled)
+    }
+
+    /// Every assertion and retraction of a block's content, oldest first,
+    /// read from the history database
+    #[instrument(skip(self))]
+    pub async fn get_block_history(&self, block_id: &str) -> Result<Vec<ContentRevision>> {
+        if self.get_block(block_id).await?.is_none() {
+            return Err(DatomicError::entity_not_found(format!("Block {}", block_id)));
+        }
+
+        let query = "[:find ?tx ?tx-instant ?content ?added
+                     :in $ ?e
+                     :where [?e :block/content ?content ?tx ?added]
+                            [?tx :db/txInstant ?tx-instant]]";
+        let revisions = self.query_view(query, None, vec![json!([":block/id", block_id])], DbView::History).await?
+            .iter()
+            .map(Self::row_to_revision)
+            .collect::<Result<Vec<_>>>()?;
+        debug!("Block {} has {} content revisions", block_id, revisions.len());
+        Ok(ContentRevision::timeline(revisions))
+    }
+
+    /// Convert a history row. `:db/txInstant` comes back from its
+    /// `java.util.Date` as an RFC 3339 string with millisecond precision.
+    fn row_to_revision(row: &HashMap<String, Value>) -> Result<ContentRevision> {
+        let missing = |column: &str| DatomicError::type_conversion_error(format!("Missing or invalid ?{} in block history", column));
+        Ok(ContentRevision {
+            tx: row.get("tx").and_then(Value::as_i64).ok_or_else(|| missing("tx"))?,
+            tx_instant: Self::row_string(row, "tx-instant")
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok_or_else(|| missing("tx-instant"))?,
+            content: Self::row_string(row, "content").ok_or_else(|| missing("content"))?,
+            added: row.get("added").and_then(Value::as_bool).ok_or_else(|| missing("added"))?,
+        })
+    }
+
+    /// Get several blocks in one query, in the order of `block_ids` and with
+    /// their audio timestamps hydrated. IDs that don't match a block are left out.
+    #[instrument(skip(self))]
+    pub async fn get_blocks_by_ids(&self, block_ids: &[String]) -> Result<Vec<Block>> {
+        if block_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ [?block-id ...]
+                     :where [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            (or-join [?e ?parent-id]
+                              (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                              (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+
+        let ids = block_ids.iter().cloned().map(Value::String).collect();
+        let mut blocks = self.query(query, vec![Value::Array(ids)]).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+
+        let found: Vec<String> = blocks.iter().map(|block| block.id.clone()).collect();
+        let mut timestamps = self.get_audio_timestamps_for_blocks(&found).await?;
+        for block in &mut blocks {
+            block.audio_timestamp = timestamps.remove(&block.id);
+        }
+
+        debug!("Retrieved {} of {} requested blocks", blocks.len(), block_ids.len());
+        Ok(Block::in_order_of(blocks, block_ids))
+    }
+
+    /// Append text to a block's content. The write is a `:db/cas` against the
+    /// content that was read, so concurrent appends can't overwrite each other;
+    /// on a conflict the block is re-read and the append retried.
+    #[instrument(skip(self, text))]
+    pub async fn append_to_block(&self, block_id: &str, text: &str) -> Result<Block> {
+        debug!("Appending {} bytes to block {}", text.len(), block_id);
+
+        for attempt in 1..=self.retry_config.max_attempts {
+            let mut block = self.get_block(block_id).await?
+                .ok_or_else(|| DatomicError::entity_not_found(format!("Block {}", block_id)))?;
+
+            let current = block.content.clone();
+            let appended = format!("{}{}", current.as_deref().unwrap_or(""), text);
+            let now = Utc::now();
+
+            let mut updated_at = HashMap::new();
+            updated_at.insert(":db/id".to_string(), json!([":block/id", block_id]));
+            updated_at.insert(":block/updated_at".to_string(), Value::String(now.to_rfc3339()));
+            let mut tx_data = vec![
+                json!([":db/cas", [":block/id", block_id], ":block/content", current, appended]),
+                json!(updated_at),
+            ];
+            tx_data.extend(Self::link_tx(block_id, current.as_deref(), Some(&appended)));
+
+            match self.transact(tx_data).await {
+                Ok(_) => {
+                    block.content = Some(appended);
+                    block.updated_at = now;
+                    self.changed(DataChange::written(&block));
+                    return Ok(block);
+                }
+                Err(e) if e.is_cas_conflict() => {
+                    debug!("Block {} changed during append (attempt {}), retrying", block_id, attempt);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(DatomicError::retry_limit_exceeded(self.retry_config.max_attempts))
+    }
+
+    /// Number of distinct blocks linking to a page with `[[page_title]]`.
+    /// This is counted live from the indexed `:block/links` attribute rather
+    /// than cached on the page: a cached count would be one more write in
+    /// every content change and could drift from the links themselves, while
+    /// the lookup only touches the blocks that actually link to the page.
+    #[instrument(skip(self))]
+    pub async fn reference_count(&self, page_title: &str) -> Result<i64> {
+        let query = "[:find ?block-id
+                     :in $ ?title
+                     :where [?b :block/links ?title]
+                            [?b :block/id ?block-id]]";
+        let results = self.query(query, vec![Value::String(page_title.to_string())]).await?;
+        Ok(results.len() as i64)
+    }
+
+    /// Rebuild `:block/links` from every block's content, for when the links
+    /// drifted from it (after an import that skipped them, say). Only blocks
+    /// whose links differ are written, a batch of blocks per transaction.
+    #[instrument(skip(self))]
+    pub async fn reindex_derived(&self) -> Result<ReindexStats> {
+        info!("Rebuilding page links from block content");
+
+        let (blocks_checked, repairs) = self.link_repairs().await?;
+        let mut stats = ReindexStats { blocks_checked, ..ReindexStats::default() };
+        for batch in repairs.chunks(REINDEX_BATCH_SIZE) {
+            let mut tx_data = Vec::new();
+            for (block_id, missing, stale) in batch {
+                tx_data.extend(missing.iter().map(|title| json!([":db/add", [":block/id", block_id], ":block/links", title])));
+                tx_data.extend(stale.iter().map(|title| json!([":db/retract", [":block/id", block_id], ":block/links", title])));
+                stats.links_added += missing.len();
+                stats.links_removed += stale.len();
+            }
+            self.transact(tx_data).await?;
+            stats.blocks_fixed += batch.len();
+        }
+
+        info!(
+            "Rebuilt page links of {} blocks: fixed {}, added {} links, removed {}",
+            stats.blocks_checked, stats.blocks_fixed, stats.links_added, stats.links_removed
+        );
+        Ok(stats)
+    }
+
+    /// Links `(block_id, missing, stale)` to change so every block's links
+    /// match its content, with the number of blocks checked
+    async fn link_repairs(&self) -> Result<(usize, Vec<LinkRepair>)> {
+        let links_query = "[:find ?block-id ?title
+                           :where [?b :block/links ?title]
+                                  [?b :block/id ?block-id]]";
+        let mut stored: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for row in self.query(links_query, Vec::new()).await? {
+            if let (Some(block_id), Some(title)) = (Self::row_string(&row, "block-id"), Self::row_string(&row, "title")) {
+                stored.entry(block_id).or_default().insert(title);
+            }
+        }
+
+        let content_query = "[:find ?block-id ?content
+                             :where [?b :block/id ?block-id]
+                                    [(get-else $ ?b :block/content \"\") ?content]]";
+        let blocks = self.query(content_query, Vec::new()).await?;
+
+        let mut repairs = Vec::new();
+        for row in &blocks {
+            let Some(block_id) = Self::row_string(row, "block-id") else { continue };
+            let content = Self::row_string(row, "content");
+            let (missing, stale) = Block::link_repairs(content.as_deref(), stored.get(&block_id).unwrap_or(&BTreeSet::new()));
+            if !missing.is_empty() || !stale.is_empty() {
+                repairs.push((block_id, missing, stale));
+            }
+        }
+        Ok((blocks.len(), repairs))
+    }
+
+    /// IDs of blocks whose `:block/links` don't match their content
+    #[instrument(skip(self))]
+    pub async fn blocks_with_stale_links(&self) -> Result<Vec<String>> {
+        let (_, repairs) = self.link_repairs().await?;
+        Ok(repairs.into_iter().map(|(block_id, _, _)| block_id).collect())
+    }
+
+    /// Every block, pages included, in no particular order
+    #[instrument(skip(self))]
+    pub async fn get_all_blocks(&self) -> Result<Vec<Block>> {
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :where [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            (or-join [?e ?parent-id]
+                              (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                              (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+        self.query(query, Vec::new()).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect()
+    }
+
+    /// Get the blocks directly under a page (or any block) in block order
+    #[instrument(skip(self))]
+    pub async fn get_page_blocks(&self, page_id: &str) -> Result<Vec<Block>> {
+        debug!("Getting blocks for page: {}", page_id);
+        
+        let query = "[:find ?e :in $ ?parent-id :where [?p :block/id ?parent-id] [?e :block/parent ?p]]";
+        let entity_ids = self.query(query, vec![Value::String(page_id.to_string())]).await?
+            .into_iter()
+            .filter_map(|mut row| row.remove("e"))
+            .collect();
+        let mut blocks = Vec::new();
+        for pulled in self.pull_many(entity_ids, BLOCK_PULL_PATTERN).await? {
+            blocks.extend(Self::pull_to_block(&pulled)?);
+        }
+        blocks.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.id.cmp(&b.id)));
+        
+        debug!("Retrieved {} blocks for page: {}", blocks.len(), page_id);
+        Ok(blocks)
+    }
+
+    /// Get the children of several blocks in a single query, keyed by parent
+    /// ID and in block order
+    #[instrument(skip(self))]
+    pub async fn get_children_for_parents(&self, parent_ids: &[String]) -> Result<HashMap<String, Vec<Block>>> {
+        if parent_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ [?parent-id ...]
+                     :where [?p :block/id ?parent-id]
+                            [?e :block/parent ?p]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+
+        let ids = parent_ids.iter().cloned().map(Value::String).collect();
+        let blocks = self.query(query, vec![Value::Array(ids)]).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+
+        debug!("Retrieved {} children for {} parents", blocks.len(), parent_ids.len());
+        Ok(Block::group_by_parent(blocks, parent_ids))
+    }
+
+    /// Children of a block in block order whose `(order, id)` comes after `after`,
+    /// at most `limit` of them
+    #[instrument(skip(self))]
+    pub async fn get_children_after(&self, parent_id: &str, after: Option<&(i32, String)>, limit: usize) -> Result<Vec<Block>> {
+        // Datalog can't sort or limit, so the query narrows by order and the
+        // ties and the limit are applied to what comes back
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ ?parent-id ?after-order
+                     :where [?p :block/id ?parent-id]
+                            [?e :block/parent ?p]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(>= ?order ?after-order)]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+        let after_order = after.map_or(i32::MIN, |(order, _)| *order);
+        let params = vec![Value::String(parent_id.to_string()), Value::Number(after_order.into())];
+        let blocks = self.query(query, params).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Block::keyset_batch(blocks, after, limit))
+    }
+
+    /// Page through a block's children `batch_size` at a time, in block order
+    pub fn child_batches(&self, parent_id: &str, batch_size: usize) -> ChildBatches<'_> {
+        ChildBatches {
+            db: self,
+            parent_id: parent_id.to_string(),
+            batch_size: batch_size.max(1),
+            after: None,
+            done: false,
+            cancel: None,
+        }
+    }
+
+    /// Get the children of a block's parent in order, including the block itself.
+    /// Pages and other top-level blocks have no siblings to move between, so
+    /// they get an empty list.
+    #[instrument(skip(self))]
+    pub async fn get_siblings(&self, block_id: &str) -> Result<Vec<Block>> {
+        let block = self.get_block(block_id).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Block {}", block_id)))?;
+        let Some(parent_id) = block.parent_id else {
+            return Ok(Vec::new());
+        };
+
+        let mut children = self.get_children_for_parents(std::slice::from_ref(&parent_id)).await?;
+        Ok(children.remove(&parent_id).unwrap_or_default())
+    }
+
+    /// Zero-based position of a block among its siblings in block order, or
+    /// `None` if the block doesn't exist. Blocks without a parent are at 0.
+    #[instrument(skip(self))]
+    pub async fn get_block_index(&self, block_id: &str) -> Result<Option<usize>> {
+        let Some(block) = self.get_block(block_id).await? else {
+            return Ok(None);
+        };
+        let Some(parent_id) = &block.parent_id else {
+            return Ok(Some(0));
+        };
+
+        let mut children = self.get_children_for_parents(std::slice::from_ref(parent_id)).await?;
+        let siblings = children.remove(parent_id).unwrap_or_default();
+        Ok(Some(block.position_among(&siblings)))
+    }
+
+    /// Block counts and nesting depth of every page, largest first. Datalog
+    /// has no recursive aggregation, so the parent links are walked in memory.
+    #[instrument(skip(self))]
+    pub async fn page_stats(&self) -> Result<Vec<PageStat>> {
+        let pages_query = "[:find ?page-id ?title
+                           :where [?p :block/is_page true]
+                                  [?p :block/id ?page-id]
+                                  [(get-else $ ?p :block/page_title \"\") ?title]]";
+        let pages: Vec<(String, Option<String>)> = self.query(pages_query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| {
+                let page_id = Self::row_string(row, "page-id")?;
+                Some((page_id, Self::row_string(row, "title").filter(|title| !title.is_empty())))
+            })
+            .collect();
+
+        let links_query = "[:find ?block-id ?parent-id
+                           :where [?b :block/parent ?p]
+                                  [?p :block/id ?parent-id]
+                                  [?b :block/id ?block-id]]";
+        let parent_links: Vec<(String, String)> = self.query(links_query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| Some((Self::row_string(row, "block-id")?, Self::row_string(row, "parent-id")?)))
+            .collect();
+
+        Ok(PageStat::compute(&pages, &parent_links))
+    }
+
+    /// The `limit` most recently updated blocks that aren't pages, newest
+    /// first, with the title of the page each one is on
+    #[instrument(skip(self))]
+    pub async fn get_recent_edits(&self, limit: i64) -> Result<Vec<RecentEdit>> {
+        let blocks_query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                            :where [?e :block/id ?block-id]
+                                   [(get-else $ ?e :block/is_page false) ?is-page]
+                                   [(= ?is-page false)]
+                                   [?e :block/created_at ?created-at]
+                                   [?e :block/updated_at ?updated-at]
+                                   [(get-else $ ?e :block/content \"\") ?content]
+                                   (or-join [?e ?parent-id]
+                                     (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                                     (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                                   [(get-else $ ?e :block/order 0) ?order]
+                                   [(get-else $ ?e :block/page_title \"\") ?page-title]
+                                   [(get-else $ ?e :block/source \"\") ?source]]";
+        let blocks = self.query(blocks_query, Vec::new()).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Datalog can't walk up to the page, so the parent links are followed in memory
+        let links_query = "[:find ?block-id ?parent-id
+                           :where [?b :block/parent ?p]
+                                  [?p :block/id ?parent-id]
+                                  [?b :block/id ?block-id]]";
+        let parents: HashMap<String, String> = self.query(links_query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| Some((Self::row_string(row, "block-id")?, Self::row_string(row, "parent-id")?)))
+            .collect();
+        let pages_query = "[:find ?page-id ?title
+                           :where [?p :block/is_page true]
+                                  [?p :block/id ?page-id]
+                                  [(get-else $ ?p :block/page_title \"\") ?title]]";
+        let pages: HashMap<String, String> = self.query(pages_query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| Some((Self::row_string(row, "page-id")?, Self::row_string(row, "title")?)))
+            .collect();
+
+        Ok(RecentEdit::latest(blocks, &parents, &pages, limit.max(0) as usize))
+    }
+
+    /// Words written on the page titled `page_title`, across every block
+    /// below it. The blocks below the page are fetched in one query and
+    /// their content is counted here.
+    #[instrument(skip(self))]
+    pub async fn page_word_count(&self, page_title: &str) -> Result<usize> {
+        let page = self.get_page_by_title(page_title).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Page {}", page_title)))?;
+
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ % ?page-id
+                     :where [?page :block/id ?page-id]
+                            (below ?page ?e)
+                            [?e :block/parent ?p]
+                            [?p :block/id ?parent-id]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+        let blocks = self.query_with_rules(query, DESCENDANT_RULES, vec![Value::String(page.id.clone())]).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Block::words_below(&page.id, &blocks))
+    }
+
+    /// Titles of every page
+    #[instrument(skip(self))]
+    pub async fn get_page_titles(&self) -> Result<Vec<String>> {
+        let query = "[:find ?title
+                     :where [?p :block/is_page true]
+                            [?p :block/page_title ?title]]";
+        Ok(self.query(query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| Self::row_string(row, "title"))
+            .collect())
+    }
+
+    /// Get a page by its title
+    #[instrument(skip(self))]
+    pub async fn get_page_by_title(&self, title: &str) -> Result<Option<Block>> {
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ ?page-title
+                     :where [?e :block/page_title ?page-title]
+                            [?e :block/is_page true]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            (or-join [?e ?parent-id]
+                              (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                              (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page true) ?is-page]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+        let results = self.query(query, vec![Value::String(title.to_string())]).await?;
+        results.first().map(Self::row_to_block).transpose()
+    }
+
+    async fn get_page(&self, page_id: &str) -> Result<Block> {
+        self.get_block(page_id).await?
+            .filter(|block| block.is_page)
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Page {}", page_id)))
+    }
+
+    /// Copy the page titled `template_title` and every block under it into a
+    /// new page titled `new_title`, in one transaction. `{{date}}` in content
+    /// becomes today's date in the UTC offset and `{{title}}` the new title.
+    /// Fails if a page already has the title, ignoring case and surrounding
+    /// whitespace. Returns the new page.
+    #[instrument(skip(self))]
+    pub async fn instantiate_template(&self, template_title: &str, new_title: &str, tz_offset_minutes: i32) -> Result<Block> {
+        let new_title = new_title.trim();
+        if new_title.is_empty() {
+            return Err(DatomicError::invalid_transaction_data("A page needs a title"));
+        }
+        let normalized = Block::normalized_title(new_title);
+        if self.get_page_titles().await?.iter().any(|title| Block::normalized_title(title) == normalized) {
+            return Err(DatomicError::invalid_transaction_data(format!("A page titled {} already exists", new_title)));
+        }
+        let template = self.get_page_by_title(template_title).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Template page {}", template_title)))?;
+        let date = Self::local_date(Utc::now(), tz_offset_minutes).ok_or_else(|| {
+            DatomicError::TypeConversionError(format!("Invalid UTC offset: {} minutes", tz_offset_minutes))
+        })?;
+
+        let descendants = self.get_descendants(&template.id).await?;
+
+        let placeholders = HashMap::from([("date", date), ("title", new_title.to_string())]);
+        let blocks = Block::instantiate_template(&template, &descendants, new_title, &placeholders, Utc::now());
+        self.create_blocks(&blocks).await?;
+        info!("Created page {} from template {} with {} blocks", new_title, template_title, descendants.len());
+
+        blocks.into_iter().next()
+            .ok_or_else(|| DatomicError::internal_error("Template copy has no page"))
+    }
+
+    /// Every block below `block_id` at any depth, a level at a time
+    async fn get_descendants(&self, block_id: &str) -> Result<Vec<Block>> {
+        // A block already seen is skipped in case of a cycle
+        let mut seen = HashSet::from([block_id.to_string()]);
+        let mut descendants = Vec::new();
+        let mut level = vec![block_id.to_string()];
+        while !level.is_empty() {
+            let mut next_level = Vec::new();
+            for (_, mut children) in self.get_children_for_parents(&level).await? {
+                children.retain(|block| seen.insert(block.id.clone()));
+                next_level.extend(children.iter().map(|block| block.id.clone()));
+                descendants.extend(children);
+            }
+            level = next_level;
+        }
+        Ok(descendants)
+    }
+
+    /// Delete a block with everything below it, along with their audio
+    /// timestamps and drafts, in one transaction. Returns how many blocks
+    /// were deleted.
+    #[instrument(skip(self))]
+    pub async fn delete_block(&self, block_id: &str) -> Result<usize> {
+        let block = self.get_block(block_id).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Block {}", block_id)))?;
+        let mut block_ids = vec![block.id.clone()];
+        block_ids.extend(self.get_descendants(block_id).await?.into_iter().map(|block| block.id));
+        let ids = Value::Array(block_ids.iter().cloned().map(Value::String).collect());
+
+        let query = "[:find ?t
+                     :in $ [?block-id ...]
+                     :where [?b :block/id ?block-id]
+                            [?t :timestamp/block ?b]]";
+        let timestamps = self.query(query, vec![ids.clone()]).await?;
+        let query = "[:find ?d :in $ [?block-id ...] :where [?d :draft/block_id ?block-id]]";
+        let drafts = self.query(query, vec![ids]).await?;
+
+        let mut tx_data: Vec<Value> = timestamps.iter().filter_map(|row| row.get("t"))
+            .chain(drafts.iter().filter_map(|row| row.get("d")))
+            .map(|entity| json!([":db/retractEntity", entity]))
+            .collect();
+        tx_data.extend(block_ids.iter().map(|id| json!([":db/retractEntity", [":block/id", id]])));
+        self.transact(tx_data).await?;
+        info!("Deleted block {} and {} blocks below it", block_id, block_ids.len() - 1);
+
+        if let Some(parent_id) = &block.parent_id {
+            self.changed(DataChange::new(DataChangeKind::BlockChanged, [parent_id.clone()]));
+        }
+        let deleted = block_ids.len();
+        self.changed(DataChange::new(DataChangeKind::BlockDeleted, block_ids));
+        Ok(deleted)
+    }
+
+    /// When anything on a page last changed: the latest `updated_at` of the
+    /// page and every block below it, or `None` if there's no such page.
+    /// Editing a block doesn't touch its page, so the page's own
+    /// `updated_at` can be far older.
+    #[instrument(skip(self))]
+    pub async fn page_last_activity(&self, page_title: &str) -> Result<Option<DateTime<Utc>>> {
+        let Some(page) = self.get_page_by_title(page_title).await? else {
+            return Ok(None);
+        };
+        let descendants = self.get_descendants(&page.id).await?;
+        Ok(descendants.iter().map(|block| block.updated_at).chain([page.updated_at]).max())
+    }
+
+    /// Pairs of pages whose titles only differ in case or surrounding
+    /// whitespace, oldest page first
+    #[instrument(skip(self))]
+    pub async fn find_duplicate_pages(&self) -> Result<Vec<(Block, Block)>> {
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :where [?e :block/is_page true]
+                            [?e :block/page_title ?page-title]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            [(get-else $ ?e :block/content \"\") ?content]
+                            (or-join [?e ?parent-id]
+                              (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                              (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page true) ?is-page]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+        let pages = self.query(query, Vec::new()).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Block::duplicate_pages(pages))
+    }
+
+    /// Fold the page `remove_id` into `keep_id`: its blocks move under the
+    /// kept page after the ones already there, its recordings follow, links
+    /// to its title are rewritten to the kept title, and the page is deleted.
+    /// Everything happens in one transaction. Returns the kept page.
+    #[instrument(skip(self))]
+    pub async fn merge_pages(&self, keep_id: &str, remove_id: &str) -> Result<Block> {
+        if keep_id == remove_id {
+            return Err(DatomicError::invalid_transaction_data(format!("Can't merge page {} into itself", keep_id)));
+        }
+        let kept = self.get_page(keep_id).await?;
+        let removed = self.get_page(remove_id).await?;
+        let now = Utc::now().to_rfc3339();
+        let mut tx_data = Vec::new();
+
+        let mut children = self.get_children_for_parents(&[keep_id.to_string(), remove_id.to_string()]).await?;
+        let next_order = children.get(keep_id).into_iter().flatten().map(|block| block.order + 1).max().unwrap_or(0);
+        let moved = children.remove(remove_id).unwrap_or_default();
+        for (i, child) in moved.iter().enumerate() {
+            tx_data.push(json!({
+                ":block/id": child.id,
+                ":block/parent": [":block/id", keep_id],
+                ":block/order": next_order + i as i32,
+                ":block/updated_at": now,
+            }));
+        }
+
+        for recording in self.get_page_recordings(remove_id).await? {
+            tx_data.push(json!([":db/add", [":audio/id", recording.id], ":audio/page", [":block/id", keep_id]]));
+        }
+
+        // Links are stored trimmed, so titles differing only in whitespace
+        // already share them
+        let kept_title = kept.page_title.clone().unwrap_or_default();
+        let removed_title = removed.page_title.clone().unwrap_or_default();
+        let mut relinked = Vec::new();
+        if kept_title.trim() != removed_title.trim() {
+            let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                         :in $ ?title
+                         :where [?e :block/links ?title]
+                                [?e :block/id ?block-id]
+                                [?e :block/created_at ?created-at]
+                                [?e :block/updated_at ?updated-at]
+                                [(get-else $ ?e :block/content \"\") ?content]
+                                (or-join [?e ?parent-id]
+                                  (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                                  (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                                [(get-else $ ?e :block/order 0) ?order]
+                                [(get-else $ ?e :block/is_page false) ?is-page]
+                                [(get-else $ ?e :block/page_title \"\") ?page-title]
+                                [(get-else $ ?e :block/source \"\") ?source]]";
+            let linking = self.query(query, vec![Value::String(removed_title.trim().to_string())]).await?
+                .iter()
+                .map(Self::row_to_block)
+                .collect::<Result<Vec<_>>>()?;
+            for block in linking {
+                let Some(content) = block.content.as_deref() else { continue };
+                let retargeted = Block::retarget_page_links(content, &removed_title, &kept_title);
+                if retargeted == content {
+                    continue;
+                }
+                tx_data.extend(Self::link_tx(&block.id, Some(content), Some(&retargeted)));
+                tx_data.push(json!({
+                    ":block/id": block.id,
+                    ":block/content": retargeted,
+                    ":block/updated_at": now,
+                }));
+                relinked.push(block.id);
+            }
+        }
+
+        tx_data.push(json!([":db/retractEntity", [":block/id", remove_id]]));
+        self.transact(tx_data).await?;
+        info!(
+            "Merged page {} into {}: moved {} blocks, rewrote links in {}",
+            remove_id, keep_id, moved.len(), relinked.len()
+        );
+        self.changed(DataChange::new(DataChangeKind::PageChanged, [keep_id.to_string()]));
+        self.changed(DataChange::new(
+            DataChangeKind::BlockChanged,
+            moved.into_iter().map(|block| block.id).chain(relinked),
+        ));
+        self.changed(DataChange::new(DataChangeKind::BlockDeleted, [remove_id.to_string()]));
+        Ok(kept)
+    }
+
+    /// Get the page of a daily note, creating it if nobody has opened that day yet.
+    /// Daily note pages are titled with their date.
+    #[instrument(skip(self))]
+    pub async fn ensure_daily_note_page(&self, date: &str) -> Result<Block> {
+        if let Some(page) = self.get_page_by_title(date).await? {
+            return Ok(page);
+        }
+
+        info!("Creating daily note for {}", date);
+        self.create_block(CreateBlockRequest {
+            content: None,
+            parent_id: None,
+            order: 0,
+            is_page: true,
+            page_title: Some(date.to_string()),
+            source: None,
+        }, None).await
+    }
+
+    /// Get daily note blocks. A day nobody has opened yet gets its page
+    /// created if `create_if_missing` is set, and otherwise has no blocks,
+    /// so looking ahead or back through days doesn't leave empty pages behind.
+    #[instrument(skip(self))]
+    pub async fn get_daily_note(&self, date: &str, create_if_missing: bool) -> Result<Vec<Block>> {
+        debug!("Getting daily note for date: {}", date);
         
-        let params = vec![Value::String(page_id.to_string())];
+        let page = if create_if_missing {
+            Some(self.ensure_daily_note_page(date).await?)
+        } else {
+            // Daily note pages are titled with their date
+            self.get_page_by_title(date).await?
+        };
+        match page {
+            Some(page) => self.get_page_blocks(&page.id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the daily note for today in the caller's timezone, given as minutes
+    /// east of UTC (the negation of JavaScript's `getTimezoneOffset()`)
+    #[instrument(skip(self))]
+    pub async fn get_today_daily_note(&self, tz_offset_minutes: i32) -> Result<Vec<Block>> {
+        let date = Self::local_date(Utc::now(), tz_offset_minutes).ok_or_else(|| {
+            DatomicError::TypeConversionError(format!("Invalid UTC offset: {} minutes", tz_offset_minutes))
+        })?;
+        self.get_daily_note(&date, true).await
+    }
+
+    /// The date daily notes are named by at `now` in a UTC offset, or `None`
+    /// if the offset is a day or more
+    pub fn local_date(now: DateTime<Utc>, tz_offset_minutes: i32) -> Option<String> {
+        let offset = FixedOffset::east_opt(tz_offset_minutes.checked_mul(60)?)?;
+        Some(now.with_timezone(&offset).format("%Y-%m-%d").to_string())
+    }
+
+    /// Blocks whose content contains `search_term`, in no particular order
+    async fn matching_blocks(&self, search_term: &str) -> Result<Vec<Block>> {
+        let query = "[:find ?block-id ?content ?parent-id ?order ?is-page ?page-title ?created-at ?updated-at ?source
+                     :in $ ?search-term
+                     :where [?e :block/content ?content]
+                            [(clojure.string/includes? ?content ?search-term)]
+                            [?e :block/id ?block-id]
+                            [?e :block/created_at ?created-at]
+                            [?e :block/updated_at ?updated-at]
+                            (or-join [?e ?parent-id]
+                              (and [?e :block/parent ?p] [?p :block/id ?parent-id])
+                              (and [(missing? $ ?e :block/parent)] [(ground \"\") ?parent-id]))
+                            [(get-else $ ?e :block/order 0) ?order]
+                            [(get-else $ ?e :block/is_page false) ?is-page]
+                            [(get-else $ ?e :block/page_title \"\") ?page-title]
+                            [(get-else $ ?e :block/source \"\") ?source]]";
+
+        let params = vec![Value::String(search_term.to_string())];
+        self.query(query, params).await?
+            .iter()
+            .map(Self::row_to_block)
+            .collect()
+    }
+
+    /// Search blocks by content, best matches first
+    #[instrument(skip(self))]
+    pub async fn search_blocks(&self, search_term: &str) -> Result<Vec<Block>> {
+        debug!("Searching blocks for term: {}", search_term);
+
+        let blocks = self.matching_blocks(search_term).await?;
+        let count = blocks.len();
+        let page = SearchPage::keyset(blocks, search_term, None, count);
+
+        debug!("Found {} blocks matching search term: {}", count, search_term);
+        Ok(page.hits.into_iter().map(|hit| hit.block).collect())
+    }
+
+    /// Up to `limit` blocks matching `search_term`, continuing after the hit
+    /// ranked `after_rank` with ID `after_id` from the previous page. Pages
+    /// are keyed on `(rank, id)` rather than an offset, so a deep page costs
+    /// no more than the first, and the ID tiebreak keeps hits of equal rank
+    /// in a stable order. A match edited between pages can still move past
+    /// the cursor or behind it, like with any keyset. An empty term matches
+    /// nothing.
+    #[instrument(skip(self))]
+    pub async fn search_blocks_after(
+        &self,
+        search_term: &str,
+        after_rank: Option<i64>,
+        after_id: Option<&str>,
+        limit: usize,
+    ) -> Result<SearchPage> {
+        if search_term.is_empty() || limit == 0 {
+            return Ok(SearchPage { hits: Vec::new(), next_rank: None, next_id: None });
+        }
+
+        // The query ranks the matches, keeps those after the cursor and
+        // returns the keys of the first `limit + 1`, the extra one telling
+        // whether there is another page. Keys sort as `[-rank id]`.
+        let query = format!("[:find (min {} ?key)
+                     :in $ ?search-term ?after-rank ?after-id
+                     :where [?e :block/content ?content]
+                            [(clojure.string/includes? ?content ?search-term)]
+                            [?e :block/id ?block-id]
+                            [(java.util.regex.Pattern/quote ?search-term) ?quoted]
+                            [(re-pattern ?quoted) ?pattern]
+                            [(re-seq ?pattern ?content) ?matches]
+                            [(count ?matches) ?rank]
+                            (or-join [?rank ?block-id ?after-rank ?after-id]
+                              [(< ?rank ?after-rank)]
+                              (and [(= ?rank ?after-rank)]
+                                   [(compare ?block-id ?after-id) ?order]
+                                   [(pos? ?order)]))
+                            [(- ?rank) ?descending]
+                            [(vector ?descending ?block-id) ?key]]", limit + 1);
+        let params = vec![
+            Value::String(search_term.to_string()),
+            Value::Number(after_rank.unwrap_or(i64::MAX).into()),
+            Value::String(after_id.unwrap_or_default().to_string()),
+        ];
+        let block_ids: Vec<String> = self.query(&query, params).await?
+            .iter()
+            .filter_map(|row| row.get("0").and_then(Value::as_array))
+            .flatten()
+            .filter_map(|key| key.get(1).and_then(Value::as_str).map(str::to_string))
+            .collect();
+
+        // The blocks are fetched with their timestamps and ranked again here
+        // the same way, to order the page and find its last key
+        let blocks = self.get_blocks_by_ids(&block_ids).await?;
+        let after = after_rank.zip(after_id);
+        let page = SearchPage::keyset(blocks, search_term, after, limit);
+
+        debug!("Returning {} blocks matching search term: {}", page.hits.len(), search_term);
+        Ok(page)
+    }
+
+    /// Read a string column from a query result row.
+    /// Rows are keyed by their :find variable name without the leading `?`.
+    fn row_string(row: &HashMap<String, Value>, column: &str) -> Option<String> {
+        row.get(column).and_then(Value::as_str).map(str::to_string)
+    }
+
+    /// Persist a new audio recording entity
+    #[instrument(skip(self))]
+    pub async fn create_audio_recording(&self, recording: &AudioRecording) -> Result<()> {
+        info!("Creating audio recording: {}", recording.id);
+
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":audio/id".to_string(), Value::String(recording.id.clone()));
+        tx_data.insert(":audio/page".to_string(), json!([":block/id", recording.page_id]));
+        tx_data.insert(":audio/path".to_string(), Value::String(recording.file_path.clone()));
+        tx_data.insert(":audio/created_at".to_string(), Value::String(recording.recorded_at.to_rfc3339()));
+        if let Some(duration) = recording.duration_seconds {
+            tx_data.insert(":audio/duration".to_string(), Value::Number(duration.into()));
+        }
+        tx_data.insert(":audio/system_audio".to_string(), Value::Bool(recording.system_audio));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Link a block to a position within an audio recording
+    #[instrument(skip(self))]
+    pub async fn create_audio_timestamp(&self, block_id: &str, recording_id: &str, timestamp_ms: i64) -> Result<()> {
+        debug!("Creating audio timestamp for block {} at {}ms of {}", block_id, timestamp_ms, recording_id);
+
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":timestamp/block".to_string(), json!([":block/id", block_id]));
+        tx_data.insert(":timestamp/recording_id".to_string(), Value::String(recording_id.to_string()));
+        tx_data.insert(":timestamp/timestamp_ms".to_string(), Value::Number(timestamp_ms.into()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Remove an audio recording and every timestamp pointing at it in one transaction
+    #[instrument(skip(self))]
+    pub async fn delete_audio_recording(&self, recording_id: &str) -> Result<()> {
+        info!("Deleting audio recording: {}", recording_id);
+
+        let query = "[:find ?t
+                     :in $ ?recording-id
+                     :where [?t :timestamp/recording_id ?recording-id]]";
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+
+        let mut tx_data: Vec<Value> = results.iter()
+            .filter_map(|row| row.get("t"))
+            .map(|timestamp_entity| json!([":db/retractEntity", timestamp_entity]))
+            .collect();
+        for segment in self.get_recording_segments(recording_id).await? {
+            let segment_id = Self::segment_id(recording_id, segment.index);
+            tx_data.push(json!([":db/retractEntity", [":segment/id", segment_id]]));
+        }
+        for entity in self.silence_entities(recording_id).await? {
+            tx_data.push(json!([":db/retractEntity", entity]));
+        }
+        for marker in self.get_recording_markers(recording_id).await? {
+            tx_data.push(json!([":db/retractEntity", [":marker/id", marker.id]]));
+        }
+        tx_data.push(json!([":db/retractEntity", [":audio/id", recording_id]]));
+
+        self.transact(tx_data).await?;
+        info!("Audio recording deleted: {}", recording_id);
+        Ok(())
+    }
+
+    /// Convert a query row holding recording-id, page-id, path, duration, created-at and
+    /// optionally system-audio
+    fn row_to_recording(row: &HashMap<String, Value>) -> Result<AudioRecording> {
+        let recorded_at = Self::row_string(row, "created-at")
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or_else(|| DatomicError::type_conversion_error("Invalid :audio/created_at value"))?;
+
+        Ok(AudioRecording {
+            id: Self::row_string(row, "recording-id")
+                .ok_or_else(|| DatomicError::type_conversion_error("Missing :audio/id value"))?,
+            page_id: Self::row_string(row, "page-id").unwrap_or_default(),
+            file_path: Self::row_string(row, "path").unwrap_or_default(),
+            duration_seconds: row.get("duration")
+                .and_then(Value::as_i64)
+                .filter(|d| *d >= 0)
+                .map(|d| d as i32),
+            recorded_at,
+            system_audio: row.get("system-audio").and_then(Value::as_bool).unwrap_or(false),
+        })
+    }
+
+    fn segment_id(recording_id: &str, index: i32) -> String {
+        format!("{}:{}", recording_id, index)
+    }
+
+    /// Store the files a rotated recording was split into
+    #[instrument(skip(self, segments))]
+    pub async fn create_recording_segments(&self, recording_id: &str, segments: &[RecordingSegment]) -> Result<()> {
+        info!("Storing {} segments for recording {}", segments.len(), recording_id);
+
+        let tx_data: Vec<Value> = segments.iter()
+            .map(|segment| {
+                let mut entity = HashMap::new();
+                entity.insert(":segment/id".to_string(), Value::String(Self::segment_id(recording_id, segment.index)));
+                entity.insert(":segment/recording_id".to_string(), Value::String(recording_id.to_string()));
+                entity.insert(":segment/index".to_string(), Value::Number(segment.index.into()));
+                entity.insert(":segment/path".to_string(), Value::String(segment.file_path.clone()));
+                entity.insert(":segment/start_ms".to_string(), Value::Number(segment.start_ms.into()));
+                entity.insert(":segment/duration_ms".to_string(), Value::Number(segment.duration_ms.into()));
+                json!(entity)
+            })
+            .collect();
+
+        if tx_data.is_empty() {
+            return Ok(());
+        }
+
+        self.transact(tx_data).await?;
+        Ok(())
+    }
+
+    /// Get the segments of a recording in order; empty if it was recorded to a single file
+    #[instrument(skip(self))]
+    pub async fn get_recording_segments(&self, recording_id: &str) -> Result<Vec<RecordingSegment>> {
+        let query = "[:find ?index ?path ?start-ms ?duration-ms
+                     :in $ ?recording-id
+                     :where [?s :segment/recording_id ?recording-id]
+                            [?s :segment/index ?index]
+                            [?s :segment/path ?path]
+                            [?s :segment/start_ms ?start-ms]
+                            [?s :segment/duration_ms ?duration-ms]]";
+
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+
+        let mut segments: Vec<RecordingSegment> = results.iter()
+            .map(|row| RecordingSegment {
+                index: row.get("index").and_then(Value::as_i64).unwrap_or(0) as i32,
+                file_path: Self::row_string(row, "path").unwrap_or_default(),
+                start_ms: row.get("start-ms").and_then(Value::as_i64).unwrap_or(0),
+                duration_ms: row.get("duration-ms").and_then(Value::as_i64).unwrap_or(0),
+            })
+            .collect();
+        segments.sort_by_key(|segment| segment.index);
+
+        Ok(segments)
+    }
+
+    /// Entity IDs of a recording's stored silent intervals
+    async fn silence_entities(&self, recording_id: &str) -> Result<Vec<Value>> {
+        let query = "[:find ?s
+                     :in $ ?recording-id
+                     :where [?s :silence/recording_id ?recording-id]]";
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+        Ok(results.iter().filter_map(|row| row.get("s").cloned()).collect())
+    }
+
+    /// Replace a recording's silence map in one transaction
+    #[instrument(skip(self, intervals))]
+    pub async fn store_silence_intervals(&self, recording_id: &str, intervals: &[SilenceInterval]) -> Result<()> {
+        info!("Storing {} silent intervals for recording {}", intervals.len(), recording_id);
+
+        let mut tx_data: Vec<Value> = self.silence_entities(recording_id).await?
+            .into_iter()
+            .map(|entity| json!([":db/retractEntity", entity]))
+            .collect();
+        for interval in intervals {
+            let mut entity = HashMap::new();
+            entity.insert(":silence/recording_id".to_string(), Value::String(recording_id.to_string()));
+            entity.insert(":silence/start_ms".to_string(), Value::Number(interval.start_ms.into()));
+            entity.insert(":silence/end_ms".to_string(), Value::Number(interval.end_ms.into()));
+            tx_data.push(json!(entity));
+        }
+
+        if tx_data.is_empty() {
+            return Ok(());
+        }
+
+        self.transact(tx_data).await?;
+        Ok(())
+    }
+
+    /// Get a recording's silence map in order; empty if it was never analyzed
+    #[instrument(skip(self))]
+    pub async fn get_silence_intervals(&self, recording_id: &str) -> Result<Vec<SilenceInterval>> {
+        let query = "[:find ?start-ms ?end-ms
+                     :in $ ?recording-id
+                     :where [?s :silence/recording_id ?recording-id]
+                            [?s :silence/start_ms ?start-ms]
+                            [?s :silence/end_ms ?end-ms]]";
+
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+
+        let mut intervals: Vec<SilenceInterval> = results.iter()
+            .map(|row| SilenceInterval {
+                start_ms: row.get("start-ms").and_then(Value::as_i64).unwrap_or(0),
+                end_ms: row.get("end-ms").and_then(Value::as_i64).unwrap_or(0),
+            })
+            .collect();
+        intervals.sort_by_key(|interval| interval.start_ms);
+
+        Ok(intervals)
+    }
+
+    /// Record that a recording's leading and trailing silence was cut: shift
+    /// every timestamp into the trimmed file, update the duration and drop
+    /// the now outdated silence map, all in one transaction
+    #[instrument(skip(self))]
+    pub async fn apply_silence_trim(&self, recording_id: &str, trim: &SilenceTrim) -> Result<()> {
+        info!("Applying silence trim to recording {}", recording_id);
+
+        let query = "[:find ?t ?timestamp-ms
+                     :in $ ?recording-id
+                     :where [?t :timestamp/recording_id ?recording-id]
+                            [?t :timestamp/timestamp_ms ?timestamp-ms]]";
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+
+        let mut tx_data = Vec::new();
+        for row in &results {
+            let (Some(entity), Some(timestamp_ms)) = (row.get("t"), row.get("timestamp-ms").and_then(Value::as_i64)) else {
+                continue;
+            };
+            let mut update = HashMap::new();
+            update.insert(":db/id".to_string(), entity.clone());
+            update.insert(":timestamp/timestamp_ms".to_string(), Value::Number(trim.shift_timestamp_ms(timestamp_ms).into()));
+            tx_data.push(json!(update));
+        }
+
+        let mut recording = HashMap::new();
+        recording.insert(":db/id".to_string(), json!([":audio/id", recording_id]));
+        recording.insert(":audio/duration".to_string(), Value::Number((trim.duration_ms / 1000).into()));
+        tx_data.push(json!(recording));
+
+        for entity in self.silence_entities(recording_id).await? {
+            tx_data.push(json!([":db/retractEntity", entity]));
+        }
+
+        self.transact(tx_data).await?;
+        info!("Shifted {} timestamps of recording {} by {}ms", results.len(), recording_id, trim.leading_ms);
+        Ok(())
+    }
+
+    /// Persist a marker dropped during a recording
+    #[instrument(skip(self))]
+    pub async fn create_recording_marker(&self, marker: &RecordingMarker) -> Result<()> {
+        info!("Adding marker at {}ms to recording {}", marker.timestamp_ms, marker.recording_id);
+
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":marker/id".to_string(), Value::String(marker.id.clone()));
+        tx_data.insert(":marker/recording_id".to_string(), Value::String(marker.recording_id.clone()));
+        tx_data.insert(":marker/timestamp_ms".to_string(), Value::Number(marker.timestamp_ms.into()));
+        if let Some(label) = &marker.label {
+            tx_data.insert(":marker/label".to_string(), Value::String(label.clone()));
+        }
+        tx_data.insert(":marker/created_at".to_string(), Value::String(marker.created_at.to_rfc3339()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Convert a query row holding marker-id, recording-id, timestamp-ms, label and created-at
+    fn row_to_marker(row: &HashMap<String, Value>) -> Result<RecordingMarker> {
+        let created_at = Self::row_string(row, "created-at")
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or_else(|| DatomicError::type_conversion_error("Invalid :marker/created_at value"))?;
+
+        Ok(RecordingMarker {
+            id: Self::row_string(row, "marker-id")
+                .ok_or_else(|| DatomicError::type_conversion_error("Missing :marker/id value"))?,
+            recording_id: Self::row_string(row, "recording-id").unwrap_or_default(),
+            timestamp_ms: row.get("timestamp-ms").and_then(Value::as_i64).unwrap_or(0),
+            label: Self::row_string(row, "label").filter(|label| !label.is_empty()),
+            created_at,
+        })
+    }
+
+    /// Get a recording's markers in recording order
+    #[instrument(skip(self))]
+    pub async fn get_recording_markers(&self, recording_id: &str) -> Result<Vec<RecordingMarker>> {
+        let query = "[:find ?marker-id ?recording-id ?timestamp-ms ?label ?created-at
+                     :in $ ?recording-id
+                     :where [?m :marker/recording_id ?recording-id]
+                            [?m :marker/id ?marker-id]
+                            [?m :marker/timestamp_ms ?timestamp-ms]
+                            [?m :marker/created_at ?created-at]
+                            [(get-else $ ?m :marker/label \"\") ?label]]";
+
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+        let mut markers = results.iter()
+            .map(Self::row_to_marker)
+            .collect::<Result<Vec<_>>>()?;
+        markers.sort_by_key(|marker| marker.timestamp_ms);
+
+        Ok(markers)
+    }
+
+    /// Get a marker by its ID
+    #[instrument(skip(self))]
+    pub async fn get_recording_marker(&self, marker_id: &str) -> Result<Option<RecordingMarker>> {
+        let query = "[:find ?marker-id ?recording-id ?timestamp-ms ?label ?created-at
+                     :in $ ?marker-id
+                     :where [?m :marker/id ?marker-id]
+                            [?m :marker/recording_id ?recording-id]
+                            [?m :marker/timestamp_ms ?timestamp-ms]
+                            [?m :marker/created_at ?created-at]
+                            [(get-else $ ?m :marker/label \"\") ?label]]";
+
+        let results = self.query(query, vec![Value::String(marker_id.to_string())]).await?;
+        results.first().map(Self::row_to_marker).transpose()
+    }
+
+    /// Turn a marker into a block under `parent_id`, stamped with the marker's
+    /// position and holding its label. The marker is removed once the block exists.
+    #[instrument(skip(self))]
+    pub async fn convert_marker_to_block(&self, marker_id: &str, parent_id: &str) -> Result<Block> {
+        let marker = self.get_recording_marker(marker_id).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Marker {}", marker_id)))?;
+        if !self.block_exists(parent_id).await? {
+            return Err(DatomicError::entity_not_found(format!("Block {}", parent_id)));
+        }
+
+        // Append after the parent's existing children
+        let query = "[:find ?c
+                     :in $ ?parent-id
+                     :where [?p :block/id ?parent-id]
+                            [?c :block/parent ?p]]";
+        let children = self.query(query, vec![Value::String(parent_id.to_string())]).await?;
+
+        let block_data = CreateBlockRequest {
+            content: marker.label.clone(),
+            parent_id: Some(parent_id.to_string()),
+            order: children.len() as i32,
+            is_page: false,
+            page_title: None,
+            source: Some("marker".to_string()),
+        };
+        let audio_meta = AudioMeta {
+            recording_id: marker.recording_id.clone(),
+            timestamp_ms: marker.timestamp_ms,
+        };
+        let block = self.create_block(block_data, Some(audio_meta)).await?;
+
+        self.transact(vec![json!([":db/retractEntity", [":marker/id", marker_id]])]).await?;
+        info!("Converted marker {} into block {}", marker_id, block.id);
+        Ok(block)
+    }
+
+    /// Save drafts of unwritten block edits, replacing any earlier draft of
+    /// the same block
+    #[instrument(skip(self, drafts))]
+    pub async fn save_drafts(&self, drafts: &[Draft]) -> Result<()> {
+        if drafts.is_empty() {
+            return Ok(());
+        }
+        let tx_data = drafts.iter()
+            .map(|draft| json!({
+                ":draft/block_id": draft.block_id,
+                ":draft/content": draft.content,
+                ":draft/saved_at": draft.saved_at.to_rfc3339(),
+            }))
+            .collect();
+        self.transact(tx_data).await?;
+        debug!("Saved drafts of {} blocks", drafts.len());
+        Ok(())
+    }
+
+    /// Convert a query row holding block-id, content and saved-at
+    fn row_to_draft(row: &HashMap<String, Value>) -> Result<Draft> {
+        let saved_at = Self::row_string(row, "saved-at")
+            .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok_or_else(|| DatomicError::type_conversion_error("Invalid :draft/saved_at value"))?;
+
+        Ok(Draft {
+            block_id: Self::row_string(row, "block-id")
+                .ok_or_else(|| DatomicError::type_conversion_error("Missing :draft/block_id value"))?,
+            content: Self::row_string(row, "content").unwrap_or_default(),
+            saved_at,
+        })
+    }
+
+    /// Every saved draft, whether or not its block was written since
+    #[instrument(skip(self))]
+    pub async fn get_drafts(&self) -> Result<Vec<Draft>> {
+        let query = "[:find ?block-id ?content ?saved-at
+                     :where [?d :draft/block_id ?block-id]
+                            [?d :draft/content ?content]
+                            [?d :draft/saved_at ?saved-at]]";
+        self.query(query, Vec::new()).await?
+            .iter()
+            .map(Self::row_to_draft)
+            .collect()
+    }
+
+    /// Get the draft of a block, if one was saved
+    #[instrument(skip(self))]
+    pub async fn get_draft(&self, block_id: &str) -> Result<Option<Draft>> {
+        let query = "[:find ?block-id ?content ?saved-at
+                     :in $ ?block-id
+                     :where [?d :draft/block_id ?block-id]
+                            [?d :draft/content ?content]
+                            [?d :draft/saved_at ?saved-at]]";
+        let results = self.query(query, vec![Value::String(block_id.to_string())]).await?;
+        results.first().map(Self::row_to_draft).transpose()
+    }
+
+    /// Remove a block's draft. Returns false if it had none.
+    #[instrument(skip(self))]
+    pub async fn delete_draft(&self, block_id: &str) -> Result<bool> {
+        if self.get_draft(block_id).await?.is_none() {
+            return Ok(false);
+        }
+        self.transact(vec![json!([":db/retractEntity", [":draft/block_id", block_id]])]).await?;
+        Ok(true)
+    }
+
+    /// Get an audio recording by its ID
+    #[instrument(skip(self))]
+    pub async fn get_recording(&self, recording_id: &str) -> Result<Option<AudioRecording>> {
+        let query = "[:find ?recording-id ?page-id ?path ?duration ?created-at ?system-audio
+                     :in $ ?recording-id
+                     :where [?r :audio/id ?recording-id]
+                            [?r :audio/page ?p]
+                            [?p :block/id ?page-id]
+                            [?r :audio/path ?path]
+                            [?r :audio/created_at ?created-at]
+                            [(get-else $ ?r :audio/duration -1) ?duration]
+                            [(get-else $ ?r :audio/system_audio false) ?system-audio]]";
+
+        let params = vec![Value::String(recording_id.to_string())];
         let results = self.query(query, params).await?;
-        
-        // Convert results to blocks
-        let blocks = Vec::new(); // Removed mut
-        for _result in results { // Prefixed with underscore
-            // TODO: Implement proper result conversion
-            // This is a placeholder
+
+        results.first().map(Self::row_to_recording).transpose()
+    }
+
+    /// Get every recording made on a page, oldest first
+    #[instrument(skip(self))]
+    pub async fn get_page_recordings(&self, page_id: &str) -> Result<Vec<AudioRecording>> {
+        let query = "[:find ?recording-id ?page-id ?path ?duration ?created-at ?system-audio
+                     :in $ ?page-id
+                     :where [?p :block/id ?page-id]
+                            [?r :audio/page ?p]
+                            [?r :audio/id ?recording-id]
+                            [?r :audio/path ?path]
+                            [?r :audio/created_at ?created-at]
+                            [(get-else $ ?r :audio/duration -1) ?duration]
+                            [(get-else $ ?r :audio/system_audio false) ?system-audio]]";
+
+        let results = self.query(query, vec![Value::String(page_id.to_string())]).await?;
+        let mut recordings = results.iter()
+            .map(Self::row_to_recording)
+            .collect::<Result<Vec<_>>>()?;
+        recordings.sort_by(|a, b| a.recorded_at.cmp(&b.recorded_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(recordings)
+    }
+
+    /// Record the progress of a recording's transcription
+    #[cfg(feature = "transcription")]
+    #[instrument(skip(self))]
+    pub async fn set_transcription_status(&self, recording_id: &str, status: &str) -> Result<()> {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), json!([":audio/id", recording_id]));
+        tx_data.insert(":audio/transcription_status".to_string(), Value::String(status.to_string()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Get recordings that never received a duration and haven't been flagged,
+    /// i.e. recordings interrupted by a crash
+    #[instrument(skip(self))]
+    pub async fn get_unfinished_recordings(&self) -> Result<Vec<AudioRecording>> {
+        let query = "[:find ?recording-id ?page-id ?path ?created-at
+                     :where [?r :audio/id ?recording-id]
+                            [?r :audio/page ?p]
+                            [?p :block/id ?page-id]
+                            [?r :audio/path ?path]
+                            [?r :audio/created_at ?created-at]
+                            [(missing? $ ?r :audio/duration)]
+                            [(missing? $ ?r :audio/status)]]";
+
+        let results = self.query(query, Vec::new()).await?;
+        let recordings = results.iter()
+            .map(Self::row_to_recording)
+            .collect::<Result<Vec<_>>>()?;
+
+        debug!("Found {} unfinished recordings", recordings.len());
+        Ok(recordings)
+    }
+
+    /// Get every recording that finished with a duration
+    #[instrument(skip(self))]
+    pub async fn get_finished_recordings(&self) -> Result<Vec<AudioRecording>> {
+        let query = "[:find ?recording-id ?page-id ?path ?duration ?created-at ?system-audio
+                     :where [?r :audio/id ?recording-id]
+                            [?r :audio/page ?p]
+                            [?p :block/id ?page-id]
+                            [?r :audio/path ?path]
+                            [?r :audio/created_at ?created-at]
+                            [?r :audio/duration ?duration]
+                            [(get-else $ ?r :audio/system_audio false) ?system-audio]]";
+
+        let results = self.query(query, Vec::new()).await?;
+        results.iter()
+            .map(Self::row_to_recording)
+            .collect()
+    }
+
+    /// Get every recording, finished or not
+    #[instrument(skip(self))]
+    pub async fn get_all_recordings(&self) -> Result<Vec<AudioRecording>> {
+        let query = "[:find ?recording-id ?page-id ?path ?created-at ?system-audio
+                     :where [?r :audio/id ?recording-id]
+                            [?r :audio/page ?p]
+                            [?p :block/id ?page-id]
+                            [?r :audio/path ?path]
+                            [?r :audio/created_at ?created-at]
+                            [(get-else $ ?r :audio/system_audio false) ?system-audio]]";
+        let mut recordings = self.query(query, Vec::new()).await?
+            .iter()
+            .map(Self::row_to_recording)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Recordings cut short have no duration, so it's looked up on its own
+        let durations_query = "[:find ?recording-id ?duration
+                               :where [?r :audio/id ?recording-id]
+                                      [?r :audio/duration ?duration]]";
+        let durations: HashMap<String, i32> = self.query(durations_query, Vec::new()).await?
+            .iter()
+            .filter_map(|row| Some((Self::row_string(row, "recording-id")?, row.get("duration")?.as_i64()? as i32)))
+            .collect();
+        for recording in &mut recordings {
+            recording.duration_seconds = durations.get(&recording.id).copied();
         }
-        
-        debug!("Retrieved {} blocks for page: {}", blocks.len(), page_id);
-        Ok(blocks)
+        Ok(recordings)
     }
 
-    /// Get daily note blocks
+    /// Set the duration of a finished recording
     #[instrument(skip(self))]
-    pub async fn get_daily_note(&self, date: &str) -> Result<Vec<Block>> {
-        debug!("Getting daily note for date: {}", date);
-        
-        let page_id = format!("daily-{}", date);
-        self.get_page_blocks(&page_id).await
+    pub async fn update_recording_duration(&self, recording_id: &str, duration_seconds: i32) -> Result<()> {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), json!([":audio/id", recording_id]));
+        tx_data.insert(":audio/duration".to_string(), Value::Number(duration_seconds.into()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
     }
 
-    /// Search blocks by content
+    /// Flag a recording that could not be completed
     #[instrument(skip(self))]
-    pub async fn search_blocks(&self, search_term: &str) -> Result<Vec<Block>> {
-        debug!("Searching blocks for term: {}", search_term);
-        
-        let query = "[:find ?block-id ?content ?created-at ?updated-at ?page-id ?parent-id ?order ?audio-file ?audio-timestamp
-                     :in $ ?search-term
-                     :where [?e :block/content ?content]
-                            [(clojure.string/includes? ?content ?search-term)]
-                            [?e :block/id ?block-id]
-                            [?e :block/created-at ?created-at]
-                            [?e :block/updated-at ?updated-at]
-                            [(get-else $ ?e :block/page nil) ?page-id]
-                            [(get-else $ ?e :block/parent nil) ?parent-id]
-                            [(get-else $ ?e :block/order 0) ?order]
-                            [(get-else $ ?e :block/audio-file nil) ?audio-file]
-                            [(get-else $ ?e :block/audio-timestamp nil) ?audio-timestamp]]";
-        
-        let params = vec![Value::String(search_term.to_string())];
+    pub async fn set_recording_status(&self, recording_id: &str, status: &str) -> Result<()> {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), json!([":audio/id", recording_id]));
+        tx_data.insert(":audio/status".to_string(), Value::String(status.to_string()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Store a recording's loudness after normalization
+    #[instrument(skip(self))]
+    pub async fn set_recording_loudness(&self, recording_id: &str, loudness_lufs: f64) -> Result<()> {
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), json!([":audio/id", recording_id]));
+        tx_data.insert(":audio/loudness_lufs".to_string(), json!(loudness_lufs));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        Ok(())
+    }
+
+    /// Move every recording's files into `new_dir` and point the recordings
+    /// and their segments at the new paths in one transaction. Recordings with
+    /// a missing file are skipped and logged. Returns how many were moved.
+    #[instrument(skip(self))]
+    pub async fn relocate_recordings(&self, new_dir: &Path) -> Result<u64> {
+        info!("Relocating recordings to {}", new_dir.display());
+        std::fs::create_dir_all(new_dir)?;
+
+        let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut tx_data = Vec::new();
+        let mut moved = 0;
+        for recording in self.get_all_recordings().await? {
+            let segments = self.get_recording_segments(&recording.id).await?;
+            let relocated = |file_path: &str| Path::new(file_path).file_name().map(|name| new_dir.join(name));
+
+            // The first segment shares the recording's path
+            let mut files = vec![recording.file_path.clone()];
+            files.extend(segments.iter().map(|segment| segment.file_path.clone()).filter(|path| *path != recording.file_path));
+            if let Some(missing) = files.iter().find(|path| !Path::new(path).is_file()) {
+                warn!("Skipping recording {}: {} is missing", recording.id, missing);
+                continue;
+            }
+            if files.iter().all(|path| relocated(path).as_deref() == Some(Path::new(path))) {
+                continue;
+            }
+
+            for file_path in &files {
+                let from = PathBuf::from(file_path);
+                let to = relocated(file_path)
+                    .ok_or_else(|| DatomicError::invalid_transaction_data(format!("{} has no file name", file_path)))?;
+                if to == from {
+                    continue;
+                }
+                if let Err(e) = Self::move_file(&from, &to) {
+                    Self::undo_moves(&moves);
+                    return Err(e.into());
+                }
+                moves.push((from, to));
+            }
+
+            let mut recording_tx = HashMap::new();
+            recording_tx.insert(":db/id".to_string(), json!([":audio/id", recording.id]));
+            recording_tx.insert(":audio/path".to_string(), Value::String(Self::path_string(relocated(&recording.file_path))));
+            tx_data.push(json!(recording_tx));
+            for segment in &segments {
+                let mut segment_tx = HashMap::new();
+                segment_tx.insert(":db/id".to_string(), json!([":segment/id", Self::segment_id(&recording.id, segment.index)]));
+                segment_tx.insert(":segment/path".to_string(), Value::String(Self::path_string(relocated(&segment.file_path))));
+                tx_data.push(json!(segment_tx));
+            }
+            moved += 1;
+        }
+
+        if tx_data.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = self.transact(tx_data).await {
+            // Without the new paths the files have to stay where the database says they are
+            Self::undo_moves(&moves);
+            return Err(e);
+        }
+        info!("Relocated {} recordings to {}", moved, new_dir.display());
+        Ok(moved)
+    }
+
+    fn path_string(path: Option<PathBuf>) -> String {
+        path.map(|path| path.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+
+    /// Move a file without replacing anything, copying it when `to` is on
+    /// another drive
+    fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+        if to.exists() {
+            return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} already exists", to.display())));
+        }
+        if std::fs::rename(from, to).is_err() {
+            std::fs::copy(from, to)?;
+            std::fs::remove_file(from)?;
+        }
+        Ok(())
+    }
+
+    fn undo_moves(moves: &[(PathBuf, PathBuf)]) {
+        for (from, to) in moves.iter().rev() {
+            if let Err(e) = Self::move_file(to, from) {
+                error!("Failed to move {} back to {}: {}", to.display(), from.display(), e);
+            }
+        }
+    }
+
+    /// Get the audio timestamp linked to a block, with its recording hydrated
+    #[instrument(skip(self))]
+    pub async fn get_block_audio_timestamp(&self, block_id: &str) -> Result<Option<AudioTimestamp>> {
+        let query = "[:find ?recording-id ?timestamp-ms
+                     :in $ ?block-id
+                     :where [?b :block/id ?block-id]
+                            [?t :timestamp/block ?b]
+                            [?t :timestamp/recording_id ?recording-id]
+                            [?t :timestamp/timestamp_ms ?timestamp-ms]]";
+
+        let params = vec![Value::String(block_id.to_string())];
         let results = self.query(query, params).await?;
-        
-        // Convert results to blocks
-        let blocks = Vec::new(); // Removed mut
-        for _result in results { // Prefixed with underscore
-            // TODO: Implement proper result conversion
-            // This is a placeholder
+
+        let row = match results.first() {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let recording_id = Self::row_string(row, "recording-id")
+            .ok_or_else(|| DatomicError::type_conversion_error("Missing :timestamp/recording_id value"))?;
+        let timestamp_ms = row.get("timestamp-ms").and_then(Value::as_i64).unwrap_or(0);
+        let recording = self.get_recording(&recording_id).await?;
+        let segments = self.get_recording_segments(&recording_id).await?;
+
+        Ok(Some(AudioTimestamp {
+            recording,
+            segment: RecordingSegment::locate(&segments, timestamp_ms).cloned(),
+            ..AudioTimestamp::new(block_id, &recording_id, timestamp_ms)
+        }))
+    }
+
+    /// Get the audio timestamps linked to several blocks in one query, keyed
+    /// by block ID. Each recording and its segments are loaded once however
+    /// many of the blocks point into it.
+    #[instrument(skip(self))]
+    pub async fn get_audio_timestamps_for_blocks(&self, block_ids: &[String]) -> Result<HashMap<String, AudioTimestamp>> {
+        if block_ids.is_empty() {
+            return Ok(HashMap::new());
         }
-        
-        debug!("Found {} blocks matching search term: {}", blocks.len(), search_term);
-        Ok(blocks)
+
+        let query = "[:find ?block-id ?recording-id ?timestamp-ms
+                     :in $ [?block-id ...]
+                     :where [?b :block/id ?block-id]
+                            [?t :timestamp/block ?b]
+                            [?t :timestamp/recording_id ?recording-id]
+                            [?t :timestamp/timestamp_ms ?timestamp-ms]]";
+        let ids = block_ids.iter().cloned().map(Value::String).collect();
+        let results = self.query(query, vec![Value::Array(ids)]).await?;
+
+        let mut recordings = HashMap::new();
+        let mut timestamps = HashMap::new();
+        for row in &results {
+            let (Some(block_id), Some(recording_id)) = (Self::row_string(row, "block-id"), Self::row_string(row, "recording-id")) else {
+                continue;
+            };
+            let timestamp_ms = row.get("timestamp-ms").and_then(Value::as_i64).unwrap_or(0);
+            if !recordings.contains_key(&recording_id) {
+                let recording = self.get_recording(&recording_id).await?;
+                let segments = self.get_recording_segments(&recording_id).await?;
+                recordings.insert(recording_id.clone(), (recording, segments));
+            }
+            let (recording, segments) = &recordings[&recording_id];
+            timestamps.insert(block_id.clone(), AudioTimestamp {
+                recording: recording.clone(),
+                segment: RecordingSegment::locate(segments, timestamp_ms).cloned(),
+                ..AudioTimestamp::new(&block_id, &recording_id, timestamp_ms)
+            });
+        }
+        Ok(timestamps)
+    }
+
+    /// Get every block timestamp pointing into a recording, earliest first,
+    /// with the recording and segment hydrated. Blocks already carry their
+    /// own timestamp, so they are matched up by `block_id` rather than embedded.
+    #[instrument(skip(self))]
+    pub async fn get_recording_timestamps(&self, recording_id: &str) -> Result<Vec<AudioTimestamp>> {
+        let query = "[:find ?block-id ?timestamp-ms
+                     :in $ ?recording-id
+                     :where [?t :timestamp/recording_id ?recording-id]
+                            [?t :timestamp/timestamp_ms ?timestamp-ms]
+                            [?t :timestamp/block ?b]
+                            [?b :block/id ?block-id]]";
+        let results = self.query(query, vec![Value::String(recording_id.to_string())]).await?;
+        if results.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let recording = self.get_recording(recording_id).await?;
+        let segments = self.get_recording_segments(recording_id).await?;
+
+        let mut timestamps: Vec<AudioTimestamp> = results.iter()
+            .filter_map(|row| {
+                let block_id = Self::row_string(row, "block-id")?;
+                let timestamp_ms = row.get("timestamp-ms").and_then(Value::as_i64)?;
+                Some(AudioTimestamp {
+                    recording: recording.clone(),
+                    segment: RecordingSegment::locate(&segments, timestamp_ms).cloned(),
+                    ..AudioTimestamp::new(&block_id, recording_id, timestamp_ms)
+                })
+            })
+            .collect();
+        timestamps.sort_by(|a, b| a.timestamp_ms.cmp(&b.timestamp_ms).then_with(|| a.block_id.cmp(&b.block_id)));
+        Ok(timestamps)
+    }
+
+    /// Whether a block with this ID exists
+    async fn block_exists(&self, block_id: &str) -> Result<bool> {
+        let query = "[:find ?b
+                     :in $ ?block-id
+                     :where [?b :block/id ?block-id]]";
+        let results = self.query(query, vec![Value::String(block_id.to_string())]).await?;
+        Ok(!results.is_empty())
+    }
+
+    /// Link an existing block to a position in an existing recording, for
+    /// annotating blocks written before or after the recording was made
+    #[instrument(skip(self))]
+    pub async fn attach_timestamp(&self, block_id: &str, recording_id: &str, timestamp_ms: i64) -> Result<AudioTimestamp> {
+        info!("Attaching block {} to {}ms of recording {}", block_id, timestamp_ms, recording_id);
+
+        if timestamp_ms < 0 {
+            return Err(DatomicError::invalid_transaction_data(format!("Negative timestamp: {}ms", timestamp_ms)));
+        }
+        if !self.block_exists(block_id).await? {
+            return Err(DatomicError::entity_not_found(format!("Block {}", block_id)));
+        }
+        let recording = self.get_recording(recording_id).await?
+            .ok_or_else(|| DatomicError::entity_not_found(format!("Recording {}", recording_id)))?;
+
+        let query = "[:find ?t
+                     :in $ ?block-id ?recording-id
+                     :where [?b :block/id ?block-id]
+                            [?t :timestamp/block ?b]
+                            [?t :timestamp/recording_id ?recording-id]]";
+        let existing = self.query(query, vec![
+            Value::String(block_id.to_string()),
+            Value::String(recording_id.to_string()),
+        ]).await?;
+        if !existing.is_empty() {
+            // A block holds at most one timestamp per recording
+            return Err(DatomicError::invalid_transaction_data(format!(
+                "Block {} already has a timestamp for recording {}", block_id, recording_id
+            )));
+        }
+
+        self.create_audio_timestamp(block_id, recording_id, timestamp_ms).await?;
+        self.changed(DataChange::new(DataChangeKind::BlockChanged, [block_id.to_string()]));
+
+        let segments = self.get_recording_segments(recording_id).await?;
+        Ok(AudioTimestamp {
+            recording: Some(recording),
+            segment: RecordingSegment::locate(&segments, timestamp_ms).cloned(),
+            ..AudioTimestamp::new(block_id, recording_id, timestamp_ms)
+        })
+    }
+
+    /// Point a block's audio timestamp at a different recording, e.g. after a re-import
+    #[instrument(skip(self))]
+    pub async fn relink_timestamp(&self, block_id: &str, old_recording_id: &str, new_recording_id: &str) -> Result<()> {
+        info!("Relinking timestamp of block {} from {} to {}", block_id, old_recording_id, new_recording_id);
+
+        let query = "[:find ?t
+                     :in $ ?block-id ?recording-id
+                     :where [?b :block/id ?block-id]
+                            [?t :timestamp/block ?b]
+                            [?t :timestamp/recording_id ?recording-id]]";
+
+        let results = self.query(query, vec![
+            Value::String(block_id.to_string()),
+            Value::String(old_recording_id.to_string()),
+        ]).await?;
+        let timestamp_entity = results.first()
+            .and_then(|row| row.get("t").cloned())
+            .ok_or_else(|| DatomicError::entity_not_found(format!(
+                "Timestamp for block {} in recording {}", block_id, old_recording_id
+            )))?;
+
+        if old_recording_id == new_recording_id {
+            return Ok(());
+        }
+
+        if self.get_recording(new_recording_id).await?.is_none() {
+            return Err(DatomicError::entity_not_found(format!("Recording {}", new_recording_id)));
+        }
+
+        let existing = self.query(query, vec![
+            Value::String(block_id.to_string()),
+            Value::String(new_recording_id.to_string()),
+        ]).await?;
+        if !existing.is_empty() {
+            // A block holds at most one timestamp per recording
+            return Err(DatomicError::invalid_transaction_data(format!(
+                "Block {} already has a timestamp for recording {}", block_id, new_recording_id
+            )));
+        }
+
+        let mut tx_data = HashMap::new();
+        tx_data.insert(":db/id".to_string(), timestamp_entity);
+        tx_data.insert(":timestamp/recording_id".to_string(), Value::String(new_recording_id.to_string()));
+
+        self.transact(vec![json!(tx_data)]).await?;
+        self.changed(DataChange::new(DataChangeKind::BlockChanged, [block_id.to_string()]));
+        info!("Timestamp relinked for block {}", block_id);
+        Ok(())
+    }
+
+    /// Run storage maintenance: request an indexing job and garbage-collect
+    /// storage segments that are no longer reachable.
+    ///
+    /// Both steps put load on the transactor and storage, so this should only
+    /// be triggered while the app is idle (e.g. from the settings screen).
+    #[instrument(skip(self))]
+    pub async fn maintenance(&self) -> Result<()> {
+        info!("Running database maintenance");
+
+        let db_uri = self.config.build_uri();
+        let jvm = self.jvm.clone();
+        let connection = self.connection.clone();
+
+        let operation = move || -> Result<()> {
+            let mut env = Self::attach(&jvm)?;
+
+            Self::with_connection(&mut env, &connection, &db_uri, |env, conn| {
+                // Ask the transactor to fold recent novelty into the indexes
+                let index_requested = env.call_method(conn, "requestIndex", "()Z", &[])
+                    .map_err(|e| Self::java_error(env, e, DatomicError::ConnectionError))?
+                    .z()?;
+                debug!("Index request accepted: {}", index_requested);
+
+                // Reclaim storage garbage older than the retention window
+                let older_than_ms = (Utc::now() - chrono::Duration::days(GC_STORAGE_RETENTION_DAYS)).timestamp_millis();
+                let older_than = env.new_object("java/util/Date", "(J)V", &[JValue::Long(older_than_ms)])?;
+                env.call_method(conn, "gcStorage", "(Ljava/util/Date;)V", &[JValue::Object(&older_than)])
+                    .map_err(|e| Self::java_error(env, e, DatomicError::ConnectionError))?;
+
+                Ok(())
+            })
+        };
+
+        self.retried("maintenance", operation).await?;
+        info!("Database maintenance completed");
+        Ok(())
+    }
+
+    /// Release the peer's connections and the cached connection's global
+    /// reference. Every later operation on this client fails with a
+    /// `ConnectionError`. With `process_exiting` Clojure's agent threads are
+    /// stopped too, after which no client can be created in this process;
+    /// without it a new client can connect again.
+    #[instrument(skip(self))]
+    pub async fn shutdown(&self, process_exiting: bool) -> Result<()> {
+        info!("Shutting down the Datomic peer");
+
+        self.connection.shut_down.store(true, Ordering::SeqCst);
+        self.connection.reset();
+        let jvm = self.jvm.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut env = Self::attach(&jvm)?;
+            env.call_static_method("datomic/Peer", "shutdown", "(Z)V", &[JValue::Bool(process_exiting as u8)])
+                .map_err(|e| Self::java_error(&mut env, e, DatomicError::ConnectionError))?;
+            Ok::<_, DatomicError>(())
+        })
+        .await
+        .map_err(|e| DatomicError::internal_error(format!("Shutdown thread failed: {}", e)))??;
+
+        // Each attached runtime thread detaches itself when it exits
+        debug!("Datomic peer shut down, {} threads still attached to the JVM", self.jvm.threads_attached());
+        Ok(())
+    }
+
+    /// Compare the attributes installed in the database against `gita_schema_edn()`
+    /// without transacting anything
+    #[instrument(skip(self))]
+    pub async fn schema_diff(&self) -> Result<SchemaDiff> {
+        debug!("Computing schema diff");
+
+        let query = "[:find ?ident :where [_ :db/ident ?ident]]";
+        let results = self.query(query, Vec::new()).await?;
+
+        let existing_idents: HashSet<String> = results.iter()
+            .filter_map(|row| Self::row_string(row, "ident"))
+            .collect();
+
+        let diff = diff_schema(&existing_idents);
+        info!("Schema diff: {} present, {} missing", diff.present.len(), diff.missing.len());
+        Ok(diff)
     }
 
     /// Health check
@@ -674,7 +2951,7 @@ impl DatomicPeerClient {
         
         let healthy = !results.is_empty();
         if healthy {
-            debug!("Health check passed");
+            debug!("Health check passed, {} connection(s) opened so far", self.connections_opened());
         } else {
             warn!("Health check failed");
         }
@@ -683,11 +2960,130 @@ impl DatomicPeerClient {
     }
 }
 
+/// Run `f` on a blocking thread, giving up with a `TimeoutError` after
+/// `limit`. A JNI call can't be interrupted, so `f` keeps its thread until
+/// it returns, but the caller doesn't wait for it.
+async fn run_blocking<T: Send + 'static>(limit: Duration, f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    match timeout(limit, tokio::task::spawn_blocking(f)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(DatomicError::internal_error(format!("Blocking thread failed: {}", e))),
+        Err(_) => Err(DatomicError::timeout_error(limit.as_millis() as u64)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::AppConfig;
     
+    #[test]
+    fn test_local_date_follows_offset() {
+        let now = DateTime::parse_from_rfc3339("2024-03-09T22:30:00Z").unwrap().with_timezone(&Utc);
+
+        assert_eq!(DatomicPeerClient::local_date(now, 0).as_deref(), Some("2024-03-09"));
+        // Past midnight two hours east of UTC, still the evening before in New York
+        assert_eq!(DatomicPeerClient::local_date(now, 120).as_deref(), Some("2024-03-10"));
+        assert_eq!(DatomicPeerClient::local_date(now, -300).as_deref(), Some("2024-03-09"));
+        assert_eq!(DatomicPeerClient::local_date(now, 90).as_deref(), Some("2024-03-10"));
+        assert_eq!(DatomicPeerClient::local_date(now, 89).as_deref(), Some("2024-03-09"));
+
+        assert_eq!(DatomicPeerClient::local_date(now, 24 * 60), None);
+    }
+
+    #[test]
+    fn test_find_columns_name_rows() {
+        let query = "[:find ?block-id ?content
+                     :in $ ?page-id
+                     :where [?b :block/id ?block-id]]";
+        assert_eq!(DatomicPeerClient::find_columns(query), vec!["block-id", "content"]);
+
+        // Expressions are keyed by their position
+        let query = "[:find ?page-id (count ?b) (pull ?p [:block/id {:block/children [*]}]) :with ?b :where [?b :block/page ?p]]";
+        assert_eq!(DatomicPeerClient::find_columns(query), vec!["page-id", "1", "2"]);
+
+        assert!(DatomicPeerClient::find_columns("[:where [?e :db/ident]]").is_empty());
+    }
+
+    #[test]
+    fn test_pulled_keys_drop_the_keyword_colon() {
+        let pulled = json!({
+            ":block/id": "b1",
+            ":block/parent": {":block/id": "p1", ":db/id": 17592186045418_i64},
+            ":block/_parent": [{":block/id": "c1"}, {":block/id": "c2"}],
+            ":audio/status": {":db/ident": ":status/done"},
+            "plain": ":not-a-key",
+            ": spaced": 1,
+        });
+        assert_eq!(DatomicPeerClient::pulled_keys(pulled), json!({
+            "block/id": "b1",
+            "block/parent": {"block/id": "p1", "db/id": 17592186045418_i64},
+            "block/_parent": [{"block/id": "c1"}, {"block/id": "c2"}],
+            "audio/status": {"db/ident": ":status/done"},
+            "plain": ":not-a-key",
+            ": spaced": 1,
+        }));
+    }
+
+    #[test]
+    fn test_pulled_block_has_every_attribute() {
+        let pulled = DatomicPeerClient::pulled_keys(json!({
+            ":block/id": "b1",
+            ":block/content": "Agenda [[Budget]]",
+            ":block/is_page": false,
+            ":block/parent": {":block/id": "p1"},
+            ":block/order": 3,
+            ":block/created_at": "2024-05-01T09:00:00+00:00",
+            ":block/updated_at": "2024-05-02T10:30:00+00:00",
+            ":block/source": "import",
+        }));
+        let block = DatomicPeerClient::pull_to_block(&pulled).unwrap().unwrap();
+        assert_eq!(block.id, "b1");
+        assert_eq!(block.content.as_deref(), Some("Agenda [[Budget]]"));
+        assert_eq!(block.parent_id.as_deref(), Some("p1"));
+        assert_eq!(block.order, 3);
+        assert!(!block.is_page);
+        assert_eq!(block.page_title, None);
+        assert_eq!(block.updated_at.to_rfc3339(), "2024-05-02T10:30:00+00:00");
+        assert_eq!(block.source.as_deref(), Some("import"));
+        let entity = DatomicPeerClient::block_entity(&block, &HashSet::new());
+        assert_eq!(entity[":block/source"], json!("import"));
+        assert_eq!(entity[":block/parent"], json!([":block/id", "p1"]));
+
+        assert!(DatomicPeerClient::pull_to_block(&Value::Null).unwrap().is_none());
+        let no_timestamps = json!({"block/id": "b3"});
+        assert!(matches!(DatomicPeerClient::pull_to_block(&no_timestamps), Err(DatomicError::TypeConversionError(_))));
+    }
+
+    #[test]
+    fn test_history_rows_keep_millisecond_instants() {
+        let row: HashMap<String, Value> = [
+            ("tx", json!(13194139534313_i64)),
+            ("tx-instant", json!("2024-05-01T09:00:00.125+00:00")),
+            ("content", json!("Agenda")),
+            ("added", json!(false)),
+        ].into_iter().map(|(column, value)| (column.to_string(), value)).collect();
+        let revision = DatomicPeerClient::row_to_revision(&row).unwrap();
+        assert_eq!(revision.tx, 13194139534313);
+        assert_eq!(revision.tx_instant.timestamp_millis(), 1714554000125);
+        assert!(!revision.added);
+
+        let mut no_instant = row.clone();
+        no_instant.remove("tx-instant");
+        assert!(matches!(DatomicPeerClient::row_to_revision(&no_instant), Err(DatomicError::TypeConversionError(_))));
+    }
+
+    #[test]
+    fn test_query_inputs_reject_nulls_and_maps() {
+        let check = DatomicPeerClient::check_query_input;
+        assert!(check(&json!("budget")).is_ok());
+        assert!(check(&json!([":block/id", "b1"])).is_ok());
+        assert!(check(&json!([1, 2.5, true])).is_ok());
+
+        assert!(matches!(check(&Value::Null), Err(DatomicError::TypeConversionError(_))));
+        assert!(matches!(check(&json!(["b1", null])), Err(DatomicError::TypeConversionError(_))));
+        assert!(matches!(check(&json!({"id": "b1"})), Err(DatomicError::TypeConversionError(_))));
+    }
+
     #[tokio::test]
     async fn test_client_creation() {
         let config = AppConfig::default();
@@ -707,4 +3103,66 @@ mod tests {
         assert_eq!(config.max_attempts, 3);
         assert_eq!(config.initial_delay_ms, 100);
     }
+
+    #[test]
+    #[ignore] // Requires a JVM with the Datomic libraries available
+    fn test_get_or_create_jvm_concurrently() {
+        let config = AppConfig::default().datomic;
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let config = config.clone();
+                std::thread::spawn(move || DatomicPeerClient::get_or_create_jvm(&config))
+            })
+            .collect();
+
+        let jvms: Vec<Arc<JavaVM>> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("JVM initialization panicked").expect("JVM initialization failed"))
+            .collect();
+
+        for jvm in &jvms[1..] {
+            assert!(Arc::ptr_eq(&jvms[0], jvm));
+        }
+
+        // Only one JVM can exist per process, so a later call with other
+        // settings gets the running one instead of starting another
+        let other_config = DatomicConfig { jvm_opts: vec!["-Xmx64m".to_string()], ..config };
+        let again = DatomicPeerClient::get_or_create_jvm(&other_config).expect("JVM re-initialization failed");
+        assert!(Arc::ptr_eq(&jvms[0], &again));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_work_times_out() {
+        let result = run_blocking(Duration::from_millis(20), || {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(1)
+        }).await;
+        assert!(matches!(result, Err(DatomicError::TimeoutError { timeout_ms: 20 })), "{:?}", result);
+
+        assert_eq!(run_blocking(Duration::from_millis(500), || Ok(2)).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_attempt_is_retried() {
+        let config = RetryConfig { initial_delay_ms: 1, ..RetryConfig::default() };
+        let attempts = Arc::new(AtomicUsize::new(0));
+
+        let result = with_retry(
+            || {
+                let attempts = attempts.clone();
+                // Only the first attempt hangs
+                run_blocking(Duration::from_millis(20), move || {
+                    if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        std::thread::sleep(Duration::from_millis(500));
+                    }
+                    Ok("done")
+                })
+            },
+            &config,
+            "test_operation",
+        ).await;
+
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
 }
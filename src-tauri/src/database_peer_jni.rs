@@ -1,21 +1,19 @@
-use std::sync::{Arc, Mutex, Once};
+use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use anyhow::{Result, anyhow};
+use once_cell::sync::OnceCell;
 use serde_json::{json, Value};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use crate::models::*;
 use crate::datomic_schema::gita_schema_edn;
+use crate::config::DatomicConfig;
 use jni::{JNIEnv, JavaVM, InitArgsBuilder, JNIVersion};
 use jni::objects::{JClass, JObject, JString, JValue};
 use jni::sys::jvalue;
 
-// Global JVM instance
-static mut JVM: Option<Arc<JavaVM>> = None;
-static INIT: Once = Once::new();
-
-// Datomic connection URI
-const DATOMIC_URI: &str = "datomic:dev://localhost:8998/gita";
+// Global JVM instance using OnceCell for thread-safe initialization
+static JVM: OnceCell<Arc<JavaVM>> = OnceCell::new();
 
 /// A real Datomic Peer API client that uses JNI to interact with Datomic
 pub struct DatomicPeerClient {
@@ -25,11 +23,11 @@ pub struct DatomicPeerClient {
 
 impl DatomicPeerClient {
     /// Create a new Datomic Peer client with JNI
-    pub async fn new() -> Result<Self> {
+    pub async fn new(config: &DatomicConfig) -> Result<Self> {
         let jvm = Self::get_or_create_jvm()?;
         
         let client = DatomicPeerClient {
-            db_uri: DATOMIC_URI.to_string(),
+            db_uri: config.build_uri(),
             jvm,
         };
 
@@ -42,30 +40,27 @@ impl DatomicPeerClient {
 
     /// Get or create the JVM instance
     fn get_or_create_jvm() -> Result<Arc<JavaVM>> {
-        unsafe {
-            INIT.call_once(|| {
-                let jvm_args = InitArgsBuilder::new()
-                    .version(JNIVersion::V8)
-                    .option("-Xmx4g")
-                    .option("-Xms1g")
-                    .option("-classpath")
-                    .option("C:\\Users\\yashd\\datomic-pro-1.0.7387\\lib\\*")
-                    .build()
-                    .expect("Failed to build JVM args");
-
-                let jvm = JavaVM::new(jvm_args)
-                    .expect("Failed to create JVM");
-                
-                JVM = Some(Arc::new(jvm));
-            });
-
-            JVM.as_ref().unwrap().clone()
-        }
+        JVM.get_or_try_init(|| {
+            let jvm_args = InitArgsBuilder::new()
+                .version(JNIVersion::V8)
+                .option("-Xmx4g")
+                .option("-Xms1g")
+                .option("-classpath")
+                .option("C:\\Users\\yashd\\datomic-pro-1.0.7387\\lib\\*")
+                .build()
+                .map_err(|e| anyhow!("Failed to build JVM args: {}", e))?;
+
+            let jvm = JavaVM::new(jvm_args)
+                .map_err(|e| anyhow!("Failed to create JVM: {}", e))?;
+
+            Ok(Arc::new(jvm))
+        })
+        .map(|jvm| jvm.clone())
     }
 
     /// Create the database if it doesn't exist
     async fn create_database(&self) -> Result<()> {
-        let env = self.jvm.attach_current_thread()?;
+        let mut env = self.jvm.attach_current_thread()?;
         
         // Call datomic.api/create-database
         let datomic_api = env.find_class("datomic/Peer")?;
@@ -99,7 +94,7 @@ impl DatomicPeerClient {
 
     /// Connect to the database
     async fn connect(&self) -> Result<JObject> {
-        let env = self.jvm.attach_current_thread()?;
+        let mut env = self.jvm.attach_current_thread()?;
         
         // Call datomic.api/connect
         let datomic_api = env.find_class("datomic/Peer")?;
@@ -125,22 +120,22 @@ impl DatomicPeerClient {
 
     /// Ensure the schema exists in the database
     pub async fn ensure_schema(&self) -> Result<()> {
-        let env = self.jvm.attach_current_thread()?;
+        let mut env = self.jvm.attach_current_thread()?;
         
         // Connect to database
         let connection = self.connect().await?;
         
         // Get current database value
-        let db = self.get_db(&env, &connection)?;
+        let db = self.get_db(&mut env, &connection)?;
         
         // Check if schema exists by looking for :block/content attribute
         let query = r#"[:find ?e . :where [?e :db/ident :block/content]]"#;
-        let query_result = self.execute_query(&env, &db, query, &[])?;
+        let query_result = self.execute_query(&mut env, &db, query, &[])?;
         
         // If schema doesn't exist, transact it
-        if self.is_empty_result(&env, &query_result)? {
+        if self.is_empty_result(&mut env, &query_result)? {
             println!("Schema not found, attempting to transact it...");
-            self.transact_schema(&env, &connection).await?;
+            self.transact_schema(&mut env, &connection).await?;
             println!("Schema transaction successful.");
         } else {
             println!("Schema already present.");
@@ -150,7 +145,7 @@ impl DatomicPeerClient {
     }
 
     /// Get the current database value
-    fn get_db(&self, env: &JNIEnv, connection: &JObject) -> Result<JObject> {
+    fn get_db(&self, env: &mut JNIEnv, connection: &JObject) -> Result<JObject> {
         let db_method = env.get_method_id(
             env.get_object_class(connection)?,
             "db",
@@ -171,7 +166,7 @@ impl DatomicPeerClient {
     }
 
     /// Execute a query
-    fn execute_query(&self, env: &JNIEnv, db: &JObject, query: &str, args: &[JValue]) -> Result<JObject> {
+    fn execute_query(&self, env: &mut JNIEnv, db: &JObject, query: &str, args: &[JValue]) -> Result<JObject> {
         let datomic_api = env.find_class("datomic/Peer")?;
         let query_method = env.get_static_method_id(
             datomic_api,
@@ -205,7 +200,7 @@ impl DatomicPeerClient {
     }
 
     /// Check if query result is empty
-    fn is_empty_result(&self, env: &JNIEnv, result: &JObject) -> Result<bool> {
+    fn is_empty_result(&self, env: &mut JNIEnv, result: &JObject) -> Result<bool> {
         let collection_class = env.find_class("java/util/Collection")?;
         let is_empty_method = env.get_method_id(collection_class, "isEmpty", "()Z")?;
         
@@ -223,7 +218,7 @@ impl DatomicPeerClient {
     }
 
     /// Transact the schema
-    async fn transact_schema(&self, env: &JNIEnv, connection: &JObject) -> Result<()> {
+    async fn transact_schema(&self, env: &mut JNIEnv, connection: &JObject) -> Result<()> {
         let schema_edn = self.convert_schema_to_edn()?;
         let schema_string = env.new_string(&schema_edn)?;
         
@@ -319,7 +314,7 @@ impl DatomicPeerClient {
 
     /// Execute a transaction
     pub async fn transact(&self, tx_data: &Value) -> Result<Value> {
-        let env = self.jvm.attach_current_thread()?;
+        let mut env = self.jvm.attach_current_thread()?;
         let connection = self.connect().await?;
         
         // Convert transaction data to EDN
@@ -374,9 +369,9 @@ impl DatomicPeerClient {
 
     /// Execute a query
     pub async fn query(&self, query: &str, args: Vec<Value>) -> Result<Value> {
-        let env = self.jvm.attach_current_thread()?;
+        let mut env = self.jvm.attach_current_thread()?;
         let connection = self.connect().await?;
-        let db = self.get_db(&env, &connection)?;
+        let db = self.get_db(&mut env, &connection)?;
         
         // Convert args to JValues
         let jargs: Vec<JValue> = args.into_iter()
@@ -389,7 +384,7 @@ impl DatomicPeerClient {
             })
             .collect();
         
-        let result = self.execute_query(&env, &db, query, &jargs)?;
+        let result = self.execute_query(&mut env, &db, query, &jargs)?;
         
         // Convert result to JSON (simplified)
         Ok(json!([]))
@@ -529,3 +524,26 @@ impl DatomicPeerClient {
         Ok(vec![])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    #[ignore] // Requires a JVM with the Datomic libraries available
+    fn test_get_or_create_jvm_concurrently() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| thread::spawn(DatomicPeerClient::get_or_create_jvm))
+            .collect();
+
+        let jvms: Vec<Arc<JavaVM>> = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("JVM initialization panicked").expect("JVM initialization failed"))
+            .collect();
+
+        for jvm in &jvms[1..] {
+            assert!(Arc::ptr_eq(&jvms[0], jvm));
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use anyhow::anyhow;
+use crate::config::{AppConfig, DatomicConfig};
+use crate::models::DatomicInstallReport;
+
+/// Jars the peer can't start without, as the file name prefixes any of
+/// which will do, and what they provide
+const REQUIRED_JARS: &[(&[&str], &str)] = &[
+    (&["datomic", "peer"], "the Datomic peer library"),
+    (&["clojure-"], "Clojure"),
+];
+
+fn jars_in(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut jars = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))? {
+        let path = entry.map_err(|e| anyhow!("Error reading directory entry: {}", e))?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some("jar") {
+            jars.push(path);
+        }
+    }
+    jars.sort();
+    Ok(jars)
+}
+
+/// Root of the Datomic installation at `configured`, which may point at the
+/// installation itself or at its `lib` directory
+pub fn install_root(configured: &Path) -> PathBuf {
+    if configured.file_name().and_then(|s| s.to_str()) == Some("lib") {
+        configured.parent().unwrap_or(configured).to_path_buf()
+    } else {
+        configured.to_path_buf()
+    }
+}
+
+/// The jars the JVM is started with: those at the root of the installation,
+/// then its dependencies in `lib`
+pub fn classpath_jars(configured: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !configured.exists() {
+        return Err(anyhow!("Configured Datomic path does not exist: {}", configured.display()));
+    }
+    let install_root = install_root(configured);
+    if !install_root.exists() {
+        return Err(anyhow!("Datomic install root does not exist: {}", install_root.display()));
+    }
+    let mut jars = jars_in(&install_root)?;
+    let lib_dir = install_root.join("lib");
+    if lib_dir.exists() {
+        jars.extend(jars_in(&lib_dir)?);
+    }
+    if jars.is_empty() {
+        return Err(anyhow!("No JAR files found in Datomic installation: {}", install_root.display()));
+    }
+    Ok(jars)
+}
+
+/// Join classpath entries with the platform's separator
+pub fn join_classpath(jars: &[PathBuf]) -> String {
+    jars.iter()
+        .map(|jar| jar.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(if cfg!(windows) { ";" } else { ":" })
+}
+
+/// Required jars missing from `jars`
+fn missing_jars(jars: &[PathBuf]) -> Vec<String> {
+    REQUIRED_JARS.iter()
+        .filter(|(prefixes, _)| !jars.iter().any(|jar| {
+            let name = jar.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+            prefixes.iter().any(|prefix| name.starts_with(prefix))
+        }))
+        .map(|(prefixes, what)| format!("{} ({}*.jar)", what, prefixes.join("*.jar or ")))
+        .collect()
+}
+
+/// The `java` executable in `java_home`, or else the first on `path`
+fn find_java(java_home: Option<&Path>, path: Option<&std::ffi::OsStr>) -> Option<PathBuf> {
+    let executable = if cfg!(windows) { "java.exe" } else { "java" };
+    let in_home = java_home.map(|home| home.join("bin").join(executable));
+    in_home.into_iter()
+        .chain(path.into_iter().flat_map(env::split_paths).map(|dir| dir.join(executable)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Check the Datomic installation the peer would load, and whether Java is
+/// installed, without starting the JVM
+pub fn validate(config: &DatomicConfig) -> DatomicInstallReport {
+    let java_home = env::var_os("JAVA_HOME").map(PathBuf::from);
+    let java_executable = find_java(java_home.as_deref(), env::var_os("PATH").as_deref());
+    let lib_path = config.datomic_lib_path.clone();
+    let detected = lib_path.is_none();
+    let lib_path = lib_path.or_else(AppConfig::detect_datomic_installation);
+    inspect(lib_path, detected, java_home, java_executable)
+}
+
+fn inspect(
+    lib_path: Option<PathBuf>,
+    detected: bool,
+    java_home: Option<PathBuf>,
+    java_executable: Option<PathBuf>,
+) -> DatomicInstallReport {
+    let mut problems = Vec::new();
+    let (classpath, missing) = match &lib_path {
+        None => {
+            problems.push("No Datomic installation configured or found; set datomic_lib_path or DATOMIC_HOME".to_string());
+            (Vec::new(), Vec::new())
+        }
+        Some(lib_path) => match classpath_jars(lib_path) {
+            Ok(jars) => {
+                let missing = missing_jars(&jars);
+                if !missing.is_empty() {
+                    problems.push(format!("The Datomic installation is missing {}", missing.join(", ")));
+                }
+                (jars, missing)
+            }
+            Err(e) => {
+                problems.push(e.to_string());
+                (Vec::new(), missing_jars(&[]))
+            }
+        },
+    };
+    if java_executable.is_none() {
+        problems.push("Java wasn't found in JAVA_HOME or on the PATH".to_string());
+    }
+
+    let display = |path: &Path| path.to_string_lossy().into_owned();
+    DatomicInstallReport {
+        install_root: lib_path.as_deref().map(|lib_path| display(&install_root(lib_path))),
+        lib_path: lib_path.as_deref().map(display),
+        detected,
+        classpath: classpath.iter().map(|jar| display(jar)).collect(),
+        missing_jars: missing,
+        java_home: java_home.as_deref().map(display),
+        java_executable: java_executable.as_deref().map(display),
+        ok: problems.is_empty(),
+        problems,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn datomic_install(dir: &Path, jars: &[&str]) -> PathBuf {
+        let root = dir.join("datomic-pro-1.0.7387");
+        fs::create_dir_all(root.join("lib")).unwrap();
+        for jar in jars {
+            fs::write(root.join(jar), b"").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_complete_install_is_ok() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = datomic_install(dir.path(), &["datomic-pro-1.0.7387.jar", "lib/clojure-1.11.1.jar", "lib/README.txt"]);
+        let java = dir.path().join("java");
+        fs::write(&java, b"").unwrap();
+
+        // Pointing at lib finds the same installation
+        let report = inspect(Some(root.join("lib")), false, None, Some(java));
+        assert!(report.ok, "{:?}", report.problems);
+        assert_eq!(report.install_root.as_deref(), Some(root.to_string_lossy().as_ref()));
+        assert_eq!(report.classpath, vec![
+            root.join("datomic-pro-1.0.7387.jar").to_string_lossy().into_owned(),
+            root.join("lib").join("clojure-1.11.1.jar").to_string_lossy().into_owned(),
+        ]);
+        assert!(report.missing_jars.is_empty());
+    }
+
+    #[test]
+    fn test_missing_jars_and_java_are_flagged() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = datomic_install(dir.path(), &["lib/clojure-1.11.1.jar"]);
+
+        let report = inspect(Some(root), false, None, None);
+        assert!(!report.ok);
+        assert_eq!(report.missing_jars, vec!["the Datomic peer library (datomic*.jar or peer*.jar)"]);
+        assert_eq!(report.problems.len(), 2);
+
+        let empty = datomic_install(&dir.path().join("empty"), &[]);
+        let report = inspect(Some(empty), false, None, None);
+        assert!(report.classpath.is_empty());
+        assert_eq!(report.missing_jars.len(), REQUIRED_JARS.len());
+        assert!(report.problems[0].starts_with("No JAR files found"));
+
+        let report = inspect(Some(dir.path().join("nowhere")), false, None, None);
+        assert!(report.problems[0].starts_with("Configured Datomic path does not exist"));
+
+        let report = inspect(None, true, None, None);
+        assert!(!report.ok);
+        assert_eq!(report.lib_path, None);
+    }
+
+    #[test]
+    fn test_java_found_in_java_home_before_path() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let executable = if cfg!(windows) { "java.exe" } else { "java" };
+        let home = dir.path().join("jdk");
+        let on_path = dir.path().join("usr-bin");
+        fs::create_dir_all(home.join("bin")).unwrap();
+        fs::create_dir_all(&on_path).unwrap();
+        fs::write(on_path.join(executable), b"").unwrap();
+
+        let path = env::join_paths([&on_path]).unwrap();
+        assert_eq!(find_java(Some(&home), Some(&path)), Some(on_path.join(executable)));
+        fs::write(home.join("bin").join(executable), b"").unwrap();
+        assert_eq!(find_java(Some(&home), Some(&path)), Some(home.join("bin").join(executable)));
+        assert_eq!(find_java(None, None), None);
+    }
+}
@@ -1,11 +1,25 @@
 use serde_json::json;
+use std::collections::HashSet;
+use crate::models::SchemaDiff;
 
 // Removed unused gita_schema() function.
 // gita_schema_edn() is used instead.
 
+/// Version of `gita_schema_edn()`. Bump it whenever attributes are added so
+/// databases created by an older build are brought up to date on connect.
+pub const SCHEMA_VERSION: i64 = 2;
+
 /// Return the schema in EDN format for the Peer API
 pub fn gita_schema_edn() -> serde_json::Value {
     json!([
+        // Schema Version
+        {
+            ":db/ident": ":gita.schema/version",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The SCHEMA_VERSION last transacted, stored on the :gita/schema entity."
+        },
+
         // Block Attributes
         {
             ":db/ident": ":block/id",
@@ -57,6 +71,19 @@ pub fn gita_schema_edn() -> serde_json::Value {
             ":db/cardinality": ":db.cardinality/one",
             ":db/doc": "The last update timestamp of the block."
         },
+        {
+            ":db/ident": ":block/links",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/many",
+            ":db/index": true,
+            ":db/doc": "Titles of the pages this block links to with [[Title]]."
+        },
+        {
+            ":db/ident": ":block/source",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "How the block was created, e.g. keyboard, import or transcription. Added in schema version 2."
+        },
 
         // Audio Recording Attributes
         {
@@ -90,6 +117,88 @@ pub fn gita_schema_edn() -> serde_json::Value {
             ":db/cardinality": ":db.cardinality/one",
             ":db/doc": "The creation timestamp of the audio recording."
         },
+        {
+            ":db/ident": ":audio/status",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Set when a recording could not be completed, e.g. \"unrecoverable\"."
+        },
+        {
+            ":db/ident": ":audio/system_audio",
+            ":db/valueType": ":db.type/boolean",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Whether system audio was mixed into the microphone recording."
+        },
+        {
+            ":db/ident": ":audio/loudness_lufs",
+            ":db/valueType": ":db.type/double",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Approximate integrated loudness of the recording after normalization."
+        },
+        {
+            ":db/ident": ":audio/transcription_status",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Progress of the recording's transcription: running, completed, failed or cancelled."
+        },
+
+        // Silence Map Attributes
+        {
+            ":db/ident": ":silence/recording_id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The ID of the recording this silent interval belongs to."
+        },
+        {
+            ":db/ident": ":silence/start_ms",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Where the silent interval starts within the recording, in milliseconds."
+        },
+        {
+            ":db/ident": ":silence/end_ms",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Where the silent interval ends within the recording, in milliseconds."
+        },
+        // Recording Segment Attributes
+        {
+            ":db/ident": ":segment/id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/unique": ":db.unique/identity",
+            ":db/doc": "The unique ID of a recording segment, \"<recording-id>:<index>\"."
+        },
+        {
+            ":db/ident": ":segment/recording_id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The ID of the recording this segment belongs to."
+        },
+        {
+            ":db/ident": ":segment/index",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The position of the segment within its recording."
+        },
+        {
+            ":db/ident": ":segment/path",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The path to the segment's audio file."
+        },
+        {
+            ":db/ident": ":segment/start_ms",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Where the segment starts within the whole recording, in milliseconds."
+        },
+        {
+            ":db/ident": ":segment/duration_ms",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The length of the segment in milliseconds."
+        },
 
         // Timestamp Attributes
         {
@@ -109,6 +218,83 @@ pub fn gita_schema_edn() -> serde_json::Value {
             ":db/valueType": ":db.type/long",
             ":db/cardinality": ":db.cardinality/one",
             ":db/doc": "The timestamp in milliseconds within the audio recording."
+        },
+
+        // Recording Marker Attributes
+        {
+            ":db/ident": ":marker/id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/unique": ":db.unique/identity",
+            ":db/doc": "The unique ID of a marker dropped during a recording."
+        },
+        {
+            ":db/ident": ":marker/recording_id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The ID of the recording the marker was dropped in."
+        },
+        {
+            ":db/ident": ":marker/timestamp_ms",
+            ":db/valueType": ":db.type/long",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The marker's position within the recording in milliseconds."
+        },
+        {
+            ":db/ident": ":marker/label",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "Optional text describing the marked moment."
+        },
+        {
+            ":db/ident": ":marker/created_at",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The creation timestamp of the marker."
+        },
+
+        // Draft Attributes
+        {
+            ":db/ident": ":draft/block_id",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/unique": ":db.unique/identity",
+            ":db/doc": "The ID of the block an unwritten edit belongs to; a block has at most one draft."
+        },
+        {
+            ":db/ident": ":draft/content",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "The block's content as last edited."
+        },
+        {
+            ":db/ident": ":draft/saved_at",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/one",
+            ":db/doc": "When the draft was saved."
         }
     ])
 }
+
+/// Idents of every attribute defined in `gita_schema_edn()`
+pub fn schema_attribute_idents() -> Vec<String> {
+    gita_schema_edn()
+        .as_array()
+        .map(|attributes| {
+            attributes.iter()
+                .filter_map(|attribute| attribute.get(":db/ident"))
+                .filter_map(|ident| ident.as_str())
+                .map(|ident| ident.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Split the expected schema attributes into those already in the database and those missing
+pub fn diff_schema(existing_idents: &HashSet<String>) -> SchemaDiff {
+    let (present, missing) = schema_attribute_idents()
+        .into_iter()
+        .partition(|ident| existing_idents.contains(ident));
+
+    SchemaDiff { missing, present }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::models::{Block, Navigation, RecordingSegment};
+
+/// URL scheme registered for links into the app
+pub const SCHEME: &str = "gita";
+
+// Blocks nested deeper than this are assumed to be in a parent cycle
+const MAX_NESTING: usize = 256;
+
+/// A parsed `gita://` link:
+/// - `gita://page/{page_id}`, optionally with `?block={block_id}`
+/// - `gita://recording/{recording_id}`, optionally with `?t={seconds}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeepLink {
+    Page { page_id: String, block_id: Option<String> },
+    Recording { recording_id: String, timestamp_ms: i64 },
+}
+
+impl DeepLink {
+    /// Parse a link, rejecting other schemes, unknown paths and bad offsets
+    pub fn parse(url: &str) -> Result<Self, AppError> {
+        let invalid = |reason: &str| {
+            AppError::validation(format!("Invalid link {}: {}", url, reason))
+                .with_details(serde_json::json!({ "url": url }))
+        };
+
+        let rest = url.split_once("://")
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(SCHEME))
+            .map(|(_, rest)| rest)
+            .ok_or_else(|| invalid("not a gita:// link"))?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let params: HashMap<&str, &str> = query.split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| param.split_once('=').unwrap_or((param, "")))
+            .collect();
+
+        // Some platforms hand the link over with a trailing slash
+        match path.trim_end_matches('/').split('/').collect::<Vec<_>>().as_slice() {
+            ["page", page_id] if !page_id.is_empty() => Ok(DeepLink::Page {
+                page_id: page_id.to_string(),
+                block_id: params.get("block").filter(|id| !id.is_empty()).map(|id| id.to_string()),
+            }),
+            ["recording", recording_id] if !recording_id.is_empty() => {
+                let timestamp_ms = match params.get("t") {
+                    Some(seconds) => seconds.parse::<f64>().ok()
+                        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                        .map(|seconds| (seconds * 1000.0).round() as i64)
+                        .ok_or_else(|| invalid("t must be a number of seconds"))?,
+                    None => 0,
+                };
+                Ok(DeepLink::Recording { recording_id: recording_id.to_string(), timestamp_ms })
+            }
+            _ => Err(invalid("expected gita://page/<id> or gita://recording/<id>")),
+        }
+    }
+
+    /// The first `gita://` link among a launch's arguments, which come after
+    /// the program path
+    pub fn find_in_args(args: &[String]) -> Option<&str> {
+        args.iter().skip(1)
+            .map(String::as_str)
+            .find(|arg| arg.split_once("://").is_some_and(|(scheme, _)| scheme.eq_ignore_ascii_case(SCHEME)))
+    }
+
+    /// The link as a shareable URL
+    pub fn to_url(&self) -> String {
+        match self {
+            DeepLink::Page { page_id, block_id: None } => format!("{}://page/{}", SCHEME, page_id),
+            DeepLink::Page { page_id, block_id: Some(block_id) } => {
+                format!("{}://page/{}?block={}", SCHEME, page_id, block_id)
+            }
+            DeepLink::Recording { recording_id, timestamp_ms: 0 } => format!("{}://recording/{}", SCHEME, recording_id),
+            DeepLink::Recording { recording_id, timestamp_ms } => {
+                format!("{}://recording/{}?t={}", SCHEME, recording_id, *timestamp_ms as f64 / 1000.0)
+            }
+        }
+    }
+}
+
+pub async fn get_page(db: &DatomicPeerClient, page_id: &str) -> Result<Block, AppError> {
+    db.get_block(page_id).await?
+        .filter(|block| block.is_page)
+        .ok_or_else(|| AppError::not_found(format!("Page not found: {}", page_id)))
+}
+
+/// The page a block is on, following its parents up
+async fn page_of_block(db: &DatomicPeerClient, block_id: &str) -> Result<Block, AppError> {
+    let missing = |id: &str| AppError::not_found(format!("Block not found: {}", id));
+    let mut block = db.get_block(block_id).await?.ok_or_else(|| missing(block_id))?;
+    for _ in 0..MAX_NESTING {
+        if block.is_page {
+            return Ok(block);
+        }
+        let parent_id = block.parent_id
+            .ok_or_else(|| AppError::not_found(format!("Block {} isn't on a page", block_id)))?;
+        block = db.get_block(&parent_id).await?.ok_or_else(|| missing(&parent_id))?;
+    }
+    Err(AppError::internal(format!("Couldn't find the page of block {}: its parents loop", block_id)))
+}
+
+/// Check that what a link points at exists and gather what the frontend
+/// needs to show it
+pub async fn resolve(db: &DatomicPeerClient, link: &DeepLink) -> Result<Navigation, AppError> {
+    match link {
+        DeepLink::Page { page_id, block_id } => {
+            let page = get_page(db, page_id).await?;
+            if let Some(block_id) = block_id {
+                if page_of_block(db, block_id).await?.id != page.id {
+                    return Err(AppError::not_found(format!("Block {} isn't on page {}", block_id, page_id)));
+                }
+            }
+            Ok(Navigation::Page {
+                page_id: page.id,
+                page_title: page.page_title.unwrap_or_default(),
+                block_id: block_id.clone(),
+            })
+        }
+        DeepLink::Recording { recording_id, timestamp_ms } => {
+            let recording = db.get_recording(recording_id).await?
+                .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+            let page = get_page(db, &recording.page_id).await?;
+            let segments = db.get_recording_segments(recording_id).await?;
+            Ok(Navigation::Recording {
+                page_id: page.id,
+                page_title: page.page_title.unwrap_or_default(),
+                segment: RecordingSegment::locate(&segments, *timestamp_ms).cloned(),
+                recording,
+                timestamp_ms: *timestamp_ms,
+            })
+        }
+    }
+}
+
+/// Shareable link to a block: its page, scrolled to the block unless it's
+/// the page itself
+pub async fn block_url(db: &DatomicPeerClient, block_id: &str) -> Result<String, AppError> {
+    let page = page_of_block(db, block_id).await?;
+    let block_id = (page.id != block_id).then(|| block_id.to_string());
+    Ok(DeepLink::Page { page_id: page.id, block_id }.to_url())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_links() {
+        assert_eq!(DeepLink::parse("gita://page/page-1").unwrap(), DeepLink::Page {
+            page_id: "page-1".to_string(),
+            block_id: None,
+        });
+        assert_eq!(DeepLink::parse("gita://page/page-1/?block=block-2").unwrap(), DeepLink::Page {
+            page_id: "page-1".to_string(),
+            block_id: Some("block-2".to_string()),
+        });
+        assert_eq!(DeepLink::parse("GITA://recording/rec-1?t=120").unwrap(), DeepLink::Recording {
+            recording_id: "rec-1".to_string(),
+            timestamp_ms: 120_000,
+        });
+        assert_eq!(DeepLink::parse("gita://recording/rec-1?t=1.5&x=y").unwrap(), DeepLink::Recording {
+            recording_id: "rec-1".to_string(),
+            timestamp_ms: 1_500,
+        });
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_links() {
+        for url in [
+            "https://page/page-1",
+            "gita://page/",
+            "gita://page/page-1/extra",
+            "gita://block/block-1",
+            "gita://recording/rec-1?t=-5",
+            "gita://recording/rec-1?t=soon",
+        ] {
+            assert!(matches!(DeepLink::parse(url), Err(AppError::Validation { .. })), "{} was accepted", url);
+        }
+    }
+
+    #[test]
+    fn test_find_link_in_args() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(DeepLink::find_in_args(&args(&["/usr/bin/gita", "--minimized", "gita://page/page-1"])), Some("gita://page/page-1"));
+        assert_eq!(DeepLink::find_in_args(&args(&["/usr/bin/gita", "GITA://recording/rec-1?t=3"])), Some("GITA://recording/rec-1?t=3"));
+        assert_eq!(DeepLink::find_in_args(&args(&["gita://page/page-1"])), None);
+        assert_eq!(DeepLink::find_in_args(&args(&["/usr/bin/gita", "https://example.com"])), None);
+        assert_eq!(DeepLink::find_in_args(&args(&[])), None);
+    }
+
+    #[test]
+    fn test_links_round_trip() {
+        for link in [
+            DeepLink::Page { page_id: "page-1".to_string(), block_id: None },
+            DeepLink::Page { page_id: "page-1".to_string(), block_id: Some("block-2".to_string()) },
+            DeepLink::Recording { recording_id: "rec-1".to_string(), timestamp_ms: 0 },
+            DeepLink::Recording { recording_id: "rec-1".to_string(), timestamp_ms: 120_250 },
+        ] {
+            assert_eq!(DeepLink::parse(&link.to_url()).unwrap(), link);
+        }
+        assert_eq!(
+            DeepLink::Recording { recording_id: "rec-1".to_string(), timestamp_ms: 120_000 }.to_url(),
+            "gita://recording/rec-1?t=120"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use serde_json::Value;
+
+// Attributes whose values are idents rather than strings
+const IDENT_ATTRIBUTES: [&str; 4] = [":db/ident", ":db/valueType", ":db/cardinality", ":db/unique"];
+
+// List forms whose third element is an attribute
+const ATTRIBUTE_OPS: [&str; 3] = [":db/add", ":db/retract", ":db/cas"];
+
+/// Write transaction data built with `json!` as EDN for `datomic.Util/readAll`.
+/// Map keys, the first element of a list form or lookup ref like
+/// `[":db/retractEntity", e]` or `[":block/id", id]`, the attribute of a
+/// `:db/add`, `:db/retract` or `:db/cas` and the values of ident attributes
+/// like `:db/valueType` become keywords. Every other string stays a string,
+/// so content that happens to start with `:` isn't turned into a keyword.
+pub fn tx_data_to_edn(tx_data: &[Value]) -> String {
+    let mut edn = String::new();
+    write_array(&mut edn, tx_data);
+    edn
+}
+
+fn write_value(edn: &mut String, value: &Value, keyword: bool) {
+    match value {
+        Value::Null => edn.push_str("nil"),
+        Value::Bool(b) => edn.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => edn.push_str(&n.to_string()),
+        Value::String(s) if keyword && is_keyword(s) => edn.push_str(s),
+        Value::String(s) => write_string(edn, s),
+        Value::Array(items) => write_array(edn, items),
+        Value::Object(map) => {
+            edn.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    edn.push_str(", ");
+                }
+                if is_keyword(key) {
+                    edn.push_str(key);
+                } else {
+                    write_string(edn, key);
+                }
+                edn.push(' ');
+                write_value(edn, value, IDENT_ATTRIBUTES.contains(&key.as_str()));
+            }
+            edn.push('}');
+        }
+    }
+}
+
+fn write_array(edn: &mut String, items: &[Value]) {
+    let op = items.first().and_then(Value::as_str).filter(|s| is_keyword(s));
+    edn.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            edn.push(' ');
+        }
+        let keyword = (i == 0 && op.is_some()) || (i == 2 && op.is_some_and(|op| ATTRIBUTE_OPS.contains(&op)));
+        write_value(edn, item, keyword);
+    }
+    edn.push(']');
+}
+
+fn write_string(edn: &mut String, s: &str) {
+    edn.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => edn.push_str("\\\""),
+            '\\' => edn.push_str("\\\\"),
+            '\n' => edn.push_str("\\n"),
+            '\r' => edn.push_str("\\r"),
+            '\t' => edn.push_str("\\t"),
+            c if c.is_control() => edn.push_str(&format!("\\u{:04x}", c as u32)),
+            c => edn.push(c),
+        }
+    }
+    edn.push('"');
+}
+
+/// Whether `s` reads as a keyword, like `:block/id`
+pub fn is_keyword(s: &str) -> bool {
+    match s.strip_prefix(':') {
+        Some(name) => !name.is_empty()
+            && !name.starts_with(':')
+            && !name.chars().any(|c| c.is_whitespace() || "\"(),;[]{}\\".contains(c)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tx_data_keeps_content_as_strings() {
+        let tx_data = vec![
+            json!({":block/id": "b1", ":block/content": ":smile: said \"hi\"\n", ":block/order": 2, ":block/parent": [":block/id", "p1"]}),
+            json!([":db/add", [":block/id", "b1"], ":block/links", ":odd title"]),
+            json!([":db/cas", [":block/id", "b1"], ":block/content", null, "new"]),
+            json!([":db/retractEntity", 17592186045418_i64]),
+        ];
+        assert_eq!(
+            tx_data_to_edn(&tx_data),
+            concat!(
+                r#"[{:block/content ":smile: said \"hi\"\n", :block/id "b1", :block/order 2, :block/parent [:block/id "p1"]} "#,
+                r#"[:db/add [:block/id "b1"] :block/links ":odd title"] "#,
+                r#"[:db/cas [:block/id "b1"] :block/content nil "new"] "#,
+                r#"[:db/retractEntity 17592186045418]]"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_schema_idents_become_keywords() {
+        let schema = vec![json!({
+            ":db/ident": ":block/links",
+            ":db/valueType": ":db.type/string",
+            ":db/cardinality": ":db.cardinality/many",
+            ":db/index": true,
+            ":db/doc": ":block/links holds page titles",
+        })];
+        assert_eq!(
+            tx_data_to_edn(&schema),
+            r#"[{:db/cardinality :db.cardinality/many, :db/doc ":block/links holds page titles", :db/ident :block/links, :db/index true, :db/valueType :db.type/string}]"#
+        );
+    }
+}
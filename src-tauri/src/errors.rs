@@ -1,5 +1,5 @@
 use thiserror::Error;
-use std::fmt;
+use serde::Serialize;
 
 #[derive(Error, Debug)]
 #[allow(dead_code)] // Acknowledging some variants/methods might be unused currently
@@ -42,6 +42,10 @@ pub enum DatomicError {
     
     #[error("Invalid transaction data: {0}")]
     InvalidTransactionData(String),
+
+    /// A block create request breaking an invariant, naming the field at fault
+    #[error("Invalid block request: {message}")]
+    InvalidBlockRequest { field: &'static str, message: String },
     
     #[error("Java class not found: {0}")]
     JavaClassNotFound(String),
@@ -66,6 +70,13 @@ pub enum DatomicError {
     
     #[error("Internal error: {0}")]
     InternalError(String),
+
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+
+    /// The storage backend doesn't implement the operation
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
 }
 
 #[allow(dead_code)] // Acknowledging some constructor methods might be unused currently
@@ -118,6 +129,10 @@ impl DatomicError {
         DatomicError::InvalidTransactionData(msg.into())
     }
     
+    pub fn invalid_block_request<T: Into<String>>(field: &'static str, msg: T) -> Self {
+        DatomicError::InvalidBlockRequest { field, message: msg.into() }
+    }
+    
     pub fn java_class_not_found<T: Into<String>>(msg: T) -> Self {
         DatomicError::JavaClassNotFound(msg.into())
     }
@@ -141,10 +156,344 @@ impl DatomicError {
     pub fn internal_error<T: Into<String>>(msg: T) -> Self {
         DatomicError::InternalError(msg.into())
     }
+
+    pub fn cancelled<T: Into<String>>(msg: T) -> Self {
+        DatomicError::Cancelled(msg.into())
+    }
+
+    pub fn unsupported<T: Into<String>>(msg: T) -> Self {
+        DatomicError::Unsupported(msg.into())
+    }
+
+    /// Whether a transaction was rejected because a `:db/cas` saw a different value
+    pub fn is_cas_conflict(&self) -> bool {
+        matches!(self, DatomicError::TransactionError(msg) if msg.contains(":db.error/cas-failed"))
+    }
+
+    /// Whether another attempt may succeed. A timeout or a failure talking
+    /// to the peer or transactor can pass; a malformed request, a missing
+    /// entity or a missing Datomic class fails the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            DatomicError::ConfigError(_)
+                | DatomicError::EntityNotFound(_)
+                | DatomicError::InvalidEntityId(_)
+                | DatomicError::InvalidTransactionData(_)
+                | DatomicError::InvalidBlockRequest { .. }
+                | DatomicError::JavaClassNotFound(_)
+                | DatomicError::JavaMethodNotFound(_)
+                | DatomicError::JvmInitializationFailed(_)
+                | DatomicError::EdnParsingError(_)
+                | DatomicError::TypeConversionError(_)
+                | DatomicError::SerializationError(_)
+                | DatomicError::JsonError(_)
+                | DatomicError::Cancelled(_)
+                | DatomicError::Unsupported(_)
+                | DatomicError::RetryLimitExceeded { .. }
+        )
+    }
 }
 
 pub type Result<T> = std::result::Result<T, DatomicError>;
 
+#[derive(Error, Debug)]
+pub enum AudioEngineError {
+    #[error("Already recording")]
+    AlreadyRecording,
+
+    #[error("Not currently recording")]
+    NotRecording,
+
+    #[error("No input device available")]
+    NoInputDevice,
+
+    /// Recordings never write over an existing file
+    #[error("Recording file already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("No input device named '{0}'")]
+    DeviceNotFound(String),
+
+    #[error("No output device named '{0}'")]
+    OutputDeviceNotFound(String),
+
+    #[error("System audio capture is not available: {0}")]
+    LoopbackUnavailable(String),
+
+    /// Reported once after a panic reset the engine to idle
+    #[error("The audio engine was reset after an internal failure: {0}")]
+    Poisoned(String),
+
+    #[error("Unsupported sample format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Audio device error: {0}")]
+    DeviceError(String),
+
+    #[error("Disk full: {0}")]
+    DiskFull(std::io::Error),
+
+    #[error("IO error: {0}")]
+    IoError(std::io::Error),
+
+    #[error("WAV error: {0}")]
+    WavError(String),
+
+    #[cfg(feature = "opus")]
+    #[error("Opus error: {0}")]
+    OpusError(String),
+
+    #[error("Internal error: {0}")]
+    InternalError(String),
+}
+
+impl AudioEngineError {
+    pub fn device_error<T: Into<String>>(msg: T) -> Self {
+        AudioEngineError::DeviceError(msg.into())
+    }
+
+    pub fn wav_error<T: Into<String>>(msg: T) -> Self {
+        AudioEngineError::WavError(msg.into())
+    }
+
+    pub fn internal_error<T: Into<String>>(msg: T) -> Self {
+        AudioEngineError::InternalError(msg.into())
+    }
+}
+
+impl From<std::io::Error> for AudioEngineError {
+    fn from(err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::StorageFull {
+            AudioEngineError::DiskFull(err)
+        } else {
+            AudioEngineError::IoError(err)
+        }
+    }
+}
+
+impl From<hound::Error> for AudioEngineError {
+    fn from(err: hound::Error) -> Self {
+        match err {
+            hound::Error::IoError(e) => e.into(),
+            other => AudioEngineError::WavError(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<audiopus::Error> for AudioEngineError {
+    fn from(err: audiopus::Error) -> Self {
+        AudioEngineError::OpusError(err.to_string())
+    }
+}
+
+#[cfg(feature = "opus")]
+impl From<ogg::OggReadError> for AudioEngineError {
+    fn from(err: ogg::OggReadError) -> Self {
+        match err {
+            ogg::OggReadError::ReadError(e) => e.into(),
+            other => AudioEngineError::OpusError(other.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "transcription")]
+#[derive(Error, Debug)]
+pub enum TranscriptionError {
+    #[error("Whisper model error: {0}")]
+    ModelError(String),
+
+    #[error("Audio error: {0}")]
+    AudioError(#[from] AudioEngineError),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatomicError),
+
+    #[error("Transcription cancelled")]
+    Cancelled,
+}
+
+#[cfg(feature = "transcription")]
+impl TranscriptionError {
+    pub fn model_error<T: Into<String>>(msg: T) -> Self {
+        TranscriptionError::ModelError(msg.into())
+    }
+}
+
+#[cfg(feature = "transcription")]
+impl From<hound::Error> for TranscriptionError {
+    fn from(err: hound::Error) -> Self {
+        TranscriptionError::AudioError(err.into())
+    }
+}
+
+/// Error returned to the frontend by Tauri commands. It serializes to
+/// `{code, message, details}`, where `code` is one of "not_found",
+/// "validation", "conflict", "audio", "database", "io" or "internal" so the
+/// UI can branch on it, and `details` carries structured context if any.
+#[derive(Error, Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum AppError {
+    #[error("{message}")]
+    NotFound { message: String, details: Option<serde_json::Value> },
+
+    #[error("{message}")]
+    Validation { message: String, details: Option<serde_json::Value> },
+
+    /// The request clashes with the current state, like starting a second recording
+    #[error("{message}")]
+    Conflict { message: String, details: Option<serde_json::Value> },
+
+    #[error("{message}")]
+    Audio { message: String, details: Option<serde_json::Value> },
+
+    #[error("{message}")]
+    Database { message: String, details: Option<serde_json::Value> },
+
+    #[error("{message}")]
+    Io { message: String, details: Option<serde_json::Value> },
+
+    #[error("{message}")]
+    Internal { message: String, details: Option<serde_json::Value> },
+}
+
+impl AppError {
+    pub fn not_found<T: Into<String>>(msg: T) -> Self {
+        AppError::NotFound { message: msg.into(), details: None }
+    }
+
+    pub fn validation<T: Into<String>>(msg: T) -> Self {
+        AppError::Validation { message: msg.into(), details: None }
+    }
+
+    pub fn conflict<T: Into<String>>(msg: T) -> Self {
+        AppError::Conflict { message: msg.into(), details: None }
+    }
+
+    pub fn audio<T: Into<String>>(msg: T) -> Self {
+        AppError::Audio { message: msg.into(), details: None }
+    }
+
+    pub fn database<T: Into<String>>(msg: T) -> Self {
+        AppError::Database { message: msg.into(), details: None }
+    }
+
+    pub fn io<T: Into<String>>(msg: T) -> Self {
+        AppError::Io { message: msg.into(), details: None }
+    }
+
+    pub fn internal<T: Into<String>>(msg: T) -> Self {
+        AppError::Internal { message: msg.into(), details: None }
+    }
+
+    /// Attach structured context for the frontend
+    pub fn with_details(mut self, value: serde_json::Value) -> Self {
+        match &mut self {
+            AppError::NotFound { details, .. }
+            | AppError::Validation { details, .. }
+            | AppError::Conflict { details, .. }
+            | AppError::Audio { details, .. }
+            | AppError::Database { details, .. }
+            | AppError::Io { details, .. }
+            | AppError::Internal { details, .. } => *details = Some(value),
+        }
+        self
+    }
+}
+
+impl From<DatomicError> for AppError {
+    fn from(err: DatomicError) -> Self {
+        let message = err.to_string();
+        match err {
+            DatomicError::EntityNotFound(_) | DatomicError::DatabaseNotFound(_) => AppError::not_found(message),
+            DatomicError::InvalidEntityId(_) | DatomicError::InvalidTransactionData(_) => AppError::validation(message),
+            DatomicError::InvalidBlockRequest { field, .. } => {
+                AppError::validation(message).with_details(serde_json::json!({ "field": field }))
+            }
+            ref cas if cas.is_cas_conflict() => AppError::conflict(message),
+            DatomicError::Cancelled(_) => AppError::conflict(message),
+            DatomicError::IoError(_) => AppError::io(message),
+            DatomicError::TimeoutError { timeout_ms } => {
+                AppError::database(message).with_details(serde_json::json!({ "timeout_ms": timeout_ms }))
+            }
+            DatomicError::RetryLimitExceeded { attempts } => {
+                AppError::database(message).with_details(serde_json::json!({ "attempts": attempts }))
+            }
+            _ => AppError::database(message),
+        }
+    }
+}
+
+impl From<AudioEngineError> for AppError {
+    fn from(err: AudioEngineError) -> Self {
+        let message = err.to_string();
+        match err {
+            AudioEngineError::DeviceNotFound(_)
+            | AudioEngineError::OutputDeviceNotFound(_)
+            | AudioEngineError::NoInputDevice => AppError::not_found(message),
+            AudioEngineError::AlreadyRecording
+            | AudioEngineError::NotRecording
+            | AudioEngineError::AlreadyExists(_) => AppError::conflict(message),
+            AudioEngineError::DiskFull(_) => AppError::io(message).with_details(serde_json::json!({ "disk_full": true })),
+            AudioEngineError::IoError(_) => AppError::io(message),
+            AudioEngineError::InternalError(_) => AppError::internal(message),
+            _ => AppError::audio(message),
+        }
+    }
+}
+
+#[cfg(feature = "transcription")]
+impl From<TranscriptionError> for AppError {
+    fn from(err: TranscriptionError) -> Self {
+        match err {
+            TranscriptionError::AudioError(e) => e.into(),
+            TranscriptionError::DatabaseError(e) => e.into(),
+            TranscriptionError::Cancelled => AppError::conflict(err.to_string()),
+            TranscriptionError::ModelError(_) => AppError::internal(err.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => AppError::not_found(err.to_string()),
+            _ => AppError::io(err.to_string()),
+        }
+    }
+}
+
+impl From<uuid::Error> for AppError {
+    fn from(err: uuid::Error) -> Self {
+        AppError::validation(format!("Invalid ID: {}", err))
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(err: anyhow::Error) -> Self {
+        // Keep the classification of the errors anyhow wraps
+        let err = match err.downcast::<DatomicError>() {
+            Ok(e) => return e.into(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<AudioEngineError>() {
+            Ok(e) => return e.into(),
+            Err(err) => err,
+        };
+        match err.downcast::<std::io::Error>() {
+            Ok(e) => e.into(),
+            Err(err) => AppError::internal(format!("{:#}", err)),
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(err: tauri::Error) -> Self {
+        AppError::internal(err.to_string())
+    }
+}
+
 #[macro_export]
 macro_rules! datomic_error {
     ($kind:ident, $msg:expr) => {
@@ -185,22 +534,24 @@ impl Default for RetryConfig {
     }
 }
 
-/// Execute a fallible operation with retry logic
-pub async fn with_retry<F, T, E>(
+/// Execute a fallible operation with retry logic. Errors that aren't
+/// `is_retryable` are returned straight away.
+pub async fn with_retry<F, Fut, T>(
     mut operation: F, // Added mut here
     config: &RetryConfig,
     operation_name: &str,
 ) -> Result<T>
 where
-    F: FnMut() -> std::result::Result<T, E> + Send + Sync, // Changed Fn to FnMut
-    E: fmt::Display + fmt::Debug + Send + Sync,
+    F: FnMut() -> Fut, // Changed Fn to FnMut
+    Fut: std::future::Future<Output = Result<T>>,
 {
     let mut delay = config.initial_delay_ms;
-    let mut last_error: Option<E> = None;
+    let mut last_error: Option<DatomicError> = None;
     
     for attempt in 1..=config.max_attempts {
-        match operation() {
+        match operation().await {
             Ok(result) => return Ok(result),
+            Err(e) if !e.is_retryable() => return Err(e),
             Err(e) => {
                 tracing::warn!(
                     "Operation '{}' failed on attempt {} of {}: {}",
@@ -243,8 +594,52 @@ mod tests {
         
         let err = DatomicError::timeout_error(5000);
         assert_eq!(err.to_string(), "Timeout error: operation timed out after 5000ms");
+
+        let err = DatomicError::transaction_error(":db.error/cas-failed Compare failed: \"old\" \"new\"");
+        assert!(err.is_cas_conflict());
+        assert!(!DatomicError::transaction_error("Transactor unavailable").is_cas_conflict());
     }
     
+    fn code(err: AppError) -> String {
+        serde_json::to_value(err).unwrap()["code"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_app_error_codes() {
+        let err = AppError::from(DatomicError::entity_not_found("Block missing-block"));
+        assert_eq!(err.to_string(), "Entity not found: Block missing-block");
+        assert_eq!(code(err), "not_found");
+
+        assert_eq!(code(AppError::from(DatomicError::invalid_transaction_data("Negative timestamp"))), "validation");
+        assert_eq!(code(AppError::from(DatomicError::transaction_error(":db.error/cas-failed Compare failed"))), "conflict");
+        assert_eq!(code(AppError::from(DatomicError::connection_error("Transactor down"))), "database");
+        assert_eq!(code(AppError::from(AudioEngineError::AlreadyRecording)), "conflict");
+        assert_eq!(code(AppError::from(AudioEngineError::DeviceNotFound("USB Mic".to_string()))), "not_found");
+        assert_eq!(code(AppError::from(AudioEngineError::wav_error("Truncated header"))), "audio");
+
+        let malformed = uuid::Uuid::parse_str("not-a-uuid").unwrap_err();
+        assert_eq!(code(AppError::from(malformed)), "validation");
+
+        // anyhow keeps the classification of the error it wraps
+        let wrapped = anyhow::Error::new(DatomicError::entity_not_found("Page Inbox"));
+        assert_eq!(code(AppError::from(wrapped)), "not_found");
+        assert_eq!(code(AppError::from(anyhow::anyhow!("Config file is unreadable"))), "internal");
+    }
+
+    #[test]
+    fn test_app_error_payload() {
+        let json = serde_json::to_value(AppError::from(DatomicError::entity_not_found("Block missing-block"))).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "code": "not_found",
+            "message": "Entity not found: Block missing-block",
+            "details": null,
+        }));
+
+        let json = serde_json::to_value(AppError::from(DatomicError::retry_limit_exceeded(3))).unwrap();
+        assert_eq!(json["code"], "database");
+        assert_eq!(json["details"]["attempts"], 3);
+    }
+
     #[tokio::test]
     async fn test_retry_success() {
         let config = RetryConfig::default();
@@ -253,11 +648,12 @@ mod tests {
         let result = with_retry(
             || {
                 attempt_count += 1;
-                if attempt_count < 3 {
-                    Err("Temporary failure")
+                let result = if attempt_count < 3 {
+                    Err(DatomicError::connection_error("Temporary failure"))
                 } else {
                     Ok(42)
-                }
+                };
+                async move { result }
             },
             &config,
             "test_operation",
@@ -278,7 +674,7 @@ mod tests {
         };
         
         let result: Result<i32> = with_retry( // Added type annotation Result<i32> which implies Result<i32, DatomicError>
-            || Err("Always fails"),
+            || async { Err(DatomicError::connection_error("Always fails")) },
             &config,
             "test_operation",
         ).await;
@@ -291,4 +687,22 @@ mod tests {
             _ => panic!("Expected RetryLimitExceeded error"),
         }
     }
+
+    #[tokio::test]
+    async fn test_retry_stops_at_errors_that_would_repeat() {
+        let mut attempt_count = 0;
+
+        let result: Result<i32> = with_retry(
+            || {
+                attempt_count += 1;
+                async { Err(DatomicError::edn_parsing_error("Unmatched delimiter")) }
+            },
+            &RetryConfig::default(),
+            "test_operation",
+        ).await;
+
+        assert!(matches!(result, Err(DatomicError::EdnParsingError(_))));
+        assert_eq!(attempt_count, 1);
+        assert!(DatomicError::timeout_error(30_000).is_retryable());
+    }
 }
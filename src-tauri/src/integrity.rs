@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::info;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::models::{Block, IntegrityReport};
+
+/// Blocks `(orphaned, in_cycles)`: those whose parent doesn't exist, and
+/// those that are their own ancestor
+pub fn tree_problems(blocks: &[Block]) -> (Vec<String>, Vec<String>) {
+    let parents: HashMap<&str, Option<&str>> = blocks.iter()
+        .map(|block| (block.id.as_str(), block.parent_id.as_deref()))
+        .collect();
+
+    let mut orphaned = Vec::new();
+    let mut in_cycles = Vec::new();
+    for block in blocks {
+        if block.parent_id.as_deref().is_some_and(|parent_id| !parents.contains_key(parent_id)) {
+            orphaned.push(block.id.clone());
+        }
+        let mut seen = HashSet::new();
+        let mut ancestor = block.parent_id.as_deref();
+        while let Some(id) = ancestor.filter(|id| seen.insert(*id)) {
+            if id == block.id {
+                in_cycles.push(block.id.clone());
+                break;
+            }
+            ancestor = parents.get(id).copied().flatten();
+        }
+    }
+    orphaned.sort();
+    in_cycles.sort();
+    (orphaned, in_cycles)
+}
+
+/// Check the schema, the block tree, page links and recordings for
+/// problems, without changing anything
+pub async fn check_integrity(db: &DatomicPeerClient) -> Result<IntegrityReport, AppError> {
+    let missing_schema = db.schema_diff().await?.missing;
+    let blocks = db.get_all_blocks().await?;
+    let (orphaned_blocks, blocks_in_cycles) = tree_problems(&blocks);
+    let blocks_with_stale_links = db.blocks_with_stale_links().await?;
+
+    let block_ids: HashSet<&str> = blocks.iter().map(|block| block.id.as_str()).collect();
+    let recordings = db.get_all_recordings().await?;
+    let mut files = Vec::new();
+    let mut dangling_timestamps = Vec::new();
+    for recording in &recordings {
+        let segments = db.get_recording_segments(&recording.id).await?;
+        if segments.is_empty() {
+            files.push(recording.file_path.clone());
+        } else {
+            files.extend(segments.into_iter().map(|segment| segment.file_path));
+        }
+        dangling_timestamps.extend(
+            db.get_recording_timestamps(&recording.id).await?.into_iter()
+                .map(|timestamp| timestamp.block_id)
+                .filter(|block_id| !block_ids.contains(block_id.as_str())),
+        );
+    }
+    let missing_recording_files = tauri::async_runtime::spawn_blocking(move || {
+        files.into_iter().filter(|file| !Path::new(file).exists()).collect()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Integrity check failed: {}", e)))?;
+
+    let report = IntegrityReport {
+        blocks_checked: blocks.len(),
+        recordings_checked: recordings.len(),
+        missing_schema,
+        orphaned_blocks,
+        blocks_in_cycles,
+        blocks_with_stale_links,
+        missing_recording_files,
+        dangling_timestamps,
+    };
+    info!(
+        "Checked {} blocks and {} recordings: {}",
+        report.blocks_checked, report.recordings_checked, if report.is_clean() { "no problems" } else { "problems found" }
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn block(id: &str, parent_id: Option<&str>) -> Block {
+        Block {
+            id: id.to_string(),
+            content: None,
+            parent_id: parent_id.map(str::to_string),
+            order: 0,
+            is_page: parent_id.is_none(),
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_tree_problems_finds_orphans_and_cycles() {
+        let blocks = vec![
+            block("page", None),
+            block("child", Some("page")),
+            block("grandchild", Some("child")),
+            block("orphan", Some("deleted")),
+            block("under-orphan", Some("orphan")),
+            block("loop-a", Some("loop-b")),
+            block("loop-b", Some("loop-a")),
+            block("into-loop", Some("loop-a")),
+        ];
+
+        let (orphaned, in_cycles) = tree_problems(&blocks);
+
+        assert_eq!(orphaned, vec!["orphan"]);
+        // Blocks hanging off a cycle aren't part of it
+        assert_eq!(in_cycles, vec!["loop-a", "loop-b"]);
+        assert_eq!(tree_problems(&blocks[..3]), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use chrono::Utc;
+use serde::Serialize;
+use crate::errors::AppError;
+use crate::models::{JobInfo, JobProgress, JobStatus};
+
+/// Finished jobs kept for `list_jobs`; the oldest are dropped
+const FINISHED_JOBS_KEPT: usize = 50;
+
+/// What a job reports while it runs and when it ends
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    Progress(JobProgress),
+    Finished(JobInfo),
+}
+
+type EventListener = Arc<dyn Fn(JobEvent) + Send + Sync>;
+
+struct JobEntry {
+    info: JobInfo,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Long-running operations started from commands, running or recently
+/// finished, oldest first
+#[derive(Default)]
+pub struct Jobs {
+    entries: Mutex<VecDeque<JobEntry>>,
+}
+
+/// A running job's side of the registry: reports progress and checks
+/// between units of work whether it was cancelled
+#[derive(Clone)]
+pub struct Job {
+    pub id: String,
+    jobs: Arc<Jobs>,
+    cancel: Arc<AtomicBool>,
+    on_event: EventListener,
+}
+
+/// Where a long operation reports how far it has got and learns between
+/// units of work that it should stop
+pub trait Progress {
+    /// Record that `done` of `total` units are finished
+    fn progress(&self, done: usize, total: usize, message: &str);
+
+    /// Fail if the operation was cancelled, to stop before the next unit
+    fn check_cancelled(&self) -> Result<(), AppError>;
+}
+
+/// Progress for operations nobody is watching, like those run from the CLI
+pub struct Untracked;
+
+impl Progress for Untracked {
+    fn progress(&self, _done: usize, _total: usize, _message: &str) {}
+
+    fn check_cancelled(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+impl Job {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+impl Progress for Job {
+    fn progress(&self, done: usize, total: usize, message: &str) {
+        let progress = JobProgress {
+            job_id: self.id.clone(),
+            done,
+            total,
+            message: message.to_string(),
+        };
+        self.jobs.update(&self.id, |info| {
+            info.done = done;
+            info.total = total;
+            info.message = Some(progress.message.clone());
+        });
+        (self.on_event)(JobEvent::Progress(progress));
+    }
+
+    fn check_cancelled(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::conflict(format!("Job {} was cancelled", self.id)));
+        }
+        Ok(())
+    }
+}
+
+impl Jobs {
+    fn entries(&self) -> MutexGuard<'_, VecDeque<JobEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn update(&self, job_id: &str, change: impl FnOnce(&mut JobInfo)) -> Option<JobInfo> {
+        let mut entries = self.entries();
+        let entry = entries.iter_mut().find(|entry| entry.info.job_id == job_id)?;
+        change(&mut entry.info);
+        Some(entry.info.clone())
+    }
+
+    /// Register a running job of `kind`, reporting to `on_event`
+    pub fn start(self: &Arc<Self>, kind: &str, on_event: impl Fn(JobEvent) + Send + Sync + 'static) -> Job {
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            jobs: self.clone(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            on_event: Arc::new(on_event),
+        };
+        self.entries().push_back(JobEntry {
+            info: JobInfo {
+                job_id: job.id.clone(),
+                kind: kind.to_string(),
+                status: JobStatus::Running,
+                done: 0,
+                total: 0,
+                message: None,
+                started_at: Utc::now(),
+                finished_at: None,
+                result: None,
+                error: None,
+            },
+            cancel: job.cancel.clone(),
+        });
+        job
+    }
+
+    /// Record how `job` ended and drop the oldest finished jobs beyond the
+    /// ones kept. A job that fails after being cancelled counts as cancelled.
+    pub fn finish(&self, job: &Job, result: Result<serde_json::Value, AppError>) {
+        let status = match &result {
+            Ok(_) => JobStatus::Completed,
+            Err(_) if job.is_cancelled() => JobStatus::Cancelled,
+            Err(_) => JobStatus::Failed,
+        };
+        let finished = self.update(&job.id, |info| {
+            info.status = status;
+            info.finished_at = Some(Utc::now());
+            match result {
+                Ok(value) => info.result = Some(value),
+                Err(e) => info.error = Some(e),
+            }
+        });
+
+        let mut entries = self.entries();
+        let mut finished_count = entries.iter().filter(|entry| entry.info.status != JobStatus::Running).count();
+        entries.retain(|entry| {
+            let expired = finished_count > FINISHED_JOBS_KEPT && entry.info.status != JobStatus::Running;
+            finished_count -= expired as usize;
+            !expired
+        });
+        drop(entries);
+
+        if let Some(info) = finished {
+            (job.on_event)(JobEvent::Finished(info));
+        }
+    }
+
+    /// Ask a running job to stop at its next check
+    pub fn cancel(&self, job_id: &str) -> Result<(), AppError> {
+        let entries = self.entries();
+        let entry = entries.iter().find(|entry| entry.info.job_id == job_id)
+            .ok_or_else(|| AppError::not_found(format!("Job not found: {}", job_id)))?;
+        if entry.info.status != JobStatus::Running {
+            return Err(AppError::conflict(format!("Job {} has already finished", job_id)));
+        }
+        entry.cancel.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Running and recently finished jobs, newest first
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.entries().iter().rev().map(|entry| entry.info.clone()).collect()
+    }
+
+    /// Run `work` as a job of `kind` on the async runtime and return its id
+    /// straight away. The result is kept with the job and sent with its
+    /// `Finished` event.
+    pub fn spawn<T, F, Fut>(self: &Arc<Self>, kind: &str, on_event: impl Fn(JobEvent) + Send + Sync + 'static, work: F) -> String
+    where
+        T: Serialize,
+        F: FnOnce(Job) -> Fut,
+        Fut: Future<Output = Result<T, AppError>> + Send + 'static,
+    {
+        let job = self.start(kind, on_event);
+        let id = job.id.clone();
+        let running = work(job.clone());
+        let jobs = self.clone();
+        tauri::async_runtime::spawn(async move {
+            let result = running.await.and_then(|value| {
+                serde_json::to_value(value).map_err(|e| AppError::internal(format!("Failed to encode job result: {}", e)))
+            });
+            jobs.finish(&job, result);
+        });
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_job_reports_progress_and_result() {
+        let jobs = Arc::new(Jobs::default());
+        let (events, received) = mpsc::channel();
+
+        let id = jobs.spawn("count", move |event| events.send(event).unwrap(), |job| async move {
+            for done in 1..=3 {
+                job.check_cancelled()?;
+                job.progress(done, 3, "counting");
+            }
+            Ok(3)
+        });
+
+        let mut progress = Vec::new();
+        let finished = loop {
+            match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+                JobEvent::Progress(update) => progress.push(update.done),
+                JobEvent::Finished(info) => break info,
+            }
+        };
+        assert_eq!(progress, vec![1, 2, 3]);
+        assert_eq!(finished.job_id, id);
+        assert_eq!(finished.status, JobStatus::Completed);
+        assert_eq!(finished.result, Some(serde_json::json!(3)));
+        assert_eq!(jobs.list()[0].done, 3);
+        assert!(matches!(jobs.cancel(&id), Err(AppError::Conflict { .. })));
+        assert!(matches!(jobs.cancel("missing"), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_cancelled_job_stops_at_next_check() {
+        let jobs = Arc::new(Jobs::default());
+        let job = jobs.start("import", |_| {});
+        job.check_cancelled().unwrap();
+
+        jobs.cancel(&job.id).unwrap();
+        let stopped = job.check_cancelled();
+        assert!(stopped.is_err());
+        jobs.finish(&job, stopped.map(|_| serde_json::Value::Null));
+
+        let info = &jobs.list()[0];
+        assert_eq!(info.status, JobStatus::Cancelled);
+        assert!(info.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_only_recent_finished_jobs_are_kept() {
+        let jobs = Arc::new(Jobs::default());
+        let running = jobs.start("export", |_| {});
+        for _ in 0..FINISHED_JOBS_KEPT + 5 {
+            let job = jobs.start("import", |_| {});
+            jobs.finish(&job, Ok(serde_json::Value::Null));
+        }
+
+        let listed = jobs.list();
+        assert_eq!(listed.len(), FINISHED_JOBS_KEPT + 1);
+        assert!(listed.iter().any(|info| info.job_id == running.id));
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use chrono::{Local, NaiveDate};
+use tracing::level_filters::LevelFilter;
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+use crate::command_log::{self, CommandLog};
+use crate::config::AppConfig;
+
+/// Days of log files kept; older ones are deleted when a new day starts
+const LOG_FILES_KEPT: usize = 7;
+const LOG_FILE_PREFIX: &str = "gita.";
+const LOG_FILE_SUFFIX: &str = ".log";
+
+/// Directory the log files are written to
+pub fn logs_dir(config: &AppConfig) -> PathBuf {
+    config.data_dir.join("logs")
+}
+
+/// Log files in `names` beyond the newest `keep`. Names hold the date, so
+/// they sort oldest first.
+fn expired_log_files(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    names.retain(|name| name.starts_with(LOG_FILE_PREFIX) && name.ends_with(LOG_FILE_SUFFIX));
+    names.sort();
+    let expired = names.len().saturating_sub(keep);
+    names.truncate(expired);
+    names
+}
+
+/// Appends to one log file per day, deleting the oldest as new ones start
+pub struct DailyLogFile {
+    dir: PathBuf,
+    current: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl DailyLogFile {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), current: Mutex::new(None) })
+    }
+
+    fn path(&self, date: NaiveDate) -> PathBuf {
+        self.dir.join(format!("{}{}{}", LOG_FILE_PREFIX, date.format("%Y-%m-%d"), LOG_FILE_SUFFIX))
+    }
+
+    fn remove_expired(&self) -> io::Result<()> {
+        let names = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        for name in expired_log_files(names, LOG_FILES_KEPT) {
+            fs::remove_file(self.dir.join(name))?;
+        }
+        Ok(())
+    }
+
+    fn append(&self, date: NaiveDate, buf: &[u8]) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap();
+        if !matches!(&*current, Some((open_date, _)) if *open_date == date) {
+            let file = OpenOptions::new().create(true).append(true).open(self.path(date))?;
+            *current = Some((date, file));
+            // Losing old logs isn't worth failing a write over
+            let _ = self.remove_expired();
+        }
+        match &mut *current {
+            Some((_, file)) => file.write_all(buf),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for &DailyLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append(Local::now().date_naive(), buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.current.lock().unwrap() {
+            Some((_, file)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for DailyLogFile {
+    type Writer = &'a DailyLogFile;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self
+    }
+}
+
+/// Log to stdout at the configured level, and to daily files under the data
+/// directory if `log_to_file` is set. Commands are recorded in `command_log`
+/// whatever the level.
+pub fn init(config: &AppConfig, command_log: Arc<CommandLog>) {
+    let level = config.log_level.parse::<LevelFilter>();
+    let level_filter = level.as_ref().copied().unwrap_or(LevelFilter::INFO);
+
+    let mut file_error = None;
+    let file_layer = if config.log_to_file {
+        match DailyLogFile::new(&logs_dir(config)) {
+            Ok(file) => Some(tracing_subscriber::fmt::layer().with_ansi(false).with_writer(file).with_filter(level_filter)),
+            Err(e) => {
+                file_error = Some(e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(level_filter))
+        .with(file_layer)
+        .with(command_log::layer(command_log))
+        .init();
+
+    if level.is_err() {
+        warn!("Unknown log level {:?}, logging at info", config.log_level);
+    }
+    if let Some(e) = file_error {
+        warn!("Can't write logs to {}: {}", logs_dir(config).display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_oldest_log_files_expire() {
+        let names = vec![
+            "gita.2026-10-03.log".to_string(),
+            "gita.2026-10-01.log".to_string(),
+            "notes.txt".to_string(),
+            "gita.2026-10-02.log".to_string(),
+        ];
+
+        assert_eq!(expired_log_files(names.clone(), 2), vec!["gita.2026-10-01.log"]);
+        assert!(expired_log_files(names, 3).is_empty());
+    }
+
+    #[test]
+    fn test_daily_log_file_starts_a_file_per_day() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = DailyLogFile::new(&dir.path().join("logs")).unwrap();
+        let first = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+        let second = NaiveDate::from_ymd_opt(2026, 10, 2).unwrap();
+
+        file.append(first, b"one\n").unwrap();
+        file.append(first, b"two\n").unwrap();
+        file.append(second, b"three\n").unwrap();
+
+        assert_eq!(fs::read_to_string(file.path(first)).unwrap(), "one\ntwo\n");
+        assert_eq!(fs::read_to_string(file.path(second)).unwrap(), "three\n");
+    }
+}
@@ -2,208 +2,2874 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod database_peer_complete;
+mod datomic_install;
 mod audio_engine;
 mod models;
 mod datomic_schema;
+mod edn;
 mod config;
 mod errors;
+mod agc;
+mod silence;
+mod pending_writes;
+mod page_bundle;
+mod tray;
+mod deep_link;
+mod reveal;
+mod archive;
+mod integrity;
+mod cli;
+mod onboarding;
+mod audio_import;
+mod command_log;
+mod logging;
+mod jobs;
+mod cancellation;
+mod path_policy;
+mod store;
+#[cfg(feature = "transcription")]
+mod transcription;
+#[cfg(feature = "binary-export")]
+mod binary_export;
+#[cfg(feature = "opus")]
+mod ogg_opus;
 
 #[cfg(test)]
 mod tests;
 
 extern crate tracing; // Removed #[macro_use]
 
-use std::sync::{Arc, Mutex};
-use tauri::Manager;
-use tracing::{info, error, Level};
-use tracing_subscriber;
+use std::sync::{Arc, Mutex, RwLock};
+use tauri::{Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use tracing::{info, warn, error, Level};
 
-use audio_engine::AudioEngine;
+use audio_engine::{AudioEngine, Encoding, MAX_PLAYBACK_GAIN_DB};
 use models::*;
 use database_peer_complete::DatomicPeerClient;
-use config::AppConfig;
+use config::{AppConfig, AudioFormat, ConfigPatch, StorageBackend};
+use errors::{AudioEngineError, AppError};
+use silence::SilenceSplit;
+use pending_writes::PendingWrites;
+use deep_link::DeepLink;
+use command_log::{CommandLog, CommandLogEntry};
+use jobs::{JobEvent, Jobs};
+use cancellation::CancelTokens;
+use path_policy::{PathPolicy, PathTokens};
+use store::{SqliteStore, Store};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "transcription")]
+use errors::TranscriptionError;
+#[cfg(feature = "transcription")]
+use transcription::{TranscriptionProgress, TranscriptionResult};
 // Removed DatomicError, Result as they are not directly used in this file
 // use errors::{DatomicError, Result};
 
-// Tauri commands for database operations
+/// When a recording's file was last written, if it still exists
+fn file_modified_at(file_path: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    std::fs::metadata(file_path).and_then(|metadata| metadata.modified()).ok().map(Into::into)
+}
+
+/// Whether an unfinished recording's file is recent enough to offer resuming it.
+/// Only WAV recordings can be appended to.
+fn is_resumable(recording: &AudioRecording) -> bool {
+    recording.file_path.ends_with(".wav") && file_modified_at(&AudioEngine::unfinished_path(&recording.file_path))
+        .is_some_and(|modified_at| InterruptedRecording::is_recent(modified_at, chrono::Utc::now()))
+}
+
+/// Repair recordings left without a duration by a crash. Files that can't be
+/// repaired are flagged so they aren't retried on every startup. Recent files
+/// are left for the user to finalize or resume, which also keeps this away
+/// from a recording still in progress.
+async fn recover_interrupted_recordings(db: &DatomicPeerClient) -> errors::Result<RecordingRecoveryReport> {
+    let mut report = RecordingRecoveryReport::default();
+    
+    for recording in db.get_unfinished_recordings().await? {
+        if is_resumable(&recording) {
+            continue;
+        }
+        // Left for a build that can repair it rather than flagged
+        if recording.file_path.ends_with(".ogg") && !cfg!(feature = "opus") {
+            warn!("Skipping recovery of Opus recording {}: this build can't repair it", recording.id);
+            continue;
+        }
+        match AudioEngine::recover_partial_file(&recording.file_path) {
+            Ok(duration) => {
+                db.update_recording_duration(&recording.id, duration).await?;
+                info!("Recovered recording {} ({}s)", recording.id, duration);
+                report.recovered += 1;
+            }
+            Err(e) => {
+                error!("Could not recover recording {}: {}", recording.id, e);
+                db.set_recording_status(&recording.id, "unrecoverable").await?;
+                report.failed += 1;
+            }
+        }
+    }
+    
+    Ok(report)
+}
+
+// How often the input level is sent to the frontend during a recording
+const LEVEL_METER_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// ID of the recording in progress, so it can be stopped when its device is unplugged
+#[derive(Default)]
+struct ActiveRecording(Mutex<Option<String>>);
+
+impl ActiveRecording {
+    /// Clear the recording in progress, if it is `recording_id`
+    fn take(&self, recording_id: &str) -> std::result::Result<(), AppError> {
+        let mut active = self.0.lock().unwrap();
+        if active.as_deref() != Some(recording_id) {
+            return Err(AppError::conflict(format!("Recording {} is not in progress", recording_id)));
+        }
+        active.take();
+        Ok(())
+    }
+}
+
+// How often the elapsed time of a recording is sent to the frontend
+const RECORDING_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+fn emit_recording_started(app_handle: &tauri::AppHandle, recording_id: &str, page_id: &str, file_path: &str) {
+    let started = RecordingStarted {
+        recording_id: recording_id.to_string(),
+        page_id: page_id.to_string(),
+        file_path: file_path.to_string(),
+    };
+    if let Err(e) = app_handle.emit("audio://recording-started", &started) {
+        error!("Failed to emit recording start: {}", e);
+    }
+    tray::show_recording(app_handle, true);
+}
+
+fn emit_recording_stopped(app_handle: &tauri::AppHandle, stopped: RecordingStopped) {
+    if let Err(e) = app_handle.emit("audio://recording-stopped", &stopped) {
+        error!("Failed to emit recording stop: {}", e);
+    }
+    tray::show_recording(app_handle, false);
+}
+
+/// Stop audio capture and store the recording's duration and segments
+async fn finish_recording(
+    recording_id: &str,
+    app_handle: &tauri::AppHandle,
+    audio_engine: &Arc<AudioEngine>,
+    store: &dyn Store,
+) -> std::result::Result<(), AppError> {
+    // Stopping waits for the writer to finish the file, so keep it off the async runtime
+    let engine = audio_engine.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || engine.stop_recording()).await
+        .unwrap_or_else(|e| Err(AudioEngineError::internal_error(format!("Stopping the recording failed: {}", e))));
+
+    // Nothing was recording, so there is no transition to report
+    if !matches!(result, Err(AudioEngineError::NotRecording)) {
+        emit_recording_stopped(app_handle, RecordingStopped {
+            recording_id: recording_id.to_string(),
+            duration_seconds: result.as_ref().ok().map(|summary| summary.duration_seconds),
+            cancelled: false,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    }
+
+    let summary = match result {
+        Ok(summary) => summary,
+        Err(AudioEngineError::NotRecording) => return Err(AppError::from(AudioEngineError::NotRecording)),
+        Err(e) => {
+            // The WAV file is incomplete, so don't record a duration for it
+            error!("Recording {} failed: {}", recording_id, e);
+            if let Err(status_err) = store.set_recording_status(recording_id, "failed").await {
+                error!("Failed to mark recording {} as failed: {}", recording_id, status_err);
+            }
+            return Err(AppError::from(e));
+        }
+    };
+
+    // Update recording duration in database
+    store.update_recording_duration(recording_id, summary.duration_seconds).await.map_err(|e| {
+        error!("Failed to update duration of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+
+    // Single-file recordings play straight from the recording's own path
+    if summary.segments.len() > 1 {
+        store.create_recording_segments(recording_id, &summary.segments).await.map_err(|e| {
+            error!("Failed to store segments of recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?;
+    }
+
+    info!("Stopped recording: {} ({}s)", recording_id, summary.duration_seconds);
+    Ok(())
+}
+
+/// Forward device changes to the frontend, stopping the recording in
+/// progress if its input device was unplugged
+fn handle_devices_changed(app_handle: &tauri::AppHandle, changes: DevicesChanged) {
+    if let Err(e) = app_handle.emit("audio://devices-changed", &changes) {
+        error!("Failed to emit device changes: {}", e);
+    }
+
+    // The watcher can fire before setup has finished managing the engine
+    let (Some(audio_engine), Some(active)) = (
+        app_handle.try_state::<Arc<AudioEngine>>(),
+        app_handle.try_state::<ActiveRecording>(),
+    ) else {
+        return;
+    };
+    let Some(device_name) = audio_engine.recording_device_name() else {
+        return;
+    };
+    if !changes.removed_input(&device_name) {
+        return;
+    }
+    let Some(recording_id) = active.0.lock().unwrap().take() else {
+        return;
+    };
+
+    error!("Input device {} disconnected during recording {}", device_name, recording_id);
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+        let store = app_handle.state::<Arc<dyn Store>>();
+        let stopped = finish_recording(&recording_id, &app_handle, &audio_engine, store.as_ref()).await.is_ok();
+
+        let lost = RecordingDeviceLost { recording_id, device_name, stopped };
+        if let Err(e) = app_handle.emit("audio://recording-device-lost", &lost) {
+            error!("Failed to emit recording device loss: {}", e);
+        }
+    });
+}
+
+// How long quitting waits for the recording and database to be saved
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Progress of saving everything before the app exits
+#[derive(Default)]
+struct Shutdown {
+    started: AtomicBool,
+    finished: AtomicBool,
+}
+
+/// Whether the app has saved its state and may exit now
+fn ready_to_exit(app_handle: &tauri::AppHandle) -> bool {
+    app_handle.try_state::<Shutdown>().is_none_or(|shutdown| shutdown.finished.load(Ordering::SeqCst))
+}
+
+/// Write pending block edits, stop the recording in progress through the
+/// normal path and close the database, then exit. Only the first call does anything, and a hung device
+/// or database delays quitting by at most `SHUTDOWN_TIMEOUT`.
+fn begin_shutdown(app_handle: &tauri::AppHandle) {
+    if app_handle.state::<Shutdown>().started.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let recording_id = app_handle.state::<ActiveRecording>().0.lock().unwrap().take();
+    let shutting_down = ShuttingDown { saving_recording: recording_id.clone() };
+    if let Err(e) = app_handle.emit("app://shutting-down", &shutting_down) {
+        error!("Failed to emit shutdown: {}", e);
+    }
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+        let store = app_handle.state::<Arc<dyn Store>>();
+        let db = app_handle.state::<Arc<DatomicPeerClient>>();
+        let pending = app_handle.state::<PendingWrites>();
+        let save = async {
+            if let Err(e) = flush_pending(&pending, store.as_ref()).await {
+                error!("Unsaved block edits are lost: {}", e);
+            }
+            if let Some(recording_id) = recording_id {
+                info!("Saving recording {} before exit", recording_id);
+                if let Err(e) = finish_recording(&recording_id, &app_handle, &audio_engine, store.as_ref()).await {
+                    error!("Failed to save recording {} before exit: {}", recording_id, e);
+                }
+            }
+            if let Err(e) = db.inner().shutdown(true).await {
+                error!("Failed to shut down the database client: {}", e);
+            }
+        };
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, save).await.is_err() {
+            error!("Saving before exit took longer than {:?}, exiting anyway", SHUTDOWN_TIMEOUT);
+        }
+
+        app_handle.state::<Shutdown>().finished.store(true, Ordering::SeqCst);
+        app_handle.exit(0);
+    });
+}
+
+/// Cancellation flags of running transcriptions, keyed by recording ID
+#[cfg(feature = "transcription")]
+#[derive(Default)]
+struct TranscriptionJobs(Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+/// Transcribe a recording on a blocking thread and add one child block per
+/// segment under the recording's page, stamped with the segment start
+#[cfg(feature = "transcription")]
+async fn run_transcription(
+    app_handle: &tauri::AppHandle,
+    db: &DatomicPeerClient,
+    recording: &AudioRecording,
+    model_path: &str,
+    cancel: Arc<AtomicBool>,
+) -> std::result::Result<usize, TranscriptionError> {
+    let file_path = recording.file_path.clone();
+    let model_path = model_path.to_string();
+    let recording_id = recording.id.clone();
+    let progress_handle = app_handle.clone();
+    let worker_cancel = cancel.clone();
+
+    let segments = tauri::async_runtime::spawn_blocking(move || {
+        transcription::transcribe_wav(&file_path, &model_path, &worker_cancel, |progress| {
+            let payload = TranscriptionProgress { recording_id: recording_id.clone(), progress };
+            if let Err(e) = progress_handle.emit("audio://transcription-progress", &payload) {
+                error!("Failed to emit transcription progress: {}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| TranscriptionError::model_error(format!("Transcription task failed: {}", e)))??;
+
+    if cancel.load(Ordering::SeqCst) {
+        return Err(TranscriptionError::Cancelled);
+    }
+
+    // Append after the page's existing children rather than colliding with them
+    let children = db.get_page_blocks(&recording.page_id).await?;
+    let next_order = children.iter().map(|block| block.order + 1).max().unwrap_or(0);
+
+    for (i, segment) in segments.iter().enumerate() {
+        let block_data = CreateBlockRequest {
+            content: Some(segment.text.clone()),
+            parent_id: Some(recording.page_id.clone()),
+            order: next_order + i as i32,
+            is_page: false,
+            page_title: None,
+            source: Some("transcription".to_string()),
+        };
+        let audio_meta = AudioMeta {
+            recording_id: recording.id.clone(),
+            timestamp_ms: segment.start_ms,
+        };
+        db.create_block(block_data, Some(audio_meta)).await?;
+    }
+
+    Ok(segments.len())
+}
+
+/// Write a block's content, dropping its draft now that the block holds the edit
+async fn write_block_content(store: &dyn Store, block_id: String, content: String) -> errors::Result<bool> {
+    let changed = store.update_block_content(&block_id, &content).await?;
+    store.delete_draft(&block_id).await?;
+    Ok(changed)
+}
+
+/// Write every pending block edit now rather than when typing pauses
+async fn flush_pending(pending: &PendingWrites, store: &dyn Store) -> std::result::Result<usize, AppError> {
+    pending.flush(|block_id, content| write_block_content(store, block_id, content)).await.map_err(|e| {
+        error!("Failed to write pending block edits: {}", e);
+        AppError::from(e)
+    })
+}
+
+// Tauri commands for database operations. Like every command they return
+// an AppError so the frontend can tell missing blocks and bad input from
+// internal failures.
+/// `create_if_missing` defaults to true; pass false to read a day without
+/// creating its page, e.g. when prefetching adjacent days
+#[tauri::command]
+async fn get_daily_note(
+    date: String,
+    create_if_missing: Option<bool>,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    let mut blocks = store.get_daily_note(&date, create_if_missing.unwrap_or(true)).await.map_err(|e| {
+        error!("Failed to get daily note for {}: {}", date, e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut blocks);
+    Ok(blocks)
+}
+
+/// Every assertion and retraction of a block's content, oldest first, for an edit timeline
+#[tauri::command]
+async fn get_block_history(
+    block_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<ContentRevision>, AppError> {
+    // An edit still waiting to be written belongs at the end of the timeline
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().get_block_history(&block_id).await.map_err(|e| {
+        error!("Failed to get the history of block {}: {}", block_id, e);
+        AppError::from(e)
+    })
+}
+
+/// Daily note for today in the frontend's timezone, so both sides agree on the date around midnight
+#[tauri::command]
+async fn get_today_daily_note(
+    tz_offset_minutes: i32,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    let mut blocks = store.get_today_daily_note(tz_offset_minutes).await.map_err(|e| {
+        error!("Failed to get today's daily note (UTC offset {} minutes): {}", tz_offset_minutes, e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut blocks);
+    Ok(blocks)
+}
+
+#[tauri::command]
+async fn create_block(
+    block_data: CreateBlockRequest,
+    audio_meta: Option<AudioMeta>,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Block, AppError> {
+    // Reject a malformed request before asking the engine for its position
+    block_data.validate()?;
+
+    // Stamp with the live recording position when the frontend asks for it
+    let audio_meta = match audio_meta {
+        Some(mut meta) if meta.timestamp_ms == AudioMeta::CURRENT_POSITION => {
+            // The engine's clock counts captured frames, so this matches the position in the file
+            let elapsed_ms = audio_engine.get_current_recording_time().map_err(AppError::from)?;
+            meta.timestamp_ms = elapsed_ms as i64;
+            Some(meta)
+        }
+        other => other,
+    };
+    
+    store.create_block(block_data, audio_meta).await.map_err(|e| {
+        error!("Failed to create block: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Queue a block's new content, written once edits to the block pause for
+/// `PendingWrites::QUIET_PERIOD`. Returns false when the block already had
+/// this content and nothing was queued.
+#[tauri::command]
+async fn update_block_content(
+    block_id: String,
+    content: String,
+    app_handle: tauri::AppHandle,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<bool, AppError> {
+    let current = match pending.content(&block_id) {
+        Some(pending_content) => Some(pending_content),
+        None => store.get_block(&block_id).await
+            .map_err(|e| {
+                error!("Failed to get block {} to update: {}", block_id, e);
+                AppError::from(e)
+            })?
+            .ok_or_else(|| AppError::not_found(format!("Block not found: {}", block_id)))?
+            .content,
+    };
+    if current.as_deref() == Some(content.as_str()) {
+        return Ok(false);
+    }
+
+    let generation = pending.queue(&block_id, content);
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(PendingWrites::QUIET_PERIOD).await;
+        let pending = app_handle.state::<PendingWrites>();
+        let store = app_handle.state::<Arc<dyn Store>>();
+        let flushed = pending.flush_block(&block_id, generation, |block_id, content| {
+            write_block_content(store.as_ref(), block_id, content)
+        }).await;
+        if let Err(e) = flushed {
+            error!("Failed to update block {}: {}", block_id, e);
+        }
+    });
+    Ok(true)
+}
+
+/// Write queued block edits without waiting for typing to pause, e.g. before
+/// navigating away from a page. Returns how many blocks were written.
+#[tauri::command]
+async fn flush_pending_writes(
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<usize, AppError> {
+    flush_pending(&pending, db.inner().as_ref()).await
+}
+
+/// Drafts of block edits that never reached the database, e.g. because the
+/// app crashed while they were pending, for offering to restore at startup
+#[tauri::command]
+async fn get_unsaved_drafts(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<Draft>, AppError> {
+    let drafts = db.inner().get_drafts().await.map_err(|e| {
+        error!("Failed to get drafts: {}", e);
+        AppError::from(e)
+    })?;
+    if drafts.is_empty() {
+        return Ok(drafts);
+    }
+    let block_ids: Vec<String> = drafts.iter().map(|draft| draft.block_id.clone()).collect();
+    let blocks = db.inner().get_blocks_by_ids(&block_ids).await.map_err(|e| {
+        error!("Failed to get blocks with drafts: {}", e);
+        AppError::from(e)
+    })?;
+    Ok(Draft::unsaved(drafts, &blocks))
+}
+
+/// Restore a block's content from its draft and return the block
+#[tauri::command]
+async fn apply_draft(
+    block_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    let draft = db.inner().get_draft(&block_id).await
+        .map_err(|e| {
+            error!("Failed to get draft of block {}: {}", block_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("No draft of block {}", block_id)))?;
+
+    // Goes through the pending writes so it replaces any edit still queued
+    let generation = pending.queue(&block_id, draft.content);
+    pending.flush_block(&block_id, generation, |block_id, content| write_block_content(db.inner().as_ref(), block_id, content))
+        .await
+        .map_err(|e| {
+            error!("Failed to apply draft of block {}: {}", block_id, e);
+            AppError::from(e)
+        })?;
+
+    let mut block = db.inner().get_block(&block_id).await
+        .map_err(|e| {
+            error!("Failed to get block {}: {}", block_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Block not found: {}", block_id)))?;
+    pending.overlay(std::slice::from_mut(&mut block));
+    info!("Applied draft of block {}", block_id);
+    Ok(block)
+}
+
+/// Throw away a block's draft, keeping the content the block has
+#[tauri::command]
+async fn discard_draft(
+    block_id: String,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    let deleted = store.delete_draft(&block_id).await.map_err(|e| {
+        error!("Failed to discard draft of block {}: {}", block_id, e);
+        AppError::from(e)
+    })?;
+    if !deleted {
+        return Err(AppError::not_found(format!("No draft of block {}", block_id)));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn append_to_block(
+    block_id: String,
+    text: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    // Append to what the user typed, not to what the database still holds
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().append_to_block(&block_id, &text).await.map_err(|e| {
+        error!("Failed to append to block {}: {}", block_id, e);
+        AppError::from(e)
+    })
+}
+
+/// How many blocks link to a page, for "linked references" counts
+#[tauri::command]
+async fn get_reference_count(
+    page_title: String,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<i64, AppError> {
+    db.inner().reference_count(&page_title).await.map_err(|e| {
+        error!("Failed to count references to {}: {}", page_title, e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn get_page_by_title(
+    title: String,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Option<Block>, AppError> {
+    let mut page = store.get_page_by_title(&title).await
+        .map_err(|e| {
+            error!("Failed to get page by title {}: {}", title, e);
+            AppError::from(e)
+        })?;
+    pending.overlay(page.as_mut_slice());
+    Ok(page)
+}
+
+#[tauri::command]
+async fn get_block_children(
+    parent_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    let mut children = store.get_block_children(&parent_id).await.map_err(|e| {
+        error!("Failed to get block children for {}: {}", parent_id, e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut children);
+    Ok(children)
+}
+
+// Most reads one execute_batch call may ask for
+const MAX_BATCH_OPS: usize = 100;
+
+fn batch_value<T: serde::Serialize>(value: T) -> std::result::Result<serde_json::Value, AppError> {
+    serde_json::to_value(value).map_err(|e| AppError::internal(format!("Failed to encode batch result: {}", e)))
+}
+
+/// Run one read of a batch the way its own command would
+async fn run_batch_op(op: BatchOp, pending: &PendingWrites, db: &DatomicPeerClient) -> std::result::Result<serde_json::Value, AppError> {
+    match op {
+        BatchOp::GetPageByTitle { title } => {
+            let mut page = db.get_page_by_title(&title).await?;
+            pending.overlay(page.as_mut_slice());
+            batch_value(page)
+        }
+        BatchOp::GetBlock { block_id } => {
+            let mut block = db.get_block(&block_id).await?;
+            pending.overlay(block.as_mut_slice());
+            batch_value(block)
+        }
+        BatchOp::GetBlockChildren { parent_id } => {
+            let mut children = db.get_children_for_parents(std::slice::from_ref(&parent_id)).await?
+                .remove(&parent_id)
+                .unwrap_or_default();
+            pending.overlay(&mut children);
+            batch_value(children)
+        }
+        BatchOp::GetReferenceCount { page_title } => batch_value(db.reference_count(&page_title).await?),
+        BatchOp::GetRecordingsForPage { page_id } => batch_value(db.get_page_recordings(&page_id).await?),
+        BatchOp::GetRecording { recording_id } => batch_value(db.get_recording(&recording_id).await?),
+        BatchOp::GetRecordingTimestamps { recording_id } => batch_value(db.get_recording_timestamps(&recording_id).await?),
+    }
+}
+
+/// Run each op in turn with `run`, keeping going past failures, and return
+/// the outcomes in op order
+async fn execute_ops<F, Fut>(ops: Vec<BatchOp>, mut run: F) -> Vec<BatchResult>
+where
+    F: FnMut(BatchOp) -> Fut,
+    Fut: std::future::Future<Output = std::result::Result<serde_json::Value, AppError>>,
+{
+    let mut results = Vec::with_capacity(ops.len());
+    for op in ops {
+        let result = run(op.clone()).await;
+        if let Err(e) = &result {
+            error!("Batched {:?} failed: {}", op, e);
+        }
+        results.push(BatchResult::from(result));
+    }
+    results
+}
+
+/// Run several reads in one call, saving the IPC round trip of each, and
+/// return their results or errors in the order of `ops`. A failing read
+/// doesn't stop the others. Writes can't be batched.
+#[tauri::command]
+async fn execute_batch(
+    ops: Vec<BatchOp>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<BatchResult>, AppError> {
+    if ops.len() > MAX_BATCH_OPS {
+        return Err(AppError::validation(format!("A batch can hold at most {} reads, not {}", MAX_BATCH_OPS, ops.len()))
+            .with_details(serde_json::json!({ "max_ops": MAX_BATCH_OPS })));
+    }
+    Ok(execute_ops(ops, |op| run_batch_op(op, &pending, db.inner())).await)
+}
+
+// Blocks per message when streaming a page
+const PAGE_BLOCK_BATCH_SIZE: usize = 200;
+
+/// Send a page's blocks over `channel` in batches as they're read, so large
+/// pages can render progressively, then a `finished` event. Returns the page.
+/// Passing a `cancel_token` lets `cancel_pending` stop the read between
+/// batches, in which case it fails without sending `finished`.
+#[tauri::command]
+async fn stream_page_blocks(
+    page_title: String,
+    channel: tauri::ipc::Channel<PageBlocksEvent>,
+    cancel_token: Option<String>,
+    cancel_tokens: tauri::State<'_, CancelTokens>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    let token = cancel_token.as_deref().map(|token_id| cancel_tokens.register(token_id)).transpose()?;
+    let stream_error = |e: errors::DatomicError| {
+        error!("Failed to stream blocks of page {}: {}", page_title, e);
+        AppError::from(e)
+    };
+    let mut page = db.inner().get_page_by_title(&page_title).await.map_err(stream_error)?
+        .ok_or_else(|| AppError::not_found(format!("Page not found: {}", page_title)))?;
+    pending.overlay(std::slice::from_mut(&mut page));
+
+    let mut batches = db.inner().child_batches(&page.id, PAGE_BLOCK_BATCH_SIZE);
+    if let Some(token) = &token {
+        batches = batches.cancel_on(token.flag.clone());
+    }
+    let mut total = 0;
+    while let Some(mut blocks) = batches.next_batch().await.map_err(stream_error)? {
+        pending.overlay(&mut blocks);
+        total += blocks.len();
+        channel.send(PageBlocksEvent::Blocks { blocks })
+            .map_err(|e| AppError::internal(format!("Failed to send page blocks: {}", e)))?;
+    }
+    channel.send(PageBlocksEvent::Finished { total })
+        .map_err(|e| AppError::internal(format!("Failed to send page blocks: {}", e)))?;
+    Ok(page)
+}
+
+/// Stop the read started with `token_id` before its next batch
+#[tauri::command]
+fn cancel_pending(
+    token_id: String,
+    cancel_tokens: tauri::State<'_, CancelTokens>,
+) -> std::result::Result<(), AppError> {
+    cancel_tokens.cancel(&token_id)
+}
+
+/// Children of several blocks at once, for expanding many outline nodes together
+#[tauri::command]
+async fn get_children_for_parents(
+    parent_ids: Vec<String>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<HashMap<String, Vec<Block>>, AppError> {
+    let mut children = db.inner().get_children_for_parents(&parent_ids).await.map_err(|e| {
+        error!("Failed to get children of {} blocks: {}", parent_ids.len(), e);
+        AppError::from(e)
+    })?;
+    for blocks in children.values_mut() {
+        pending.overlay(blocks);
+    }
+    Ok(children)
+}
+
+/// Several blocks at once in the order asked for, for loading a selection or
+/// a set of search results. IDs that don't match a block are left out.
+#[tauri::command]
+async fn get_blocks_by_ids(
+    ids: Vec<String>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    let mut blocks = db.inner().get_blocks_by_ids(&ids).await.map_err(|e| {
+        error!("Failed to get {} blocks: {}", ids.len(), e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut blocks);
+    Ok(blocks)
+}
+
+/// A block and its siblings in order, for moving between bullets
+#[tauri::command]
+async fn get_siblings(
+    block_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    let mut siblings = db.inner().get_siblings(&block_id).await.map_err(|e| {
+        error!("Failed to get siblings of block {}: {}", block_id, e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut siblings);
+    Ok(siblings)
+}
+
+/// Position of a block among its siblings, for jumping to the nth bullet
+#[tauri::command]
+async fn get_block_index(
+    block_id: String,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Option<usize>, AppError> {
+    db.inner().get_block_index(&block_id).await.map_err(|e| {
+        error!("Failed to get the index of block {}: {}", block_id, e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn search_blocks(
+    query: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<Block>, AppError> {
+    // Matching happens in the database, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().search_blocks(&query).await.map_err(|e| {
+        error!("Failed to search blocks for '{}': {}", query, e);
+        AppError::from(e)
+    })
+}
+
+/// A page of up to `limit` search results, continuing after the
+/// `next_rank` and `next_id` of the previous page when they're given
+#[tauri::command]
+async fn search_blocks_after(
+    query: String,
+    after_rank: Option<i64>,
+    after_id: Option<String>,
+    limit: usize,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<SearchPage, AppError> {
+    if limit == 0 {
+        return Err(AppError::validation("Search page size must be at least 1"));
+    }
+    // Matching happens in the database, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().search_blocks_after(&query, after_rank, after_id.as_deref(), limit).await.map_err(|e| {
+        error!("Failed to search blocks for '{}': {}", query, e);
+        AppError::from(e)
+    })
+}
+
+/// Create a page titled `new_title` from a copy of the template page
+/// `template_title`, with `{{date}}` filled in for the frontend's timezone
+#[tauri::command]
+async fn instantiate_template(
+    template_title: String,
+    new_title: String,
+    tz_offset_minutes: i32,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    // The copy is read from the database, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().instantiate_template(&template_title, &new_title, tz_offset_minutes).await.map_err(|e| {
+        error!("Failed to create page {} from template {}: {}", new_title, template_title, e);
+        AppError::from(e)
+    })
+}
+
+/// Pages whose titles only differ in case or surrounding whitespace
+#[tauri::command]
+async fn find_duplicate_pages(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<(Block, Block)>, AppError> {
+    db.inner().find_duplicate_pages().await.map_err(|e| {
+        error!("Failed to find duplicate pages: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Merge a duplicate page into the one being kept, returning the kept page
+#[tauri::command]
+async fn merge_pages(
+    keep_id: String,
+    remove_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    // Links are rewritten from the stored content, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().merge_pages(&keep_id, &remove_id).await.map_err(|e| {
+        error!("Failed to merge page {} into {}: {}", remove_id, keep_id, e);
+        AppError::from(e)
+    })
+}
+
+/// Delete a block and everything below it
+#[tauri::command]
+async fn delete_block(
+    block_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    // Write queued edits first, so none is left waiting on a deleted block
+    flush_pending(&pending, store.as_ref()).await?;
+    store.delete_block(&block_id).await.map_err(|e| {
+        error!("Failed to delete block {}: {}", block_id, e);
+        AppError::from(e)
+    })?;
+    Ok(())
+}
+
+/// Create a recording for a page and start capturing into it with the
+/// configured device and format
+async fn begin_recording(
+    page_id: &str,
+    split_on_silence: Option<bool>,
+    app_handle: &tauri::AppHandle,
+) -> std::result::Result<String, AppError> {
+    let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+    let config = app_handle.state::<RwLock<AppConfig>>();
+    let active = app_handle.state::<ActiveRecording>();
+    let store = app_handle.state::<Arc<dyn Store>>();
+    let recording_id = uuid::Uuid::new_v4().to_string();
+    
+    let (input_device, segment_minutes, silence_split, capture_system_audio, format, encoding) = {
+        let config = config.read().unwrap();
+        let audio = &config.audio;
+        let encoding = match audio.format {
+            AudioFormat::Wav => Encoding::Wav(Some(audio.sample_rate), audio.bits_per_sample),
+            AudioFormat::Opus => Encoding::Opus(audio.opus_bitrate),
+        };
+        // The caller's flag wins over the configured default
+        let silence_split = split_on_silence.unwrap_or(audio.split_on_silence).then(|| SilenceSplit {
+            threshold_db: audio.silence_split_threshold_db,
+            min_silence_ms: audio.silence_split_seconds as u64 * 1000,
+        });
+        (audio.input_device.clone(), audio.segment_minutes, silence_split, audio.capture_system_audio, audio.format, encoding)
+    };
+    let file_path = format!("./audio/{}.{}", recording_id, format.extension());
+    
+    // Create audio recording entry in database
+    let recording = AudioRecording {
+        id: recording_id.clone(),
+        page_id: page_id.to_string(),
+        file_path: file_path.clone(),
+        duration_seconds: None,
+        recorded_at: chrono::Utc::now(),
+        system_audio: capture_system_audio,
+    };
+    
+    store.create_audio_recording(&recording).await.map_err(|e| {
+        error!("Failed to create audio recording for page {}: {}", page_id, e);
+        AppError::from(e)
+    })?;
+    
+    // Start audio capture on the user's chosen input device
+    if let Err(e) = audio_engine.start_recording(&file_path, input_device.as_deref(), segment_minutes, silence_split, capture_system_audio, encoding) {
+        error!("Failed to start recording {}: {}", recording_id, e);
+        // Nothing was captured, so the entry would only show up as interrupted
+        if let Err(delete_error) = store.delete_audio_recording(&recording_id).await {
+            error!("Failed to delete recording {} that didn't start: {}", recording_id, delete_error);
+        }
+        return Err(AppError::from(e));
+    }
+    *active.0.lock().unwrap() = Some(recording_id.clone());
+    emit_recording_started(app_handle, &recording_id, page_id, &file_path);
+    
+    Ok(recording_id)
+}
+
+/// The global shortcut toggling recording, if one is registered
+#[derive(Default)]
+struct RecordingHotkey(Mutex<Option<Shortcut>>);
+
+/// Set while the shortcut or tray is still starting or stopping a recording
+#[derive(Default)]
+struct RecordingToggle(AtomicBool);
+
+fn parse_hotkey(accelerator: &str) -> std::result::Result<Shortcut, AppError> {
+    accelerator.parse::<Shortcut>().map_err(|e| {
+        AppError::validation(format!("Invalid shortcut {}: {}", accelerator, e))
+            .with_details(serde_json::json!({ "accelerator": accelerator }))
+    })
+}
+
+/// Register `shortcut` to toggle recording. Fails if another app holds it.
+fn register_recording_hotkey(app_handle: &tauri::AppHandle, accelerator: &str, shortcut: Shortcut) -> std::result::Result<(), AppError> {
+    app_handle.global_shortcut()
+        .on_shortcut(shortcut, |app_handle, _, event| {
+            if event.state() == ShortcutState::Pressed {
+                toggle_recording_in_background(app_handle);
+            }
+        })
+        .map_err(|e| {
+            AppError::conflict(format!("Shortcut {} is unavailable: {}", accelerator, e))
+                .with_details(serde_json::json!({ "accelerator": accelerator }))
+        })
+}
+
+/// Toggle recording in the background. Presses while the last one is still
+/// being handled are ignored, so a double press can't start two recordings.
+fn toggle_recording_in_background(app_handle: &tauri::AppHandle) {
+    if app_handle.state::<RecordingToggle>().0.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = toggle_recording(&app_handle).await {
+            error!("Toggling recording failed: {}", e);
+        }
+        app_handle.state::<RecordingToggle>().0.store(false, Ordering::SeqCst);
+    });
+}
+
+fn show_main_window(app_handle: &tauri::AppHandle) {
+    if let Some(window) = app_handle.get_webview_window("main") {
+        if let Err(e) = window.show().and_then(|_| window.unminimize()).and_then(|_| window.set_focus()) {
+            error!("Failed to show main window: {}", e);
+        }
+    }
+}
+
+/// Bring the main window forward and have the frontend show today's daily note
+fn open_today(app_handle: &tauri::AppHandle) {
+    show_main_window(app_handle);
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    if let Err(e) = app_handle.emit("app://open-daily-note", &today) {
+        error!("Failed to emit open daily note: {}", e);
+    }
+}
+
+/// Navigation from the `gita://` link the app was launched with, until the
+/// frontend is ready to take it
+#[derive(Default)]
+struct LaunchNavigation(Mutex<Option<Navigation>>);
+
+async fn resolve_deep_link(url: &str, db: &DatomicPeerClient) -> std::result::Result<Navigation, AppError> {
+    let resolved = match DeepLink::parse(url) {
+        Ok(link) => deep_link::resolve(db, &link).await,
+        Err(e) => Err(e),
+    };
+    resolved.map_err(|e| {
+        error!("Failed to open link {}: {}", url, e);
+        e
+    })
+}
+
+/// Bring the main window forward and have the frontend show what a
+/// `gita://` link opened while running points at
+fn open_deep_link(app_handle: &tauri::AppHandle, url: String) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let db = app_handle.state::<Arc<DatomicPeerClient>>();
+        let Ok(navigation) = resolve_deep_link(&url, db.inner()).await else {
+            return;
+        };
+        show_main_window(&app_handle);
+        if let Err(e) = app_handle.emit("app://navigate", &navigation) {
+            error!("Failed to emit navigation: {}", e);
+        }
+    });
+}
+
+fn handle_tray_action(app_handle: &tauri::AppHandle, action: tray::TrayAction) {
+    match action {
+        tray::TrayAction::OpenToday => open_today(app_handle),
+        tray::TrayAction::ToggleRecording => toggle_recording_in_background(app_handle),
+        tray::TrayAction::Quit => begin_shutdown(app_handle),
+    }
+}
+
+/// Stop the recording in progress, or start one for today's daily note
+async fn toggle_recording(app_handle: &tauri::AppHandle) -> std::result::Result<(), AppError> {
+    let store = app_handle.state::<Arc<dyn Store>>();
+    let active = app_handle.state::<ActiveRecording>().0.lock().unwrap().take();
+    if let Some(recording_id) = active {
+        let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+        return finish_recording(&recording_id, app_handle, &audio_engine, store.as_ref()).await;
+    }
+
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let page = store.ensure_daily_note_page(&today).await.map_err(|e| {
+        error!("Failed to open daily note for {}: {}", today, e);
+        AppError::from(e)
+    })?;
+    let recording_id = begin_recording(&page.id, None, app_handle).await?;
+    info!("Started recording {} from the shortcut", recording_id);
+    Ok(())
+}
+
+// Audio commands
+#[tauri::command]
+async fn start_recording(
+    page_id: String,
+    split_on_silence: Option<bool>,
+    app_handle: tauri::AppHandle,
+) -> std::result::Result<String, AppError> {
+    begin_recording(&page_id, split_on_silence, &app_handle).await
+}
+
+#[tauri::command]
+async fn stop_recording(
+    recording_id: String,
+    app_handle: tauri::AppHandle,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    active: tauri::State<'_, ActiveRecording>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    active.take(&recording_id)?;
+    finish_recording(&recording_id, &app_handle, &audio_engine, store.as_ref()).await
+}
+
+#[tauri::command]
+async fn cancel_recording(
+    recording_id: String,
+    app_handle: tauri::AppHandle,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    active: tauri::State<'_, ActiveRecording>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    active.take(&recording_id)?;
+    // Stop audio capture and delete the partial file
+    audio_engine.cancel_recording().map_err(AppError::from)?;
+    emit_recording_stopped(&app_handle, RecordingStopped {
+        recording_id: recording_id.clone(),
+        duration_seconds: None,
+        cancelled: true,
+        error: None,
+    });
+    
+    store.delete_audio_recording(&recording_id).await.map_err(|e| {
+        error!("Failed to delete cancelled recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Cancelled recording: {}", recording_id);
+    Ok(())
+}
+
+/// Move every recording's files into `new_dir` and update their paths,
+/// returning how many recordings were moved. Recordings whose file is missing
+/// are left alone. `new_dir` is a folder picked with `pick_folder` or one the
+/// `PathPolicy` allows.
+#[tauri::command]
+async fn relocate_recordings(
+    new_dir: String,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    active: tauri::State<'_, ActiveRecording>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<u64, AppError> {
+    let new_dir = allowed_path(&new_dir, &config, &path_tokens)?;
+    if let Some(recording_id) = active.0.lock().unwrap().as_ref() {
+        return Err(AppError::conflict(format!("Recording {} is still being written", recording_id)));
+    }
+    db.inner().relocate_recordings(&new_dir).await.map_err(|e| {
+        error!("Failed to relocate recordings to {}: {}", new_dir.display(), e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn recover_recordings(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<RecordingRecoveryReport, AppError> {
+    recover_interrupted_recordings(db.inner()).await.map_err(|e| {
+        error!("Failed to recover recordings: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Recent recordings that were cut short by the app closing, with the audio
+/// their files actually hold
+async fn find_interrupted_recordings(
+    audio_engine: &AudioEngine,
+    store: &dyn Store,
+) -> std::result::Result<Vec<InterruptedRecording>, AppError> {
+    let recordings = store.get_unfinished_recordings().await.map_err(|e| {
+        error!("Failed to get unfinished recordings: {}", e);
+        AppError::from(e)
+    })?;
+    
+    let now = chrono::Utc::now();
+    Ok(recordings.into_iter()
+        .filter(|recording| !audio_engine.is_recording_to(&recording.file_path))
+        .filter_map(|recording| {
+            let unfinished = AudioEngine::unfinished_path(&recording.file_path);
+            let modified_at = file_modified_at(&unfinished)
+                .filter(|&modified_at| InterruptedRecording::is_recent(modified_at, now))?;
+            let check = AudioEngine::inspect_wav_file(&unfinished).ok()?;
+            Some(InterruptedRecording { duration_seconds: (check.duration_ms / 1000) as i32, modified_at, recording })
+        })
+        .collect())
+}
+
+/// Load a recording that was interrupted and isn't being recorded right now
+async fn interrupted_recording(
+    recording_id: &str,
+    audio_engine: &AudioEngine,
+    store: &dyn Store,
+) -> std::result::Result<AudioRecording, AppError> {
+    let recording = store.get_recording(recording_id).await
+        .map_err(|e| {
+            error!("Failed to load recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+    
+    if recording.duration_seconds.is_some() {
+        return Err(AppError::conflict(format!("Recording {} already finished", recording_id)));
+    }
+    if audio_engine.is_recording_to(&recording.file_path) {
+        return Err(AppError::conflict(format!("Recording {} is still being written", recording_id)));
+    }
+    Ok(recording)
+}
+
+#[tauri::command]
+async fn get_interrupted_recordings(
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Vec<InterruptedRecording>, AppError> {
+    find_interrupted_recordings(&audio_engine, store.as_ref()).await
+}
+
+/// Close off an interrupted recording with the audio already in its file and
+/// return its duration in seconds
+#[tauri::command]
+async fn finalize_interrupted_recording(
+    recording_id: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<i32, AppError> {
+    let recording = interrupted_recording(&recording_id, &audio_engine, store.as_ref()).await?;
+    
+    let file_path = recording.file_path.clone();
+    let duration = tauri::async_runtime::spawn_blocking(move || AudioEngine::recover_partial_file(&file_path))
+        .await
+        .map_err(|e| AppError::internal(format!("Finalize task failed: {}", e)))?
+        .map_err(|e| {
+            error!("Failed to finalize recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?;
+    
+    store.update_recording_duration(&recording_id, duration).await.map_err(|e| {
+        error!("Failed to update duration of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Finalized interrupted recording {} ({}s)", recording_id, duration);
+    Ok(duration)
+}
+
+/// Keep recording onto the end of an interrupted recording's file. The time
+/// the app was closed is left out, so block timestamps continue from the
+/// audio already recorded. Stop it with `stop_recording` as usual; cancelling
+/// discards the whole recording.
+#[tauri::command]
+async fn resume_interrupted_recording(
+    recording_id: String,
+    app_handle: tauri::AppHandle,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    active: tauri::State<'_, ActiveRecording>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    let recording = interrupted_recording(&recording_id, &audio_engine, store.as_ref()).await?;
+    let input_device = config.read().unwrap().audio.input_device.clone();
+    
+    audio_engine.resume_recording(&recording.file_path, input_device.as_deref(), recording.system_audio).map_err(|e| {
+        error!("Failed to resume recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    *active.0.lock().unwrap() = Some(recording_id.clone());
+    emit_recording_started(&app_handle, &recording_id, &recording.page_id, &recording.file_path);
+    
+    info!("Resumed interrupted recording: {}", recording_id);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_current_recording_time(
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+) -> std::result::Result<u64, AppError> {
+    audio_engine.get_current_recording_time().map_err(AppError::from)
+}
+
+/// Load a recording that isn't being written along with the files it was
+/// written to, in order
+async fn recording_files(
+    recording_id: &str,
+    audio_engine: &AudioEngine,
+    store: &dyn Store,
+) -> std::result::Result<(AudioRecording, Vec<String>), AppError> {
+    let recording = store.get_recording(recording_id).await
+        .map_err(|e| {
+            error!("Failed to load recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+    
+    if audio_engine.is_recording_to(&recording.file_path) {
+        return Err(AppError::conflict(format!("Recording {} is still being written", recording_id)));
+    }
+    
+    let segments = store.get_recording_segments(recording_id).await.map_err(|e| {
+        error!("Failed to get segments of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    let file_paths = if segments.is_empty() {
+        vec![recording.file_path.clone()]
+    } else {
+        segments.into_iter().map(|segment| segment.file_path).collect()
+    };
+    
+    Ok((recording, file_paths))
+}
+
+/// Load a recording for post-processing along with the files it was written to.
+/// Refuses recordings that are still being written or were interrupted.
+async fn finished_recording_files(
+    recording_id: &str,
+    audio_engine: &AudioEngine,
+    store: &dyn Store,
+) -> std::result::Result<(AudioRecording, Vec<String>), AppError> {
+    let (recording, file_paths) = recording_files(recording_id, audio_engine, store).await?;
+    
+    // Recordings without a duration are still being written or were interrupted
+    if recording.duration_seconds.is_none() {
+        return Err(AppError::conflict(format!("Recording {} is still being written", recording_id)));
+    }
+    
+    Ok((recording, file_paths))
+}
+
+/// Work out a recording's duration from the headers of its files and store it
+async fn store_duration_from_files(
+    recording_id: &str,
+    file_paths: Vec<String>,
+    store: &dyn Store,
+) -> std::result::Result<i32, AppError> {
+    let duration = tauri::async_runtime::spawn_blocking(move || {
+        file_paths.iter().map(|path| AudioEngine::compute_wav_duration(path)).sum::<std::result::Result<f64, _>>()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Duration task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to read the duration of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    let duration_seconds = duration as i32;
+    store.update_recording_duration(recording_id, duration_seconds).await.map_err(|e| {
+        error!("Failed to update duration of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    Ok(duration_seconds)
+}
+
+/// Re-derive a recording's duration from its files, for imported recordings
+/// and ones recovered without a duration. Returns the stored duration in seconds.
+#[tauri::command]
+async fn refresh_recording_duration(
+    recording_id: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<i32, AppError> {
+    let (_, file_paths) = recording_files(&recording_id, &audio_engine, store.as_ref()).await?;
+    let duration_seconds = store_duration_from_files(&recording_id, file_paths, store.as_ref()).await?;
+    info!("Refreshed duration of recording {}: {}s", recording_id, duration_seconds);
+    Ok(duration_seconds)
+}
+
+/// A file the webview can play in place of a recording file. Ogg/Opus files
+/// are decoded to WAV next to the original, and recordings played louder or
+/// quieter get a gain-adjusted copy; either is reused until the recording
+/// changes.
+fn playback_file(file_path: &str, gain_db: f32) -> std::result::Result<String, AudioEngineError> {
+    let (stem, source) = match file_path.strip_suffix(".ogg") {
+        Some(stem) => {
+            let decoded = format!("{}.playback.wav", stem);
+            let recorded_at = file_modified_at(file_path);
+            if recorded_at.is_none() || file_modified_at(&decoded) < recorded_at {
+                AudioEngine::decode_opus_to_wav(file_path, &decoded)?;
+            }
+            (stem, decoded)
+        }
+        None => (file_path.strip_suffix(".wav").unwrap_or(file_path), file_path.to_string()),
+    };
+    if gain_db == 0.0 {
+        return Ok(source);
+    }
+
+    let adjusted = format!("{}.playback{:+.1}dB.wav", stem, gain_db);
+    let source_modified_at = file_modified_at(&source);
+    if source_modified_at.is_none() || file_modified_at(&adjusted) < source_modified_at {
+        AudioEngine::write_playback_gain(&source, &adjusted, gain_db)?;
+    }
+    Ok(adjusted)
+}
+
+/// IDs of recordings being played, from `get_playback_files` until `stop_playback`
+#[derive(Default)]
+struct PlayingRecordings(Mutex<HashSet<String>>);
+
+/// Files to play for a finished recording, in order, `gain_db` louder or
+/// quieter than recorded (0 dB if not given). The recording itself is never
+/// changed, and counts as playing until `stop_playback` is called.
+#[tauri::command]
+async fn get_playback_files(
+    recording_id: String,
+    gain_db: Option<f32>,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+    playing: tauri::State<'_, PlayingRecordings>,
+) -> std::result::Result<Vec<String>, AppError> {
+    let gain_db = gain_db.unwrap_or(0.0);
+    if !gain_db.is_finite() || gain_db.abs() > MAX_PLAYBACK_GAIN_DB {
+        return Err(AppError::validation(format!(
+            "Playback gain must be within ±{} dB, not {}",
+            MAX_PLAYBACK_GAIN_DB, gain_db
+        )));
+    }
+    let (_, file_paths) = finished_recording_files(&recording_id, &audio_engine, db.inner().as_ref()).await?;
+    
+    let files = tauri::async_runtime::spawn_blocking(move || {
+        file_paths.iter().map(|path| playback_file(path, gain_db)).collect::<std::result::Result<Vec<_>, _>>()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Playback preparation task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to prepare recording {} for playback: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    playing.0.lock().unwrap().insert(recording_id);
+    Ok(files)
+}
+
+/// Mark a recording as no longer playing, so it can be processed again
+#[tauri::command]
+fn stop_playback(recording_id: String, playing: tauri::State<'_, PlayingRecordings>) {
+    playing.0.lock().unwrap().remove(&recording_id);
+}
+
+/// Hand files dropped onto the window to the webview as path tokens, so
+/// `attach_dropped_audio` only reads files the user actually dropped
+fn emit_files_dropped(app_handle: &tauri::AppHandle, paths: Vec<std::path::PathBuf>) {
+    let path_tokens = app_handle.state::<PathTokens>();
+    let files: Vec<DroppedFile> = paths
+        .into_iter()
+        .map(|path| {
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            DroppedFile { token: path_tokens.issue(path), name }
+        })
+        .collect();
+    if let Err(e) = app_handle.emit("app://files-dropped", &files) {
+        error!("Failed to emit dropped files: {}", e);
+    }
+}
+
+/// Attach WAV files dropped onto the window to a page as finished
+/// recordings, copying them into the recordings directory. `paths` are the
+/// tokens from `app://files-dropped`, or paths the `PathPolicy` allows.
+/// Files that can't be attached are reported without stopping the rest.
+#[tauri::command]
+async fn attach_dropped_audio(
+    page_id: String,
+    paths: Vec<String>,
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<DroppedAudioReport, AppError> {
+    if paths.is_empty() {
+        return Err(AppError::validation("No files to attach"));
+    }
+    let page = db.inner().get_block(&page_id).await.map_err(|e| {
+        error!("Failed to get page {}: {}", page_id, e);
+        AppError::from(e)
+    })?;
+    if !page.is_some_and(|page| page.is_page) {
+        return Err(AppError::not_found(format!("Page not found: {}", page_id)));
+    }
+    let recordings_dir = config.read().unwrap().audio.recordings_dir.clone();
+
+    let mut report = DroppedAudioReport::default();
+    for path in paths {
+        let file = match allowed_path(&path, &config, &path_tokens) {
+            Ok(file) => file,
+            Err(error) => {
+                report.errors.push(DroppedFileError { path, error });
+                continue;
+            }
+        };
+        let progress_handle = app_handle.clone();
+        let progress_path = path.clone();
+        let on_progress = move |bytes_copied, total_bytes| {
+            let payload = ImportProgress { path: progress_path.clone(), bytes_copied, total_bytes };
+            if let Err(e) = progress_handle.emit("audio://import-progress", &payload) {
+                error!("Failed to emit import progress: {}", e);
+            }
+        };
+        match audio_import::attach_wav(db.inner(), &page_id, &file, &recordings_dir, on_progress).await {
+            Ok(recording) => report.recordings.push(recording),
+            Err(error) => {
+                warn!("Couldn't attach {} to page {}: {}", path, page_id, error);
+                report.errors.push(DroppedFileError { path, error });
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Normalize a finished recording to `target_lufs`, rewriting its files in
+/// place. Refused while the recording is playing; call `stop_playback` first.
+#[tauri::command]
+async fn normalize_recording(
+    recording_id: String,
+    target_lufs: f32,
+    app_handle: tauri::AppHandle,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+    playing: tauri::State<'_, PlayingRecordings>,
+) -> std::result::Result<NormalizationReport, AppError> {
+    if playing.0.lock().unwrap().contains(&recording_id) {
+        return Err(AppError::conflict(format!("Recording {} is playing", recording_id)));
+    }
+    let (_, file_paths) = finished_recording_files(&recording_id, &audio_engine, db.inner().as_ref()).await?;
+    
+    let progress_id = recording_id.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        AudioEngine::normalize_wav_files(&file_paths, target_lufs, |progress| {
+            let payload = ProcessingProgress { recording_id: progress_id.clone(), progress };
+            if let Err(e) = app_handle.emit("audio://normalize-progress", &payload) {
+                error!("Failed to emit normalization progress: {}", e);
+            }
+        })
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Normalization task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to normalize recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    // The gain is capped to avoid clipping, so the target isn't always reached
+    db.inner().set_recording_loudness(&recording_id, report.measured_lufs + report.gain_db).await.map_err(|e| {
+        error!("Failed to store loudness of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Normalized recording {} by {:.1} dB", recording_id, report.gain_db);
+    Ok(report)
+}
+
+/// Find a recording's silent stretches and store them as its silence map
+#[tauri::command]
+async fn analyze_silence(
+    recording_id: String,
+    threshold_db: f32,
+    min_duration_ms: i64,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<SilenceInterval>, AppError> {
+    let (_, file_paths) = finished_recording_files(&recording_id, &audio_engine, db.inner().as_ref()).await?;
+    
+    let intervals = tauri::async_runtime::spawn_blocking(move || {
+        AudioEngine::detect_silence(&file_paths, threshold_db, min_duration_ms)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Silence analysis task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to analyze silence of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    db.inner().store_silence_intervals(&recording_id, &intervals).await.map_err(|e| {
+        error!("Failed to store silence map of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    Ok(intervals)
+}
+
+/// Cut the leading and trailing silence found by `analyze_silence` from a
+/// recording and move its block timestamps to match. The trimmed file only
+/// replaces the original once the timestamps have been updated.
+#[tauri::command]
+async fn trim_silence(
+    recording_id: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<SilenceTrim, AppError> {
+    let (recording, file_paths) = finished_recording_files(&recording_id, &audio_engine, db.inner().as_ref()).await?;
+    if file_paths.len() > 1 {
+        return Err(AppError::conflict(format!("Recording {} is split into segments and can't be trimmed", recording_id)));
+    }
+    
+    let intervals = db.inner().get_silence_intervals(&recording_id).await.map_err(|e| {
+        error!("Failed to get silence map of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    if intervals.is_empty() {
+        return Err(AppError::validation(format!("Recording {} has no silence map, run analyze_silence first", recording_id)));
+    }
+    
+    let duration_ms = AudioEngine::wav_duration_ms(&recording.file_path).map_err(AppError::from)?;
+    let trim = SilenceTrim::from_silence(&intervals, duration_ms);
+    if trim.is_empty() {
+        return Ok(trim);
+    }
+    
+    let source = recording.file_path.clone();
+    let temp_path = format!("{}.trimming", recording.file_path);
+    let destination = temp_path.clone();
+    let (start_ms, end_ms) = (trim.leading_ms, trim.leading_ms + trim.duration_ms);
+    tauri::async_runtime::spawn_blocking(move || {
+        AudioEngine::write_trimmed_wav(&source, &destination, start_ms, end_ms)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Trim task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to trim recording {}: {}", recording_id, e);
+        let _ = std::fs::remove_file(&temp_path);
+        AppError::from(e)
+    })?;
+    
+    if let Err(e) = db.inner().apply_silence_trim(&recording_id, &trim).await {
+        error!("Failed to shift timestamps of recording {}: {}", recording_id, e);
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(AppError::from(e));
+    }
+    
+    std::fs::rename(&temp_path, &recording.file_path).map_err(|e| {
+        error!("Failed to replace recording {} with its trimmed file: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Trimmed {}ms of leading and {}ms of trailing silence from {}", trim.leading_ms, trim.trailing_ms, recording_id);
+    Ok(trim)
+}
+
+// Recordings checked at once by verify_all_recordings, each on a blocking thread
+const VERIFY_CONCURRENCY: usize = 4;
+
+/// Check a finished recording's files, and with `repair` rewrite stale
+/// headers from the file sizes and store the duration of the audio on disk
+async fn verify_recording_files(
+    recording_id: &str,
+    repair: bool,
+    audio_engine: &AudioEngine,
+    store: &dyn Store,
+) -> std::result::Result<RecordingVerification, AppError> {
+    let (recording, file_paths) = finished_recording_files(recording_id, audio_engine, store).await?;
+    
+    let id = recording_id.to_string();
+    let stored_duration = recording.duration_seconds;
+    let verified_paths = file_paths.clone();
+    let mut verification = tauri::async_runtime::spawn_blocking(move || {
+        let verification = AudioEngine::verify_wav_files(&id, stored_duration, &verified_paths);
+        if !repair || !verification.headers_stale() {
+            return Ok(verification);
+        }
+        for file in verification.files.iter().filter(|file| !file.header_matches()) {
+            AudioEngine::repair_wav_file(&file.file_path)?;
+        }
+        let mut repaired = AudioEngine::verify_wav_files(&id, stored_duration, &verified_paths);
+        repaired.repaired = true;
+        Ok::<_, AudioEngineError>(repaired)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Verification task failed: {}", e)))?
+    .map_err(|e| {
+        error!("Failed to repair recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    if repair && verification.duration_stale() {
+        // Headers have been repaired by now, so they hold the true length
+        let duration_seconds = store_duration_from_files(recording_id, file_paths, store).await?;
+        verification = RecordingVerification {
+            repaired: true,
+            ..RecordingVerification::new(recording_id, Some(duration_seconds), verification.files, verification.unreadable)
+        };
+    }
+    
+    if !verification.issues.is_empty() {
+        info!("Recording {} has {} issues", recording_id, verification.issues.len());
+    }
+    Ok(verification)
+}
+
+/// Check a recording's files against its stored metadata, optionally
+/// repairing whichever of the two is stale
+#[tauri::command]
+async fn verify_recording(
+    recording_id: String,
+    repair: bool,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<RecordingVerification, AppError> {
+    verify_recording_files(&recording_id, repair, &audio_engine, db.inner().as_ref()).await
+}
+
+/// Verify every finished recording a few at a time, emitting progress as each
+/// one completes. Recordings that can't be checked are logged and left out.
+#[tauri::command]
+async fn verify_all_recordings(
+    repair: bool,
+    app_handle: tauri::AppHandle,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<RecordingVerification>, AppError> {
+    let recordings = db.inner().get_finished_recordings().await.map_err(|e| {
+        error!("Failed to list recordings: {}", e);
+        AppError::from(e)
+    })?;
+    let total = recordings.len();
+    let permits = Arc::new(tokio::sync::Semaphore::new(VERIFY_CONCURRENCY));
+    
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    for recording in recordings {
+        let app_handle = app_handle.clone();
+        let permits = permits.clone();
+        let sender = sender.clone();
+        tauri::async_runtime::spawn(async move {
+            let Ok(_permit) = permits.acquire_owned().await else {
+                return;
+            };
+            let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+            let db = app_handle.state::<Arc<DatomicPeerClient>>();
+            let result = verify_recording_files(&recording.id, repair, &audio_engine, db.inner().as_ref()).await;
+            let _ = sender.send((recording.id, result));
+        });
+    }
+    drop(sender);
+    
+    let mut verifications = Vec::with_capacity(total);
+    let mut done = 0;
+    while let Some((recording_id, result)) = receiver.recv().await {
+        done += 1;
+        match result {
+            Ok(verification) => verifications.push(verification),
+            Err(e) => error!("Could not verify recording {}: {}", recording_id, e),
+        }
+        let payload = ProcessingProgress { recording_id, progress: done as f32 / total as f32 };
+        if let Err(e) = app_handle.emit("audio://verify-progress", &payload) {
+            error!("Failed to emit verification progress: {}", e);
+        }
+    }
+    
+    Ok(verifications)
+}
+
+#[cfg(feature = "transcription")]
+#[tauri::command]
+async fn transcribe_recording(
+    recording_id: String,
+    model_path: String,
+    app_handle: tauri::AppHandle,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+    jobs: tauri::State<'_, TranscriptionJobs>,
+) -> std::result::Result<(), AppError> {
+    let recording = db.inner().get_recording(&recording_id).await
+        .map_err(|e| {
+            error!("Failed to load recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+    
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut jobs = jobs.0.lock().unwrap();
+        if jobs.contains_key(&recording_id) {
+            return Err(AppError::conflict(format!("Recording {} is already being transcribed", recording_id)));
+        }
+        jobs.insert(recording_id.clone(), cancel.clone());
+    } // Mutex guard is dropped here
+    
+    if let Err(e) = db.inner().set_transcription_status(&recording_id, "running").await {
+        error!("Failed to mark recording {} as transcribing: {}", recording_id, e);
+        jobs.0.lock().unwrap().remove(&recording_id);
+        return Err(AppError::from(e));
+    }
+    
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let db = app_handle.state::<Arc<DatomicPeerClient>>();
+        let (status, segments) = match run_transcription(&app_handle, db.inner(), &recording, &model_path, cancel).await {
+            Ok(segments) => {
+                info!("Transcribed recording {} into {} blocks", recording.id, segments);
+                ("completed", segments)
+            }
+            Err(TranscriptionError::Cancelled) => {
+                info!("Transcription of recording {} cancelled", recording.id);
+                ("cancelled", 0)
+            }
+            Err(e) => {
+                error!("Transcription of recording {} failed: {}", recording.id, e);
+                ("failed", 0)
+            }
+        };
+        
+        app_handle.state::<TranscriptionJobs>().0.lock().unwrap().remove(&recording.id);
+        if let Err(e) = db.inner().set_transcription_status(&recording.id, status).await {
+            error!("Failed to store transcription status of {}: {}", recording.id, e);
+        }
+        
+        let result = TranscriptionResult { recording_id: recording.id.clone(), status: status.to_string(), segments };
+        if let Err(e) = app_handle.emit("audio://transcription-finished", &result) {
+            error!("Failed to emit transcription result: {}", e);
+        }
+    });
+    
+    Ok(())
+}
+
+#[cfg(not(feature = "transcription"))]
+#[tauri::command]
+async fn transcribe_recording(
+    recording_id: String,
+    model_path: String,
+) -> std::result::Result<(), AppError> {
+    error!("Cannot transcribe recording {} with model {}: transcription support not built", recording_id, model_path);
+    Err(AppError::internal("Transcription is not available in this build (enable the `transcription` feature)"))
+}
+
+#[cfg(feature = "transcription")]
+#[tauri::command]
+async fn cancel_transcription(
+    recording_id: String,
+    jobs: tauri::State<'_, TranscriptionJobs>,
+) -> std::result::Result<(), AppError> {
+    match jobs.0.lock().unwrap().get(&recording_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::SeqCst);
+            info!("Cancelling transcription of recording {}", recording_id);
+            Ok(())
+        }
+        None => Err(AppError::conflict(format!("Recording {} is not being transcribed", recording_id))),
+    }
+}
+
+#[cfg(not(feature = "transcription"))]
+#[tauri::command]
+async fn cancel_transcription(
+    recording_id: String,
+) -> std::result::Result<(), AppError> {
+    Err(AppError::conflict(format!("Recording {} is not being transcribed", recording_id)))
+}
+
+#[tauri::command]
+async fn get_audio_devices(
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+) -> std::result::Result<Vec<AudioDevice>, AppError> {
+    audio_engine.get_audio_devices().map_err(AppError::from)
+}
+
+#[tauri::command]
+async fn get_input_device_caps(
+    device_name: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+) -> std::result::Result<DeviceCaps, AppError> {
+    audio_engine.device_capabilities(&device_name).map_err(AppError::from)
+}
+
+#[tauri::command]
+async fn get_device_capabilities(
+    device_name: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+) -> std::result::Result<Vec<DeviceConfigRange>, AppError> {
+    audio_engine.supported_input_configs(&device_name).map_err(|e| {
+        error!("Failed to get capabilities of {}: {}", device_name, e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+fn get_config(config: tauri::State<'_, RwLock<AppConfig>>) -> AppConfig {
+    config.read().unwrap().clone()
+}
+
+/// Change audio settings, taking effect from the next recording, and save
+/// them. Nothing changes if any setting is invalid or a new recordings
+/// directory can't be written to. Returns the updated configuration.
+#[tauri::command]
+async fn update_config(
+    patch: ConfigPatch,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<AppConfig, AppError> {
+    if let Some(recordings_dir) = &patch.recordings_dir {
+        page_bundle::check_writable(recordings_dir)?;
+    }
+    
+    let mut config = config.write().unwrap();
+    let patched = config.patched(&patch).map_err(|e| AppError::validation(e.to_string()))?;
+    patched.save().map_err(|e| {
+        error!("Failed to save configuration: {}", e);
+        AppError::from(e)
+    })?;
+    *config = patched;
+    
+    info!("Configuration updated: {:?}", patch);
+    Ok(config.clone())
+}
+
+#[tauri::command]
+async fn set_active_input_device(
+    name: String,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    if !audio_engine.has_input_device(&name).map_err(AppError::from)? {
+        return Err(AppError::from(AudioEngineError::DeviceNotFound(name)));
+    }
+    
+    let mut config = config.write().unwrap();
+    config.audio.input_device = Some(name.clone());
+    config.save().map_err(|e| {
+        error!("Failed to save active input device {}: {}", name, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Active input device set to {}", name);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_active_input_device(
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<Option<String>, AppError> {
+    let stored = config.read().unwrap().audio.input_device.clone();
+    match stored {
+        Some(name) => Ok(Some(name)),
+        None => Ok(audio_engine.default_input_device_name()),
+    }
+}
+
+/// Turn mixing system audio into new recordings on or off. Fails on
+/// platforms without loopback capture.
+#[tauri::command]
+async fn set_capture_system_audio(
+    enabled: bool,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    if enabled && !audio_engine.system_audio_available() {
+        return Err(AppError::from(AudioEngineError::LoopbackUnavailable("this platform has no loopback devices".to_string())));
+    }
+    
+    let mut config = config.write().unwrap();
+    config.audio.capture_system_audio = enabled;
+    config.save().map_err(|e| {
+        error!("Failed to save system audio capture setting: {}", e);
+        AppError::from(e)
+    })?;
+    
+    info!("System audio capture {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+/// Change the global shortcut that toggles recording, or turn it off with an
+/// empty accelerator. The old shortcut keeps working if the new one is
+/// invalid or already taken by another app.
+#[tauri::command]
+async fn set_recording_hotkey(
+    accelerator: String,
+    app_handle: tauri::AppHandle,
+    hotkey: tauri::State<'_, RecordingHotkey>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    let accelerator = accelerator.trim();
+    let shortcut = if accelerator.is_empty() { None } else { Some(parse_hotkey(accelerator)?) };
+    
+    let mut current = hotkey.0.lock().unwrap();
+    if *current != shortcut {
+        if let Some(shortcut) = shortcut {
+            register_recording_hotkey(&app_handle, accelerator, shortcut)?;
+        }
+        if let Some(old) = current.take() {
+            if let Err(e) = app_handle.global_shortcut().unregister(old) {
+                error!("Failed to unregister the old recording shortcut: {}", e);
+            }
+        }
+        *current = shortcut;
+    }
+    
+    let mut config = config.write().unwrap();
+    config.audio.recording_hotkey = accelerator.to_string();
+    config.save().map_err(|e| {
+        error!("Failed to save recording shortcut {}: {}", accelerator, e);
+        AppError::from(e)
+    })?;
+    
+    info!("Recording shortcut set to {}", if accelerator.is_empty() { "nothing" } else { accelerator });
+    Ok(())
+}
+
+/// Turn automatic gain control on or off, taking effect immediately if recording
+#[tauri::command]
+async fn set_agc(
+    enabled: bool,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    audio_engine.set_agc(enabled);
+    
+    let mut config = config.write().unwrap();
+    config.audio.agc_enabled = enabled;
+    config.save().map_err(|e| {
+        error!("Failed to save AGC setting: {}", e);
+        AppError::from(e)
+    })?;
+    
+    info!("Automatic gain control {}", if enabled { "enabled" } else { "disabled" });
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_monitoring(
+    enabled: bool,
+    output_device: Option<String>,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    // Remember an explicitly chosen output device for next time
+    let output_device = {
+        let mut config = config.write().unwrap();
+        if output_device.is_some() && output_device != config.audio.monitor_output_device {
+            config.audio.monitor_output_device = output_device;
+            config.save().map_err(|e| {
+                error!("Failed to save monitoring output device: {}", e);
+                AppError::from(e)
+            })?;
+        }
+        config.audio.monitor_output_device.clone()
+    };
+    
+    audio_engine.set_monitoring(enabled, output_device).map_err(|e| {
+        error!("Failed to set monitoring: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Flag the current moment of a running recording
+#[tauri::command]
+async fn add_recording_marker(
+    recording_id: String,
+    label: Option<String>,
+    audio_engine: tauri::State<'_, Arc<AudioEngine>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<RecordingMarker, AppError> {
+    let recording = db.inner().get_recording(&recording_id).await
+        .map_err(|e| {
+            error!("Failed to load recording {}: {}", recording_id, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+    
+    if !audio_engine.is_recording_to(&recording.file_path) {
+        return Err(AppError::from(AudioEngineError::NotRecording));
+    }
+    let timestamp_ms = audio_engine.get_current_recording_time().map_err(AppError::from)?;
+    
+    let marker = RecordingMarker {
+        id: uuid::Uuid::new_v4().to_string(),
+        recording_id,
+        timestamp_ms: timestamp_ms as i64,
+        label,
+        created_at: chrono::Utc::now(),
+    };
+    db.inner().create_recording_marker(&marker).await.map_err(|e| {
+        error!("Failed to add marker to recording {}: {}", marker.recording_id, e);
+        AppError::from(e)
+    })?;
+    
+    Ok(marker)
+}
+
+#[tauri::command]
+async fn get_recording_markers(
+    recording_id: String,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<RecordingMarker>, AppError> {
+    db.inner().get_recording_markers(&recording_id).await.map_err(|e| {
+        error!("Failed to get markers of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })
+}
+
 #[tauri::command]
-async fn get_daily_note(
-    date: String,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<Vec<Block>, String> {
-    db.inner().get_daily_note(&date).await.map_err(|e| {
-        error!("Failed to get daily note for {}: {}", date, e);
-        e.to_string()
+async fn convert_marker_to_block(
+    marker_id: String,
+    parent_id: String,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Block, AppError> {
+    db.inner().convert_marker_to_block(&marker_id, &parent_id).await.map_err(|e| {
+        error!("Failed to convert marker {} into a block: {}", marker_id, e);
+        AppError::from(e)
     })
 }
 
+/// Export a page's blocks as base64-encoded bincode for bulk transfer
+#[cfg(feature = "binary-export")]
 #[tauri::command]
-async fn create_block(
-    block_data: CreateBlockRequest,
-    audio_meta: Option<AudioMeta>,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<Block, String> {
-    db.inner().create_block(block_data, audio_meta).await.map_err(|e| {
-        error!("Failed to create block: {}", e);
-        e.to_string()
+async fn export_blocks_binary(
+    page_id: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    let mut blocks = db.inner().get_page_blocks(&page_id).await.map_err(|e| {
+        error!("Failed to get blocks of page {} for export: {}", page_id, e);
+        AppError::from(e)
+    })?;
+    pending.overlay(&mut blocks);
+    
+    binary_export::export_blocks_base64(&blocks).map_err(|e| {
+        error!("Failed to export blocks of page {}: {}", page_id, e);
+        AppError::from(e)
     })
 }
 
+#[cfg(not(feature = "binary-export"))]
 #[tauri::command]
-async fn update_block_content(
+async fn export_blocks_binary(
+    page_id: String,
+) -> std::result::Result<String, AppError> {
+    error!("Cannot export blocks of page {}: binary export support not built", page_id);
+    Err(AppError::internal("Binary export is not available in this build (enable the `binary-export` feature)"))
+}
+
+/// Shareable `gita://` link opening a block's page at the block
+#[tauri::command]
+async fn get_block_url(
     block_id: String,
-    content: String,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<(), String> {
-    let mut updates = std::collections::HashMap::new();
-    updates.insert("content".to_string(), serde_json::Value::String(content));
-    db.inner().update_block(&block_id, updates).await.map_err(|e| {
-        error!("Failed to update block {}: {}", block_id, e);
-        e.to_string()
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    deep_link::block_url(db.inner(), &block_id).await.map_err(|e| {
+        error!("Failed to build link for block {}: {}", block_id, e);
+        e
+    })
+}
+
+/// Take where the `gita://` link the app was launched with points, once
+#[tauri::command]
+fn take_launch_navigation(launch: tauri::State<'_, LaunchNavigation>) -> Option<Navigation> {
+    launch.0.lock().unwrap().take()
+}
+
+/// Where a `gita://` link points, e.g. for a window opened on a page
+#[tauri::command]
+async fn resolve_link(
+    url: String,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Navigation, AppError> {
+    resolve_deep_link(&url, db.inner()).await
+}
+
+// Size of windows opened on a single page
+const PAGE_WINDOW_SIZE: (f64, f64) = (900.0, 700.0);
+
+/// Label of the window showing a page. Labels only allow a few characters.
+fn page_window_label(page_id: &str) -> String {
+    let id: String = page_id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("page-{}", id)
+}
+
+/// Open a page in a window of its own, routed to `index.html?page={page_id}`,
+/// or bring forward the window already showing it. Every window hears the
+/// `data://` events, so edits in one show up in the others.
+#[tauri::command]
+async fn open_page_window(
+    page_id: String,
+    app_handle: tauri::AppHandle,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<(), AppError> {
+    let page = deep_link::get_page(db.inner(), &page_id).await.map_err(|e| {
+        error!("Failed to open a window for page {}: {}", page_id, e);
+        e
     })?;
+
+    let label = page_window_label(&page_id);
+    if let Some(window) = app_handle.get_webview_window(&label) {
+        return window.show().and_then(|_| window.unminimize()).and_then(|_| window.set_focus()).map_err(|e| {
+            error!("Failed to show the window of page {}: {}", page_id, e);
+            AppError::internal(format!("Failed to show the window of page {}: {}", page_id, e))
+        });
+    }
+
+    let mut route = tauri::Url::parse("http://localhost/index.html").expect("route base is a valid URL");
+    route.query_pairs_mut().append_pair("page", &page_id);
+    let path = format!("index.html?{}", route.query().unwrap_or_default());
+    let (width, height) = PAGE_WINDOW_SIZE;
+    tauri::WebviewWindowBuilder::new(&app_handle, label, tauri::WebviewUrl::App(path.into()))
+        .title(page.page_title.unwrap_or_default())
+        .inner_size(width, height)
+        .build()
+        .map_err(|e| {
+            error!("Failed to open a window for page {}: {}", page_id, e);
+            AppError::internal(format!("Failed to open a window for page {}: {}", page_id, e))
+        })?;
     Ok(())
 }
 
+/// Copy a page's recordings into `out_dir` with a `transcript.json` of their
+/// block timestamps, returning the directory. `out_dir` is a folder picked
+/// with `pick_folder` or one the `PathPolicy` allows.
 #[tauri::command]
-async fn get_page_by_title(
-    title: String,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<Option<Block>, String> {
-    db.inner().get_page_blocks(&title).await
-        .map(|blocks| blocks.first().cloned())
+async fn export_page_bundle(
+    page_title: String,
+    out_dir: String,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    let bundle_dir = allowed_path(&out_dir, &config, &path_tokens)?;
+    // Snippets come from the database, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    page_bundle::export_page_bundle(db.inner(), &page_title, &bundle_dir).await
+        .map(|dir| dir.to_string_lossy().into_owned())
         .map_err(|e| {
-            error!("Failed to get page by title {}: {}", title, e);
-            e.to_string()
+            error!("Failed to export page {} to {}: {}", page_title, out_dir, e);
+            e
         })
 }
 
 #[tauri::command]
-async fn get_block_children(
-    parent_id: String,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<Vec<Block>, String> {
-    db.inner().get_page_blocks(&parent_id).await.map_err(|e| {
-        error!("Failed to get block children for {}: {}", parent_id, e);
-        e.to_string()
+async fn get_block_audio_timestamp(
+    block_id: String,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Option<AudioTimestamp>, AppError> {
+    store.get_block_audio_timestamp(&block_id).await.map_err(|e| {
+        error!("Failed to get audio timestamp for block {}: {}", block_id, e);
+        AppError::from(e)
     })
 }
 
 #[tauri::command]
-async fn search_blocks(
-    query: String,
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<Vec<Block>, String> {
-    db.inner().search_blocks(&query).await.map_err(|e| {
-        error!("Failed to search blocks for '{}': {}", query, e);
-        e.to_string()
+async fn attach_timestamp(
+    block_id: String,
+    recording_id: String,
+    timestamp_ms: Option<i64>,
+    seconds: Option<i32>, // Older callers pass whole seconds instead of `timestamp_ms`
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<AudioTimestamp, AppError> {
+    let timestamp_ms = timestamp_ms
+        .or(seconds.map(|seconds| seconds as i64 * 1000))
+        .ok_or_else(|| AppError::validation("attach_timestamp needs timestamp_ms"))?;
+    store.attach_timestamp(&block_id, &recording_id, timestamp_ms).await.map_err(|e| {
+        error!("Failed to attach block {} to recording {}: {}", block_id, recording_id, e);
+        AppError::from(e)
     })
 }
 
+/// Roughly how many seconds of audio in the configured format still fit on
+/// the disk holding the recordings directory, for a "time left" indicator
 #[tauri::command]
-async fn delete_block(
+async fn get_recording_time_remaining(
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<u64, AppError> {
+    let audio = config.read().unwrap().audio.clone();
+    // The directory is only created with the first recording
+    let dir = audio.recordings_dir.ancestors()
+        .find(|dir| dir.is_dir())
+        .unwrap_or(&audio.recordings_dir);
+    let free_bytes = fs4::available_space(dir).map_err(|e| {
+        error!("Failed to read free space on {}: {}", dir.display(), e);
+        AppError::io(format!("Failed to read free space on {}: {}", dir.display(), e))
+    })?;
+
+    Ok(match audio.format {
+        AudioFormat::Wav => AudioEngine::estimated_remaining_seconds(free_bytes, audio.sample_rate, audio.channels, audio.bits_per_sample),
+        // Opus is written at a constant bitrate whatever the channels
+        AudioFormat::Opus => AudioEngine::estimated_remaining_seconds(free_bytes, audio.opus_bitrate, 1, 1),
+    })
+}
+
+/// A recording by its ID, or `None` if there is no such recording.
+/// `:audio/id` is a unique identity, so this is an index lookup.
+#[tauri::command]
+async fn get_recording(
+    recording_id: String,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Option<AudioRecording>, AppError> {
+    store.get_recording(&recording_id).await.map_err(|e| {
+        error!("Failed to get recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })
+}
+
+/// All block timestamps in a recording, earliest first, for a transcript-like view
+#[tauri::command]
+async fn get_recording_timestamps(
+    recording_id: String,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<Vec<AudioTimestamp>, AppError> {
+    store.get_recording_timestamps(&recording_id).await.map_err(|e| {
+        error!("Failed to get timestamps of recording {}: {}", recording_id, e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn relink_timestamp(
     block_id: String,
-    _db: tauri::State<'_, DatomicPeerClient>, // Prefixed with underscore
-) -> std::result::Result<(), String> {
-    // TODO: Implement delete_block in the peer client
-    error!("Delete block not yet implemented for block_id: {}", block_id);
-    Err("Delete block not yet implemented".to_string())
+    old_recording_id: String,
+    new_recording_id: String,
+    store: tauri::State<'_, Arc<dyn Store>>,
+) -> std::result::Result<(), AppError> {
+    store.relink_timestamp(&block_id, &old_recording_id, &new_recording_id).await.map_err(|e| {
+        error!("Failed to relink timestamp for block {}: {}", block_id, e);
+        AppError::from(e)
+    })
 }
 
-// Audio commands
 #[tauri::command]
-async fn start_recording(
-    page_id: String,
-    audio_engine: tauri::State<'_, Arc<Mutex<AudioEngine>>>,
-    _db: tauri::State<'_, DatomicPeerClient>, // Prefixed with underscore
-) -> std::result::Result<String, String> {
-    let recording_id = uuid::Uuid::new_v4().to_string();
-    let file_path = format!("./audio/{}.wav", recording_id);
-    
-    // Create audio recording entry in database
-    let _recording = AudioRecording { // Underscore to silence unused warning for now
-        id: recording_id.clone(),
-        page_id: page_id.clone(),
-        file_path: file_path.clone(),
-        duration_seconds: None,
-        recorded_at: chrono::Utc::now(),
+async fn run_maintenance(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<(), AppError> {
+    db.inner().maintenance().await.map_err(|e| {
+        error!("Database maintenance failed: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Rebuild page links from block content after they drifted apart
+#[tauri::command]
+async fn reindex_derived(
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<ReindexStats, AppError> {
+    // Links are rebuilt from the stored content, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().reindex_derived().await.map_err(|e| {
+        error!("Failed to rebuild page links: {}", e);
+        AppError::from(e)
+    })
+}
+
+fn copy_to_clipboard(app_handle: &tauri::AppHandle, text: &str) -> std::result::Result<(), AppError> {
+    app_handle.clipboard().write_text(text).map_err(|e| {
+        error!("Failed to copy to the clipboard: {}", e);
+        AppError::internal(format!("Failed to copy to the clipboard: {}", e))
+    })
+}
+
+/// Copy a block and everything below it to the clipboard as a Markdown
+/// outline, returning the Markdown as well
+#[tauri::command]
+async fn copy_block_as_markdown(
+    block_id: String,
+    app_handle: tauri::AppHandle,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    let markdown = archive::block_markdown(db.inner(), &block_id).await.map_err(|e| {
+        error!("Failed to render block {} as Markdown: {}", block_id, e);
+        e
+    })?;
+    copy_to_clipboard(&app_handle, &markdown)?;
+    Ok(markdown)
+}
+
+/// Copy the `((block-id))` token embedding a block to the clipboard,
+/// returning it as well
+#[tauri::command]
+async fn copy_block_reference(
+    block_id: String,
+    app_handle: tauri::AppHandle,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    let block = db.inner().get_block(&block_id).await.map_err(|e| {
+        error!("Failed to get block {}: {}", block_id, e);
+        AppError::from(e)
+    })?;
+    if block.is_none() {
+        return Err(AppError::not_found(format!("Block not found: {}", block_id)));
+    }
+    let token = Block::embed_token(&block_id);
+    copy_to_clipboard(&app_handle, &token)?;
+    Ok(token)
+}
+
+/// Most recently edited blocks across all pages, newest first
+#[tauri::command]
+async fn get_recent_edits(
+    limit: i64,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<RecentEdit>, AppError> {
+    if limit < 1 {
+        return Err(AppError::validation(format!("Limit must be at least 1, not {}", limit)));
+    }
+    // Edits still waiting to be written are the most recent of all
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().get_recent_edits(limit).await.map_err(|e| {
+        error!("Failed to get recent edits: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Words written on a page, across every block below it
+#[tauri::command]
+async fn get_page_word_count(
+    page_title: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<usize, AppError> {
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().page_word_count(&page_title).await.map_err(|e| {
+        error!("Failed to count words on page {}: {}", page_title, e);
+        AppError::from(e)
+    })
+}
+
+/// When anything on a page last changed, counting edits to blocks at any
+/// depth below it; `None` if there's no page with that title
+#[tauri::command]
+async fn get_page_last_activity(
+    page_title: String,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Option<chrono::DateTime<chrono::Utc>>, AppError> {
+    // An edit still waiting to be written is the latest activity
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    db.inner().page_last_activity(&page_title).await.map_err(|e| {
+        error!("Failed to get the last activity on page {}: {}", page_title, e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn get_page_stats(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<Vec<PageStat>, AppError> {
+    db.inner().page_stats().await.map_err(|e| {
+        error!("Failed to compute page stats: {}", e);
+        AppError::from(e)
+    })
+}
+
+#[tauri::command]
+async fn get_schema_diff(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<SchemaDiff, AppError> {
+    db.inner().schema_diff().await.map_err(|e| {
+        error!("Failed to compute schema diff: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Schema version of the connected database, for bug reports
+#[tauri::command]
+async fn get_schema_version(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<i64, AppError> {
+    db.inner().schema_version().await.map_err(|e| {
+        error!("Failed to read schema version: {}", e);
+        AppError::from(e)
+    })
+}
+
+/// Send a job's progress and its end to the frontend as `job://progress`
+/// and `job://finished`
+fn emit_job_event(app_handle: &tauri::AppHandle) -> impl Fn(JobEvent) + Send + Sync + 'static {
+    let app_handle = app_handle.clone();
+    move |event| {
+        let emitted = match &event {
+            JobEvent::Progress(progress) => app_handle.emit("job://progress", progress),
+            JobEvent::Finished(info) => app_handle.emit("job://finished", info),
+        };
+        if let Err(e) = emitted {
+            error!("Failed to emit job event: {}", e);
+        }
+    }
+}
+
+/// A path the webview named for an import, export or backup, if it's the
+/// token of a path picked in a dialog or the `PathPolicy` allows it
+fn allowed_path(path_or_token: &str, config: &RwLock<AppConfig>, path_tokens: &PathTokens) -> std::result::Result<std::path::PathBuf, AppError> {
+    PathPolicy::new(&config.read().unwrap()).resolve(path_tokens, path_or_token).map_err(|e| {
+        warn!("Refused path {}: {}", path_or_token, e);
+        e
+    })
+}
+
+/// Ask where to save a file, returning a token that stands for the chosen
+/// path in `export_json`, or nothing if the dialog was dismissed
+#[tauri::command]
+async fn pick_save_path(
+    title: String,
+    file_name: Option<String>,
+    app_handle: tauri::AppHandle,
+    path_tokens: tauri::State<'_, PathTokens>,
+) -> std::result::Result<Option<String>, AppError> {
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        let dialog = app_handle.dialog().file().set_title(title);
+        match file_name {
+            Some(file_name) => dialog.set_file_name(file_name),
+            None => dialog,
+        }.blocking_save_file()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Save dialog failed: {}", e)))?;
+    picked_path_token(picked, &path_tokens)
+}
+
+/// Ask for a folder, returning a token that stands for it in
+/// `import_markdown`, `create_backup` and `export_page_bundle`, or nothing
+/// if the dialog was dismissed
+#[tauri::command]
+async fn pick_folder(
+    title: String,
+    app_handle: tauri::AppHandle,
+    path_tokens: tauri::State<'_, PathTokens>,
+) -> std::result::Result<Option<String>, AppError> {
+    let picked = tauri::async_runtime::spawn_blocking(move || app_handle.dialog().file().set_title(title).blocking_pick_folder())
+        .await
+        .map_err(|e| AppError::internal(format!("Folder dialog failed: {}", e)))?;
+    picked_path_token(picked, &path_tokens)
+}
+
+fn picked_path_token(picked: Option<tauri_plugin_dialog::FilePath>, path_tokens: &PathTokens) -> std::result::Result<Option<String>, AppError> {
+    let Some(picked) = picked else {
+        return Ok(None);
     };
-    
-    // TODO: Implement create_audio_recording in the peer client
-    // db.inner().create_audio_recording(recording).await.map_err(|e| e.to_string())?;
-    
-    // Start audio capture
-    let engine = audio_engine.lock().unwrap();
-    engine.start_recording(&file_path).map_err(|e| e.to_string())?;
-    
-    Ok(recording_id)
+    let path = picked.into_path().map_err(|e| AppError::validation(format!("Can't use the chosen location: {}", e)))?;
+    Ok(Some(path_tokens.issue(path)))
 }
 
+/// Ask for the folder imports and exports may use by path from now on, and
+/// save it in the configuration. Returns the folder, or nothing if the
+/// dialog was dismissed.
 #[tauri::command]
-async fn stop_recording(
+async fn choose_export_dir(
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<Option<String>, AppError> {
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        app_handle.dialog().file().set_title("Choose a folder for exports").blocking_pick_folder()
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Folder dialog failed: {}", e)))?;
+    let Some(picked) = picked else {
+        return Ok(None);
+    };
+    let export_dir = picked.into_path().map_err(|e| AppError::validation(format!("Can't use the chosen folder: {}", e)))?;
+
+    let mut config = config.write().unwrap();
+    let mut updated = config.clone();
+    updated.export_dir = Some(export_dir.clone());
+    updated.save().map_err(|e| {
+        error!("Failed to save export folder {}: {}", export_dir.display(), e);
+        AppError::from(e)
+    })?;
+    *config = updated;
+    info!("Export folder set to {}", export_dir.display());
+    Ok(Some(export_dir.to_string_lossy().into_owned()))
+}
+
+/// Start exporting the whole database as JSON to `out_path`, returning the
+/// job's id. Its summary comes with the `job://finished` event. `out_path`
+/// is a file picked with `pick_save_path` or one the `PathPolicy` allows.
+#[tauri::command]
+async fn export_json(
+    out_path: String,
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    jobs: tauri::State<'_, Arc<Jobs>>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<String, AppError> {
+    let out_path = allowed_path(&out_path, &config, &path_tokens)?;
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    Ok(jobs.spawn("export_json", emit_job_event(&app_handle), move |job| async move {
+        let db = app_handle.state::<Arc<DatomicPeerClient>>();
+        archive::export_json(db.inner(), &out_path, &job).await.map_err(|e| {
+            error!("Failed to export to {}: {}", out_path.display(), e);
+            e
+        })
+    }))
+}
+
+/// Start creating a page from each Markdown file in `dir`, returning the
+/// job's id. Its report comes with the `job://finished` event. `dir` is a
+/// folder picked with `pick_folder` or one the `PathPolicy` allows.
+#[tauri::command]
+async fn import_markdown(
+    dir: String,
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    jobs: tauri::State<'_, Arc<Jobs>>,
+) -> std::result::Result<String, AppError> {
+    let dir = allowed_path(&dir, &config, &path_tokens)?;
+    Ok(jobs.spawn("import_markdown", emit_job_event(&app_handle), move |job| async move {
+        let db = app_handle.state::<Arc<DatomicPeerClient>>();
+        archive::import_markdown(db.inner(), &dir, &job).await.map_err(|e| {
+            error!("Failed to import Markdown from {}: {}", dir.display(), e);
+            e
+        })
+    }))
+}
+
+/// Ask a running job to stop before its next unit of work
+#[tauri::command]
+fn cancel_job(
+    job_id: String,
+    jobs: tauri::State<'_, Arc<Jobs>>,
+) -> std::result::Result<(), AppError> {
+    jobs.cancel(&job_id)?;
+    info!("Cancelling job {}", job_id);
+    Ok(())
+}
+
+/// Running and recently finished jobs, newest first
+#[tauri::command]
+fn list_jobs(jobs: tauri::State<'_, Arc<Jobs>>) -> Vec<JobInfo> {
+    jobs.list()
+}
+
+#[tauri::command]
+async fn check_integrity(
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<IntegrityReport, AppError> {
+    // Links are checked against the stored content, so it needs the latest edits
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    integrity::check_integrity(db.inner()).await.map_err(|e| {
+        error!("Integrity check failed: {}", e);
+        e
+    })
+}
+
+/// Back up the database and recordings into a new directory under
+/// `out_dir`, or the backups folder in the data directory. `out_dir` is a
+/// folder picked with `pick_folder` or one the `PathPolicy` allows.
+#[tauri::command]
+async fn create_backup(
+    out_dir: Option<String>,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    path_tokens: tauri::State<'_, PathTokens>,
+    pending: tauri::State<'_, PendingWrites>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<BackupReport, AppError> {
+    let backups_dir = out_dir.map(|out_dir| allowed_path(&out_dir, &config, &path_tokens)).transpose()?;
+    let (recordings_dir, backups_dir) = {
+        let config = config.read().unwrap();
+        let backups_dir = backups_dir.unwrap_or_else(|| archive::default_backups_dir(&config));
+        (config.audio.recordings_dir.clone(), backups_dir)
+    };
+    flush_pending(&pending, db.inner().as_ref()).await?;
+    archive::backup(db.inner(), &recordings_dir, &backups_dir).await.map_err(|e| {
+        error!("Backup to {} failed: {}", backups_dir.display(), e);
+        e
+    })
+}
+
+/// Directories the file manager may be opened on
+fn data_roots(config: &AppConfig) -> Vec<std::path::PathBuf> {
+    vec![config.data_dir.clone(), config.audio.recordings_dir.clone()]
+}
+
+/// Show a recording's file in the OS file manager. Split recordings reveal
+/// their first file that's still there.
+#[tauri::command]
+async fn reveal_recording(
     recording_id: String,
-    audio_engine: tauri::State<'_, Arc<Mutex<AudioEngine>>>,
-    _db: tauri::State<'_, DatomicPeerClient>, // Prefixed with underscore
-) -> std::result::Result<(), String> {
-    // Stop audio capture and get duration
-    let _duration = { // Underscore to silence unused warning for now
-        let engine = audio_engine.lock().unwrap();
-        engine.stop_recording().map_err(|e| e.to_string())?
-    }; // Mutex guard is dropped here
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<(), AppError> {
+    let lookup_error = |e: errors::DatomicError| {
+        error!("Failed to load recording {}: {}", recording_id, e);
+        AppError::from(e)
+    };
+    let recording = db.inner().get_recording(&recording_id).await.map_err(lookup_error)?
+        .ok_or_else(|| AppError::not_found(format!("Recording not found: {}", recording_id)))?;
+    let segments = db.inner().get_recording_segments(&recording_id).await.map_err(lookup_error)?;
+    let file = segments.into_iter()
+        .map(|segment| segment.file_path)
+        .find(|file_path| std::path::Path::new(file_path).exists())
+        .unwrap_or(recording.file_path);
     
-    // Update recording duration in database
-    // TODO: Implement update_audio_recording in the peer client
-    info!("Stopped recording: {}", recording_id);
-    Ok(())
+    let roots = data_roots(&config.read().unwrap());
+    reveal::reveal_file(&app_handle, std::path::Path::new(&file), &roots).map_err(|e| {
+        error!("Failed to reveal recording {}: {}", recording_id, e);
+        e
+    })
 }
 
+/// Open the app's data directory in the OS file manager
 #[tauri::command]
-async fn get_audio_devices(
-    audio_engine: tauri::State<'_, Arc<Mutex<AudioEngine>>>,
-) -> std::result::Result<Vec<AudioDevice>, String> {
-    let engine = audio_engine.lock().unwrap();
-    engine.get_audio_devices().map_err(|e| e.to_string())
+fn open_data_dir(
+    app_handle: tauri::AppHandle,
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<(), AppError> {
+    let (data_dir, roots) = {
+        let config = config.read().unwrap();
+        (config.data_dir.clone(), data_roots(&config))
+    };
+    reveal::open_dir(&app_handle, &data_dir, &roots).map_err(|e| {
+        error!("Failed to open data directory: {}", e);
+        e
+    })
+}
+
+/// Total size of the files below `dir`, skipping anything unreadable
+fn directory_size(dir: &std::path::Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries.flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => directory_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
 }
 
+/// Check the Datomic installation and Java the database client starts the
+/// JVM with, without starting it, so settings can say what to fix
 #[tauri::command]
-async fn get_block_audio_timestamp(
-    block_id: String,
-    _db: tauri::State<'_, DatomicPeerClient>, // Prefixed with underscore
-) -> std::result::Result<Option<AudioTimestamp>, String> {
-    // TODO: Implement get_block_audio_timestamp in the peer client
-    info!("get_block_audio_timestamp called for: {}", block_id);
-    Ok(None)
+fn validate_datomic_install(
+    config: tauri::State<'_, RwLock<AppConfig>>,
+) -> std::result::Result<DatomicInstallReport, AppError> {
+    let datomic = config.read().unwrap().datomic.clone();
+    let report = datomic_install::validate(&datomic);
+    if !report.ok {
+        warn!("Datomic installation problems: {}", report.problems.join("; "));
+    }
+    Ok(report)
+}
+
+/// Version, schema version and data locations, for the About dialog and
+/// bug reports. Works without a reachable database.
+#[tauri::command]
+async fn get_app_info(
+    config: tauri::State<'_, RwLock<AppConfig>>,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<AppInfo, AppError> {
+    let (data_dir, recordings_dir) = {
+        let config = config.read().unwrap();
+        (config.data_dir.clone(), config.audio.recordings_dir.clone())
+    };
+
+    let database_available = db.inner().health_check().await.unwrap_or_else(|e| {
+        error!("Health check failed: {}", e);
+        false
+    });
+    let schema_version = if database_available {
+        db.inner().schema_version().await
+            .map_err(|e| error!("Failed to read schema version: {}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    let recordings_size_bytes = tauri::async_runtime::spawn_blocking({
+        let recordings_dir = recordings_dir.clone();
+        move || directory_size(&recordings_dir)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Measuring recordings failed: {}", e)))?;
+
+    Ok(AppInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        database_available,
+        data_dir: data_dir.to_string_lossy().into_owned(),
+        recordings_dir: recordings_dir.to_string_lossy().into_owned(),
+        recordings_size_bytes,
+    })
+}
+
+/// The latest `limit` commands with their timings, newest first, for the
+/// debug panel
+#[tauri::command]
+fn get_recent_command_log(
+    limit: usize,
+    command_log: tauri::State<'_, Arc<CommandLog>>,
+) -> std::result::Result<Vec<CommandLogEntry>, AppError> {
+    if limit == 0 {
+        return Err(AppError::validation(format!("Limit must be at least 1, not {}", limit)));
+    }
+    Ok(command_log.recent(limit))
+}
+
+/// Whether this is a fresh vault, for deciding on a first-run experience.
+/// `tz_offset_minutes` is east of UTC, as for `get_today_daily_note`.
+#[tauri::command]
+async fn get_onboarding_state(
+    tz_offset_minutes: i32,
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<OnboardingState, AppError> {
+    onboarding::onboarding_state(db.inner(), tz_offset_minutes).await.map_err(|e| {
+        error!("Failed to get onboarding state: {}", e);
+        e
+    })
+}
+
+/// Create the welcome page for new users; does nothing if it exists
+#[tauri::command]
+async fn seed_sample_content(
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<SampleContent, AppError> {
+    onboarding::seed_sample_content(db.inner()).await.map_err(|e| {
+        error!("Failed to seed sample content: {}", e);
+        e
+    })
 }
 
 #[tauri::command]
 async fn health_check(
-    db: tauri::State<'_, DatomicPeerClient>,
-) -> std::result::Result<bool, String> {
+    db: tauri::State<'_, Arc<DatomicPeerClient>>,
+) -> std::result::Result<bool, AppError> {
     db.inner().health_check().await.map_err(|e| {
         error!("Health check failed: {}", e);
-        e.to_string()
+        AppError::from(e)
     })
 }
 
 fn main() {
+    // Load environment variables from .env file
+    dotenvy::dotenv().ok();
+    
+    // Commands like `gita backup` run headless for scripts: their JSON goes
+    // to stdout, so logs go to stderr and only warnings are shown
+    let cli_command = cli::parse(std::env::args_os()).unwrap_or_else(|e| {
+        let _ = e.print();
+        // --help and --version come through as errors too
+        std::process::exit(if e.use_stderr() { cli::EXIT_USAGE } else { cli::EXIT_OK })
+    });
+    if let Some(command) = cli_command {
+        tracing_subscriber::fmt()
+            .with_max_level(Level::WARN)
+            .with_writer(std::io::stderr)
+            .init();
+        std::process::exit(cli::run(command));
+    }
+    
+    // Load configuration first so logging can follow it
+    let config = AppConfig::load()
+        .expect("Failed to load application configuration");
+    
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
-        .init();
+    let command_log = Arc::new(CommandLog::default());
+    logging::init(&config, command_log.clone());
     
     info!("Starting Gita application");
     
-    // Load environment variables from .env file
-    dotenvy::dotenv().ok();
-    
     tauri::Builder::default()
+        // Must come first: a second launch hands its arguments to this instance
+        // and exits, and a `gita://` link among them is opened here
+        .plugin(tauri_plugin_single_instance::init(|app_handle, argv, _cwd| {
+            show_main_window(app_handle);
+            if let Some(url) = DeepLink::find_in_args(&argv) {
+                open_deep_link(app_handle, url.to_string());
+            }
+        }))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_shell::init())
-        .setup(|app| {
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .setup(move |app| {
             info!("Setting up Tauri application");
             
-            // Load configuration
-            let config = AppConfig::load()
-                .expect("Failed to load application configuration");
-            
             info!("Loaded configuration: {:?}", config);
             
             // Initialize Datomic peer client
@@ -220,38 +2886,319 @@ fn main() {
                 }
             });
             
+            // Tell every window when blocks or pages change, whatever wrote them,
+            // both as a batch and block by block as db-changed
+            let app_handle = app.handle().clone();
+            datomic_client.on_change(move |change| {
+                if let Err(e) = app_handle.emit(change.kind.event(), &change) {
+                    error!("Failed to emit {}: {}", change.kind.event(), e);
+                }
+                for db_changed in change.per_block() {
+                    if let Err(e) = app_handle.emit("db-changed", &db_changed) {
+                        error!("Failed to emit db-changed for {}: {}", db_changed.id, e);
+                    }
+                }
+            });
+            
             // Initialize audio engine
-            let audio_engine = Arc::new(Mutex::new(
-                AudioEngine::new().expect("Failed to initialize audio engine")
-            ));
+            let audio_engine = Arc::new(AudioEngine::new().expect("Failed to initialize audio engine"));
+            audio_engine.configure_agc(
+                config.audio.agc_enabled,
+                config.audio.agc_attack_ms,
+                config.audio.agc_release_ms,
+            );
+            
+            // Tell the frontend which file a recording continues in after a split
+            let app_handle = app.handle().clone();
+            audio_engine.on_segment_split(move |split| {
+                if let Err(e) = app_handle.emit("audio://segment-split", &split) {
+                    error!("Failed to emit segment split: {}", e);
+                }
+            });
             
             // Create necessary directories
             std::fs::create_dir_all(&config.audio.recordings_dir)
                 .expect("Failed to create recordings directory");
             
+            // Tell the frontend to refresh its device list when devices are plugged in or out
+            if config.audio.device_poll_interval_ms > 0 {
+                let app_handle = app.handle().clone();
+                let interval = std::time::Duration::from_millis(config.audio.device_poll_interval_ms);
+                let watcher_result = audio_engine.start_device_watcher(interval, move |changes| {
+                    handle_devices_changed(&app_handle, changes);
+                });
+                if let Err(e) = watcher_result {
+                    error!("Failed to start device watcher: {}", e);
+                }
+            }
+            
             info!("Application setup completed successfully");
             
+            let recording_hotkey = config.audio.recording_hotkey.trim().to_string();
+            // Commands covered by `Store` go through the trait, the rest use
+            // Datomic features directly
+            let datomic_client = Arc::new(datomic_client);
+            let store: Arc<dyn Store> = match config.backend {
+                StorageBackend::Datomic => datomic_client.clone(),
+                StorageBackend::Sqlite => {
+                    warn!("The SQLite backend isn't implemented yet, so blocks and recordings can't be stored");
+                    Arc::new(SqliteStore)
+                }
+            };
+            app.manage(store);
             app.manage(datomic_client);
             app.manage(audio_engine);
+            app.manage(RwLock::new(config));
+            app.manage(command_log);
+            app.manage(ActiveRecording::default());
+            app.manage(Shutdown::default());
+            app.manage(PendingWrites::default());
+            app.manage(Arc::new(Jobs::default()));
+            app.manage(CancelTokens::default());
+            app.manage(PathTokens::default());
+            app.manage(RecordingHotkey::default());
+            app.manage(RecordingToggle::default());
+            app.manage(PlayingRecordings::default());
+            
+            // Show whether a recording is running and offer quick actions from the tray
+            if let Err(e) = tray::create(app, handle_tray_action) {
+                error!("Failed to create tray icon: {}", e);
+            }
+            
+            // Toggle recording into today's daily note from any app
+            if !recording_hotkey.is_empty() {
+                let registered = parse_hotkey(&recording_hotkey)
+                    .and_then(|shortcut| register_recording_hotkey(app.handle(), &recording_hotkey, shortcut).map(|_| shortcut));
+                match registered {
+                    Ok(shortcut) => *app.state::<RecordingHotkey>().0.lock().unwrap() = Some(shortcut),
+                    Err(e) => error!("Failed to register recording shortcut: {}", e),
+                }
+            }
+            #[cfg(feature = "transcription")]
+            app.manage(TranscriptionJobs::default());
+            
+            // Open `gita://` links, including the one the app was launched with
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                error!("Failed to register the {} URL scheme: {}", deep_link::SCHEME, e);
+            }
+            let launch_url = app.deep_link().get_current().ok().flatten()
+                .and_then(|urls| urls.into_iter().next());
+            let launch_navigation = launch_url.and_then(|url| {
+                let db = app.state::<Arc<DatomicPeerClient>>();
+                tauri::async_runtime::block_on(resolve_deep_link(url.as_str(), db.inner())).ok()
+            });
+            app.manage(LaunchNavigation(Mutex::new(launch_navigation)));
+            let app_handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    open_deep_link(&app_handle, url.to_string());
+                }
+            });
+            
+            // Keep drafts of edits that are still waiting to be written
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut ticks = tokio::time::interval(PendingWrites::DRAFT_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    let pending = app_handle.state::<PendingWrites>();
+                    let db = app_handle.state::<Arc<DatomicPeerClient>>();
+                    let saved = pending.save_drafts(|drafts| async move { db.inner().save_drafts(&drafts).await }).await;
+                    if let Err(e) = saved {
+                        error!("Failed to save drafts of pending edits: {}", e);
+                    }
+                }
+            });
+            
+            // Stream input levels to the level meter while recording
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut ticks = tokio::time::interval(LEVEL_METER_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+                    let level = audio_engine.input_level();
+                    if let Some(level) = level {
+                        if let Err(e) = app_handle.emit("audio://input-level", &level) {
+                            error!("Failed to emit input level: {}", e);
+                        }
+                    }
+                }
+            });
+            
+            // Tick the elapsed time of the recording in progress
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut ticks = tokio::time::interval(RECORDING_PROGRESS_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    let Some(recording_id) = app_handle.state::<ActiveRecording>().0.lock().unwrap().clone() else {
+                        continue;
+                    };
+                    let Ok(elapsed_ms) = app_handle.state::<Arc<AudioEngine>>().get_current_recording_time() else {
+                        continue;
+                    };
+                    let progress = RecordingProgress { recording_id, elapsed_ms };
+                    if let Err(e) = app_handle.emit("audio://recording-progress", &progress) {
+                        error!("Failed to emit recording progress: {}", e);
+                    }
+                    tray::show_elapsed(&app_handle, elapsed_ms);
+                }
+            });
+            
+            // Recover recordings interrupted by a previous crash in the background
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let db = app_handle.state::<Arc<DatomicPeerClient>>();
+                match recover_interrupted_recordings(db.inner()).await {
+                    Ok(report) => {
+                        if report.recovered + report.failed > 0 {
+                            info!("Recording recovery: {} recovered, {} failed", report.recovered, report.failed);
+                        }
+                        if let Err(e) = app_handle.emit("audio://recordings-recovered", &report) {
+                            error!("Failed to emit recovery report: {}", e);
+                        }
+                    }
+                    Err(e) => error!("Recording recovery failed: {}", e),
+                }
+                
+                // Offer to finalize or resume recordings the app closed on recently
+                let audio_engine = app_handle.state::<Arc<AudioEngine>>();
+                match find_interrupted_recordings(&audio_engine, db.inner().as_ref()).await {
+                    Ok(interrupted) if !interrupted.is_empty() => {
+                        if let Err(e) = app_handle.emit("audio://recordings-interrupted", &interrupted) {
+                            error!("Failed to emit interrupted recordings: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to find interrupted recordings: {}", e),
+                }
+            });
             
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(command_log::instrument_commands(tauri::generate_handler![
             get_daily_note,
+            get_today_daily_note,
+            get_block_history,
             create_block,
             update_block_content,
+            flush_pending_writes,
+            get_unsaved_drafts,
+            apply_draft,
+            discard_draft,
+            append_to_block,
             get_page_by_title,
+            get_reference_count,
             get_block_children,
+            get_children_for_parents,
+            get_blocks_by_ids,
+            stream_page_blocks,
+            cancel_pending,
+            execute_batch,
+            get_siblings,
+            get_block_index,
             search_blocks,
+            search_blocks_after,
+            find_duplicate_pages,
+            instantiate_template,
+            merge_pages,
+            export_blocks_binary,
+            export_page_bundle,
+            get_block_url,
+            take_launch_navigation,
+            resolve_link,
+            open_page_window,
             delete_block,
             start_recording,
             stop_recording,
+            cancel_recording,
+            recover_recordings,
+            relocate_recordings,
+            get_interrupted_recordings,
+            finalize_interrupted_recording,
+            resume_interrupted_recording,
+            get_current_recording_time,
+            add_recording_marker,
+            get_recording_markers,
+            convert_marker_to_block,
+            attach_dropped_audio,
+            normalize_recording,
+            get_playback_files,
+            stop_playback,
+            analyze_silence,
+            trim_silence,
+            verify_recording,
+            refresh_recording_duration,
+            verify_all_recordings,
+            transcribe_recording,
+            cancel_transcription,
             get_audio_devices,
+            get_input_device_caps,
+            get_device_capabilities,
+            get_config,
+            update_config,
+            set_active_input_device,
+            get_active_input_device,
+            set_capture_system_audio,
+            set_monitoring,
+            set_agc,
+            set_recording_hotkey,
             get_block_audio_timestamp,
+            attach_timestamp,
+            get_recording,
+            get_recording_time_remaining,
+            get_recording_timestamps,
+            relink_timestamp,
+            run_maintenance,
+            reindex_derived,
+            export_json,
+            pick_save_path,
+            pick_folder,
+            choose_export_dir,
+            import_markdown,
+            cancel_job,
+            list_jobs,
+            check_integrity,
+            create_backup,
+            get_page_stats,
+            get_page_last_activity,
+            get_page_word_count,
+            get_recent_edits,
+            copy_block_as_markdown,
+            copy_block_reference,
+            get_schema_diff,
+            get_schema_version,
+            get_app_info,
+            validate_datomic_install,
+            get_onboarding_state,
+            seed_sample_content,
+            reveal_recording,
+            open_data_dir,
+            get_recent_command_log,
             health_check
-        ])
-        .run(tauri::generate_context!())
-        .expect("Error while running Tauri application");
+        ]))
+        .build(tauri::generate_context!())
+        .expect("Error while building Tauri application")
+        .run(|app_handle, event| match event {
+            // Closing the window or quitting saves the recording first
+            tauri::RunEvent::WindowEvent { event: tauri::WindowEvent::CloseRequested { api, .. }, .. } if !ready_to_exit(app_handle) => {
+                api.prevent_close();
+                begin_shutdown(app_handle);
+            }
+            tauri::RunEvent::WindowEvent { event: tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }), .. } => {
+                emit_files_dropped(app_handle, paths);
+            }
+            tauri::RunEvent::ExitRequested { api, .. } if !ready_to_exit(app_handle) => {
+                api.prevent_exit();
+                begin_shutdown(app_handle);
+            }
+            tauri::RunEvent::Exit => {
+                app_handle.state::<Arc<AudioEngine>>().stop_device_watcher();
+            }
+            _ => {}
+        });
     
     info!("Gita application shut down");
 }
@@ -1,7 +1,9 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use crate::errors::{AppError, DatomicError};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Block {
     pub id: String,
     pub content: Option<String>,
@@ -12,7 +14,310 @@ pub struct Block {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     #[serde(default)]
-    pub audio_timestamp: Option<AudioTimestamp>,
+    pub audio_timestamp: Option<AudioTimestamp>,    /// How the block was created, e.g. "keyboard", "import" or
+    /// "transcription". `None` if that wasn't recorded.
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+impl Block {
+    /// Group blocks under each requested parent in `order`. Every parent gets
+    /// an entry, empty if it has no children.
+    pub fn group_by_parent(blocks: Vec<Block>, parent_ids: &[String]) -> HashMap<String, Vec<Block>> {
+        let mut grouped: HashMap<String, Vec<Block>> = parent_ids.iter()
+            .map(|parent_id| (parent_id.clone(), Vec::new()))
+            .collect();
+        for block in blocks {
+            if let Some(children) = block.parent_id.as_ref().and_then(|parent_id| grouped.get_mut(parent_id)) {
+                children.push(block);
+            }
+        }
+        for children in grouped.values_mut() {
+            children.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.id.cmp(&b.id)));
+        }
+        grouped
+    }
+
+    /// `blocks` in the order of `ids`, for queries that return rows in no
+    /// particular order. IDs without a block are left out, as are repeats.
+    pub fn in_order_of(blocks: Vec<Block>, ids: &[String]) -> Vec<Block> {
+        let mut by_id: HashMap<String, Block> = blocks.into_iter().map(|block| (block.id.clone(), block)).collect();
+        ids.iter().filter_map(|id| by_id.remove(id)).collect()
+    }
+
+    /// The first `limit` blocks in block order that come after the
+    /// `(order, id)` key `after`, for paging through children by keyset.
+    /// The ID breaks ties between blocks sharing an order.
+    pub fn keyset_batch(blocks: Vec<Block>, after: Option<&(i32, String)>, limit: usize) -> Vec<Block> {
+        let mut batch: Vec<Block> = blocks.into_iter()
+            .filter(|block| after.is_none_or(|(order, id)| (block.order, &block.id) > (*order, id)))
+            .collect();
+        batch.sort_by(|a, b| a.order.cmp(&b.order).then_with(|| a.id.cmp(&b.id)));
+        batch.truncate(limit);
+        batch
+    }
+
+    /// How well this block matches a search for `term`: the number of times
+    /// it appears in the content
+    pub fn search_rank(&self, term: &str) -> i64 {
+        match &self.content {
+            Some(content) if !term.is_empty() => content.matches(term).count() as i64,
+            _ => 0,
+        }
+    }
+
+    /// Copies of a template page and the blocks under it with new IDs, the
+    /// page titled `new_title` and every `{{name}}` in content replaced by its
+    /// value in `placeholders`. The page comes first; unknown placeholders
+    /// are left as they are.
+    pub fn instantiate_template(
+        page: &Block,
+        descendants: &[Block],
+        new_title: &str,
+        placeholders: &HashMap<&str, String>,
+        now: DateTime<Utc>,
+    ) -> Vec<Block> {
+        let new_ids: HashMap<&str, String> = std::iter::once(page)
+            .chain(descendants)
+            .map(|block| (block.id.as_str(), uuid::Uuid::new_v4().to_string()))
+            .collect();
+        let copy = |block: &Block| Block {
+            id: new_ids[block.id.as_str()].clone(),
+            content: block.content.as_deref().map(|content| Self::fill_placeholders(content, placeholders)),
+            parent_id: block.parent_id.as_deref().and_then(|parent_id| new_ids.get(parent_id).cloned()),
+            created_at: now,
+            updated_at: now,
+            audio_timestamp: None,
+            ..block.clone()
+        };
+
+        let new_page = Block { page_title: Some(new_title.to_string()), parent_id: None, ..copy(page) };
+        std::iter::once(new_page).chain(descendants.iter().map(copy)).collect()
+    }
+
+    /// `content` with each `{{name}}` replaced by its value in `placeholders`
+    pub fn fill_placeholders(content: &str, placeholders: &HashMap<&str, String>) -> String {
+        placeholders.iter().fold(content.to_string(), |content, (name, value)| {
+            content.replace(&format!("{{{{{}}}}}", name), value)
+        })
+    }
+
+    /// Zero-based position of this block among `siblings` in block order.
+    /// Only the order matters, not whether the order values are contiguous.
+    pub fn position_among(&self, siblings: &[Block]) -> usize {
+        siblings.iter()
+            .filter(|sibling| (sibling.order, &sibling.id) < (self.order, &self.id))
+            .count()
+    }
+
+    /// Titles of the pages `content` links to with `[[Title]]`
+    pub fn page_links(content: &str) -> BTreeSet<String> {
+        let mut links = BTreeSet::new();
+        let mut rest = content;
+        while let Some(start) = rest.find("[[") {
+            rest = &rest[start + 2..];
+            let Some(end) = rest.find("]]") else { break };
+            let title = rest[..end].trim();
+            if !title.is_empty() {
+                links.insert(title.to_string());
+            }
+            rest = &rest[end + 2..];
+        }
+        links
+    }
+
+    /// The `((id))` token that embeds block `block_id` in other content
+    pub fn embed_token(block_id: &str) -> String {
+        format!("(({}))", block_id)
+    }
+
+    /// Whitespace-separated words in `content`. Link and tag markup isn't a
+    /// word by itself, so `[[Project X]]` is two words and `#todo` one, and
+    /// `((block-id))` embeds aren't counted.
+    pub fn word_count(content: &str) -> usize {
+        content.split_whitespace()
+            .filter(|word| !(word.starts_with("((") && word.ends_with("))")))
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+
+    /// Words in the content of the blocks below `page_id` at any depth
+    pub fn words_below(page_id: &str, blocks: &[Block]) -> usize {
+        let mut children: HashMap<&str, Vec<&Block>> = HashMap::new();
+        for block in blocks {
+            if let Some(parent_id) = block.parent_id.as_deref() {
+                children.entry(parent_id).or_default().push(block);
+            }
+        }
+
+        let mut words = 0;
+        let mut pending = vec![page_id];
+        let mut seen = HashSet::from([page_id]);
+        while let Some(id) = pending.pop() {
+            for &child in children.get(id).into_iter().flatten() {
+                // Guard against parent cycles in corrupted data
+                if seen.insert(child.id.as_str()) {
+                    if !child.is_page {
+                        words += child.content.as_deref().map_or(0, Self::word_count);
+                    }
+                    pending.push(child.id.as_str());
+                }
+            }
+        }
+        words
+    }
+
+    /// `content` with its `[[from]]` links pointing at the page titled `to` instead
+    pub fn retarget_page_links(content: &str, from: &str, to: &str) -> String {
+        let mut retargeted = String::with_capacity(content.len());
+        let mut rest = content;
+        while let Some(start) = rest.find("[[") {
+            let Some(end) = rest[start + 2..].find("]]").map(|end| start + 2 + end) else { break };
+            retargeted.push_str(&rest[..start]);
+            if rest[start + 2..end].trim() == from.trim() {
+                retargeted.push_str(&format!("[[{}]]", to.trim()));
+            } else {
+                retargeted.push_str(&rest[start..end + 2]);
+            }
+            rest = &rest[end + 2..];
+        }
+        retargeted.push_str(rest);
+        retargeted
+    }
+
+    /// Title as compared when looking for duplicate pages
+    pub fn normalized_title(title: &str) -> String {
+        title.trim().to_lowercase()
+    }
+
+    /// Pages whose titles only differ in case or surrounding whitespace, as
+    /// `(original, duplicate)` pairs with the oldest page of each title as
+    /// the original
+    pub fn duplicate_pages(pages: Vec<Block>) -> Vec<(Block, Block)> {
+        let mut by_title: BTreeMap<String, Vec<Block>> = BTreeMap::new();
+        for page in pages {
+            if let Some(title) = page.page_title.as_deref().map(Self::normalized_title) {
+                by_title.entry(title).or_default().push(page);
+            }
+        }
+
+        let mut duplicates = Vec::new();
+        for mut pages in by_title.into_values() {
+            pages.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+            let mut pages = pages.into_iter();
+            let Some(original) = pages.next() else { continue };
+            duplicates.extend(pages.map(|duplicate| (original.clone(), duplicate)));
+        }
+        duplicates
+    }
+
+    /// Links `(missing, stale)` to add to and retract from `stored` so it
+    /// matches the links in `content`
+    pub fn link_repairs(content: Option<&str>, stored: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+        let expected = content.map(Self::page_links).unwrap_or_default();
+        (expected.difference(stored).cloned().collect(), stored.difference(&expected).cloned().collect())
+    }
+
+    /// Page links `(added, removed)` when a block's content changes from `old` to `new`
+    pub fn link_changes(old: Option<&str>, new: Option<&str>) -> (Vec<String>, Vec<String>) {
+        let old = old.map(Self::page_links).unwrap_or_default();
+        let new = new.map(Self::page_links).unwrap_or_default();
+        (new.difference(&old).cloned().collect(), old.difference(&new).cloned().collect())
+    }
+
+    /// Whether applying `updates` (keyed by attribute name, with or without
+    /// the `block/` prefix) would leave the block as it is. Attributes the
+    /// block doesn't expose count as changes.
+    pub fn is_unchanged_by(&self, updates: &HashMap<String, serde_json::Value>) -> bool {
+        let current = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => return false,
+        };
+        updates.iter().all(|(key, value)| {
+            let field = match key.strip_prefix("block/").unwrap_or(key).replace('-', "_").as_str() {
+                "parent" => "parent_id".to_string(),
+                other => other.to_string(),
+            };
+            current.get(&field) == Some(value)
+        })
+    }
+}
+
+/// A recently edited block with the page it's on, for the activity feed
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecentEdit {
+    pub block: Block,
+    pub page_id: Option<String>, // None for blocks that aren't under a page
+    pub page_title: Option<String>,
+}
+
+impl RecentEdit {
+    /// The `limit` most recently updated of `blocks`, newest first, each with
+    /// the page found by following `parents` (block ID to parent ID) up to
+    /// one of `pages` (page ID to title)
+    pub fn latest(mut blocks: Vec<Block>, parents: &HashMap<String, String>, pages: &HashMap<String, String>, limit: usize) -> Vec<RecentEdit> {
+        blocks.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then_with(|| a.id.cmp(&b.id)));
+        blocks.truncate(limit);
+        blocks.into_iter()
+            .map(|block| {
+                let mut seen = HashSet::new();
+                let mut ancestor = block.parent_id.clone();
+                // Guard against parent cycles in corrupted data
+                while let Some(id) = ancestor.filter(|id| seen.insert(id.clone())) {
+                    if let Some(title) = pages.get(&id) {
+                        return RecentEdit { block, page_id: Some(id), page_title: Some(title.clone()) };
+                    }
+                    ancestor = parents.get(&id).cloned();
+                }
+                RecentEdit { block, page_id: None, page_title: None }
+            })
+            .collect()
+    }
+}
+
+/// Size of a page's block tree, for finding bloated pages
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PageStat {
+    pub page_id: String,
+    pub title: Option<String>,
+    pub block_count: usize, // Blocks below the page at any depth
+    pub max_depth: usize,   // 1 for blocks directly under the page
+}
+
+impl PageStat {
+    /// Count each page's descendants and their deepest nesting from
+    /// `(block_id, parent_id)` links, largest page first
+    pub fn compute(pages: &[(String, Option<String>)], parent_links: &[(String, String)]) -> Vec<PageStat> {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (block_id, parent_id) in parent_links {
+            children.entry(parent_id.as_str()).or_default().push(block_id.as_str());
+        }
+
+        let mut stats: Vec<PageStat> = pages.iter()
+            .map(|(page_id, title)| {
+                let mut block_count = 0;
+                let mut max_depth = 0;
+                let mut pending = vec![(page_id.as_str(), 0)];
+                let mut seen = HashSet::from([page_id.as_str()]);
+
+                while let Some((id, depth)) = pending.pop() {
+                    for &child in children.get(id).into_iter().flatten() {
+                        // Guard against parent cycles in corrupted data
+                        if seen.insert(child) {
+                            block_count += 1;
+                            max_depth = max_depth.max(depth + 1);
+                            pending.push((child, depth + 1));
+                        }
+                    }
+                }
+
+                PageStat { page_id: page_id.clone(), title: title.clone(), block_count, max_depth }
+            })
+            .collect();
+
+        stats.sort_by(|a, b| b.block_count.cmp(&a.block_count).then_with(|| a.page_id.cmp(&b.page_id)));
+        stats
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -22,36 +327,672 @@ pub struct CreateBlockRequest {
     pub order: i32,
     pub is_page: bool,
     pub page_title: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>, // Left unset for blocks typed on the keyboard
+}
+
+impl CreateBlockRequest {
+    /// Check the request describes a block that fits in the outline: a page
+    /// has a title and no parent, and every other block has a parent
+    pub fn validate(&self) -> crate::errors::Result<()> {
+        let present = |value: &Option<String>| value.as_deref().is_some_and(|value| !value.trim().is_empty());
+        if self.is_page {
+            if !present(&self.page_title) {
+                return Err(DatomicError::invalid_block_request("page_title", "A page needs a title"));
+            }
+            if self.parent_id.is_some() {
+                return Err(DatomicError::invalid_block_request("parent_id", "A page can't have a parent block"));
+            }
+        } else if !present(&self.parent_id) {
+            return Err(DatomicError::invalid_block_request("parent_id", "A block needs a parent"));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "AudioMetaFields")]
 pub struct AudioMeta {
     pub recording_id: String,
-    pub timestamp: i32,
+    pub timestamp_ms: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl AudioMeta {
+    /// Timestamp value asking the backend to stamp the block with the
+    /// recording's current position at creation time
+    pub const CURRENT_POSITION: i64 = -1;
+}
+
+/// `AudioMeta` as sent by the frontend, which may still use the older
+/// whole-second `timestamp` field
+#[derive(Deserialize)]
+struct AudioMetaFields {
+    recording_id: String,
+    timestamp_ms: Option<i64>,
+    timestamp: Option<i32>,
+}
+
+impl From<AudioMetaFields> for AudioMeta {
+    fn from(fields: AudioMetaFields) -> Self {
+        let timestamp_ms = match (fields.timestamp_ms, fields.timestamp) {
+            (Some(ms), _) => ms,
+            (None, Some(seconds)) if seconds as i64 == AudioMeta::CURRENT_POSITION => AudioMeta::CURRENT_POSITION,
+            (None, Some(seconds)) => seconds as i64 * 1000,
+            (None, None) => 0,
+        };
+        AudioMeta { recording_id: fields.recording_id, timestamp_ms }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AudioRecording {
     pub id: String,
     pub page_id: String,
     pub file_path: String,
     pub duration_seconds: Option<i32>,
     pub recorded_at: DateTime<Utc>,
+    #[serde(default)]
+    pub system_audio: bool, // Microphone mixed with system audio
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AudioTimestamp {
     pub block_id: String,
     pub recording_id: String,
-    pub timestamp_seconds: i32,
+    #[serde(default)]
+    pub timestamp_ms: i64,
+    pub timestamp_seconds: i32, // Whole seconds of `timestamp_ms`, for older callers
     pub recording: Option<AudioRecording>,
+    #[serde(default)]
+    pub segment: Option<RecordingSegment>, // File holding this timestamp when the recording was split
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl AudioTimestamp {
+    /// A timestamp without its recording or segment filled in
+    pub fn new(block_id: &str, recording_id: &str, timestamp_ms: i64) -> Self {
+        AudioTimestamp {
+            block_id: block_id.to_string(),
+            recording_id: recording_id.to_string(),
+            timestamp_ms,
+            timestamp_seconds: (timestamp_ms / 1000) as i32,
+            recording: None,
+            segment: None,
+        }
+    }
+}
+
+/// One file of a recording that was rotated every few minutes. Offsets are
+/// relative to the start of the whole recording.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecordingSegment {
+    pub index: i32,
+    pub file_path: String,
+    pub start_ms: i64,
+    pub duration_ms: i64,
+}
+
+/// Sent when a recording closes its current file and moves on to a new one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SegmentSplit {
+    pub index: i32,         // Index of the new segment
+    pub file_path: String,  // File the new segment is written to
+    pub start_ms: i64,
+}
+
+impl RecordingSegment {
+    /// Find the segment containing a recording-relative timestamp. Timestamps
+    /// past the end map to the last segment.
+    pub fn locate(segments: &[RecordingSegment], timestamp_ms: i64) -> Option<&RecordingSegment> {
+        segments.iter()
+            .filter(|segment| segment.start_ms <= timestamp_ms)
+            .max_by_key(|segment| segment.start_ms)
+            .or_else(|| segments.iter().min_by_key(|segment| segment.start_ms))
+    }
+}
+
+/// `transcript.json` of an exported page bundle, listing the copied
+/// recordings and the blocks written during each
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BundleManifest {
+    pub page_id: String,
+    pub page_title: String,
+    pub exported_at: DateTime<Utc>,
+    pub recordings: Vec<BundledRecording>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BundledRecording {
+    pub recording_id: String,
+    pub files: Vec<String>, // Copied file names in the bundle, in playback order
+    pub recorded_at: DateTime<Utc>,
+    pub duration_seconds: Option<i32>,
+    pub timestamps: Vec<BundledTimestamp>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BundledTimestamp {
+    pub block_id: String,
+    pub snippet: String, // Start of the block's content
+    pub seconds: f64,    // Offset into the whole recording
+}
+
+/// Everything in the database as one JSON document, for backups and
+/// moving notes elsewhere
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DatabaseExport {
+    pub app_version: String,
+    pub schema_version: i64,
+    pub exported_at: DateTime<Utc>,
+    pub blocks: Vec<Block>,
+    pub recordings: Vec<AudioRecording>,
+    pub timestamps: Vec<AudioTimestamp>,
+}
+
+/// Where a JSON export was written and how much it holds
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportSummary {
+    pub path: String,
+    pub blocks: usize,
+    pub recordings: usize,
+    pub timestamps: usize,
+}
+
+/// Pages created from a directory of Markdown files
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub pages_created: Vec<String>,
+    pub pages_skipped: Vec<String>, // Titles that already had a page, left untouched
+    pub blocks_created: usize,
+}
+
+/// A backup directory holding a JSON export and a copy of the recordings
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BackupReport {
+    pub dir: String,
+    pub export: ExportSummary,
+    pub files_copied: usize,
+    pub bytes_copied: u64,
+}
+
+/// Problems found by checking the database for consistency. Lists hold
+/// block IDs unless noted otherwise.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    pub blocks_checked: usize,
+    pub recordings_checked: usize,
+    pub missing_schema: Vec<String>, // Attribute idents
+    pub orphaned_blocks: Vec<String>, // Parent no longer exists
+    pub blocks_in_cycles: Vec<String>,
+    pub blocks_with_stale_links: Vec<String>,
+    pub missing_recording_files: Vec<String>, // File paths
+    pub dangling_timestamps: Vec<String>, // Timestamps of blocks that no longer exist
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing_schema.is_empty()
+            && self.orphaned_blocks.is_empty()
+            && self.blocks_in_cycles.is_empty()
+            && self.blocks_with_stale_links.is_empty()
+            && self.missing_recording_files.is_empty()
+            && self.dangling_timestamps.is_empty()
+    }
+}
+
+/// What the frontend needs to decide on a first-run experience
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OnboardingState {
+    pub fresh_vault: bool, // No pages besides today's daily note
+    pub page_count: usize,
+    pub sample_page_id: Option<String>, // The welcome page, if it's been seeded and not deleted
+}
+
+impl OnboardingState {
+    pub fn new(page_titles: &[String], today: &str, sample_page_id: Option<String>) -> Self {
+        OnboardingState {
+            fresh_vault: page_titles.iter().all(|title| title == today),
+            page_count: page_titles.len(),
+            sample_page_id,
+        }
+    }
+}
+
+/// The welcome page, and whether this call created it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SampleContent {
+    pub page: Block,
+    pub created: bool,
+}
+
+/// A moment flagged during a recording without writing a block
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingMarker {
+    pub id: String,
+    pub recording_id: String,
+    pub timestamp_ms: i64,
+    pub label: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Content of a block edit that hasn't been written yet, saved every few
+/// seconds so it can be recovered after a crash
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Draft {
+    pub block_id: String,
+    pub content: String,
+    pub saved_at: DateTime<Utc>,
+}
+
+impl Draft {
+    /// Drafts saved after their block was last written, so they hold edits
+    /// the block doesn't have. Drafts of blocks not in `blocks` are left out.
+    pub fn unsaved(drafts: Vec<Draft>, blocks: &[Block]) -> Vec<Draft> {
+        let updated_at: HashMap<&str, DateTime<Utc>> = blocks.iter().map(|block| (block.id.as_str(), block.updated_at)).collect();
+        drafts.into_iter()
+            .filter(|draft| updated_at.get(draft.block_id.as_str()).is_some_and(|&updated_at| draft.saved_at > updated_at))
+            .collect()
+    }
+}
+
+/// A block matching a search, with the rank it's sorted by
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SearchHit {
+    pub rank: i64,
+    pub block: Block,
+}
+
+/// A page of search results, best matches first. `next_rank` and `next_id`
+/// are the key of the last hit to continue from, or `None` on the last page.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub next_rank: Option<i64>,
+    pub next_id: Option<String>,
+}
+
+impl SearchPage {
+    /// The first `limit` of `blocks` ranked for `term` that come after the
+    /// `(rank, id)` key `after`, by rank from highest and then ID. The ID
+    /// breaks ties between blocks of the same rank, so as long as the matches
+    /// don't change between pages every one lands on exactly one page.
+    pub fn keyset(blocks: Vec<Block>, term: &str, after: Option<(i64, &str)>, limit: usize) -> SearchPage {
+        let mut hits: Vec<SearchHit> = blocks.into_iter()
+            .map(|block| SearchHit { rank: block.search_rank(term), block })
+            .filter(|hit| after.is_none_or(|(rank, id)| hit.rank < rank || (hit.rank == rank && hit.block.id.as_str() > id)))
+            .collect();
+        hits.sort_by(|a, b| b.rank.cmp(&a.rank).then_with(|| a.block.id.cmp(&b.block.id)));
+
+        let more = hits.len() > limit;
+        hits.truncate(limit);
+        let last = hits.last().filter(|_| more);
+        SearchPage {
+            next_rank: last.map(|hit| hit.rank),
+            next_id: last.map(|hit| hit.block.id.clone()),
+            hits,
+        }
+    }
+}
+
+/// A point in the database's past: a transaction, by its ID or basis t,
+/// or a wall-clock instant
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TxOrInstant {
+    Tx(i64),
+    Instant(DateTime<Utc>),
+}
+
+/// One assertion or retraction of a block's content
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ContentRevision {
+    pub tx: i64,
+    pub tx_instant: DateTime<Utc>,
+    pub content: String,
+    pub added: bool, // false when this content was retracted
+}
+
+impl ContentRevision {
+    /// `revisions` in the order they happened: by transaction, and within
+    /// one the retraction of the old content before the new content
+    pub fn timeline(mut revisions: Vec<ContentRevision>) -> Vec<ContentRevision> {
+        revisions.sort_by(|a, b| a.tx.cmp(&b.tx).then_with(|| a.added.cmp(&b.added)));
+        revisions
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingSummary {
+    pub duration_seconds: i32,
+    pub segments: Vec<RecordingSegment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AudioDevice {
     pub name: String,
     pub is_default: bool,
     pub device_type: String, // "input" or "output"
+    #[serde(default)]
+    pub default_config: Option<DeviceConfigSummary>, // None if the device couldn't report one
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DeviceConfigSummary {
+    pub sample_format: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// One range of input configurations a device supports
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DeviceConfigRange {
+    pub sample_format: String,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub channels: u16,
+    pub is_supported: bool, // Whether recording can use this sample format
+}
+
+/// Devices plugged in or unplugged since the last check, and the defaults
+/// afterwards. Removed devices are never reported as the default.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DevicesChanged {
+    pub added: Vec<AudioDevice>,
+    pub removed: Vec<AudioDevice>,
+    pub default_input: Option<String>,
+    pub default_output: Option<String>,
+}
+
+impl DevicesChanged {
+    /// Whether an input device with this name was unplugged
+    pub fn removed_input(&self, name: &str) -> bool {
+        self.removed.iter().any(|device| device.device_type == "input" && device.name == name)
+    }
+}
+
+/// Sent when the device a recording was capturing from disappears and the
+/// recording was stopped in response
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingDeviceLost {
+    pub recording_id: String,
+    pub device_name: String,
+    pub stopped: bool, // Whether the partial recording was saved
+}
+
+/// Sent as `audio://recording-started` when a recording starts or an
+/// interrupted one is resumed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingStarted {
+    pub recording_id: String,
+    pub page_id: String,
+    pub file_path: String,
+}
+
+/// Sent as `audio://recording-stopped` whenever a recording ends, whether it
+/// was stopped, cancelled, stopped automatically or failed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingStopped {
+    pub recording_id: String,
+    pub duration_seconds: Option<i32>, // None if the recording failed or was cancelled
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+/// Sent over the channel of `stream_page_blocks`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PageBlocksEvent {
+    /// The next blocks of the page, in order
+    Blocks { blocks: Vec<Block> },
+    /// Every block has been sent
+    Finished { total: usize },
+}
+
+/// Which `data://` event a change is sent as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataChangeKind {
+    BlockCreated,
+    BlockChanged,
+    BlockDeleted,
+    PageCreated,
+    PageChanged,
+}
+
+impl DataChangeKind {
+    /// New blocks and pages go out with the changes, since a window
+    /// refreshes the same way for either
+    pub fn event(self) -> &'static str {
+        match self {
+            DataChangeKind::BlockCreated | DataChangeKind::BlockChanged => "data://block-changed",
+            DataChangeKind::BlockDeleted => "data://block-deleted",
+            DataChangeKind::PageCreated | DataChangeKind::PageChanged => "data://page-changed",
+        }
+    }
+}
+
+/// What happened to one block or page, sent as a `db-changed` event for
+/// views that track blocks one by one
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DbChanged {
+    pub kind: DbChangedKind,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum DbChangedKind {
+    #[serde(rename = "block_created")]
+    Created,
+    #[serde(rename = "block_updated")]
+    Updated,
+    #[serde(rename = "block_deleted")]
+    Deleted,
+}
+
+/// What a write changed, sent to every window so the others can refresh
+/// what they show
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DataChange {
+    #[serde(skip)]
+    pub kind: DataChangeKind,
+    pub ids: Vec<String>,
+}
+
+impl DataChange {
+    /// A change to `ids`, each listed once in the order first given
+    pub fn new(kind: DataChangeKind, ids: impl IntoIterator<Item = String>) -> Self {
+        let mut seen = HashSet::new();
+        let ids = ids.into_iter().filter(|id| seen.insert(id.clone())).collect();
+        Self { kind, ids }
+    }
+
+    /// A write to an existing block or page
+    pub fn written(block: &Block) -> Self {
+        let kind = if block.is_page { DataChangeKind::PageChanged } else { DataChangeKind::BlockChanged };
+        Self::new(kind, [block.id.clone()])
+    }
+
+    /// Changes for newly added blocks: new pages, new blocks, and the
+    /// parents whose children they join
+    pub fn added(blocks: &[Block]) -> Vec<Self> {
+        let pages = Self::new(
+            DataChangeKind::PageCreated,
+            blocks.iter().filter(|block| block.is_page).map(|page| page.id.clone()),
+        );
+        let new_blocks = Self::new(
+            DataChangeKind::BlockCreated,
+            blocks.iter().filter(|block| !block.is_page).map(|block| block.id.clone()),
+        );
+        let new_ids: HashSet<&str> = blocks.iter().map(|block| block.id.as_str()).collect();
+        let parents = Self::new(
+            DataChangeKind::BlockChanged,
+            blocks.iter()
+                .filter(|block| !block.is_page)
+                .filter_map(|block| block.parent_id.clone())
+                .filter(|parent_id| !new_ids.contains(parent_id.as_str())),
+        );
+        [pages, new_blocks, parents].into_iter().filter(|change| !change.ids.is_empty()).collect()
+    }
+
+    /// The change as one `db-changed` event per block or page
+    pub fn per_block(&self) -> Vec<DbChanged> {
+        let kind = match self.kind {
+            DataChangeKind::BlockCreated | DataChangeKind::PageCreated => DbChangedKind::Created,
+            DataChangeKind::BlockChanged | DataChangeKind::PageChanged => DbChangedKind::Updated,
+            DataChangeKind::BlockDeleted => DbChangedKind::Deleted,
+        };
+        self.ids.iter().map(|id| DbChanged { kind, id: id.clone() }).collect()
+    }
+}
+
+/// Where a job started with `Jobs::spawn` has got to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// A long-running operation, as listed by `list_jobs` and sent as
+/// `job://finished` when it ends
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct JobInfo {
+    pub job_id: String,
+    pub kind: String, // The command that started it, e.g. "export_json"
+    pub status: JobStatus,
+    pub done: usize,
+    pub total: usize,
+    pub message: Option<String>, // Latest progress message
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub result: Option<serde_json::Value>, // What the command would have returned
+    pub error: Option<AppError>,
+}
+
+/// Sent as `job://progress` as a job works through its units
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub done: usize,
+    pub total: usize,
+    pub message: String,
+}
+
+/// One read of an `execute_batch` call, mirroring a read command. Only
+/// reads can be batched; writes keep their own commands.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)] // Named after the commands they mirror
+pub enum BatchOp {
+    GetPageByTitle { title: String },
+    GetBlock { block_id: String },
+    GetBlockChildren { parent_id: String },
+    GetReferenceCount { page_title: String },
+    GetRecordingsForPage { page_id: String },
+    GetRecording { recording_id: String },
+    GetRecordingTimestamps { recording_id: String },
+}
+
+/// Outcome of one `BatchOp`, at the same position as the op
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchResult {
+    Ok { value: serde_json::Value },
+    Error { error: AppError },
+}
+
+impl From<Result<serde_json::Value, AppError>> for BatchResult {
+    fn from(result: Result<serde_json::Value, AppError>) -> Self {
+        match result {
+            Ok(value) => BatchResult::Ok { value },
+            Err(error) => BatchResult::Error { error },
+        }
+    }
+}
+
+/// What rebuilding the page links from block content changed
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ReindexStats {
+    pub blocks_checked: usize,
+    pub blocks_fixed: usize, // Blocks whose links had drifted from their content
+    pub links_added: usize,
+    pub links_removed: usize,
+}
+
+/// Versions and locations for the About dialog and bug reports
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AppInfo {
+    pub version: String,
+    pub schema_version: Option<i64>, // None when the database couldn't be reached
+    pub database_available: bool,
+    pub data_dir: String,
+    pub recordings_dir: String,
+    pub recordings_size_bytes: u64, // Recordings are the app's own on-disk data; the database lives with the transactor
+}
+
+/// Whether the Datomic installation and Java look usable, checked without
+/// starting the JVM
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DatomicInstallReport {
+    pub lib_path: Option<String>,
+    pub detected: bool, // No path was configured, so the usual install locations were searched
+    pub install_root: Option<String>,
+    pub classpath: Vec<String>, // Jars the JVM would be started with, in order
+    pub missing_jars: Vec<String>,
+    pub java_home: Option<String>,
+    pub java_executable: Option<String>,
+    pub problems: Vec<String>, // What to fix, in words for the settings screen
+    pub ok: bool,
+}
+
+/// Sent as `app://shutting-down` when the app starts saving before it exits
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShuttingDown {
+    pub saving_recording: Option<String>, // Recording being finalized, if any
+}
+
+/// Sent as `app://navigate` when a `gita://` link is opened
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Navigation {
+    /// Open a page, scrolled to one of its blocks if the link named one
+    Page {
+        page_id: String,
+        page_title: String,
+        block_id: Option<String>,
+    },
+    /// Open the page a recording was made on and play it from `timestamp_ms`
+    Recording {
+        page_id: String,
+        page_title: String,
+        recording: AudioRecording,
+        timestamp_ms: i64,
+        segment: Option<RecordingSegment>, // File holding `timestamp_ms` when the recording was split
+    },
+}
+
+/// Sent as `audio://recording-progress` once a second while recording
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingProgress {
+    pub recording_id: String,
+    pub elapsed_ms: u64,
+}
+
+/// Input level while recording, after automatic gain control
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct InputLevel {
+    pub rms_db: f32,
+    pub peak_db: f32,
+    pub agc_gain_db: Option<f32>, // Gain AGC is applying, None when it's off
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceCaps {
+    pub device_name: String,
+    pub sample_format: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub is_supported: bool, // Whether recording can use this sample format
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,3 +1003,215 @@ pub struct RecordingState {
     pub start_time: Option<DateTime<Utc>>,
 }
 
+/// A recording cut short by the app closing whose file is recent enough to
+/// offer finalizing or resuming it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InterruptedRecording {
+    pub recording: AudioRecording,
+    pub duration_seconds: i32, // Audio actually in the file
+    pub modified_at: DateTime<Utc>,
+}
+
+impl InterruptedRecording {
+    /// How long after its file was last written a recording can still be resumed
+    pub const RESUME_WINDOW_HOURS: i64 = 24;
+
+    pub fn is_recent(modified_at: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        now - modified_at < chrono::Duration::hours(Self::RESUME_WINDOW_HOURS)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RecordingRecoveryReport {
+    pub recovered: usize,
+    pub failed: usize,
+}
+
+/// What a recording file's header claims compared with what is on disk
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WavFileCheck {
+    pub file_path: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub duration_ms: i64, // Length of the audio actually in the file
+    pub header_data_len: u64,
+    pub actual_data_len: u64,
+}
+
+impl WavFileCheck {
+    pub fn header_matches(&self) -> bool {
+        self.header_data_len == self.actual_data_len
+    }
+}
+
+/// Result of checking a recording's files against each other and its stored metadata
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingVerification {
+    pub recording_id: String,
+    pub files: Vec<WavFileCheck>,
+    pub unreadable: Vec<String>, // Files that couldn't be opened, with the reason
+    pub stored_duration_seconds: Option<i32>,
+    pub issues: Vec<String>, // Empty when everything agrees
+    pub repaired: bool,
+}
+
+impl RecordingVerification {
+    /// Stored durations are whole seconds, so allow for the truncated fraction
+    pub const DURATION_TOLERANCE_MS: i64 = 1000;
+
+    pub fn new(recording_id: &str, stored_duration_seconds: Option<i32>, files: Vec<WavFileCheck>, unreadable: Vec<String>) -> Self {
+        let mut verification = RecordingVerification {
+            recording_id: recording_id.to_string(),
+            files,
+            unreadable,
+            stored_duration_seconds,
+            issues: Vec::new(),
+            repaired: false,
+        };
+        verification.issues = verification.find_issues();
+        verification
+    }
+
+    /// Length of the audio in all readable files
+    pub fn measured_duration_ms(&self) -> i64 {
+        self.files.iter().map(|file| file.duration_ms).sum()
+    }
+
+    /// Whether any file's header disagrees with its size
+    pub fn headers_stale(&self) -> bool {
+        self.files.iter().any(|file| !file.header_matches())
+    }
+
+    /// Whether the stored duration disagrees with the audio on disk. Only
+    /// meaningful when every file could be read.
+    pub fn duration_stale(&self) -> bool {
+        self.unreadable.is_empty() && self.stored_duration_seconds.is_none_or(|seconds| {
+            (self.measured_duration_ms() - seconds as i64 * 1000).abs() >= Self::DURATION_TOLERANCE_MS
+        })
+    }
+
+    fn find_issues(&self) -> Vec<String> {
+        let mut issues = self.unreadable.clone();
+
+        for file in self.files.iter().filter(|file| !file.header_matches()) {
+            issues.push(format!(
+                "{}: header declares {} bytes of audio but the file holds {}",
+                file.file_path, file.header_data_len, file.actual_data_len
+            ));
+        }
+        if let Some(first) = self.files.first() {
+            for file in self.files.iter().skip(1) {
+                if (file.sample_rate, file.channels) != (first.sample_rate, first.channels) {
+                    issues.push(format!(
+                        "{}: {} Hz, {} channels differs from the first file's {} Hz, {} channels",
+                        file.file_path, file.sample_rate, file.channels, first.sample_rate, first.channels
+                    ));
+                }
+            }
+        }
+        if self.duration_stale() {
+            issues.push(match self.stored_duration_seconds {
+                Some(seconds) => format!("Stored duration {}s differs from the {}ms of audio on disk", seconds, self.measured_duration_ms()),
+                None => "No duration stored".to_string(),
+            });
+        }
+
+        issues
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SchemaDiff {
+    pub missing: Vec<String>, // Schema attributes not yet in the database
+    pub present: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NormalizationReport {
+    pub measured_lufs: f64, // Approximate loudness before normalizing
+    pub target_lufs: f32,
+    pub gain_db: f64, // Gain applied, lower than requested if peaks would clip
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProcessingProgress {
+    pub recording_id: String,
+    pub progress: f32, // 0.0 to 1.0
+}
+
+/// A file dropped onto the window, sent in `app://files-dropped`. The
+/// webview passes `token` to `attach_dropped_audio` in place of the path.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DroppedFile {
+    pub token: String,
+    pub name: String,
+}
+
+/// Sent as `audio://import-progress` while a dropped file is copied into the
+/// recordings directory. `path` is the token or path the file was given as.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportProgress {
+    pub path: String,
+    pub bytes_copied: u64,
+    pub total_bytes: u64,
+}
+
+/// A dropped file that wasn't attached, and why
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DroppedFileError {
+    pub path: String,
+    pub error: AppError,
+}
+
+/// Recordings made from dropped files, in the order the files were given,
+/// and the files that couldn't be attached
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct DroppedAudioReport {
+    pub recordings: Vec<AudioRecording>,
+    pub errors: Vec<DroppedFileError>,
+}
+
+/// A stretch of a recording quieter than the analysis threshold, in
+/// milliseconds from the start of the recording
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SilenceInterval {
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+/// Leading and trailing silence cut from a recording, and how long it is afterwards
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SilenceTrim {
+    pub leading_ms: i64,
+    pub trailing_ms: i64,
+    pub duration_ms: i64,
+}
+
+impl SilenceTrim {
+    /// Work out the trim from a recording's silence map. Only silence touching
+    /// the start or end of the recording is removed.
+    pub fn from_silence(intervals: &[SilenceInterval], duration_ms: i64) -> Self {
+        let leading_ms = intervals.iter()
+            .find(|interval| interval.start_ms <= 0)
+            .map_or(0, |interval| interval.end_ms.min(duration_ms));
+        let trailing_ms = intervals.iter()
+            .find(|interval| interval.end_ms >= duration_ms && interval.start_ms > 0)
+            .map_or(0, |interval| duration_ms - interval.start_ms.max(leading_ms));
+
+        SilenceTrim {
+            leading_ms,
+            trailing_ms,
+            duration_ms: duration_ms - leading_ms - trailing_ms,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leading_ms == 0 && self.trailing_ms == 0
+    }
+
+    /// Move a timestamp from the untrimmed recording into the trimmed one.
+    /// Timestamps inside the removed silence snap to the nearest kept edge.
+    pub fn shift_timestamp_ms(&self, timestamp_ms: i64) -> i64 {
+        (timestamp_ms - self.leading_ms).clamp(0, self.duration_ms.max(0))
+    }
+}
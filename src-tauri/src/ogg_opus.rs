@@ -0,0 +1,203 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use audiopus::coder::{Decoder, Encoder};
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+use ogg::{PacketReader, PacketWriteEndInfo, PacketWriter};
+use crate::audio_engine::OPUS_SAMPLE_RATE;
+use crate::errors::AudioEngineError;
+
+type Result<T> = std::result::Result<T, AudioEngineError>;
+
+// 20ms frames, the usual choice for speech
+const FRAME_SIZE: usize = 960;
+
+// Longest packet Opus can produce (120ms) and its size bound
+const MAX_FRAME_SIZE: usize = 5760;
+const MAX_PACKET_BYTES: usize = 4000;
+
+const STREAM_SERIAL: u32 = 0x6769_7461;
+const VENDOR: &str = "gita";
+
+fn opus_channels(channels: u16) -> Result<Channels> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        n => Err(AudioEngineError::UnsupportedFormat(format!("Opus recordings can't have {} channels", n))),
+    }
+}
+
+/// Encodes interleaved 48kHz audio into an Ogg/Opus file (RFC 7845)
+pub struct OggOpusWriter {
+    packets: PacketWriter<BufWriter<File>>,
+    encoder: Encoder,
+    channels: usize,
+    pre_skip: u64,
+    pending: Vec<f32>, // Samples that don't fill a whole frame yet
+    frames_written: u64,
+    // The newest packet is held back so the last one can be marked as the end of the stream
+    held: Option<Vec<u8>>,
+}
+
+impl OggOpusWriter {
+    pub fn create(path: &str, channels: u16, bitrate: u32) -> Result<Self> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, opus_channels(channels)?, Application::Voip)?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(bitrate as i32))?;
+        let pre_skip = encoder.lookahead()?;
+
+        let mut packets = PacketWriter::new(BufWriter::new(crate::audio_engine::create_new_file(path)?));
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // Version
+        head.push(channels as u8);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&OPUS_SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // Output gain
+        head.push(0); // Mono or stereo channel mapping
+        packets.write_packet(head.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+        tags.extend_from_slice(VENDOR.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes()); // No user comments
+        packets.write_packet(tags.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(OggOpusWriter {
+            packets,
+            encoder,
+            channels: channels as usize,
+            pre_skip: pre_skip as u64,
+            pending: Vec::with_capacity(FRAME_SIZE * channels as usize),
+            frames_written: 0,
+            held: None,
+        })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Queue interleaved samples, encoding every whole 20ms frame
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let frame_len = FRAME_SIZE * self.channels;
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() == frame_len {
+                self.encode_pending()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_pending(&mut self) -> Result<()> {
+        let mut packet = vec![0u8; MAX_PACKET_BYTES];
+        let len = self.encoder.encode_float(&self.pending, &mut packet)?;
+        packet.truncate(len);
+        self.pending.clear();
+
+        if let Some(previous) = self.held.replace(packet) {
+            let granule = self.frames_written * FRAME_SIZE as u64;
+            self.packets.write_packet(previous.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::NormalPacket, granule)?;
+        }
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Write buffered pages to disk
+    pub fn flush(&mut self) -> Result<()> {
+        self.packets.inner_mut().flush()?;
+        Ok(())
+    }
+
+    /// Encode the remaining samples and end the stream. Silence is encoded
+    /// until the encoder's delay has been flushed out, and the last page's
+    /// granule position trims it again so the file decodes to exactly the
+    /// samples that were written.
+    pub fn finalize(mut self) -> Result<()> {
+        let audio_frames = self.frames_written * FRAME_SIZE as u64 + (self.pending.len() / self.channels) as u64;
+        let granule = self.pre_skip + audio_frames;
+        while self.frames_written * (FRAME_SIZE as u64) < granule {
+            self.pending.resize(FRAME_SIZE * self.channels, 0.0);
+            self.encode_pending()?;
+        }
+        if let Some(last) = self.held.take() {
+            self.packets.write_packet(last.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndStream, granule)?;
+        }
+        self.packets.into_inner().flush()?;
+        Ok(())
+    }
+}
+
+/// Copy an Ogg/Opus file that was never finalized, e.g. because the app
+/// crashed, into `destination`, keeping every complete packet and ending the
+/// stream after the last one. Returns the number of frames it decodes to.
+pub fn repair_ogg_opus(source: &str, destination: &str) -> Result<u64> {
+    let mut packets = PacketReader::new(BufReader::new(File::open(source)?));
+
+    let head = packets.read_packet()?
+        .ok_or_else(|| AudioEngineError::UnsupportedFormat(format!("{} is empty", source)))?;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return Err(AudioEngineError::UnsupportedFormat(format!("{} is not an Ogg/Opus file", source)));
+    }
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as u64;
+    let tags = packets.read_packet()?
+        .ok_or_else(|| AudioEngineError::UnsupportedFormat(format!("{} has no audio", source)))?;
+
+    let mut writer = PacketWriter::new(BufWriter::new(crate::audio_engine::create_new_file(destination)?));
+    writer.write_packet(head.data.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+    writer.write_packet(tags.data.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+    // The last page is usually torn, which ends the packets that can be read.
+    // The newest packet is held back so it can be marked as the end of the stream.
+    let mut held: Option<(Vec<u8>, u64)> = None;
+    while let Ok(Some(packet)) = packets.read_packet() {
+        let granule = packet.absgp_page();
+        if let Some((data, granule)) = held.replace((packet.data, granule)) {
+            writer.write_packet(data.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::NormalPacket, granule)?;
+        }
+    }
+    let end_granule = match held {
+        Some((data, granule)) => {
+            writer.write_packet(data.into_boxed_slice(), STREAM_SERIAL, PacketWriteEndInfo::EndStream, granule)?;
+            granule
+        }
+        None => 0,
+    };
+    writer.into_inner().flush()?;
+    Ok(end_granule.saturating_sub(pre_skip))
+}
+
+/// Decode an Ogg/Opus file to interleaved 48kHz samples, returning the
+/// channel count and the samples
+pub fn read_ogg_opus(path: &str) -> Result<(u16, Vec<f32>)> {
+    let mut packets = PacketReader::new(BufReader::new(File::open(path)?));
+
+    let head = packets.read_packet()?
+        .ok_or_else(|| AudioEngineError::UnsupportedFormat(format!("{} is empty", path)))?;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return Err(AudioEngineError::UnsupportedFormat(format!("{} is not an Ogg/Opus file", path)));
+    }
+    let channels = head.data[9] as u16;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    let mut decoder = Decoder::new(SampleRate::Hz48000, opus_channels(channels)?)?;
+
+    // Comment header
+    packets.read_packet()?;
+
+    let mut samples = Vec::new();
+    let mut buffer = vec![0f32; MAX_FRAME_SIZE * channels as usize];
+    let mut end_granule = None;
+    while let Some(packet) = packets.read_packet()? {
+        let frames = decoder.decode_float(Some((&packet.data).try_into()?), (&mut buffer).try_into()?, false)?;
+        samples.extend_from_slice(&buffer[..frames * channels as usize]);
+        if packet.last_in_stream() {
+            end_granule = Some(packet.absgp_page() as usize);
+        }
+    }
+
+    // Drop the encoder's start-up delay and the padding of the last frame
+    if let Some(end) = end_granule {
+        samples.truncate(end.saturating_mul(channels as usize));
+    }
+    samples.drain(..(pre_skip * channels as usize).min(samples.len()));
+    Ok((channels, samples))
+}
@@ -0,0 +1,93 @@
+use chrono::Utc;
+use tracing::info;
+use crate::archive;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::models::{OnboardingState, SampleContent};
+
+/// Title of the page seeded for new users
+pub const WELCOME_PAGE_TITLE: &str = "Welcome to Gita";
+
+// Outline of the welcome page. It's made of ordinary blocks, so deleting
+// the page gets rid of it like any other.
+const WELCOME_OUTLINE: &str = "\
+- Gita keeps your notes as an outline of blocks. Press Enter at the end of a block to start the next one.
+- Blocks can nest under other blocks
+  - Like this one
+    - And this one, two levels down
+  - Nesting keeps details under the point they belong to
+- Link to a page by putting its title in double brackets, like [[Welcome to Gita]]
+  - Clicking a link opens the page, and a page knows how many blocks link to it
+- Every day gets its own daily note, a good place to start writing
+- Audio timestamps
+  - Press the microphone button to record into the page you're on
+  - Blocks you write while recording remember how far into the recording you were
+  - The play button beside such a block plays the recording from that moment
+- When you're done here, delete this page like any other";
+
+/// Whether this looks like a first run, given today's date in the
+/// caller's timezone (minutes east of UTC)
+pub async fn onboarding_state(db: &DatomicPeerClient, tz_offset_minutes: i32) -> Result<OnboardingState, AppError> {
+    let today = DatomicPeerClient::local_date(Utc::now(), tz_offset_minutes)
+        .ok_or_else(|| AppError::validation(format!("Invalid UTC offset: {} minutes", tz_offset_minutes)))?;
+    let titles = db.get_page_titles().await?;
+    let sample_page_id = db.get_page_by_title(WELCOME_PAGE_TITLE).await?.map(|page| page.id);
+    Ok(OnboardingState::new(&titles, &today, sample_page_id))
+}
+
+/// Create the welcome page in one transaction, unless it already exists
+pub async fn seed_sample_content(db: &DatomicPeerClient) -> Result<SampleContent, AppError> {
+    if let Some(page) = db.get_page_by_title(WELCOME_PAGE_TITLE).await? {
+        return Ok(SampleContent { page, created: false });
+    }
+
+    let blocks = archive::outline_page(WELCOME_PAGE_TITLE, WELCOME_OUTLINE, Utc::now());
+    db.create_blocks(&blocks).await?;
+    info!("Seeded the welcome page with {} blocks", blocks.len() - 1);
+
+    let page = blocks.into_iter().next()
+        .ok_or_else(|| AppError::internal("Welcome page has no blocks"))?;
+    Ok(SampleContent { page, created: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Block;
+
+    #[test]
+    fn test_welcome_page_shows_nesting_and_links() {
+        let blocks = archive::outline_page(WELCOME_PAGE_TITLE, WELCOME_OUTLINE, Utc::now());
+        let page = &blocks[0];
+        let depth = |block: &Block| {
+            let mut depth = 0;
+            let mut parent_id = block.parent_id.clone();
+            while let Some(id) = parent_id.filter(|id| *id != page.id) {
+                depth += 1;
+                parent_id = blocks.iter().find(|block| block.id == id).and_then(|block| block.parent_id.clone());
+            }
+            depth
+        };
+
+        assert_eq!(blocks.iter().filter(|block| block.is_page).count(), 1);
+        assert_eq!(blocks[1..].iter().map(depth).max(), Some(2));
+        // Its only link is to itself, so seeding leaves no dangling links
+        let links: Vec<_> = blocks.iter()
+            .filter_map(|block| block.content.as_deref())
+            .flat_map(Block::page_links)
+            .collect();
+        assert_eq!(links, vec![WELCOME_PAGE_TITLE]);
+    }
+
+    #[test]
+    fn test_fresh_vault_allows_only_todays_note() {
+        let today = "2024-05-01".to_string();
+        assert!(OnboardingState::new(&[], &today, None).fresh_vault);
+        assert!(OnboardingState::new(std::slice::from_ref(&today), &today, None).fresh_vault);
+        assert!(!OnboardingState::new(&["2024-04-30".to_string()], &today, None).fresh_vault);
+
+        let seeded = OnboardingState::new(&[today.clone(), WELCOME_PAGE_TITLE.to_string()], &today, Some("page-1".to_string()));
+        assert!(!seeded.fresh_vault);
+        assert_eq!(seeded.page_count, 2);
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use tracing::{info, warn};
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::AppError;
+use crate::models::{AudioRecording, AudioTimestamp, BundleManifest, BundledRecording, BundledTimestamp};
+
+/// Name of the manifest written next to the copied recordings
+pub const MANIFEST_FILE: &str = "transcript.json";
+
+// Length of the block content kept in the manifest
+const SNIPPET_CHARS: usize = 80;
+
+/// A recording to bundle, with the files it was written to and its block
+/// timestamps paired with the content of their blocks
+pub struct RecordingExport {
+    pub recording: AudioRecording,
+    pub files: Vec<String>,
+    pub timestamps: Vec<(AudioTimestamp, Option<String>)>,
+}
+
+/// Start of a block's content on one line, for recognising it in the manifest
+fn snippet(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
+
+/// Create `out_dir` if needed and make sure files can be written to it
+pub fn check_writable(out_dir: &Path) -> Result<(), AppError> {
+    let not_writable = |e: std::io::Error| {
+        AppError::validation(format!("Can't write to {}: {}", out_dir.display(), e))
+            .with_details(serde_json::json!({ "out_dir": out_dir.display().to_string() }))
+    };
+    fs::create_dir_all(out_dir).map_err(not_writable)?;
+    let probe = out_dir.join(".gita-write-check");
+    fs::write(&probe, b"").map_err(not_writable)?;
+    fs::remove_file(&probe).map_err(not_writable)?;
+    Ok(())
+}
+
+/// Copy the recordings into `out_dir` and write the manifest beside them.
+/// Files keep their names unless two recordings share one, in which case
+/// the later copy is prefixed with its recording ID.
+pub fn write_bundle(out_dir: &Path, page_id: &str, page_title: &str, recordings: Vec<RecordingExport>) -> Result<BundleManifest, AppError> {
+    let mut used_names = HashSet::new();
+    let mut bundled = Vec::with_capacity(recordings.len());
+
+    for export in recordings {
+        let mut files = Vec::with_capacity(export.files.len());
+        for file_path in &export.files {
+            let source = Path::new(file_path);
+            let file_name = source.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .ok_or_else(|| AppError::validation(format!("Recording file has no name: {}", file_path)))?;
+            let file_name = if used_names.contains(&file_name) {
+                format!("{}-{}", export.recording.id, file_name)
+            } else {
+                file_name
+            };
+            fs::copy(source, out_dir.join(&file_name))?;
+            used_names.insert(file_name.clone());
+            files.push(file_name);
+        }
+
+        let timestamps = export.timestamps.into_iter()
+            .map(|(timestamp, content)| BundledTimestamp {
+                block_id: timestamp.block_id,
+                snippet: content.as_deref().map(snippet).unwrap_or_default(),
+                seconds: timestamp.timestamp_ms as f64 / 1000.0,
+            })
+            .collect();
+
+        bundled.push(BundledRecording {
+            recording_id: export.recording.id,
+            files,
+            recorded_at: export.recording.recorded_at,
+            duration_seconds: export.recording.duration_seconds,
+            timestamps,
+        });
+    }
+
+    let manifest = BundleManifest {
+        page_id: page_id.to_string(),
+        page_title: page_title.to_string(),
+        exported_at: Utc::now(),
+        recordings: bundled,
+    };
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| AppError::internal(format!("Failed to encode {}: {}", MANIFEST_FILE, e)))?;
+    fs::write(out_dir.join(MANIFEST_FILE), json)?;
+    Ok(manifest)
+}
+
+/// Copy all of a page's finished recordings into `out_dir` with a
+/// `transcript.json` mapping each one to its block timestamps, for archiving.
+/// Returns the output directory.
+pub async fn export_page_bundle(db: &DatomicPeerClient, page_title: &str, out_dir: &Path) -> Result<PathBuf, AppError> {
+    check_writable(out_dir)?;
+
+    let page = db.get_page_by_title(page_title).await?
+        .ok_or_else(|| AppError::not_found(format!("Page not found: {}", page_title)))?;
+
+    let mut recordings = Vec::new();
+    for recording in db.get_page_recordings(&page.id).await? {
+        // Recordings still being written or cut short have no duration yet
+        if recording.duration_seconds.is_none() {
+            warn!("Leaving unfinished recording {} out of the bundle", recording.id);
+            continue;
+        }
+
+        let segments = db.get_recording_segments(&recording.id).await?;
+        let files = if segments.is_empty() {
+            vec![recording.file_path.clone()]
+        } else {
+            segments.into_iter().map(|segment| segment.file_path).collect()
+        };
+
+        let mut timestamps = Vec::new();
+        for timestamp in db.get_recording_timestamps(&recording.id).await? {
+            let content = db.get_block(&timestamp.block_id).await?.and_then(|block| block.content);
+            timestamps.push((timestamp, content));
+        }
+
+        recordings.push(RecordingExport { recording, files, timestamps });
+    }
+
+    let out_dir = out_dir.to_path_buf();
+    let page_id = page.id.clone();
+    let title = page_title.to_string();
+    let manifest = tauri::async_runtime::spawn_blocking({
+        let out_dir = out_dir.clone();
+        move || write_bundle(&out_dir, &page_id, &title, recordings)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Export task failed: {}", e)))??;
+
+    info!("Exported {} recordings of page {} to {}", manifest.recordings.len(), page_title, out_dir.display());
+    Ok(out_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(block_id: &str, timestamp_ms: i64) -> AudioTimestamp {
+        AudioTimestamp::new(block_id, "recording-1", timestamp_ms)
+    }
+
+    #[test]
+    fn test_bundle_copies_recording_and_writes_manifest() {
+        let source_dir = tempfile::TempDir::new().unwrap();
+        let out_dir = tempfile::TempDir::new().unwrap();
+        let file_path = source_dir.path().join("recording-1.wav");
+        fs::write(&file_path, b"RIFF audio bytes").unwrap();
+
+        let recording = AudioRecording {
+            id: "recording-1".to_string(),
+            page_id: "page-1".to_string(),
+            file_path: file_path.to_string_lossy().into_owned(),
+            duration_seconds: Some(120),
+            recorded_at: Utc::now(),
+            system_audio: false,
+        };
+        let export = RecordingExport {
+            recording: recording.clone(),
+            files: vec![recording.file_path.clone()],
+            timestamps: vec![
+                (timestamp("block-1", 1_500), Some("Agenda for the\nweekly sync".to_string())),
+                (timestamp("block-2", 65_250), Some("x".repeat(100))),
+            ],
+        };
+
+        let manifest = write_bundle(out_dir.path(), "page-1", "Weekly sync", vec![export]).unwrap();
+
+        assert_eq!(fs::read(out_dir.path().join("recording-1.wav")).unwrap(), b"RIFF audio bytes");
+
+        let written: BundleManifest = serde_json::from_slice(&fs::read(out_dir.path().join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(written, manifest);
+        assert_eq!(written.page_title, "Weekly sync");
+        assert_eq!(written.recordings.len(), 1);
+
+        let bundled = &written.recordings[0];
+        assert_eq!(bundled.recording_id, "recording-1");
+        assert_eq!(bundled.files, vec!["recording-1.wav"]);
+        assert_eq!(bundled.duration_seconds, Some(120));
+        assert_eq!(bundled.timestamps, vec![
+            BundledTimestamp { block_id: "block-1".to_string(), snippet: "Agenda for the weekly sync".to_string(), seconds: 1.5 },
+            BundledTimestamp { block_id: "block-2".to_string(), snippet: format!("{}…", "x".repeat(80)), seconds: 65.25 },
+        ]);
+    }
+
+    #[test]
+    fn test_bundle_needs_writable_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("not-a-directory");
+        fs::write(&file, b"").unwrap();
+
+        assert!(check_writable(&dir.path().join("new").join("bundle")).is_ok());
+        assert!(matches!(check_writable(&file), Err(AppError::Validation { .. })));
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::config::AppConfig;
+use crate::errors::AppError;
+
+/// Paths the user picked in a native dialog, handed to the webview as opaque
+/// tokens so it never has to send a path back. Each token works once.
+#[derive(Default)]
+pub struct PathTokens {
+    paths: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl PathTokens {
+    fn paths(&self) -> MutexGuard<'_, HashMap<String, PathBuf>> {
+        self.paths.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Remember a path the user picked and return the token standing for it
+    pub fn issue(&self, path: PathBuf) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        self.paths().insert(token.clone(), path);
+        token
+    }
+
+    /// The path behind `token`, which can't be used again
+    pub fn redeem(&self, token: &str) -> Option<PathBuf> {
+        self.paths().remove(token)
+    }
+}
+
+/// Where commands may read and write the files and folders the webview names
+/// for imports, exports and backups: a path picked in a dialog, passed as its
+/// token, or one inside the data directory or the export folder the user chose
+pub struct PathPolicy {
+    roots: Vec<PathBuf>,
+}
+
+impl PathPolicy {
+    pub fn new(config: &AppConfig) -> Self {
+        let roots = std::iter::once(config.data_dir.clone()).chain(config.export_dir.clone()).collect();
+        PathPolicy { roots }
+    }
+
+    /// The path behind a token from `tokens`, or else `path_or_token` itself
+    /// once it's checked to be inside an allowed folder
+    pub fn resolve(&self, tokens: &PathTokens, path_or_token: &str) -> Result<PathBuf, AppError> {
+        match tokens.redeem(path_or_token) {
+            Some(picked) => Ok(picked),
+            None => resolve_within(Path::new(path_or_token), &self.roots),
+        }
+    }
+}
+
+/// Resolve symlinks in `path` and make sure it's inside one of `roots`. It
+/// doesn't have to exist yet, as for an export about to be written, but the
+/// part that does is resolved so a link can't lead outside.
+fn resolve_within(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, AppError> {
+    let details = serde_json::json!({ "path": path.display().to_string() });
+    let rejected = |reason: &str| AppError::validation(format!("{} {}", path.display(), reason)).with_details(details.clone());
+    if !path.is_absolute() {
+        return Err(rejected("isn't an absolute path"));
+    }
+    if path.components().any(|component| component == Component::ParentDir) {
+        return Err(rejected("can't contain .."));
+    }
+
+    // A dangling link counts as existing, since writing would follow it
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while existing.symlink_metadata().is_err() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return Err(rejected("is on a drive that doesn't exist")),
+        }
+    }
+    let mut resolved = existing.canonicalize().map_err(|_| rejected("leads through a broken link"))?;
+    resolved.extend(missing.iter().rev());
+
+    // Roots that don't exist yet can't contain anything
+    if roots.iter().filter_map(|root| root.canonicalize().ok()).any(|root| resolved.starts_with(root)) {
+        Ok(resolved)
+    } else {
+        Err(rejected("is outside the data directory and the chosen export folder"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(data_dir: &Path, export_dir: Option<&Path>) -> PathPolicy {
+        PathPolicy::new(&AppConfig {
+            data_dir: data_dir.to_path_buf(),
+            export_dir: export_dir.map(Path::to_path_buf),
+            ..AppConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_paths_inside_allowed_folders_resolve() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let export_dir = tempfile::TempDir::new().unwrap();
+        let policy = policy(data_dir.path(), Some(export_dir.path()));
+        let tokens = PathTokens::default();
+        let data_root = data_dir.path().canonicalize().unwrap();
+
+        // Exports may name files and folders that don't exist yet
+        let backup = data_dir.path().join("backups").join("today");
+        assert_eq!(policy.resolve(&tokens, backup.to_str().unwrap()).unwrap(), data_root.join("backups").join("today"));
+        let export = export_dir.path().join("notes.json");
+        assert_eq!(policy.resolve(&tokens, export.to_str().unwrap()).unwrap(), export_dir.path().canonicalize().unwrap().join("notes.json"));
+        assert_eq!(policy.resolve(&tokens, data_dir.path().to_str().unwrap()).unwrap(), data_root);
+    }
+
+    #[test]
+    fn test_traversal_and_outside_paths_are_rejected() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let elsewhere = tempfile::TempDir::new().unwrap();
+        let policy = policy(data_dir.path(), None);
+        let tokens = PathTokens::default();
+        let rejected = |path: &str| matches!(policy.resolve(&tokens, path), Err(AppError::Validation { .. }));
+
+        assert!(rejected("../../etc/passwd"));
+        assert!(rejected(data_dir.path().join("../../etc/passwd").to_str().unwrap()));
+        assert!(rejected(data_dir.path().join("backups/../../outside").to_str().unwrap()));
+        assert!(rejected("backups/today"));
+        assert!(rejected(elsewhere.path().join("export.json").to_str().unwrap()));
+        assert!(rejected(""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_out_of_allowed_folders_are_rejected() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let elsewhere = tempfile::TempDir::new().unwrap();
+        let policy = policy(data_dir.path(), None);
+        let tokens = PathTokens::default();
+
+        std::os::unix::fs::symlink(elsewhere.path(), data_dir.path().join("escape")).unwrap();
+        let through_link = data_dir.path().join("escape").join("export.json");
+        assert!(matches!(policy.resolve(&tokens, through_link.to_str().unwrap()), Err(AppError::Validation { .. })));
+
+        // Writing to a dangling link would create its target
+        std::os::unix::fs::symlink(elsewhere.path().join("missing.json"), data_dir.path().join("dangling.json")).unwrap();
+        let dangling = data_dir.path().join("dangling.json");
+        assert!(matches!(policy.resolve(&tokens, dangling.to_str().unwrap()), Err(AppError::Validation { .. })));
+        std::fs::write(elsewhere.path().join("missing.json"), b"").unwrap();
+        assert!(matches!(policy.resolve(&tokens, dangling.to_str().unwrap()), Err(AppError::Validation { .. })));
+    }
+
+    #[test]
+    fn test_picked_paths_are_used_once_by_token() {
+        let data_dir = tempfile::TempDir::new().unwrap();
+        let policy = policy(data_dir.path(), None);
+        let tokens = PathTokens::default();
+        let picked = PathBuf::from("/home/someone/Desktop/notes.json");
+
+        let token = tokens.issue(picked.clone());
+        assert_eq!(policy.resolve(&tokens, &token).unwrap(), picked);
+        assert!(matches!(policy.resolve(&tokens, &token), Err(AppError::Validation { .. })));
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use chrono::Utc;
+use crate::errors::Result;
+use crate::models::{Block, Draft};
+
+struct PendingWrite {
+    content: String,
+    generation: u64, // Bumped by every edit, so a flush can tell it was overtaken
+    drafted: bool,   // Whether this content was saved as a draft
+}
+
+/// Block content edits that haven't been written to the database yet. The
+/// editor saves on every keystroke, so only the latest content of each block
+/// is kept and written once typing pauses. Reads overlay the pending content
+/// so they never see what the database still holds.
+#[derive(Default)]
+pub struct PendingWrites {
+    writes: Mutex<HashMap<String, PendingWrite>>,
+    next_generation: AtomicU64,
+}
+
+impl PendingWrites {
+    /// How long a block has to go without edits before its content is written
+    pub const QUIET_PERIOD: Duration = Duration::from_millis(300);
+
+    /// How often edits still waiting to be written are saved as drafts. Typing
+    /// without a pause keeps an edit pending for as long as it goes on.
+    pub const DRAFT_INTERVAL: Duration = Duration::from_secs(5);
+
+    fn writes(&self) -> std::sync::MutexGuard<'_, HashMap<String, PendingWrite>> {
+        self.writes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the block's pending content, returning the edit's generation
+    /// to flush it with once the quiet period has passed
+    pub fn queue(&self, block_id: &str, content: String) -> u64 {
+        let generation = self.next_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.writes().insert(block_id.to_string(), PendingWrite { content, generation, drafted: false });
+        generation
+    }
+
+    /// Content waiting to be written for a block
+    pub fn content(&self, block_id: &str) -> Option<String> {
+        self.writes().get(block_id).map(|write| write.content.clone())
+    }
+
+    /// Show pending content in blocks read from the database
+    pub fn overlay(&self, blocks: &mut [Block]) {
+        let writes = self.writes();
+        if writes.is_empty() {
+            return;
+        }
+        for block in blocks {
+            if let Some(write) = writes.get(&block.id) {
+                block.content = Some(write.content.clone());
+            }
+        }
+    }
+
+    /// Drop an edit once it's written, unless a newer one arrived meanwhile.
+    /// Until then reads keep overlaying it, so they can't catch the database
+    /// before the write lands.
+    fn written(&self, block_id: &str, generation: u64) {
+        let mut writes = self.writes();
+        if writes.get(block_id).is_some_and(|write| write.generation == generation) {
+            writes.remove(block_id);
+        }
+    }
+
+    /// Write a block's content if `generation` is still its latest edit,
+    /// keeping it pending if the write fails. Returns whether `write` was called.
+    pub async fn flush_block<F, Fut>(&self, block_id: &str, generation: u64, write: F) -> Result<bool>
+    where
+        F: FnOnce(String, String) -> Fut,
+        Fut: Future<Output = Result<bool>>,
+    {
+        let content = match self.writes().get(block_id) {
+            Some(pending) if pending.generation == generation => pending.content.clone(),
+            _ => return Ok(false), // Overtaken by a newer edit, which flushes itself
+        };
+        write(block_id.to_string(), content).await?;
+        self.written(block_id, generation);
+        Ok(true)
+    }
+
+    /// Save pending edits that changed since the last call as drafts.
+    /// Returns how many were saved.
+    pub async fn save_drafts<F, Fut>(&self, save: F) -> Result<usize>
+    where
+        F: FnOnce(Vec<Draft>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let saved_at = Utc::now();
+        let (drafts, generations): (Vec<Draft>, Vec<u64>) = self.writes().iter()
+            .filter(|(_, write)| !write.drafted)
+            .map(|(block_id, write)| {
+                let draft = Draft { block_id: block_id.clone(), content: write.content.clone(), saved_at };
+                (draft, write.generation)
+            })
+            .unzip();
+        if drafts.is_empty() {
+            return Ok(0);
+        }
+
+        let saved: Vec<(String, u64)> = drafts.iter().map(|draft| draft.block_id.clone()).zip(generations).collect();
+        save(drafts).await?;
+        // Edits made while saving are newer than their draft and saved next time
+        let mut writes = self.writes();
+        for (block_id, generation) in &saved {
+            if let Some(write) = writes.get_mut(block_id).filter(|write| write.generation == *generation) {
+                write.drafted = true;
+            }
+        }
+        Ok(saved.len())
+    }
+
+    /// Write every pending edit now. Edits that fail stay pending for the
+    /// next flush, and the first error is returned after trying them all.
+    /// Returns how many blocks were written.
+    pub async fn flush<F, Fut>(&self, mut write: F) -> Result<usize>
+    where
+        F: FnMut(String, String) -> Fut,
+        Fut: Future<Output = Result<bool>>,
+    {
+        let pending: Vec<(String, String, u64)> = self.writes().iter()
+            .map(|(block_id, write)| (block_id.clone(), write.content.clone(), write.generation))
+            .collect();
+
+        let mut flushed = 0;
+        let mut first_error = None;
+        for (block_id, content, generation) in pending {
+            match write(block_id.clone(), content).await {
+                Ok(_) => {
+                    self.written(&block_id, generation);
+                    flushed += 1;
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(flushed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::errors::DatomicError;
+
+    fn block(id: &str, content: &str) -> Block {
+        Block {
+            id: id.to_string(),
+            content: Some(content.to_string()),
+            parent_id: Some("page".to_string()),
+            order: 0,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        }
+    }
+
+    /// Stand-in for the database, recording every write
+    #[derive(Default)]
+    struct Store {
+        content: Mutex<HashMap<String, String>>,
+        writes: Mutex<usize>,
+        drafts: Mutex<HashMap<String, Draft>>,
+    }
+
+    impl Store {
+        async fn write(&self, block_id: String, content: String) -> Result<bool> {
+            *self.writes.lock().unwrap() += 1;
+            Ok(self.content.lock().unwrap().insert(block_id, content.clone()).as_ref() != Some(&content))
+        }
+
+        async fn save_drafts(&self, drafts: Vec<Draft>) -> Result<()> {
+            let mut saved = self.drafts.lock().unwrap();
+            for draft in drafts {
+                saved.insert(draft.block_id.clone(), draft);
+            }
+            Ok(())
+        }
+
+        fn read(&self, pending: &PendingWrites, ids: &[&str]) -> Vec<Block> {
+            let content = self.content.lock().unwrap();
+            let mut blocks: Vec<Block> = ids.iter()
+                .map(|id| block(id, content.get(*id).map_or("", String::as_str)))
+                .collect();
+            pending.overlay(&mut blocks);
+            blocks
+        }
+    }
+
+    fn contents(blocks: &[Block]) -> Vec<&str> {
+        blocks.iter().map(|block| block.content.as_deref().unwrap_or_default()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_reads_see_pending_content_between_writes() {
+        let pending = PendingWrites::default();
+        let store = Store::default();
+
+        // Typing a word queues one edit per keystroke
+        let mut generations = Vec::new();
+        for typed in ["H", "He", "Hel", "Hell", "Hello"] {
+            generations.push(pending.queue("a", typed.to_string()));
+            assert_eq!(contents(&store.read(&pending, &["a", "b"])), vec![typed, ""]);
+        }
+
+        // Timers of the overtaken keystrokes don't write anything
+        for &generation in &generations[..4] {
+            assert!(!pending.flush_block("a", generation, |id, content| store.write(id, content)).await.unwrap());
+        }
+        assert_eq!(*store.writes.lock().unwrap(), 0);
+
+        // Only the last one does, after which reads come from the store
+        assert!(pending.flush_block("a", generations[4], |id, content| store.write(id, content)).await.unwrap());
+        assert_eq!(*store.writes.lock().unwrap(), 1);
+        assert_eq!(pending.content("a"), None);
+        assert_eq!(contents(&store.read(&pending, &["a"])), vec!["Hello"]);
+
+        // An edit arriving while a write is in flight stays pending
+        let generation = pending.queue("a", "Hello!".to_string());
+        pending.flush_block("a", generation, |id, content| {
+            pending.queue("a", "Hello!!".to_string());
+            store.write(id, content)
+        }).await.unwrap();
+        assert_eq!(pending.content("a").as_deref(), Some("Hello!!"));
+        assert_eq!(contents(&store.read(&pending, &["a"])), vec!["Hello!!"]);
+    }
+
+    #[tokio::test]
+    async fn test_flush_writes_everything_pending() {
+        let pending = PendingWrites::default();
+        let store = Store::default();
+        pending.queue("a", "first draft".to_string());
+        pending.queue("b", "other block".to_string());
+        pending.queue("a", "final draft".to_string());
+
+        // Shutdown flushes without waiting for the quiet period
+        let flushed = pending.flush(|id, content| store.write(id, content)).await.unwrap();
+        assert_eq!(flushed, 2);
+        assert_eq!((pending.content("a"), pending.content("b")), (None, None));
+        assert_eq!(store.content.lock().unwrap().get("a").map(String::as_str), Some("final draft"));
+        assert_eq!(store.content.lock().unwrap().get("b").map(String::as_str), Some("other block"));
+
+        // Nothing is left to write afterwards
+        assert_eq!(pending.flush(|id, content| store.write(id, content)).await.unwrap(), 0);
+        assert_eq!(*store.writes.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_content_pending() {
+        let pending = PendingWrites::default();
+        pending.queue("a", "kept".to_string());
+        pending.queue("b", "written".to_string());
+
+        let result = pending.flush(|id, _| async move {
+            if id == "a" { Err(DatomicError::query_error("database unavailable")) } else { Ok(true) }
+        }).await;
+        assert!(result.is_err());
+        assert_eq!(pending.content("a").as_deref(), Some("kept"));
+        assert_eq!(pending.content("b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_drafts_recover_edits_lost_in_a_crash() {
+        let store = Store::default();
+        let written_at = Utc::now() - chrono::Duration::seconds(10);
+        store.write("a".to_string(), "Hello".to_string()).await.unwrap();
+        store.write("b".to_string(), "Other".to_string()).await.unwrap();
+
+        // Typing without a pause keeps the edit pending between draft saves
+        let pending = PendingWrites::default();
+        pending.queue("a", "Hello wor".to_string());
+        assert_eq!(pending.save_drafts(|drafts| store.save_drafts(drafts)).await.unwrap(), 1);
+        assert_eq!(pending.save_drafts(|drafts| store.save_drafts(drafts)).await.unwrap(), 0);
+        pending.queue("a", "Hello world".to_string());
+        assert_eq!(pending.save_drafts(|drafts| store.save_drafts(drafts)).await.unwrap(), 1);
+
+        // The app dies before the edit is written
+        drop(pending);
+        assert_eq!(store.content.lock().unwrap()["a"], "Hello");
+
+        // On the next start the draft is newer than the block it belongs to
+        let blocks = vec![
+            Block { updated_at: written_at, ..block("a", "Hello") },
+            Block { updated_at: written_at, ..block("b", "Other") },
+        ];
+        let drafts: Vec<Draft> = store.drafts.lock().unwrap().values().cloned().collect();
+        let unsaved = Draft::unsaved(drafts.clone(), &blocks);
+        assert_eq!(unsaved.len(), 1);
+        assert_eq!((unsaved[0].block_id.as_str(), unsaved[0].content.as_str()), ("a", "Hello world"));
+
+        // Applying it writes the content, after which the draft is out of date
+        store.write("a".to_string(), unsaved[0].content.clone()).await.unwrap();
+        let rewritten = vec![block("a", "Hello world")];
+        assert!(Draft::unsaved(drafts, &rewritten).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+use tauri_plugin_shell::ShellExt;
+use tracing::info;
+use crate::errors::AppError;
+
+/// Resolve `path` and make sure it exists inside one of `roots`, so the
+/// file manager is only ever pointed at the app's own files
+pub fn ensure_within(path: &Path, roots: &[PathBuf]) -> Result<PathBuf, AppError> {
+    let details = serde_json::json!({ "path": path.display().to_string() });
+    let resolved = path.canonicalize().map_err(|_| {
+        AppError::not_found(format!("File not found: {}", path.display())).with_details(details.clone())
+    })?;
+    // Roots that don't exist yet can't contain anything
+    if roots.iter().filter_map(|root| root.canonicalize().ok()).any(|root| resolved.starts_with(root)) {
+        Ok(resolved)
+    } else {
+        Err(AppError::validation(format!("{} is outside the app's data directories", path.display())).with_details(details))
+    }
+}
+
+/// Program and arguments showing `path` in the file manager, with the file
+/// selected where the platform supports it
+fn reveal_command(path: &Path) -> (&'static str, Vec<OsString>) {
+    if cfg!(target_os = "windows") {
+        let mut select = OsString::from("/select,");
+        select.push(path);
+        ("explorer", vec![select])
+    } else if cfg!(target_os = "macos") {
+        ("open", vec!["-R".into(), path.into()])
+    } else {
+        // xdg-open can't select a file, so open the folder holding it
+        ("xdg-open", vec![path.parent().unwrap_or(path).into()])
+    }
+}
+
+fn open_command(dir: &Path) -> (&'static str, Vec<OsString>) {
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    (program, vec![dir.into()])
+}
+
+fn spawn(app_handle: &AppHandle, (program, args): (&'static str, Vec<OsString>)) -> Result<(), AppError> {
+    app_handle.shell().command(program).args(args).spawn()
+        .map(|_| ())
+        .map_err(|e| AppError::internal(format!("Failed to start {}: {}", program, e)))
+}
+
+/// Show a file in the OS file manager, selecting it where possible
+pub fn reveal_file(app_handle: &AppHandle, file: &Path, roots: &[PathBuf]) -> Result<(), AppError> {
+    let file = ensure_within(file, roots)?;
+    info!("Revealing {}", file.display());
+    spawn(app_handle, reveal_command(&file))
+}
+
+/// Open a directory in the OS file manager
+pub fn open_dir(app_handle: &AppHandle, dir: &Path, roots: &[PathBuf]) -> Result<(), AppError> {
+    let dir = ensure_within(dir, roots)?;
+    info!("Opening {}", dir.display());
+    spawn(app_handle, open_command(&dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_only_existing_files_inside_roots_are_allowed() {
+        let root = tempfile::TempDir::new().unwrap();
+        let elsewhere = tempfile::TempDir::new().unwrap();
+        let roots = vec![root.path().to_path_buf()];
+        let inside = root.path().join("recording.wav");
+        let outside = elsewhere.path().join("secret.txt");
+        fs::write(&inside, b"RIFF").unwrap();
+        fs::write(&outside, b"").unwrap();
+
+        assert_eq!(ensure_within(&inside, &roots).unwrap(), inside.canonicalize().unwrap());
+        assert!(ensure_within(root.path(), &roots).is_ok());
+        assert!(matches!(ensure_within(&outside, &roots), Err(AppError::Validation { .. })));
+
+        // Climbing out of a root with `..` is caught once the path is resolved
+        let escape = root.path().join("..").join(elsewhere.path().file_name().unwrap()).join("secret.txt");
+        assert!(matches!(ensure_within(&escape, &roots), Err(AppError::Validation { .. })));
+
+        assert!(matches!(ensure_within(&root.path().join("missing.wav"), &roots), Err(AppError::NotFound { .. })));
+    }
+
+    #[test]
+    fn test_reveal_selects_file_where_supported() {
+        let (program, args) = reveal_command(Path::new("/data/recordings/a.wav"));
+        if cfg!(target_os = "windows") {
+            assert_eq!((program, args), ("explorer", vec![OsString::from("/select,/data/recordings/a.wav")]));
+        } else if cfg!(target_os = "macos") {
+            assert_eq!((program, args), ("open", vec![OsString::from("-R"), OsString::from("/data/recordings/a.wav")]));
+        } else {
+            assert_eq!((program, args), ("xdg-open", vec![OsString::from("/data/recordings")]));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::agc::db_to_gain;
+
+/// When a pause in the input is long enough to start a new recording file
+#[derive(Debug, Clone, Copy)]
+pub struct SilenceSplit {
+    pub threshold_db: f32,  // RMS level below which the input counts as silent, in dBFS
+    pub min_silence_ms: u64,
+}
+
+/// Watches the RMS level of incoming buffers and reports when the input has
+/// stayed below the threshold for long enough. Only one split is reported per
+/// pause, and none before the first sound, so a recording that starts quiet
+/// doesn't produce a string of empty files.
+pub struct SilenceDetector {
+    threshold: f32, // Linear RMS
+    min_silence_frames: u64,
+    silent_frames: u64,
+    armed: bool, // Sound was heard since the last split
+}
+
+impl SilenceDetector {
+    pub fn new(split: SilenceSplit, sample_rate: u32) -> Self {
+        SilenceDetector {
+            threshold: db_to_gain(split.threshold_db),
+            min_silence_frames: sample_rate as u64 * split.min_silence_ms / 1000,
+            silent_frames: 0,
+            armed: false,
+        }
+    }
+
+    /// Feed a buffer of interleaved samples. Returns true when the pause has
+    /// just become long enough to split the recording before this buffer.
+    pub fn feed(&mut self, samples: &[f32], channels: u16) -> bool {
+        if samples.is_empty() {
+            return false;
+        }
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+        if rms >= self.threshold {
+            self.silent_frames = 0;
+            self.armed = true;
+            return false;
+        }
+
+        self.silent_frames += (samples.len() / channels.max(1) as usize) as u64;
+        if self.armed && self.silent_frames >= self.min_silence_frames {
+            self.armed = false;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 16000;
+
+    // 100ms buffers, like the ones a capture stream delivers
+    fn loud() -> Vec<f32> {
+        (0..1600).map(|i| 0.3 * (i as f32 * 0.1).sin()).collect()
+    }
+
+    fn quiet() -> Vec<f32> {
+        (0..1600).map(|i| 0.001 * (i as f32 * 0.1).sin()).collect()
+    }
+
+    fn detector() -> SilenceDetector {
+        SilenceDetector::new(SilenceSplit { threshold_db: -40.0, min_silence_ms: 500 }, SAMPLE_RATE)
+    }
+
+    #[test]
+    fn test_split_after_long_pause() {
+        let mut detector = detector();
+        assert!(!detector.feed(&loud(), 1));
+
+        // Four quiet buffers make 400ms, the fifth reaches the minimum
+        let splits: Vec<bool> = (0..5).map(|_| detector.feed(&quiet(), 1)).collect();
+        assert_eq!(splits, vec![false, false, false, false, true]);
+
+        // The rest of the pause doesn't split again
+        assert!((0..20).all(|_| !detector.feed(&quiet(), 1)));
+
+        // Sound re-arms the detector for the next pause
+        assert!(!detector.feed(&loud(), 1));
+        assert_eq!((0..5).filter(|_| detector.feed(&quiet(), 1)).count(), 1);
+    }
+
+    #[test]
+    fn test_short_pauses_do_not_split() {
+        let mut detector = detector();
+        for _ in 0..10 {
+            assert!(!detector.feed(&loud(), 1));
+            for _ in 0..4 {
+                assert!(!detector.feed(&quiet(), 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_leading_silence_does_not_split() {
+        let mut detector = detector();
+        assert!((0..20).all(|_| !detector.feed(&quiet(), 1)));
+        assert!(!detector.feed(&loud(), 1));
+    }
+
+    #[test]
+    fn test_pause_is_measured_in_frames() {
+        // A stereo buffer of 1600 samples holds 50ms
+        let mut detector = detector();
+        detector.feed(&loud(), 2);
+        let splits = (0..10).map(|_| detector.feed(&quiet(), 2)).position(|split| split);
+        assert_eq!(splits, Some(9));
+    }
+}
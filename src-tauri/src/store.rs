@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use serde_json::Value;
+use crate::database_peer_complete::DatomicPeerClient;
+use crate::errors::{DatomicError, Result};
+use crate::models::{AudioMeta, AudioRecording, AudioTimestamp, Block, CreateBlockRequest, RecordingSegment};
+
+/// Future returned by `Store` methods, boxed so the trait can be used as `dyn Store`
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// The block and recording operations the commands need from a storage
+/// backend. The backend is chosen at startup from `AppConfig::backend` and
+/// managed as `Arc<dyn Store>`. A backend that can't do something returns
+/// `DatomicError::Unsupported` rather than pretending it worked.
+pub trait Store: Send + Sync {
+    fn create_block(&self, block_data: CreateBlockRequest, audio_meta: Option<AudioMeta>) -> StoreFuture<'_, Block>;
+
+    /// Replace a block's content, returning whether anything changed
+    fn update_block_content<'a>(&'a self, block_id: &'a str, content: &'a str) -> StoreFuture<'a, bool>;
+
+    /// Delete a block and every block below it, returning how many were deleted
+    fn delete_block<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, usize>;
+
+    fn get_block<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, Option<Block>>;
+
+    /// The blocks of a day's note, creating its page first if `create_if_missing`
+    fn get_daily_note<'a>(&'a self, date: &'a str, create_if_missing: bool) -> StoreFuture<'a, Vec<Block>>;
+
+    /// Today's daily note in a UTC offset given in minutes east of UTC, creating its page if needed
+    fn get_today_daily_note(&self, tz_offset_minutes: i32) -> StoreFuture<'_, Vec<Block>>;
+
+    /// The page of a day's note, created if nobody has opened that day yet
+    fn ensure_daily_note_page<'a>(&'a self, date: &'a str) -> StoreFuture<'a, Block>;
+
+    fn get_page_by_title<'a>(&'a self, title: &'a str) -> StoreFuture<'a, Option<Block>>;
+
+    /// Direct children of a block or page, in order
+    fn get_block_children<'a>(&'a self, parent_id: &'a str) -> StoreFuture<'a, Vec<Block>>;
+
+    /// Drop the draft kept for a block's unsaved edit, returning whether there was one
+    fn delete_draft<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, bool>;
+
+    fn create_audio_recording<'a>(&'a self, recording: &'a AudioRecording) -> StoreFuture<'a, ()>;
+
+    fn delete_audio_recording<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, ()>;
+
+    fn update_recording_duration<'a>(&'a self, recording_id: &'a str, duration_seconds: i32) -> StoreFuture<'a, ()>;
+
+    fn set_recording_status<'a>(&'a self, recording_id: &'a str, status: &'a str) -> StoreFuture<'a, ()>;
+
+    fn get_recording<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Option<AudioRecording>>;
+
+    /// Recordings that never got a duration, because they are still running or were interrupted
+    fn get_unfinished_recordings(&self) -> StoreFuture<'_, Vec<AudioRecording>>;
+
+    fn create_recording_segments<'a>(&'a self, recording_id: &'a str, segments: &'a [RecordingSegment]) -> StoreFuture<'a, ()>;
+
+    /// The files a recording was split into, in order; empty for a single-file recording
+    fn get_recording_segments<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Vec<RecordingSegment>>;
+
+    fn attach_timestamp<'a>(&'a self, block_id: &'a str, recording_id: &'a str, timestamp_ms: i64) -> StoreFuture<'a, AudioTimestamp>;
+
+    /// Move a block's timestamp from one recording to another
+    fn relink_timestamp<'a>(&'a self, block_id: &'a str, old_recording_id: &'a str, new_recording_id: &'a str) -> StoreFuture<'a, ()>;
+
+    fn get_block_audio_timestamp<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, Option<AudioTimestamp>>;
+
+    /// Every block timestamp in a recording, earliest first
+    fn get_recording_timestamps<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Vec<AudioTimestamp>>;
+}
+
+impl Store for DatomicPeerClient {
+    fn create_block(&self, block_data: CreateBlockRequest, audio_meta: Option<AudioMeta>) -> StoreFuture<'_, Block> {
+        Box::pin(self.create_block(block_data, audio_meta))
+    }
+
+    fn update_block_content<'a>(&'a self, block_id: &'a str, content: &'a str) -> StoreFuture<'a, bool> {
+        let updates = HashMap::from([("content".to_string(), Value::String(content.to_string()))]);
+        Box::pin(self.update_block(block_id, updates))
+    }
+
+    fn delete_block<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, usize> {
+        Box::pin(self.delete_block(block_id))
+    }
+
+    fn get_block<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, Option<Block>> {
+        Box::pin(self.get_block(block_id))
+    }
+
+    fn get_daily_note<'a>(&'a self, date: &'a str, create_if_missing: bool) -> StoreFuture<'a, Vec<Block>> {
+        Box::pin(self.get_daily_note(date, create_if_missing))
+    }
+
+    fn get_today_daily_note(&self, tz_offset_minutes: i32) -> StoreFuture<'_, Vec<Block>> {
+        Box::pin(self.get_today_daily_note(tz_offset_minutes))
+    }
+
+    fn ensure_daily_note_page<'a>(&'a self, date: &'a str) -> StoreFuture<'a, Block> {
+        Box::pin(self.ensure_daily_note_page(date))
+    }
+
+    fn get_page_by_title<'a>(&'a self, title: &'a str) -> StoreFuture<'a, Option<Block>> {
+        Box::pin(self.get_page_by_title(title))
+    }
+
+    fn get_block_children<'a>(&'a self, parent_id: &'a str) -> StoreFuture<'a, Vec<Block>> {
+        Box::pin(self.get_page_blocks(parent_id))
+    }
+
+    fn delete_draft<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, bool> {
+        Box::pin(self.delete_draft(block_id))
+    }
+
+    fn create_audio_recording<'a>(&'a self, recording: &'a AudioRecording) -> StoreFuture<'a, ()> {
+        Box::pin(self.create_audio_recording(recording))
+    }
+
+    fn delete_audio_recording<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(self.delete_audio_recording(recording_id))
+    }
+
+    fn update_recording_duration<'a>(&'a self, recording_id: &'a str, duration_seconds: i32) -> StoreFuture<'a, ()> {
+        Box::pin(self.update_recording_duration(recording_id, duration_seconds))
+    }
+
+    fn set_recording_status<'a>(&'a self, recording_id: &'a str, status: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(self.set_recording_status(recording_id, status))
+    }
+
+    fn get_recording<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Option<AudioRecording>> {
+        Box::pin(self.get_recording(recording_id))
+    }
+
+    fn get_unfinished_recordings(&self) -> StoreFuture<'_, Vec<AudioRecording>> {
+        Box::pin(self.get_unfinished_recordings())
+    }
+
+    fn create_recording_segments<'a>(&'a self, recording_id: &'a str, segments: &'a [RecordingSegment]) -> StoreFuture<'a, ()> {
+        Box::pin(self.create_recording_segments(recording_id, segments))
+    }
+
+    fn get_recording_segments<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Vec<RecordingSegment>> {
+        Box::pin(self.get_recording_segments(recording_id))
+    }
+
+    fn attach_timestamp<'a>(&'a self, block_id: &'a str, recording_id: &'a str, timestamp_ms: i64) -> StoreFuture<'a, AudioTimestamp> {
+        Box::pin(self.attach_timestamp(block_id, recording_id, timestamp_ms))
+    }
+
+    fn relink_timestamp<'a>(&'a self, block_id: &'a str, old_recording_id: &'a str, new_recording_id: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(self.relink_timestamp(block_id, old_recording_id, new_recording_id))
+    }
+
+    fn get_block_audio_timestamp<'a>(&'a self, block_id: &'a str) -> StoreFuture<'a, Option<AudioTimestamp>> {
+        Box::pin(self.get_block_audio_timestamp(block_id))
+    }
+
+    fn get_recording_timestamps<'a>(&'a self, recording_id: &'a str) -> StoreFuture<'a, Vec<AudioTimestamp>> {
+        Box::pin(self.get_recording_timestamps(recording_id))
+    }
+}
+
+/// The SQLite backend. It isn't implemented yet, so every operation fails
+/// with `DatomicError::Unsupported`.
+pub struct SqliteStore;
+
+fn unsupported<'a, T: Send + 'a>(operation: &str) -> StoreFuture<'a, T> {
+    let e = DatomicError::unsupported(format!("The SQLite backend doesn't support {} yet", operation));
+    Box::pin(async move { Err(e) })
+}
+
+impl Store for SqliteStore {
+    fn create_block(&self, _block_data: CreateBlockRequest, _audio_meta: Option<AudioMeta>) -> StoreFuture<'_, Block> {
+        unsupported("creating blocks")
+    }
+
+    fn update_block_content<'a>(&'a self, _block_id: &'a str, _content: &'a str) -> StoreFuture<'a, bool> {
+        unsupported("updating blocks")
+    }
+
+    fn delete_block<'a>(&'a self, _block_id: &'a str) -> StoreFuture<'a, usize> {
+        unsupported("deleting blocks")
+    }
+
+    fn get_block<'a>(&'a self, _block_id: &'a str) -> StoreFuture<'a, Option<Block>> {
+        unsupported("reading blocks")
+    }
+
+    fn get_daily_note<'a>(&'a self, _date: &'a str, _create_if_missing: bool) -> StoreFuture<'a, Vec<Block>> {
+        unsupported("reading daily notes")
+    }
+
+    fn get_today_daily_note(&self, _tz_offset_minutes: i32) -> StoreFuture<'_, Vec<Block>> {
+        unsupported("reading daily notes")
+    }
+
+    fn ensure_daily_note_page<'a>(&'a self, _date: &'a str) -> StoreFuture<'a, Block> {
+        unsupported("creating daily notes")
+    }
+
+    fn get_page_by_title<'a>(&'a self, _title: &'a str) -> StoreFuture<'a, Option<Block>> {
+        unsupported("reading pages")
+    }
+
+    fn get_block_children<'a>(&'a self, _parent_id: &'a str) -> StoreFuture<'a, Vec<Block>> {
+        unsupported("reading blocks")
+    }
+
+    fn delete_draft<'a>(&'a self, _block_id: &'a str) -> StoreFuture<'a, bool> {
+        unsupported("deleting drafts")
+    }
+
+    fn create_audio_recording<'a>(&'a self, _recording: &'a AudioRecording) -> StoreFuture<'a, ()> {
+        unsupported("creating recordings")
+    }
+
+    fn delete_audio_recording<'a>(&'a self, _recording_id: &'a str) -> StoreFuture<'a, ()> {
+        unsupported("deleting recordings")
+    }
+
+    fn update_recording_duration<'a>(&'a self, _recording_id: &'a str, _duration_seconds: i32) -> StoreFuture<'a, ()> {
+        unsupported("updating recordings")
+    }
+
+    fn set_recording_status<'a>(&'a self, _recording_id: &'a str, _status: &'a str) -> StoreFuture<'a, ()> {
+        unsupported("updating recordings")
+    }
+
+    fn get_recording<'a>(&'a self, _recording_id: &'a str) -> StoreFuture<'a, Option<AudioRecording>> {
+        unsupported("reading recordings")
+    }
+
+    fn get_unfinished_recordings(&self) -> StoreFuture<'_, Vec<AudioRecording>> {
+        unsupported("reading recordings")
+    }
+
+    fn create_recording_segments<'a>(&'a self, _recording_id: &'a str, _segments: &'a [RecordingSegment]) -> StoreFuture<'a, ()> {
+        unsupported("creating recording segments")
+    }
+
+    fn get_recording_segments<'a>(&'a self, _recording_id: &'a str) -> StoreFuture<'a, Vec<RecordingSegment>> {
+        unsupported("reading recording segments")
+    }
+
+    fn get_block_audio_timestamp<'a>(&'a self, _block_id: &'a str) -> StoreFuture<'a, Option<AudioTimestamp>> {
+        unsupported("reading timestamps")
+    }
+
+    fn get_recording_timestamps<'a>(&'a self, _recording_id: &'a str) -> StoreFuture<'a, Vec<AudioTimestamp>> {
+        unsupported("reading timestamps")
+    }
+
+    fn attach_timestamp<'a>(&'a self, _block_id: &'a str, _recording_id: &'a str, _timestamp_ms: i64) -> StoreFuture<'a, AudioTimestamp> {
+        unsupported("attaching timestamps")
+    }
+
+    fn relink_timestamp<'a>(&'a self, _block_id: &'a str, _old_recording_id: &'a str, _new_recording_id: &'a str) -> StoreFuture<'a, ()> {
+        unsupported("relinking timestamps")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_store_is_unsupported() {
+        let store: &dyn Store = &SqliteStore;
+        assert!(matches!(store.get_block("b1").await, Err(DatomicError::Unsupported(_))));
+        assert!(matches!(store.get_unfinished_recordings().await, Err(DatomicError::Unsupported(_))));
+    }
+}
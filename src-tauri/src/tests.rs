@@ -9,8 +9,8 @@ mod tests {
 
     use crate::config::AppConfig;
     use crate::errors::{DatomicError, RetryConfig, with_retry};
-    use crate::datomic_schema::gita_schema_edn;
-    use crate::models::{Block, AudioDevice, AudioRecording, CreateBlockRequest, AudioTimestamp};
+    use crate::datomic_schema::{gita_schema_edn, diff_schema, schema_attribute_idents};
+    use crate::models::{Block, AudioDevice, AudioMeta, AudioRecording, ContentRevision, CreateBlockRequest, AudioTimestamp, PageStat, RecentEdit, RecordingSegment, SearchPage, SilenceInterval, SilenceTrim};
     use chrono::Utc; // For Utc::now()
     use uuid::Uuid; // For Uuid::new_v4()
     
@@ -62,11 +62,12 @@ mod tests {
         let result: Result<i32, String> = with_retry( // Explicit type for clarity
             || {
                 attempt_count += 1;
-                if attempt_count < 3 {
-                    Err("Temporary failure".to_string())
+                let result = if attempt_count < 3 {
+                    Err(DatomicError::connection_error("Temporary failure"))
                 } else {
                     Ok(42)
-                }
+                };
+                async move { result }
             },
             &config,
             "test_operation",
@@ -81,7 +82,7 @@ mod tests {
         let result_fail: Result<i32, DatomicError> = with_retry( // Explicit type
             || {
                 attempt_count += 1;
-                Err(DatomicError::InternalError("Always fails".to_string())) // Use DatomicError
+                async { Err(DatomicError::InternalError("Always fails".to_string())) } // Use DatomicError
             },
             &config,
             "test_operation_fail",
@@ -124,6 +125,31 @@ mod tests {
         assert!(schema_str.contains(":timestamp/timestamp_ms")); // Corrected to match schema
     }
     
+    /// Test schema diff against a mocked set of installed idents
+    #[tokio::test]
+    async fn test_schema_diff() {
+        let all_idents = schema_attribute_idents();
+        assert!(all_idents.contains(&":block/id".to_string()));
+        
+        // Database with only the block attributes plus an unrelated built-in ident
+        let existing: std::collections::HashSet<String> = all_idents.iter()
+            .filter(|ident| ident.starts_with(":block/"))
+            .cloned()
+            .chain(std::iter::once(":db/add".to_string()))
+            .collect();
+        
+        let diff = diff_schema(&existing);
+        assert!(diff.present.iter().all(|ident| ident.starts_with(":block/")));
+        assert!(diff.missing.contains(&":audio/id".to_string()));
+        assert!(diff.missing.contains(&":timestamp/timestamp_ms".to_string()));
+        assert!(!diff.present.contains(&":db/add".to_string()));
+        assert_eq!(diff.present.len() + diff.missing.len(), all_idents.len());
+        
+        // Fully installed schema has nothing missing
+        let full: std::collections::HashSet<String> = all_idents.into_iter().collect();
+        assert!(diff_schema(&full).missing.is_empty());
+    }
+    
     /// Test model serialization/deserialization
     #[tokio::test]
     async fn test_model_serialization() {
@@ -144,6 +170,7 @@ mod tests {
             // audio_file: None, // Block model doesn't have audio_file
             is_page: false, // Added missing field
             audio_timestamp: None,
+            source: None,
         };
         
         // Test JSON serialization
@@ -238,6 +265,7 @@ mod tests {
             name: "Test Audio Device".to_string(),
             is_default: true,
             device_type: "input".to_string(), // Added missing field
+            default_config: None,
         };
         
         // Test serialization
@@ -263,6 +291,7 @@ mod tests {
             // duration_seconds: Some(120.5), // Model has Option<u32>
             duration_seconds: Some(120),
             recorded_at: Utc::now(),
+            system_audio: false,
         };
         
         // Test serialization
@@ -275,6 +304,39 @@ mod tests {
         assert_eq!(recording.duration_seconds, deserialized.duration_seconds);
     }
     
+    /// Test page and parent invariants of create block requests
+    #[test]
+    fn test_create_block_request_invariants() {
+        use crate::errors::AppError;
+
+        let request = |is_page: bool, page_title: Option<&str>, parent_id: Option<&str>| CreateBlockRequest {
+            content: None,
+            is_page,
+            page_title: page_title.map(str::to_string),
+            parent_id: parent_id.map(str::to_string),
+            order: 0,
+            source: None,
+        };
+        let rejected_field = |request: CreateBlockRequest| match request.validate() {
+            Err(DatomicError::InvalidBlockRequest { field, .. }) => field,
+            other => panic!("Expected InvalidBlockRequest, got {:?}", other),
+        };
+
+        assert!(request(true, Some("Journal"), None).validate().is_ok());
+        assert!(request(false, None, Some("page-1")).validate().is_ok());
+
+        assert_eq!(rejected_field(request(true, None, None)), "page_title");
+        assert_eq!(rejected_field(request(true, Some("  "), None)), "page_title");
+        assert_eq!(rejected_field(request(true, Some("Journal"), Some("page-1"))), "parent_id");
+        assert_eq!(rejected_field(request(false, None, None)), "parent_id");
+        assert_eq!(rejected_field(request(false, Some("Journal"), Some(""))), "parent_id");
+
+        // The command error names the field for the UI
+        let json = serde_json::to_value(AppError::from(request(false, None, None).validate().unwrap_err())).unwrap();
+        assert_eq!(json["code"], "validation");
+        assert_eq!(json["details"]["field"], "parent_id");
+    }
+
     /// Test create block request validation
     #[tokio::test]
     async fn test_create_block_request() {
@@ -287,6 +349,7 @@ mod tests {
             parent_id: None,
             // order: Some(0), // Model has i32
             order: 0,
+            source: None,
         };
         
         // Test serialization
@@ -301,30 +364,603 @@ mod tests {
         assert_eq!(request.page_title, deserialized.page_title);
     }
     
+    /// Test mapping recording timestamps onto rotated segments
+    #[tokio::test]
+    async fn test_locate_recording_segment() {
+        let segments: Vec<RecordingSegment> = (0..3)
+            .map(|index| RecordingSegment {
+                index,
+                file_path: format!("rec-{:03}.wav", index),
+                start_ms: index as i64 * 60_000,
+                duration_ms: if index < 2 { 60_000 } else { 30_000 },
+            })
+            .collect();
+        
+        assert_eq!(RecordingSegment::locate(&segments, 0).unwrap().index, 0);
+        assert_eq!(RecordingSegment::locate(&segments, 59_999).unwrap().index, 0);
+        assert_eq!(RecordingSegment::locate(&segments, 60_000).unwrap().index, 1);
+        assert_eq!(RecordingSegment::locate(&segments, 125_000).unwrap().index, 2);
+        // Timestamps stamped after the last frame still belong to the last segment
+        assert_eq!(RecordingSegment::locate(&segments, 500_000).unwrap().index, 2);
+        assert!(RecordingSegment::locate(&[], 1_000).is_none());
+    }
+    
+    /// Test batch execution past failed ops
+    #[tokio::test]
+    async fn test_batch_keeps_going_past_failed_ops() {
+        use crate::errors::AppError;
+        use crate::models::{BatchOp, BatchResult};
+
+        let ops: Vec<BatchOp> = serde_json::from_value(serde_json::json!([
+            { "op": "get_reference_count", "page_title": "Meetings" },
+            { "op": "get_recording", "recording_id": "missing" },
+            { "op": "get_block_children", "parent_id": "page-1" },
+        ])).unwrap();
+        assert_eq!(ops[1], BatchOp::GetRecording { recording_id: "missing".to_string() });
+
+        let results = crate::execute_ops(ops, |op| async move {
+            match op {
+                BatchOp::GetReferenceCount { .. } => Ok(serde_json::json!(3)),
+                BatchOp::GetRecording { .. } => Err(AppError::database("Query timed out")),
+                _ => Ok(serde_json::json!([])),
+            }
+        }).await;
+
+        assert_eq!(serde_json::to_value(&results).unwrap(), serde_json::json!([
+            { "status": "ok", "value": 3 },
+            { "status": "error", "error": { "code": "database", "message": "Query timed out", "details": null } },
+            { "status": "ok", "value": [] },
+        ]));
+        assert!(matches!(results[1], BatchResult::Error { error: AppError::Database { .. } }));
+    }
+
+    /// Test data change events for added blocks
+    #[tokio::test]
+    async fn test_data_changes_for_added_blocks() {
+        use crate::models::{DataChange, DataChangeKind};
+
+        let block = |id: &str, parent: Option<&str>| Block {
+            id: id.to_string(),
+            content: None,
+            parent_id: parent.map(str::to_string),
+            order: 0,
+            is_page: parent.is_none(),
+            page_title: parent.is_none().then(|| id.to_string()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        let blocks = vec![block("notes", None), block("first", Some("notes")), block("second", Some("notes"))];
+
+        let changes = DataChange::added(&blocks);
+        assert_eq!(changes, vec![
+            DataChange::new(DataChangeKind::PageCreated, ["notes".to_string()]),
+            DataChange::new(DataChangeKind::BlockCreated, ["first", "second"].map(str::to_string)),
+        ]);
+        assert_eq!(changes[0].kind.event(), "data://page-changed");
+        assert_eq!(changes[1].kind.event(), "data://block-changed");
+        assert_eq!(serde_json::to_value(&changes[0]).unwrap(), serde_json::json!({ "ids": ["notes"] }));
+        assert_eq!(DataChange::written(&blocks[1]).kind, DataChangeKind::BlockChanged);
+        assert!(DataChange::added(&[]).is_empty());
+
+        // A block added under an existing page changes that page's children
+        assert_eq!(DataChange::added(&blocks[1..2]), vec![
+            DataChange::new(DataChangeKind::BlockCreated, ["first".to_string()]),
+            DataChange::new(DataChangeKind::BlockChanged, ["notes".to_string()]),
+        ]);
+    }
+
+    /// Test change listener events for new blocks
+    #[tokio::test]
+    async fn test_new_block_is_published_as_block_created() {
+        use crate::models::{DataChange, DataChangeKind, DbChanged, DbChangedKind};
+
+        let block = Block {
+            id: "fresh".to_string(),
+            content: Some("New idea".to_string()),
+            parent_id: Some("notes".to_string()),
+            order: 0,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // What create_block hands the change listener, as main.rs forwards it
+        let published: Vec<DbChanged> = DataChange::added(std::slice::from_ref(&block)).iter()
+            .flat_map(DataChange::per_block)
+            .collect();
+        assert_eq!(published, vec![
+            DbChanged { kind: DbChangedKind::Created, id: "fresh".to_string() },
+            DbChanged { kind: DbChangedKind::Updated, id: "notes".to_string() },
+        ]);
+        assert_eq!(serde_json::to_value(&published[0]).unwrap(), serde_json::json!({ "kind": "block_created", "id": "fresh" }));
+
+        let deleted = DataChange::new(DataChangeKind::BlockDeleted, ["gone".to_string()]).per_block();
+        assert_eq!(serde_json::to_value(&deleted).unwrap(), serde_json::json!([{ "kind": "block_deleted", "id": "gone" }]));
+    }
+
+    /// Test page window labels
+    #[tokio::test]
+    async fn test_page_window_labels_are_valid() {
+        assert_eq!(crate::page_window_label("daily-2026-10-16"), "page-daily-2026-10-16");
+        assert_eq!(crate::page_window_label("a b/c"), "page-a_b_c");
+    }
+
+    /// Test directory size calculation
+    #[tokio::test]
+    async fn test_directory_size_counts_nested_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("a.wav"), [0u8; 100]).unwrap();
+        fs::create_dir(temp_dir.path().join("archive")).unwrap();
+        fs::write(temp_dir.path().join("archive").join("b.wav"), [0u8; 50]).unwrap();
+
+        assert_eq!(crate::directory_size(temp_dir.path()), 150);
+        assert_eq!(crate::directory_size(&temp_dir.path().join("missing")), 0);
+    }
+    
+    /// Test page statistics ranking
+    #[tokio::test]
+    async fn test_page_stats_rank_larger_pages_first() {
+        let pages = vec![
+            ("small".to_string(), Some("Small page".to_string())),
+            ("large".to_string(), Some("Large page".to_string())),
+            ("empty".to_string(), None),
+        ];
+        let link = |block: &str, parent: &str| (block.to_string(), parent.to_string());
+        let parent_links = vec![
+            link("s1", "small"),
+            link("l1", "large"),
+            link("l2", "large"),
+            link("l1a", "l1"),
+            link("l1a1", "l1a"),
+            link("orphan", "deleted-page"),
+        ];
+
+        let stats = PageStat::compute(&pages, &parent_links);
+        let summary: Vec<(&str, usize, usize)> = stats.iter()
+            .map(|s| (s.page_id.as_str(), s.block_count, s.max_depth))
+            .collect();
+        assert_eq!(summary, vec![("large", 4, 3), ("small", 1, 1), ("empty", 0, 0)]);
+        assert_eq!(stats[0].title.as_deref(), Some("Large page"));
+    }
+
+    /// Test recent edits ordering
+    #[tokio::test]
+    async fn test_recent_edits_newest_first_with_their_page() {
+        let edited = |id: &str, parent: &str, minutes_ago: i64| Block {
+            id: id.to_string(),
+            content: Some(id.to_string()),
+            parent_id: Some(parent.to_string()),
+            order: 0,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now() - chrono::Duration::days(1),
+            updated_at: Utc::now() - chrono::Duration::minutes(minutes_ago),
+            audio_timestamp: None,
+            source: None,
+        };
+        let blocks = vec![edited("old", "journal", 30), edited("nested", "parent", 1), edited("parent", "journal", 10), edited("orphan", "gone", 5)];
+        let parents = [("nested", "parent"), ("parent", "journal"), ("old", "journal"), ("orphan", "gone")]
+            .into_iter()
+            .map(|(block, parent)| (block.to_string(), parent.to_string()))
+            .collect();
+        let pages = [("journal".to_string(), "Journal".to_string())].into_iter().collect();
+
+        let recent = RecentEdit::latest(blocks, &parents, &pages, 3);
+        let summary: Vec<(&str, Option<&str>)> = recent.iter()
+            .map(|edit| (edit.block.id.as_str(), edit.page_title.as_deref()))
+            .collect();
+        assert_eq!(summary, vec![("nested", Some("Journal")), ("orphan", None), ("parent", Some("Journal"))]);
+        assert_eq!(recent[0].page_id.as_deref(), Some("journal"));
+    }
+
+    /// Test grouping children by parent
+    #[tokio::test]
+    async fn test_children_grouped_by_parent_in_order() {
+        let child = |id: &str, parent: &str, order: i32| Block {
+            id: id.to_string(),
+            content: Some(id.to_string()),
+            parent_id: Some(parent.to_string()),
+            order,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // One query returns every child, interleaved and out of order
+        let blocks = vec![
+            child("b2", "b", 1),
+            child("a3", "a", 2),
+            child("a1", "a", 0),
+            child("b1", "b", 0),
+            child("a2", "a", 1),
+        ];
+        let parent_ids = vec!["a".to_string(), "b".to_string(), "leaf".to_string()];
+
+        let grouped = Block::group_by_parent(blocks, &parent_ids);
+        let ids = |parent: &str| grouped[parent].iter().map(|b| b.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids("a"), vec!["a1", "a2", "a3"]);
+        assert_eq!(ids("b"), vec!["b1", "b2"]);
+        assert!(ids("leaf").is_empty());
+    }
+
+    /// Test ordering blocks by requested IDs
+    #[tokio::test]
+    async fn test_blocks_returned_in_requested_order() {
+        let block = |id: &str| Block {
+            id: id.to_string(),
+            content: Some(id.to_string()),
+            parent_id: Some("page".to_string()),
+            order: 0,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // The query returns rows in its own order
+        let blocks = vec![block("a"), block("b"), block("c")];
+        let ids: Vec<String> = ["c", "missing", "a", "b", "a"].iter().map(|id| id.to_string()).collect();
+
+        let ordered = Block::in_order_of(blocks, &ids);
+        let ordered_ids: Vec<&str> = ordered.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ordered_ids, vec!["c", "a", "b"]);
+    }
+
+    /// Test keyset batching of blocks
+    #[tokio::test]
+    async fn test_keyset_batches_page_through_every_block_once() {
+        let child = |id: &str, order: i32| Block {
+            id: id.to_string(),
+            content: Some(id.to_string()),
+            parent_id: Some("page".to_string()),
+            order,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // Ties on order, like blocks inserted concurrently, fall back to the ID
+        let blocks = vec![child("e", 3), child("b", 1), child("d", 1), child("a", 0), child("c", 1)];
+
+        let mut after = None;
+        let mut seen = Vec::new();
+        loop {
+            let batch = Block::keyset_batch(blocks.clone(), after.as_ref(), 2);
+            let Some(last) = batch.last() else { break };
+            after = Some((last.order, last.id.clone()));
+            seen.extend(batch.into_iter().map(|block| block.id));
+        }
+        assert_eq!(seen, vec!["a", "b", "c", "d", "e"]);
+    }
+
+    /// Test search result cursors
+    #[tokio::test]
+    async fn test_search_cursor_pages_through_every_match_once() {
+        let block = |id: &str, content: &str| Block {
+            id: id.to_string(),
+            content: Some(content.to_string()),
+            parent_id: Some("page".to_string()),
+            order: 0,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // Several blocks share a rank, so the ID has to break the ties
+        let blocks = vec![
+            block("d", "budget"),
+            block("a", "budget budget"),
+            block("e", "budget review"),
+            block("b", "budget"),
+            block("c", "budget, budget, budget"),
+            block("f", "budget budget"),
+        ];
+
+        let mut after: Option<(i64, String)> = None;
+        let mut seen = Vec::new();
+        loop {
+            let page = SearchPage::keyset(blocks.clone(), "budget", after.as_ref().map(|(rank, id)| (*rank, id.as_str())), 2);
+            seen.extend(page.hits.iter().map(|hit| (hit.rank, hit.block.id.clone())));
+            match page.next_rank.zip(page.next_id) {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        let expected = [(3, "c"), (2, "a"), (2, "f"), (1, "b"), (1, "d"), (1, "e")];
+        assert_eq!(seen, expected.map(|(rank, id)| (rank, id.to_string())).to_vec());
+    }
+
+    /// Test content history timeline
+    #[tokio::test]
+    async fn test_content_timeline_retracts_before_asserting() {
+        let revision = |tx: i64, content: &str, added: bool| ContentRevision {
+            tx,
+            tx_instant: Utc::now(),
+            content: content.to_string(),
+            added,
+        };
+        // History queries return a set, so the rows come in any order
+        let timeline = ContentRevision::timeline(vec![
+            revision(3, "second", true),
+            revision(9, "third", true),
+            revision(1, "first", true),
+            revision(9, "second", false),
+            revision(3, "first", false),
+        ]);
+        let steps: Vec<(i64, &str, bool)> = timeline.iter().map(|r| (r.tx, r.content.as_str(), r.added)).collect();
+        assert_eq!(steps, vec![
+            (1, "first", true),
+            (3, "first", false),
+            (3, "second", true),
+            (9, "second", false),
+            (9, "third", true),
+        ]);
+    }
+
+    /// Test page templates
+    #[tokio::test]
+    async fn test_template_copies_blocks_with_placeholders_filled() {
+        let block = |id: &str, parent: Option<&str>, content: Option<&str>, order: i32| Block {
+            id: id.to_string(),
+            content: content.map(str::to_string),
+            parent_id: parent.map(str::to_string),
+            order,
+            is_page: parent.is_none(),
+            page_title: parent.is_none().then(|| "Meeting template".to_string()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        let page = block("template", None, None, 0);
+        let descendants = vec![
+            block("agenda", Some("template"), Some("Agenda for {{date}}"), 0),
+            block("notes", Some("agenda"), Some("Notes on {{title}} ({{owner}})"), 1),
+        ];
+        let placeholders = std::collections::HashMap::from([("date", "2024-03-09".to_string()), ("title", "Standup".to_string())]);
+
+        let copy = Block::instantiate_template(&page, &descendants, "Standup", &placeholders, Utc::now());
+        assert_eq!(copy.len(), 3);
+        let (new_page, agenda, notes) = (&copy[0], &copy[1], &copy[2]);
+        assert!(new_page.is_page);
+        assert_eq!(new_page.page_title.as_deref(), Some("Standup"));
+        assert!(copy.iter().all(|block| !["template", "agenda", "notes"].contains(&block.id.as_str())));
+
+        assert_eq!(agenda.content.as_deref(), Some("Agenda for 2024-03-09"));
+        assert_eq!(agenda.parent_id.as_deref(), Some(new_page.id.as_str()));
+        // Unknown placeholders are left for the user to fill in
+        assert_eq!(notes.content.as_deref(), Some("Notes on Standup ({{owner}})"));
+        assert_eq!(notes.parent_id.as_deref(), Some(agenda.id.as_str()));
+        assert_eq!(notes.order, 1);
+    }
+
+    /// Test block word counts
+    #[tokio::test]
+    async fn test_word_count_skips_markup() {
+        assert_eq!(Block::word_count("Budget for [[Project X]] #todo"), 5);
+        assert_eq!(Block::word_count("See ((6f1c2a)) - then\n  stop."), 3);
+        assert_eq!(Block::word_count("  "), 0);
+    }
+
+    /// Test word counts over a page tree
+    #[tokio::test]
+    async fn test_words_below_sum_the_page_tree() {
+        let block = |id: &str, parent: Option<&str>, content: Option<&str>| Block {
+            id: id.to_string(),
+            content: content.map(str::to_string),
+            parent_id: parent.map(str::to_string),
+            order: 0,
+            is_page: parent.is_none(),
+            page_title: parent.is_none().then(|| id.to_string()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        let blocks = vec![
+            block("draft", None, None),
+            block("intro", Some("draft"), Some("Once upon a time")),
+            block("detail", Some("intro"), Some("there was [[Gita]]")),
+            block("other", None, None),
+            block("elsewhere", Some("other"), Some("Not counted")),
+        ];
+
+        assert_eq!(Block::words_below("draft", &blocks), 7);
+        assert_eq!(Block::words_below("missing", &blocks), 0);
+    }
+
+    /// Test block positions among siblings
+    #[tokio::test]
+    async fn test_block_index_ignores_gaps_in_order() {
+        let child = |id: &str, order: i32| Block {
+            id: id.to_string(),
+            content: Some(id.to_string()),
+            parent_id: Some("page".to_string()),
+            order,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        // Orders left sparse by deletes and moves, listed out of order
+        let siblings = vec![child("third", 700), child("first", 3), child("second", 40)];
+
+        let indices: Vec<usize> = siblings.iter().map(|block| block.position_among(&siblings)).collect();
+        assert_eq!(indices, vec![2, 0, 1]);
+    }
+
+    /// Test page link tracking
+    #[tokio::test]
+    async fn test_page_links_follow_content_changes() {
+        let links: Vec<String> = Block::page_links("See [[Project X]] and [[ Meetings ]], again [[Project X]], not [[]] or [[open").into_iter().collect();
+        assert_eq!(links, vec!["Meetings", "Project X"]);
+
+        let (added, removed) = Block::link_changes(Some("[[A]] and [[B]]"), Some("[[B]] and [[C]]"));
+        assert_eq!(added, vec!["C"]);
+        assert_eq!(removed, vec!["A"]);
+
+        // Clearing the content drops every link
+        let (added, removed) = Block::link_changes(Some("[[A]]"), None);
+        assert!(added.is_empty());
+        assert_eq!(removed, vec!["A"]);
+
+        // Drifted links are repaired against the content
+        let stored = ["A".to_string(), "Stale".to_string()].into_iter().collect();
+        let (missing, stale) = Block::link_repairs(Some("[[A]] and [[B]]"), &stored);
+        assert_eq!(missing, vec!["B"]);
+        assert_eq!(stale, vec!["Stale"]);
+        assert_eq!(Block::link_repairs(Some("[[A]]"), &["A".to_string()].into_iter().collect()), (vec![], vec![]));
+    }
+
+    /// Test duplicate page detection
+    #[tokio::test]
+    async fn test_duplicate_pages_ignore_case_and_whitespace() {
+        let page = |id: &str, title: &str, age_days: i64| Block {
+            id: id.to_string(),
+            content: None,
+            parent_id: None,
+            order: 0,
+            is_page: true,
+            page_title: Some(title.to_string()),
+            created_at: Utc::now() - chrono::Duration::days(age_days),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        let pages = vec![
+            page("copy", " foo ", 1),
+            page("original", "Foo", 2),
+            page("other", "Food", 3),
+        ];
+
+        let duplicates = Block::duplicate_pages(pages);
+        let ids: Vec<(&str, &str)> = duplicates.iter().map(|(a, b)| (a.id.as_str(), b.id.as_str())).collect();
+        assert_eq!(ids, vec![("original", "copy")]);
+    }
+
+    /// Test retargeting page links
+    #[tokio::test]
+    async fn test_retarget_page_links() {
+        assert_eq!(
+            Block::retarget_page_links("See [[ foo ]], [[Food]] and [[foo]]", " foo ", "Foo"),
+            "See [[Foo]], [[Food]] and [[Foo]]"
+        );
+        // Unclosed links are left alone
+        assert_eq!(Block::retarget_page_links("[[foo]] then [[foo", "foo", "Foo"), "[[Foo]] then [[foo");
+    }
+
+    /// Test block embed tokens
+    #[tokio::test]
+    async fn test_embed_token() {
+        assert_eq!(Block::embed_token("6f1c2a"), "((6f1c2a))");
+    }
+
+    /// Test detecting no-op block updates
+    #[tokio::test]
+    async fn test_block_unchanged_by_matching_updates() {
+        let block = Block {
+            id: "block-1".to_string(),
+            content: Some("Same words".to_string()),
+            parent_id: Some("page-1".to_string()),
+            order: 2,
+            is_page: false,
+            page_title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            audio_timestamp: None,
+            source: None,
+        };
+        let updates = |pairs: &[(&str, serde_json::Value)]| -> std::collections::HashMap<String, serde_json::Value> {
+            pairs.iter().map(|(key, value)| (key.to_string(), value.clone())).collect()
+        };
+
+        assert!(block.is_unchanged_by(&updates(&[("content", serde_json::json!("Same words"))])));
+        assert!(block.is_unchanged_by(&updates(&[("block/content", serde_json::json!("Same words")), ("order", serde_json::json!(2))])));
+        assert!(block.is_unchanged_by(&updates(&[("block/parent", serde_json::json!("page-1"))])));
+
+        assert!(!block.is_unchanged_by(&updates(&[("content", serde_json::json!("Same words!"))])));
+        assert!(!block.is_unchanged_by(&updates(&[("content", serde_json::json!("Same words")), ("order", serde_json::json!(3))])));
+        assert!(!block.is_unchanged_by(&updates(&[("block/audio-file", serde_json::json!("a.wav"))])));
+    }
+
+    /// Test silence trimming and timestamp shifts
+    #[tokio::test]
+    async fn test_silence_trim_shifts_timestamps() {
+        let intervals = vec![
+            SilenceInterval { start_ms: 0, end_ms: 4000 },
+            SilenceInterval { start_ms: 20000, end_ms: 25000 },
+            SilenceInterval { start_ms: 55000, end_ms: 60000 },
+        ];
+        let trim = SilenceTrim::from_silence(&intervals, 60000);
+        assert_eq!(trim, SilenceTrim { leading_ms: 4000, trailing_ms: 5000, duration_ms: 51000 });
+
+        // Timestamps move back by the leading silence
+        assert_eq!(trim.shift_timestamp_ms(10000), 6000);
+        assert_eq!(trim.shift_timestamp_ms(4000), 0);
+        // Silence in the middle of the recording is kept
+        assert_eq!(trim.shift_timestamp_ms(22000), 18000);
+        // Timestamps inside the cut silence snap to the kept audio
+        assert_eq!(trim.shift_timestamp_ms(1000), 0);
+        assert_eq!(trim.shift_timestamp_ms(58000), 51000);
+
+        // Nothing is cut when the recording neither starts nor ends quietly
+        let untouched = SilenceTrim::from_silence(&intervals[1..2], 60000);
+        assert!(untouched.is_empty());
+        assert_eq!(untouched.shift_timestamp_ms(30000), 30000);
+
+        // A recording that is silent throughout trims down to nothing
+        let silent = SilenceTrim::from_silence(&[SilenceInterval { start_ms: 0, end_ms: 60000 }], 60000);
+        assert_eq!(silent.duration_ms, 0);
+        assert_eq!(silent.shift_timestamp_ms(30000), 0);
+    }
+
     /// Test timestamp models
     #[tokio::test]
     async fn test_timestamp_models() {
         // use uuid::Uuid; // Already imported
         
-        let timestamp = AudioTimestamp {
-            // id: Uuid::new_v4().to_string(), // Model has no id
-            block_id: Uuid::new_v4().to_string(),
-            recording_id: Uuid::new_v4().to_string(),
-            // timestamp_ms: 5000, // Model has timestamp_seconds: u32
-            timestamp_seconds: 5,
-            recording: None, // Added field
-        };
+        let timestamp = AudioTimestamp::new(&Uuid::new_v4().to_string(), &Uuid::new_v4().to_string(), 5250);
+        assert_eq!(timestamp.timestamp_seconds, 5);
         
         // Test serialization
         let json = serde_json::to_string(&timestamp).unwrap();
         let deserialized: AudioTimestamp = serde_json::from_str(&json).unwrap();
         
-        // assert_eq!(timestamp.id, deserialized.id);
         assert_eq!(timestamp.block_id, deserialized.block_id);
         assert_eq!(timestamp.recording_id, deserialized.recording_id);
-        // assert_eq!(timestamp.timestamp_ms, deserialized.timestamp_ms);
+        assert_eq!(timestamp.timestamp_ms, deserialized.timestamp_ms);
         assert_eq!(timestamp.timestamp_seconds, deserialized.timestamp_seconds);
     }
+
+    /// Test audio metadata deserialization
+    #[tokio::test]
+    async fn test_audio_meta_accepts_seconds_and_milliseconds() {
+        let meta: AudioMeta = serde_json::from_str(r#"{"recording_id": "r", "timestamp_ms": 1250}"#).unwrap();
+        assert_eq!(meta.timestamp_ms, 1250);
+
+        // The older whole-second field is converted
+        let meta: AudioMeta = serde_json::from_str(r#"{"recording_id": "r", "timestamp": 12}"#).unwrap();
+        assert_eq!(meta.timestamp_ms, 12_000);
+
+        // Asking for the live position works with either field
+        let meta: AudioMeta = serde_json::from_str(r#"{"recording_id": "r", "timestamp": -1}"#).unwrap();
+        assert_eq!(meta.timestamp_ms, AudioMeta::CURRENT_POSITION);
+        let meta: AudioMeta = serde_json::from_str(r#"{"recording_id": "r", "timestamp_ms": -1}"#).unwrap();
+        assert_eq!(meta.timestamp_ms, AudioMeta::CURRENT_POSITION);
+    }
 }
 
 /// Integration tests for the complete system
@@ -335,8 +971,23 @@ mod integration_tests {
     use tempfile::TempDir;
     use crate::config::AppConfig;
     use crate::database_peer_complete::DatomicPeerClient;
-    use crate::models::{CreateBlockRequest, Block}; // Added Block
-    use crate::errors::DatomicError; // Added for matching error
+    use crate::datomic_schema::SCHEMA_VERSION;
+    use crate::models::{CreateBlockRequest, Block, AudioMeta, AudioRecording, RecordingMarker, SilenceInterval, SilenceTrim}; // Added Block
+    use crate::errors::{AppError, DatomicError}; // Added for matching error
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    /// A fresh page for a test's blocks to live under
+    async fn create_test_page(client: &DatomicPeerClient, prefix: &str) -> Block {
+        client.create_block(CreateBlockRequest {
+            content: None,
+            is_page: true,
+            page_title: Some(format!("{}-{}", prefix, Uuid::new_v4())),
+            parent_id: None,
+            order: 0,
+            source: None,
+        }, None).await.unwrap()
+    }
     
     /// Test complete application setup (requires Datomic)
     #[tokio::test]
@@ -367,6 +1018,7 @@ mod integration_tests {
                 page_title: Some("integration-test-page".to_string()), // Changed from page_id
                 parent_id: None,
                 order: 0, // Model uses i32
+                source: None,
             };
             
             let block_result: Result<Block, DatomicError> = client.create_block(block_request, None).await;
@@ -387,7 +1039,1557 @@ mod integration_tests {
             println!("Skipping integration test - Datomic not available: {:?}", result.err());
         }
     }
-    
+
+    /// Test that connecting brings the schema to the latest version (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_schema_version() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            assert_eq!(client.schema_version().await.unwrap(), SCHEMA_VERSION);
+            assert!(client.schema_diff().await.unwrap().missing.is_empty());
+        } else {
+            println!("Skipping schema version test - Datomic not available");
+        }
+    }
+
+    /// Test that query results come back as rows keyed by :find variable (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_query_result_conversion() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let idents = client.query("[:find ?ident :where [_ :db/ident ?ident]]", Vec::new()).await.unwrap();
+            assert!(idents.iter().any(|row| row.get("ident") == Some(&serde_json::json!(":block/id"))));
+
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("query-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let rows = client.query("[:find ?e ?is-page :where [?e :block/is_page ?is-page]]", Vec::new()).await.unwrap();
+            assert!(rows.iter().all(|row| row.get("e").is_some_and(serde_json::Value::is_i64)));
+            assert!(rows.iter().any(|row| row.get("is-page") == Some(&serde_json::Value::Bool(true))));
+            assert!(client.get_block(&page.id).await.unwrap().is_some());
+        } else {
+            println!("Skipping query conversion test - Datomic not available");
+        }
+    }
+
+    /// Test that transactions persist, resolve tempids and report cas failures (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_transact_reports_tempids_and_failures() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let block_id = Uuid::new_v4().to_string();
+            let now = Utc::now().to_rfc3339();
+            let report = client.transact(vec![serde_json::json!({
+                ":db/id": "new-block",
+                ":block/id": block_id,
+                ":block/content": ":starts with a colon",
+                ":block/created_at": now,
+                ":block/updated_at": now,
+            })]).await.unwrap();
+            assert!(report["tempids"]["new-block"].is_i64());
+            assert!(report["tx-id"].is_i64());
+
+            let block = client.get_block(&block_id).await.unwrap().unwrap();
+            assert_eq!(block.content.as_deref(), Some(":starts with a colon"));
+
+            let stale = client.transact(vec![serde_json::json!(
+                [":db/cas", [":block/id", block_id], ":block/content", "not the content", "new content"]
+            )]).await;
+            assert!(matches!(stale, Err(ref e) if e.is_cas_conflict()));
+        } else {
+            println!("Skipping transact test - Datomic not available");
+        }
+    }
+
+    /// Test creating a page from a template page (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_instantiate_template() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let template_title = format!("template-test-{}", Uuid::new_v4());
+            let template = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(template_title.clone()),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            for (order, content) in ["Attendees", "Decisions for {{title}}"].into_iter().enumerate() {
+                client.create_block(CreateBlockRequest {
+                    content: Some(content.to_string()),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(template.id.clone()),
+                    order: order as i32,
+                    source: None,
+                }, None).await.unwrap();
+            }
+
+            let new_title = format!("from-template-{}", Uuid::new_v4());
+            let page = client.instantiate_template(&template_title, &new_title, 0).await.unwrap();
+            assert_eq!(page.page_title.as_deref(), Some(new_title.as_str()));
+            let children = client.get_children_for_parents(std::slice::from_ref(&page.id)).await.unwrap().remove(&page.id).unwrap();
+            let contents: Vec<_> = children.iter().filter_map(|block| block.content.clone()).collect();
+            assert_eq!(contents, vec!["Attendees".to_string(), format!("Decisions for {}", new_title)]);
+
+            let taken = client.instantiate_template(&template_title, &new_title.to_uppercase(), 0).await;
+            assert!(matches!(taken, Err(DatomicError::InvalidTransactionData(_))));
+        } else {
+            println!("Skipping template test - Datomic not available");
+        }
+    }
+
+    /// Test that queries run with their :in parameters (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_query_parameters_are_bound() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            // Any title works as a date here, and a fresh one keeps the page empty
+            let date = format!("daily-test-{}", Uuid::new_v4());
+            let page = client.ensure_daily_note_page(&date).await.unwrap();
+            let term = format!("needle-{}", Uuid::new_v4());
+            for (order, content) in [format!("first {}", term), "second".to_string()].into_iter().enumerate() {
+                client.create_block(CreateBlockRequest {
+                    content: Some(content),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(page.id.clone()),
+                    order: order as i32,
+                    source: None,
+                }, None).await.unwrap();
+            }
+
+            let page_blocks = client.get_page_blocks(&page.id).await.unwrap();
+            assert!(page_blocks.len() >= 2);
+            assert!(page_blocks.iter().all(|block| block.parent_id.as_deref() == Some(page.id.as_str())));
+            assert_eq!(client.get_daily_note(&date, false).await.unwrap(), page_blocks);
+
+            let found = client.search_blocks(&term).await.unwrap();
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].content.as_deref(), Some(format!("first {}", term).as_str()));
+
+            // Lookup refs are bound as [keyword value] lists
+            let rows = client.query(
+                "[:find ?title :in $ ?page :where [?page :block/page_title ?title]]",
+                vec![serde_json::json!([":block/id", page.id])],
+            ).await.unwrap();
+            assert_eq!(rows[0].get("title"), Some(&serde_json::json!(date)));
+
+            let null = client.query("[:find ?e :in $ ?id :where [?e :block/id ?id]]", vec![serde_json::Value::Null]).await;
+            assert!(matches!(null, Err(DatomicError::TypeConversionError(_))));
+        } else {
+            println!("Skipping query parameter test - Datomic not available");
+        }
+    }
+
+    /// Test pulling blocks by lookup ref (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_pull_blocks() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("pull-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let child = client.create_block(CreateBlockRequest {
+                content: Some("pulled".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let pulled = client.pull(serde_json::json!([":block/id", child.id]), "[:block/id :block/content]").await.unwrap();
+            assert_eq!(pulled, serde_json::json!({"block/id": child.id, "block/content": "pulled"}));
+
+            let many = client.pull_many(
+                vec![serde_json::json!([":block/id", page.id]), serde_json::json!([":block/id", child.id])],
+                "[:block/id]",
+            ).await.unwrap();
+            assert_eq!(many, vec![serde_json::json!({"block/id": page.id}), serde_json::json!({"block/id": child.id})]);
+
+            let block = client.get_block(&child.id).await.unwrap().unwrap();
+            assert_eq!(block.parent_id.as_deref(), Some(page.id.as_str()));
+            assert_eq!(block.content.as_deref(), Some("pulled"));
+            assert_eq!(client.get_page_blocks(&page.id).await.unwrap(), vec![block]);
+            assert!(client.get_block(&Uuid::new_v4().to_string()).await.unwrap().is_none());
+        } else {
+            println!("Skipping pull test - Datomic not available");
+        }
+    }
+
+    /// Test that repeated operations share one connection (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_operations_reuse_the_connection() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            // Creating the client connected once to install the schema
+            assert_eq!(client.connections_opened(), 1);
+
+            let started = std::time::Instant::now();
+            for _ in 0..50 {
+                assert!(client.health_check().await.unwrap());
+            }
+            let elapsed = started.elapsed();
+            println!("50 queries on the shared connection took {:?}", elapsed);
+
+            let page = create_test_page(&client, "reuse-test").await;
+            client.create_block(CreateBlockRequest {
+                content: Some("connection reuse".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            assert_eq!(client.connections_opened(), 1);
+        } else {
+            println!("Skipping connection reuse test - Datomic not available");
+        }
+    }
+
+    /// Test that a block's source is stored and read back (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_block_source_round_trips() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "source-test").await;
+            let imported = client.create_block(CreateBlockRequest {
+                content: Some("From a markdown file".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: Some("import".to_string()),
+            }, None).await.unwrap();
+            let typed = client.create_block(CreateBlockRequest {
+                content: Some("Typed".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 1,
+                source: None,
+            }, None).await.unwrap();
+
+            assert_eq!(client.get_block(&imported.id).await.unwrap().unwrap().source.as_deref(), Some("import"));
+            assert_eq!(client.get_block(&typed.id).await.unwrap().unwrap().source, None);
+            let both = client.get_blocks_by_ids(&[imported.id.clone(), typed.id.clone()]).await.unwrap();
+            assert_eq!(both[0].source.as_deref(), Some("import"));
+        } else {
+            println!("Skipping block source test - Datomic not available");
+        }
+    }
+
+    /// Test a shut down client refuses work and a new one can connect (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_shutdown_then_reconnect() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            assert!(client.health_check().await.unwrap());
+            client.shutdown(false).await.unwrap();
+            assert!(matches!(client.health_check().await, Err(DatomicError::ConnectionError(_))));
+
+            let client = DatomicPeerClient::new(AppConfig::default()).await.unwrap();
+            assert!(client.health_check().await.unwrap());
+            client.shutdown(false).await.unwrap();
+        } else {
+            println!("Skipping shutdown test - Datomic not available");
+        }
+    }
+
+    /// Test the peer client through the `Store` trait the commands use (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_store_over_peer_client() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let store: std::sync::Arc<dyn crate::store::Store> = std::sync::Arc::new(client);
+            let title = format!("store-test-{}", Uuid::new_v4());
+            let page = store.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(title.clone()),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let block = store.create_block(CreateBlockRequest {
+                content: Some("draft".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            assert!(store.update_block_content(&block.id, "final").await.unwrap());
+            assert_eq!(store.get_page_by_title(&title).await.unwrap().unwrap().id, page.id);
+            let children = store.get_block_children(&page.id).await.unwrap();
+            assert_eq!(children[0].content.as_deref(), Some("final"));
+
+            assert_eq!(store.delete_block(&page.id).await.unwrap(), 2);
+            assert!(store.get_block(&block.id).await.unwrap().is_none());
+        } else {
+            println!("Skipping store test - Datomic not available");
+        }
+    }
+
+    /// Test paging through search results ranked and cut by the query (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_search_blocks_after_pages_in_rank_order() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "search-page-test").await;
+            // A term with regex characters checks it is matched literally
+            let term = format!("needle.{}", Uuid::new_v4());
+            for (order, repeats) in [1, 3, 2, 3, 1].into_iter().enumerate() {
+                client.create_block(CreateBlockRequest {
+                    content: Some(vec![term.as_str(); repeats].join(" ")),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(page.id.clone()),
+                    order: order as i32,
+                    source: None,
+                }, None).await.unwrap();
+            }
+
+            let mut ranks = Vec::new();
+            let mut cursor: Option<(i64, String)> = None;
+            loop {
+                let (after_rank, after_id) = cursor.clone().unzip();
+                let result = client.search_blocks_after(&term, after_rank, after_id.as_deref(), 2).await.unwrap();
+                assert!(result.hits.len() <= 2);
+                ranks.extend(result.hits.iter().map(|hit| hit.rank));
+                match result.next_rank.zip(result.next_id) {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+            assert_eq!(ranks, vec![3, 3, 2, 1, 1]);
+            assert!(client.search_blocks_after("", None, None, 2).await.unwrap().hits.is_empty());
+        } else {
+            println!("Skipping search paging test - Datomic not available");
+        }
+    }
+
+    /// Test a child block is stored under its parent and read back as its child (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_child_block_read_back_through_parent() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "parent-test").await;
+            let child = client.create_block(CreateBlockRequest {
+                content: Some("child".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let children = crate::store::Store::get_block_children(&client, &page.id).await.unwrap();
+            assert_eq!(children.len(), 1);
+            assert_eq!(children[0].id, child.id);
+            assert_eq!(children[0].parent_id.as_deref(), Some(page.id.as_str()));
+        } else {
+            println!("Skipping parent test - Datomic not available");
+        }
+    }
+
+    /// Test deleting a block removes the blocks below it (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_delete_block_with_children() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("delete-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let parent = client.create_block(CreateBlockRequest {
+                content: Some("parent".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let child = client.create_block(CreateBlockRequest {
+                content: Some("child".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(parent.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            assert_eq!(client.delete_block(&parent.id).await.unwrap(), 2);
+            assert!(client.get_block(&parent.id).await.unwrap().is_none());
+            assert!(client.get_block(&child.id).await.unwrap().is_none());
+            assert!(client.get_page_blocks(&page.id).await.unwrap().is_empty());
+
+            let again = client.delete_block(&parent.id).await;
+            assert!(matches!(again, Err(DatomicError::EntityNotFound(_))));
+        } else {
+            println!("Skipping delete block test - Datomic not available");
+        }
+    }
+
+    /// Test that an edit deep below a page counts as activity on it (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_page_last_activity_follows_deep_edits() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let title = format!("activity-test-{}", Uuid::new_v4());
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(title.clone()),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let mut parent_id = page.id.clone();
+            for depth in 0..3 {
+                parent_id = client.create_block(CreateBlockRequest {
+                    content: Some(format!("depth {}", depth)),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(parent_id),
+                    order: 0,
+                    source: None,
+                }, None).await.unwrap().id;
+            }
+            let before = client.page_last_activity(&title).await.unwrap().unwrap();
+
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("content".to_string(), serde_json::json!("edited deep down"));
+            client.update_block(&parent_id, updates).await.unwrap();
+
+            let after = client.page_last_activity(&title).await.unwrap().unwrap();
+            assert!(after > before);
+            assert_eq!(after, client.get_block(&parent_id).await.unwrap().unwrap().updated_at);
+            assert!(after > client.get_page_by_title(&title).await.unwrap().unwrap().updated_at);
+
+            assert!(client.page_last_activity(&format!("missing-{}", Uuid::new_v4())).await.unwrap().is_none());
+        } else {
+            println!("Skipping page activity test - Datomic not available");
+        }
+    }
+
+    /// Test reading a block's content history (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_block_history() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "history-test").await;
+            let block = client.create_block(CreateBlockRequest {
+                content: Some("first".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("content".to_string(), serde_json::json!("second"));
+            client.update_block(&block.id, updates).await.unwrap();
+
+            let history = client.get_block_history(&block.id).await.unwrap();
+            let steps: Vec<(&str, bool)> = history.iter().map(|r| (r.content.as_str(), r.added)).collect();
+            assert_eq!(steps, vec![("first", true), ("first", false), ("second", true)]);
+            assert!(history.windows(2).all(|pair| pair[0].tx_instant <= pair[1].tx_instant));
+
+            // The first transaction's view only has the original content
+            let rows = client.query_as_of(
+                "[:find ?content :in $ ?e :where [?e :block/content ?content]]",
+                vec![serde_json::json!([":block/id", block.id])],
+                crate::models::TxOrInstant::Tx(history[0].tx),
+            ).await.unwrap();
+            assert_eq!(rows[0].get("content"), Some(&serde_json::json!("first")));
+
+            let missing = client.get_block_history(&Uuid::new_v4().to_string()).await;
+            assert!(matches!(missing, Err(DatomicError::EntityNotFound(_))));
+        } else {
+            println!("Skipping block history test - Datomic not available");
+        }
+    }
+
+    /// Test reading a day without creating its page (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_daily_note_without_creating() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let date = format!("daily-test-{}", Uuid::new_v4());
+
+            assert!(client.get_daily_note(&date, false).await.unwrap().is_empty());
+            assert!(client.get_page_by_title(&date).await.unwrap().is_none());
+
+            assert!(client.get_daily_note(&date, true).await.unwrap().is_empty());
+            assert!(client.get_page_by_title(&date).await.unwrap().is_some());
+        } else {
+            println!("Skipping daily note test - Datomic not available");
+        }
+    }
+
+    /// Test relinking a block timestamp to a re-imported recording (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_relink_timestamp() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("relink-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let mut recordings = Vec::new();
+            for name in ["original.wav", "reimported.wav"] {
+                let recording = AudioRecording {
+                    id: Uuid::new_v4().to_string(),
+                    page_id: page.id.clone(),
+                    file_path: format!("/tmp/{}", name),
+                    duration_seconds: Some(60),
+                    recorded_at: Utc::now(),
+                    system_audio: false,
+                };
+                client.create_audio_recording(&recording).await.unwrap();
+                recordings.push(recording);
+            }
+
+            client.create_audio_timestamp(&page.id, &recordings[0].id, 12_500).await.unwrap();
+            client.relink_timestamp(&page.id, &recordings[0].id, &recordings[1].id).await.unwrap();
+
+            let timestamp = client.get_block_audio_timestamp(&page.id).await.unwrap()
+                .expect("Timestamp should still exist after relinking");
+            assert_eq!(timestamp.recording_id, recordings[1].id);
+            assert_eq!(timestamp.timestamp_ms, 12_500);
+            assert_eq!(timestamp.timestamp_seconds, 12);
+            assert_eq!(timestamp.recording.unwrap().file_path, "/tmp/reimported.wav");
+
+            // Relinking to a recording that doesn't exist is rejected
+            let missing = client.relink_timestamp(&page.id, &recordings[1].id, "missing-recording").await;
+            assert!(matches!(missing, Err(DatomicError::EntityNotFound(_))));
+
+            // Relinking a timestamp the block doesn't have is rejected, even onto the same recording
+            let unlinked = client.relink_timestamp(&page.id, &recordings[0].id, &recordings[0].id).await;
+            assert!(matches!(unlinked, Err(DatomicError::EntityNotFound(_))));
+        } else {
+            println!("Skipping relink test - Datomic not available");
+        }
+    }
+
+    /// Test attaching a recording position to an existing block (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_attach_timestamp() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: Some("Written before the meeting".to_string()),
+                is_page: true,
+                page_title: Some(format!("attach-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let recording = AudioRecording {
+                id: Uuid::new_v4().to_string(),
+                page_id: page.id.clone(),
+                file_path: "/tmp/attach.wav".to_string(),
+                duration_seconds: Some(120),
+                recorded_at: Utc::now(),
+                system_audio: false,
+            };
+            client.create_audio_recording(&recording).await.unwrap();
+
+            let attached = client.attach_timestamp(&page.id, &recording.id, 42_300).await.unwrap();
+            assert_eq!(attached.timestamp_ms, 42_300);
+            assert_eq!(attached.timestamp_seconds, 42);
+            assert_eq!(attached.recording.unwrap().file_path, "/tmp/attach.wav");
+
+            let timestamp = client.get_block_audio_timestamp(&page.id).await.unwrap()
+                .expect("Attached timestamp should be readable");
+            assert_eq!(timestamp.recording_id, recording.id);
+            assert_eq!(timestamp.timestamp_ms, 42_300);
+
+            // Attaching to a missing block or recording is rejected
+            let missing_block = client.attach_timestamp("missing-block", &recording.id, 1000).await;
+            assert!(matches!(missing_block, Err(DatomicError::EntityNotFound(_))));
+            let missing_recording = client.attach_timestamp(&page.id, "missing-recording", 1000).await;
+            assert!(matches!(missing_recording, Err(DatomicError::EntityNotFound(_))));
+        } else {
+            println!("Skipping attach test - Datomic not available");
+        }
+    }
+
+    /// Test looking up a recording by its ID (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_recording() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("recording-lookup-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let recording = AudioRecording {
+                id: Uuid::new_v4().to_string(),
+                page_id: page.id.clone(),
+                file_path: "/tmp/lookup.wav".to_string(),
+                duration_seconds: Some(42),
+                recorded_at: Utc::now(),
+                system_audio: true,
+            };
+            client.create_audio_recording(&recording).await.unwrap();
+
+            let found = client.get_recording(&recording.id).await.unwrap().unwrap();
+            assert_eq!(found.id, recording.id);
+            assert_eq!(found.page_id, page.id);
+            assert_eq!(found.file_path, recording.file_path);
+            assert_eq!(found.duration_seconds, Some(42));
+            assert!(found.system_audio);
+
+            assert!(client.get_recording(&Uuid::new_v4().to_string()).await.unwrap().is_none());
+        } else {
+            println!("Skipping recording lookup test - Datomic not available");
+        }
+    }
+
+    /// Test listing every block timestamp of a recording (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_recording_timestamps() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("timestamps-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let recording = AudioRecording {
+                id: Uuid::new_v4().to_string(),
+                page_id: page.id.clone(),
+                file_path: "/tmp/timestamps.wav".to_string(),
+                duration_seconds: Some(300),
+                recorded_at: Utc::now(),
+                system_audio: false,
+            };
+            client.create_audio_recording(&recording).await.unwrap();
+
+            // Written later in the meeting but created first
+            let later = client.create_block(CreateBlockRequest {
+                content: Some("Action items".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, Some(AudioMeta { recording_id: recording.id.clone(), timestamp_ms: 95_000 })).await.unwrap();
+            let earlier = client.create_block(CreateBlockRequest {
+                content: Some("Introductions".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 1,
+                source: None,
+            }, Some(AudioMeta { recording_id: recording.id.clone(), timestamp_ms: 4_500 })).await.unwrap();
+
+            let timestamps = client.get_recording_timestamps(&recording.id).await.unwrap();
+            let order: Vec<(&str, i64)> = timestamps.iter().map(|t| (t.block_id.as_str(), t.timestamp_ms)).collect();
+            assert_eq!(order, vec![(earlier.id.as_str(), 4_500), (later.id.as_str(), 95_000)]);
+            assert!(timestamps.iter().all(|t| t.recording.as_ref().is_some_and(|r| r.id == recording.id)));
+
+            assert!(client.get_recording_timestamps("missing-recording").await.unwrap().is_empty());
+        } else {
+            println!("Skipping recording timestamps test - Datomic not available");
+        }
+    }
+
+    /// Test moving recording files to a new folder updates their paths (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_relocate_recordings() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("relocate-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let old_dir = tempfile::TempDir::new().unwrap();
+            let new_dir = tempfile::TempDir::new().unwrap();
+            let mut recordings = Vec::new();
+            for name in ["first.wav", "second.wav"] {
+                let file_path = old_dir.path().join(name);
+                std::fs::write(&file_path, b"RIFF").unwrap();
+                let recording = AudioRecording {
+                    id: Uuid::new_v4().to_string(),
+                    page_id: page.id.clone(),
+                    file_path: file_path.to_string_lossy().into_owned(),
+                    duration_seconds: Some(60),
+                    recorded_at: Utc::now(),
+                    system_audio: false,
+                };
+                client.create_audio_recording(&recording).await.unwrap();
+                recordings.push(recording);
+            }
+
+            // Recordings left behind by other tests may be moved as well
+            let moved = client.relocate_recordings(new_dir.path()).await.unwrap();
+            assert!(moved >= 2);
+
+            for recording in &recordings {
+                let relocated = client.get_recording(&recording.id).await.unwrap().unwrap();
+                let file_name = std::path::Path::new(&recording.file_path).file_name().unwrap();
+                assert_eq!(std::path::PathBuf::from(&relocated.file_path), new_dir.path().join(file_name));
+                assert!(std::path::Path::new(&relocated.file_path).is_file());
+                assert!(!std::path::Path::new(&recording.file_path).exists());
+            }
+        } else {
+            println!("Skipping relocate recordings test - Datomic not available");
+        }
+    }
+
+    /// Test that trimming silence moves block timestamps with the audio (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_apply_silence_trim() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("trim-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let recording = AudioRecording {
+                id: Uuid::new_v4().to_string(),
+                page_id: page.id.clone(),
+                file_path: "/tmp/trim.wav".to_string(),
+                duration_seconds: Some(60),
+                recorded_at: Utc::now(),
+                system_audio: false,
+            };
+            client.create_audio_recording(&recording).await.unwrap();
+            client.attach_timestamp(&page.id, &recording.id, 12_000).await.unwrap();
+
+            let intervals = vec![
+                SilenceInterval { start_ms: 0, end_ms: 5000 },
+                SilenceInterval { start_ms: 50000, end_ms: 60000 },
+            ];
+            client.store_silence_intervals(&recording.id, &intervals).await.unwrap();
+            assert_eq!(client.get_silence_intervals(&recording.id).await.unwrap(), intervals);
+
+            let trim = SilenceTrim::from_silence(&intervals, 60000);
+            client.apply_silence_trim(&recording.id, &trim).await.unwrap();
+
+            let timestamp = client.get_block_audio_timestamp(&page.id).await.unwrap().unwrap();
+            assert_eq!(timestamp.timestamp_ms, 7000);
+            assert_eq!(timestamp.recording.unwrap().duration_seconds, Some(45));
+            assert!(client.get_silence_intervals(&recording.id).await.unwrap().is_empty());
+        } else {
+            println!("Skipping silence trim test - Datomic not available");
+        }
+    }
+
+    /// Test that concurrent appends to one block are all kept (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_concurrent_append_to_block() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let client = std::sync::Arc::new(client);
+            let page = create_test_page(&client, "append-test").await;
+            let block = client.create_block(CreateBlockRequest {
+                content: Some("start".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let tasks: Vec<_> = ["-a", "-b"].into_iter()
+                .map(|text| {
+                    let client = client.clone();
+                    let block_id = block.id.clone();
+                    tokio::spawn(async move {
+                        for _ in 0..5 {
+                            client.append_to_block(&block_id, text).await.unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+
+            let content = client.get_block(&block.id).await.unwrap().unwrap().content.unwrap();
+            assert!(content.starts_with("start"));
+            assert_eq!(content.matches("-a").count(), 5);
+            assert_eq!(content.matches("-b").count(), 5);
+        } else {
+            println!("Skipping append test - Datomic not available");
+        }
+    }
+
+    /// Test fetching the children of several parents at once (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_children_for_parents() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "children-test").await;
+            let mut parent_ids = Vec::new();
+            for _ in 0..2 {
+                let parent = client.create_block(CreateBlockRequest {
+                    content: Some("parent".to_string()),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(page.id.clone()),
+                    order: 0,
+                    source: None,
+                }, None).await.unwrap();
+                // Create children in reverse so the query can't rely on insertion order
+                for order in (0..3).rev() {
+                    client.create_block(CreateBlockRequest {
+                        content: Some(format!("child {}", order)),
+                        is_page: false,
+                        page_title: None,
+                        parent_id: Some(parent.id.clone()),
+                        order,
+                        source: None,
+                    }, None).await.unwrap();
+                }
+                parent_ids.push(parent.id);
+            }
+
+            let children = client.get_children_for_parents(&parent_ids).await.unwrap();
+            assert_eq!(children.len(), 2);
+            for parent_id in &parent_ids {
+                let orders: Vec<i32> = children[parent_id].iter().map(|b| b.order).collect();
+                assert_eq!(orders, vec![0, 1, 2]);
+                assert!(children[parent_id].iter().all(|b| b.parent_id.as_ref() == Some(parent_id)));
+            }
+        } else {
+            println!("Skipping children test - Datomic not available");
+        }
+    }
+
+    /// Test the children query matches parents by ID, one level down only (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_children_for_parents_query() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "children-query-test").await;
+            let child = client.create_block(CreateBlockRequest {
+                content: Some("child".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let grandchild = client.create_block(CreateBlockRequest {
+                content: Some("grandchild".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(child.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let parent_ids = vec![page.id.clone(), child.id.clone(), grandchild.id.clone()];
+            let children = client.get_children_for_parents(&parent_ids).await.unwrap();
+            let ids = |parent_id: &String| children[parent_id].iter().map(|b| b.id.clone()).collect::<Vec<_>>();
+            assert_eq!(ids(&page.id), vec![child.id.clone()]);
+            assert_eq!(ids(&child.id), vec![grandchild.id.clone()]);
+            assert!(ids(&grandchild.id).is_empty());
+        } else {
+            println!("Skipping children query test - Datomic not available");
+        }
+    }
+
+    /// Test that blocks fetched together come back in the order asked for (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_blocks_by_ids() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = create_test_page(&client, "by-ids-test").await;
+            let mut ids = Vec::new();
+            for order in 0..3 {
+                let block = client.create_block(CreateBlockRequest {
+                    content: Some(format!("selected {}", order)),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    source: None,
+                }, None).await.unwrap();
+                ids.push(block.id);
+            }
+            ids.reverse();
+            ids.insert(1, Uuid::new_v4().to_string());
+
+            let blocks = client.get_blocks_by_ids(&ids).await.unwrap();
+            let found: Vec<&String> = blocks.iter().map(|b| &b.id).collect();
+            assert_eq!(found, vec![&ids[0], &ids[2], &ids[3]]);
+        } else {
+            println!("Skipping blocks-by-ids test - Datomic not available");
+        }
+    }
+
+    /// Test that a block's siblings come back in order (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_get_siblings() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("siblings-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let mut children = Vec::new();
+            for order in [2, 0, 1] {
+                children.push(client.create_block(CreateBlockRequest {
+                    content: Some(format!("bullet {}", order)),
+                    is_page: false,
+                    page_title: None,
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    source: None,
+                }, None).await.unwrap());
+            }
+
+            let siblings = client.get_siblings(&children[0].id).await.unwrap();
+            let orders: Vec<i32> = siblings.iter().map(|b| b.order).collect();
+            assert_eq!(orders, vec![0, 1, 2]);
+            assert!(siblings.iter().any(|b| b.id == children[0].id));
+
+            // Pages have no parent, so nothing to navigate between
+            assert!(client.get_siblings(&page.id).await.unwrap().is_empty());
+        } else {
+            println!("Skipping siblings test - Datomic not available");
+        }
+    }
+
+    /// Test that reference counts follow links being added and removed (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_reference_count() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let title = format!("references-test-{}", Uuid::new_v4());
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(title.clone()),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 0);
+
+            let block = client.create_block(CreateBlockRequest {
+                content: Some(format!("Follow up in [[{}]]", title)),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 1);
+
+            // Linking twice from the same block still counts it once
+            client.append_to_block(&block.id, &format!(" and [[{}]]", title)).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 1);
+
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("content".to_string(), serde_json::Value::String("No links left".to_string()));
+            client.update_block(&block.id, updates).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 0);
+        } else {
+            println!("Skipping reference count test - Datomic not available");
+        }
+    }
+
+    /// Test that rewriting a block's current content is skipped (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_update_block_skips_unchanged_content() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let block = client.create_block(CreateBlockRequest {
+                content: Some("Draft".to_string()),
+                is_page: true,
+                page_title: Some(format!("noop-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let content = |text: &str| {
+                let mut updates = std::collections::HashMap::new();
+                updates.insert("content".to_string(), serde_json::Value::String(text.to_string()));
+                updates
+            };
+
+            assert!(!client.update_block(&block.id, content("Draft")).await.unwrap());
+            let unchanged = client.get_block(&block.id).await.unwrap().unwrap();
+            assert_eq!(unchanged.updated_at, block.updated_at);
+
+            assert!(client.update_block(&block.id, content("Final")).await.unwrap());
+            let changed = client.get_block(&block.id).await.unwrap().unwrap();
+            assert_eq!(changed.content.as_deref(), Some("Final"));
+            assert!(changed.updated_at >= block.updated_at);
+        } else {
+            println!("Skipping no-op update test - Datomic not available");
+        }
+    }
+
+    /// Test that writes tell the change listener what they touched (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_writes_report_data_changes() {
+        use crate::models::{DataChange, DataChangeKind};
+        use std::sync::{Arc, Mutex};
+
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let changes = Arc::new(Mutex::new(Vec::new()));
+            let seen = changes.clone();
+            client.on_change(move |change| seen.lock().unwrap().push(change));
+
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("change-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            let block = client.create_block(CreateBlockRequest {
+                content: Some("Draft".to_string()),
+                is_page: false,
+                page_title: None,
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+            client.append_to_block(&block.id, " two").await.unwrap();
+
+            assert_eq!(*changes.lock().unwrap(), vec![
+                DataChange::new(DataChangeKind::PageCreated, [page.id.clone()]),
+                DataChange::new(DataChangeKind::BlockCreated, [block.id.clone()]),
+                DataChange::new(DataChangeKind::BlockChanged, [page.id.clone()]),
+                DataChange::new(DataChangeKind::BlockChanged, [block.id.clone()]),
+            ]);
+        } else {
+            println!("Skipping data change test - Datomic not available");
+        }
+    }
+
+    /// Test converting a recording marker into a stamped block (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_convert_marker_to_block() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                is_page: true,
+                page_title: Some(format!("marker-test-{}", Uuid::new_v4())),
+                parent_id: None,
+                order: 0,
+                source: None,
+            }, None).await.unwrap();
+
+            let recording = AudioRecording {
+                id: Uuid::new_v4().to_string(),
+                page_id: page.id.clone(),
+                file_path: "/tmp/markers.wav".to_string(),
+                duration_seconds: None,
+                recorded_at: Utc::now(),
+                system_audio: false,
+            };
+            client.create_audio_recording(&recording).await.unwrap();
+
+            for (timestamp_ms, label) in [(95_500, Some("Decision")), (12_250, None)] {
+                client.create_recording_marker(&RecordingMarker {
+                    id: Uuid::new_v4().to_string(),
+                    recording_id: recording.id.clone(),
+                    timestamp_ms,
+                    label: label.map(str::to_string),
+                    created_at: Utc::now(),
+                }).await.unwrap();
+            }
+
+            let markers = client.get_recording_markers(&recording.id).await.unwrap();
+            let positions: Vec<i64> = markers.iter().map(|m| m.timestamp_ms).collect();
+            assert_eq!(positions, vec![12_250, 95_500]);
+
+            let block = client.convert_marker_to_block(&markers[1].id, &page.id).await.unwrap();
+            assert_eq!(block.content.as_deref(), Some("Decision"));
+            assert_eq!(block.parent_id.as_deref(), Some(page.id.as_str()));
+            let timestamp = block.audio_timestamp.unwrap();
+            assert_eq!(timestamp.recording_id, recording.id);
+            assert_eq!(timestamp.timestamp_ms, 95_500);
+
+            // The converted marker is gone
+            assert_eq!(client.get_recording_markers(&recording.id).await.unwrap().len(), 1);
+        } else {
+            println!("Skipping marker test - Datomic not available");
+        }
+    }
+
+    /// Test that importing Markdown creates nested pages once (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_import_markdown_creates_pages_once() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let dir = tempfile::TempDir::new().unwrap();
+            let title = format!("Imported {}", Uuid::new_v4());
+            std::fs::write(dir.path().join(format!("{}.md", title)), "- Agenda\n  - Budget\n- Actions").unwrap();
+
+            let report = crate::archive::import_markdown(&client, dir.path(), &crate::jobs::Untracked).await.unwrap();
+            assert_eq!(report.pages_created, vec![title.clone()]);
+            assert_eq!(report.blocks_created, 3);
+
+            let page = client.get_page_by_title(&title).await.unwrap().expect("page was imported");
+            let top: Vec<Option<String>> = client.get_children_after(&page.id, None, 10).await.unwrap()
+                .into_iter()
+                .map(|block| block.content)
+                .collect();
+            assert_eq!(top, vec![Some("Agenda".to_string()), Some("Actions".to_string())]);
+
+            let again = crate::archive::import_markdown(&client, dir.path(), &crate::jobs::Untracked).await.unwrap();
+            assert_eq!(again.pages_skipped, vec![title]);
+            assert_eq!(again.blocks_created, 0);
+        } else {
+            println!("Skipping Markdown import test - Datomic not available");
+        }
+    }
+
+    /// Test that seeding the welcome page twice only creates it once (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_seed_sample_content_is_idempotent() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let first = crate::onboarding::seed_sample_content(&client).await.unwrap();
+            let second = crate::onboarding::seed_sample_content(&client).await.unwrap();
+            assert!(!second.created);
+            assert_eq!(second.page.id, first.page.id);
+
+            let state = crate::onboarding::onboarding_state(&client, 0).await.unwrap();
+            assert_eq!(state.sample_page_id, Some(first.page.id));
+            assert!(!state.fresh_vault);
+        } else {
+            println!("Skipping sample content test - Datomic not available");
+        }
+    }
+
+    /// Test that the third of three siblings is at index 2 (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_block_index_among_siblings() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(format!("Index {}", Uuid::new_v4())),
+                source: None,
+            }, None).await.unwrap();
+            let mut blocks = Vec::new();
+            for order in [5, 17, 100] {
+                blocks.push(client.create_block(CreateBlockRequest {
+                    content: Some(format!("Block at {}", order)),
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    is_page: false,
+                    page_title: None,
+                    source: None,
+                }, None).await.unwrap());
+            }
+
+            assert_eq!(client.get_block_index(&blocks[2].id).await.unwrap(), Some(2));
+            assert_eq!(client.get_block_index(&blocks[0].id).await.unwrap(), Some(0));
+            assert_eq!(client.get_block_index(&Uuid::new_v4().to_string()).await.unwrap(), None);
+        } else {
+            println!("Skipping block index test - Datomic not available");
+        }
+    }
+
+    /// Test that a page's word count sums its blocks (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_page_word_count_sums_blocks() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let title = format!("Chapter {}", Uuid::new_v4());
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(title.clone()),
+                source: None,
+            }, None).await.unwrap();
+            for (order, content) in ["It was a dark night", "and stormy"].into_iter().enumerate() {
+                client.create_block(CreateBlockRequest {
+                    content: Some(content.to_string()),
+                    parent_id: Some(page.id.clone()),
+                    order: order as i32,
+                    is_page: false,
+                    page_title: None,
+                    source: None,
+                }, None).await.unwrap();
+            }
+
+            assert_eq!(client.page_word_count(&title).await.unwrap(), 7);
+            assert!(client.page_word_count(&Uuid::new_v4().to_string()).await.is_err());
+        } else {
+            println!("Skipping word count test - Datomic not available");
+        }
+    }
+
+    /// Test that the two latest edits come back newest first (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_recent_edits_surface_newest_first() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(format!("Activity {}", Uuid::new_v4())),
+                source: None,
+            }, None).await.unwrap();
+            let mut blocks = Vec::new();
+            for order in 0..2 {
+                blocks.push(client.create_block(CreateBlockRequest {
+                    content: Some(format!("Draft {}", order)),
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    is_page: false,
+                    page_title: None,
+                    source: None,
+                }, None).await.unwrap());
+            }
+
+            for block in [&blocks[1], &blocks[0]] {
+                let updates = [("content".to_string(), serde_json::json!(format!("Edited {}", block.id)))].into_iter().collect();
+                client.update_block(&block.id, updates).await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+
+            let recent = client.get_recent_edits(2).await.unwrap();
+            let ids: Vec<&str> = recent.iter().map(|edit| edit.block.id.as_str()).collect();
+            assert_eq!(ids, vec![blocks[0].id.as_str(), blocks[1].id.as_str()]);
+            assert!(recent.iter().all(|edit| edit.page_id.as_deref() == Some(page.id.as_str())));
+        } else {
+            println!("Skipping recent edits test - Datomic not available");
+        }
+    }
+
+    /// Test that reindexing restores backlinks after the links were corrupted (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_reindex_restores_backlinks() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let title = format!("Linked page {}", Uuid::new_v4());
+            let page = create_test_page(&client, "reindex-test").await;
+            let block = client.create_block(CreateBlockRequest {
+                content: Some(format!("See [[{}]]", title)),
+                parent_id: Some(page.id.clone()),
+                order: 0,
+                is_page: false,
+                page_title: None,
+                source: None,
+            }, None).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 1);
+
+            // Drop the real link and add one the content doesn't have
+            client.transact(vec![
+                serde_json::json!([":db/retract", [":block/id", block.id], ":block/links", title]),
+                serde_json::json!([":db/add", [":block/id", block.id], ":block/links", "Nowhere"]),
+            ]).await.unwrap();
+            assert_eq!(client.reference_count(&title).await.unwrap(), 0);
+
+            let stats = client.reindex_derived().await.unwrap();
+            assert!(stats.blocks_fixed >= 1);
+            assert_eq!(client.reference_count(&title).await.unwrap(), 1);
+            assert_eq!(client.reindex_derived().await.unwrap().blocks_fixed, 0);
+        } else {
+            println!("Skipping reindex test - Datomic not available");
+        }
+    }
+
+    /// Test that batching a page's children yields each once, in order (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_child_batches_yield_every_block_in_order() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(format!("Large page {}", Uuid::new_v4())),
+                source: None,
+            }, None).await.unwrap();
+            let mut expected = Vec::new();
+            for order in [4, 0, 2, 2, 1, 3, 2] {
+                let block = client.create_block(CreateBlockRequest {
+                    content: Some(format!("Block {}", order)),
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    is_page: false,
+                    page_title: None,
+                    source: None,
+                }, None).await.unwrap();
+                expected.push((order, block.id));
+            }
+            expected.sort();
+
+            let mut batches = client.child_batches(&page.id, 3);
+            let mut streamed = Vec::new();
+            while let Some(batch) = batches.next_batch().await.unwrap() {
+                assert!(batch.len() <= 3);
+                streamed.extend(batch.into_iter().map(|block| (block.order, block.id)));
+            }
+            assert_eq!(streamed, expected);
+            assert!(batches.next_batch().await.unwrap().is_none());
+        } else {
+            println!("Skipping child batches test - Datomic not available");
+        }
+    }
+
+    /// Test that cancelling between batches stops the read with an error (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_cancelled_child_batches_fail_before_next_batch() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let page = client.create_block(CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(format!("Cancelled page {}", Uuid::new_v4())),
+                source: None,
+            }, None).await.unwrap();
+            for order in 0..4 {
+                client.create_block(CreateBlockRequest {
+                    content: Some(format!("Block {}", order)),
+                    parent_id: Some(page.id.clone()),
+                    order,
+                    is_page: false,
+                    page_title: None,
+                    source: None,
+                }, None).await.unwrap();
+            }
+
+            let cancel = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+            let mut batches = client.child_batches(&page.id, 2).cancel_on(cancel.clone());
+            assert_eq!(batches.next_batch().await.unwrap().map(|batch| batch.len()), Some(2));
+
+            cancel.store(true, std::sync::atomic::Ordering::SeqCst);
+            let result = batches.next_batch().await;
+            assert!(matches!(result, Err(DatomicError::Cancelled(_))));
+            assert!(matches!(AppError::from(result.unwrap_err()), AppError::Conflict { .. }));
+        } else {
+            println!("Skipping cancelled batches test - Datomic not available");
+        }
+    }
+
+    /// Test detecting and merging pages titled `Foo` and ` foo ` (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_merge_duplicate_pages() {
+        if let Ok(client) = DatomicPeerClient::new(AppConfig::default()).await {
+            let suffix = Uuid::new_v4().to_string();
+            let (kept_title, removed_title) = (format!("Foo {}", suffix), format!(" foo {} ", suffix));
+            let page = |title: &str| CreateBlockRequest {
+                content: None,
+                parent_id: None,
+                order: 0,
+                is_page: true,
+                page_title: Some(title.to_string()),
+                source: None,
+            };
+            let kept = client.create_block(page(&kept_title), None).await.unwrap();
+            let removed = client.create_block(page(&removed_title), None).await.unwrap();
+            let child = client.create_block(CreateBlockRequest {
+                content: Some("Notes from the duplicate".to_string()),
+                parent_id: Some(removed.id.clone()),
+                order: 0,
+                is_page: false,
+                page_title: None,
+                source: None,
+            }, None).await.unwrap();
+            let linking = client.create_block(CreateBlockRequest {
+                content: Some(format!("See [[foo {}]]", suffix)),
+                parent_id: Some(kept.id.clone()),
+                order: 0,
+                is_page: false,
+                page_title: None,
+                source: None,
+            }, None).await.unwrap();
+
+            let duplicates = client.find_duplicate_pages().await.unwrap();
+            assert!(duplicates.iter().any(|(a, b)| a.id == kept.id && b.id == removed.id));
+
+            client.merge_pages(&kept.id, &removed.id).await.unwrap();
+
+            assert_eq!(client.get_block(&removed.id).await.unwrap(), None);
+            let moved = client.get_block(&child.id).await.unwrap().unwrap();
+            assert_eq!(moved.parent_id.as_deref(), Some(kept.id.as_str()));
+            assert_eq!(moved.order, 1); // After the kept page's own block
+            let relinked = client.get_block(&linking.id).await.unwrap().unwrap();
+            assert_eq!(relinked.content, Some(format!("See [[{}]]", kept_title)));
+            assert_eq!(client.reference_count(&kept_title).await.unwrap(), 1);
+            assert!(!client.find_duplicate_pages().await.unwrap().iter().any(|(a, _)| a.id == kept.id));
+        } else {
+            println!("Skipping duplicate page test - Datomic not available");
+        }
+    }
+
+    /// Test that updating a missing block or with non-text content is an error (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_update_missing_block_is_not_found() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("content".to_string(), serde_json::Value::String("Lost edit".to_string()));
+
+            let err = client.update_block(&Uuid::new_v4().to_string(), updates).await.unwrap_err();
+            assert!(matches!(AppError::from(err), AppError::NotFound { .. }));
+
+            // Content that isn't text is refused rather than wiping the block's links
+            let page = create_test_page(&client, "update-test").await;
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("content".to_string(), serde_json::Value::Null);
+            let err = client.update_block(&page.id, updates).await.unwrap_err();
+            assert!(matches!(err, DatomicError::InvalidTransactionData(_)));
+        } else {
+            println!("Skipping update test - Datomic not available");
+        }
+    }
+
+    /// Test storage maintenance on a populated database (requires Datomic)
+    #[tokio::test]
+    #[ignore] // Ignore by default as it requires Datomic setup
+    async fn test_maintenance() {
+        let config = AppConfig::default();
+
+        if let Ok(client) = DatomicPeerClient::new(config).await {
+            for i in 0..10 {
+                let block_request = CreateBlockRequest {
+                    content: Some(format!("Maintenance test block {}", i)),
+                    is_page: true,
+                    page_title: Some(format!("maintenance-test-{}", Uuid::new_v4())),
+                    parent_id: None,
+                    order: i,
+                    source: None,
+                };
+                client.create_block(block_request, None).await.unwrap();
+            }
+
+            let result = client.maintenance().await;
+            assert!(result.is_ok(), "Maintenance failed: {:?}", result.err());
+        } else {
+            println!("Skipping maintenance test - Datomic not available");
+        }
+    }
+
     /// Test error handling in real scenarios
     #[tokio::test]
     async fn test_error_scenarios() {
@@ -447,6 +2649,7 @@ mod integration_tests {
                 page_title: Some("performance-test-page".to_string()), // Changed from page_id
                 parent_id: None,
                 order: 0, // Model uses i32
+                source: None,
             };
             
             let create_result = client.create_block(block_request, None).await;
@@ -489,6 +2692,7 @@ mod property_tests {
             // page_id: None, // Field removed from CreateBlockRequest
             parent_id: None,
             order: 0, // Model uses i32
+            source: None,
         };
         
         // We can't test the actual creation without Datomic,
@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use hound::WavReader;
+use serde::{Deserialize, Serialize};
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use crate::audio_engine::AudioEngine;
+use crate::errors::TranscriptionError;
+
+type Result<T> = std::result::Result<T, TranscriptionError>;
+
+// Whisper models expect 16kHz mono input
+const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+// Long recordings are decoded and transcribed a few minutes at a time to bound memory use
+const CHUNK_SECONDS: u32 = 300;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptSegment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptionProgress {
+    pub recording_id: String,
+    pub progress: f32, // 0.0 to 1.0
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptionResult {
+    pub recording_id: String,
+    pub status: String, // "completed", "failed" or "cancelled"
+    pub segments: usize,
+}
+
+/// Run a local whisper.cpp model over a WAV file, calling `on_progress` after
+/// each chunk. Checks `cancel` between chunks and returns `Cancelled` if set.
+pub fn transcribe_wav(
+    file_path: &str,
+    model_path: &str,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(f32),
+) -> Result<Vec<TranscriptSegment>> {
+    let context = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
+        .map_err(|e| TranscriptionError::model_error(format!("Failed to load model {}: {}", model_path, e)))?;
+    let mut state = context.create_state()
+        .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+
+    let mut reader = WavReader::open(file_path)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let total_frames = reader.duration() as usize;
+    let chunk_frames = (spec.sample_rate * CHUNK_SECONDS) as usize;
+
+    let mut samples = AudioEngine::read_f32_samples(&mut reader);
+    let mut segments = Vec::new();
+    let mut frames_done = 0usize;
+
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Err(TranscriptionError::Cancelled);
+        }
+
+        let chunk: Vec<f32> = samples.by_ref().take(chunk_frames * channels).collect::<std::result::Result<_, _>>()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let chunk_start_ms = frames_done as i64 * 1000 / spec.sample_rate as i64;
+        frames_done += chunk.len() / channels;
+
+        let audio = to_whisper_input(&chunk, channels, spec.sample_rate);
+
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_special(false);
+        params.set_print_timestamps(false);
+
+        state.full(params, &audio)
+            .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+
+        let segment_count = state.full_n_segments()
+            .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+        for i in 0..segment_count {
+            let text = state.full_get_segment_text(i)
+                .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+            // Whisper reports segment bounds in centiseconds
+            let t0 = state.full_get_segment_t0(i)
+                .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+            let t1 = state.full_get_segment_t1(i)
+                .map_err(|e| TranscriptionError::model_error(e.to_string()))?;
+
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            segments.push(TranscriptSegment {
+                start_ms: chunk_start_ms + t0 * 10,
+                end_ms: chunk_start_ms + t1 * 10,
+                text: text.to_string(),
+            });
+        }
+
+        if total_frames > 0 {
+            on_progress((frames_done as f32 / total_frames as f32).min(1.0));
+        }
+    }
+
+    Ok(segments)
+}
+
+/// Downmix interleaved samples to mono and linearly resample to 16kHz
+fn to_whisper_input(samples: &[f32], channels: usize, sample_rate: u32) -> Vec<f32> {
+    let mono: Vec<f32> = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    if sample_rate == WHISPER_SAMPLE_RATE || mono.is_empty() {
+        return mono;
+    }
+
+    let step = sample_rate as f64 / WHISPER_SAMPLE_RATE as f64;
+    let out_len = (mono.len() as f64 / step) as usize;
+    (0..out_len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let index = pos as usize;
+            let frac = (pos - index as f64) as f32;
+            let next = mono.get(index + 1).copied().unwrap_or(mono[index]);
+            mono[index] + (next - mono[index]) * frac
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_whisper_input_is_mono_16khz() {
+        // One second of stereo audio at 48kHz with the channels out of phase
+        let samples: Vec<f32> = (0..48000).flat_map(|_| [0.5f32, -0.5f32]).collect();
+        let audio = to_whisper_input(&samples, 2, 48000);
+
+        assert_eq!(audio.len(), 16000);
+        assert!(audio.iter().all(|&s| s == 0.0));
+    }
+}
@@ -0,0 +1,158 @@
+use tauri::image::Image;
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, Wry};
+use tracing::error;
+
+const TRAY_ID: &str = "main";
+const IDLE_TOOLTIP: &str = "Gita";
+
+/// Tray menu entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrayAction {
+    OpenToday,
+    ToggleRecording,
+    Quit,
+}
+
+impl TrayAction {
+    fn id(self) -> &'static str {
+        match self {
+            TrayAction::OpenToday => "open-today",
+            TrayAction::ToggleRecording => "toggle-recording",
+            TrayAction::Quit => "quit",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        [TrayAction::OpenToday, TrayAction::ToggleRecording, TrayAction::Quit].into_iter()
+            .find(|action| action.id() == id)
+    }
+}
+
+/// Parts of the tray that change with the recording state
+struct Tray {
+    toggle_item: MenuItem<Wry>,
+    idle_icon: Image<'static>,
+    recording_icon: Image<'static>,
+}
+
+/// Copy of an RGBA icon with a red dot in its bottom right corner
+fn with_recording_dot(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut marked = rgba.to_vec();
+    let radius = width.min(height) as f32 * 0.22;
+    let (center_x, center_y) = (width as f32 - radius - 0.5, height as f32 - radius - 0.5);
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+            if dx * dx + dy * dy <= radius * radius {
+                let i = ((y * width + x) * 4) as usize;
+                marked[i..i + 4].copy_from_slice(&[0xE5, 0x39, 0x35, 0xFF]);
+            }
+        }
+    }
+    marked
+}
+
+/// Elapsed recording time as `m:ss`, or `h:mm:ss` past an hour
+fn format_elapsed(elapsed_ms: u64) -> String {
+    let seconds = elapsed_ms / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// Add the tray icon, calling `on_action` when a menu entry is chosen
+pub fn create(app: &tauri::App, on_action: impl Fn(&AppHandle, TrayAction) + Send + Sync + 'static) -> tauri::Result<()> {
+    let idle_icon = app.default_window_icon().cloned()
+        .ok_or_else(|| tauri::Error::AssetNotFound("default window icon".to_string()))?
+        .to_owned();
+    let recording_icon = Image::new_owned(
+        with_recording_dot(idle_icon.rgba(), idle_icon.width(), idle_icon.height()),
+        idle_icon.width(),
+        idle_icon.height(),
+    );
+
+    let open_item = MenuItem::with_id(app, TrayAction::OpenToday.id(), "Open today's note", true, None::<&str>)?;
+    let toggle_item = MenuItem::with_id(app, TrayAction::ToggleRecording.id(), "Start recording", true, None::<&str>)?;
+    let quit_item = MenuItem::with_id(app, TrayAction::Quit.id(), "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&open_item, &toggle_item, &quit_item])?;
+
+    TrayIconBuilder::with_id(TRAY_ID)
+        .icon(idle_icon.clone())
+        .tooltip(IDLE_TOOLTIP)
+        .menu(&menu)
+        .on_menu_event(move |app_handle, event| {
+            if let Some(action) = TrayAction::from_id(event.id().as_ref()) {
+                on_action(app_handle, action);
+            }
+        })
+        .build(app)?;
+
+    app.manage(Tray { toggle_item, idle_icon, recording_icon });
+    Ok(())
+}
+
+/// Switch the icon, tooltip and menu between idle and recording
+pub fn show_recording(app_handle: &AppHandle, recording: bool) {
+    let (Some(tray), Some(icon)) = (app_handle.try_state::<Tray>(), app_handle.tray_by_id(TRAY_ID)) else {
+        return;
+    };
+    let (image, tooltip, toggle_text) = if recording {
+        (&tray.recording_icon, format!("{} - Recording {}", IDLE_TOOLTIP, format_elapsed(0)), "Stop recording")
+    } else {
+        (&tray.idle_icon, IDLE_TOOLTIP.to_string(), "Start recording")
+    };
+
+    let updated = icon.set_icon(Some(image.clone()))
+        .and_then(|_| icon.set_tooltip(Some(tooltip)))
+        .and_then(|_| tray.toggle_item.set_text(toggle_text));
+    if let Err(e) = updated {
+        error!("Failed to update tray: {}", e);
+    }
+}
+
+/// Show how long the recording in progress has been running
+pub fn show_elapsed(app_handle: &AppHandle, elapsed_ms: u64) {
+    if let Some(icon) = app_handle.tray_by_id(TRAY_ID) {
+        let tooltip = format!("{} - Recording {}", IDLE_TOOLTIP, format_elapsed(elapsed_ms));
+        if let Err(e) = icon.set_tooltip(Some(tooltip)) {
+            error!("Failed to update tray tooltip: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(0), "0:00");
+        assert_eq!(format_elapsed(65_999), "1:05");
+        assert_eq!(format_elapsed(3_600_000 + 61_000), "1:01:01");
+    }
+
+    #[test]
+    fn test_recording_dot_marks_corner_only() {
+        let (width, height) = (32, 32);
+        let icon = vec![0x10; (width * height * 4) as usize];
+        let marked = with_recording_dot(&icon, width, height);
+
+        let pixel = |x: u32, y: u32| &marked[((y * width + x) * 4) as usize..][..4];
+        assert_eq!(pixel(0, 0), &[0x10; 4]);
+        assert_eq!(pixel(width - 4, height - 4), &[0xE5, 0x39, 0x35, 0xFF]);
+        assert_eq!(marked.len(), icon.len());
+    }
+
+    #[test]
+    fn test_tray_action_ids_round_trip() {
+        for action in [TrayAction::OpenToday, TrayAction::ToggleRecording, TrayAction::Quit] {
+            assert_eq!(TrayAction::from_id(action.id()), Some(action));
+        }
+        assert_eq!(TrayAction::from_id("unknown"), None);
+    }
+}